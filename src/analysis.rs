@@ -0,0 +1,186 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Empirical analysis of how many steps a program takes on random inputs.
+//!
+//! The program is run many times, with some natural registers set to random values within given
+//! ranges each time. The report shows how often the program halted within a step limit, and the
+//! distribution of the number of steps it took when it did. The same seed always gives the same
+//! inputs, so results can be reproduced.
+
+use std::{ fmt::Display, ops::RangeInclusive };
+
+use crate::{
+    machine::Machine,
+    memory::{ Register, RegisterNumber },
+};
+
+/// The width of the longest bar in the histogram, in characters.
+const BAR_WIDTH: usize = 40;
+/// How many buckets the histogram has, at most.
+const BUCKETS: usize = 10;
+
+/// The step counts of a program run on random inputs, which can be displayed as a report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    runs: usize,
+    step_limit: usize,
+    /// The number of steps taken by each run which halted, sorted in ascending order.
+    halted_steps: Vec<usize>,
+}
+
+impl Analysis {
+    /// Run the program `runs` times. Each time, the natural registers given in `ranges` are set to
+    /// a random value in their range, and every other register keeps its initial value. A run
+    /// which hasn't halted after `step_limit` steps is stopped. Afterwards, the machine is reset
+    /// with its original initial registers.
+    pub fn sample(
+        machine: &mut Machine,
+        ranges: &[(usize, RangeInclusive<u128>)],
+        runs: usize,
+        seed: u64,
+        step_limit: usize,
+    ) -> Analysis {
+        let mut rng = SplitMix64(seed);
+        let original = machine.get_initial_memory().clone();
+        let mut halted_steps = Vec::new();
+        for _ in 0..runs {
+            let mut inputs = original.clone();
+            for (register, range) in ranges {
+                let value = Register::from(rng.next_in(range));
+                inputs.set_register(RegisterNumber::Natural(*register), value);
+            }
+            machine.set_initial_memory(inputs);
+            machine.reset();
+            while !machine.is_halted() && machine.get_steps() < step_limit {
+                if !matches!(machine.step(), Ok(None)) {
+                    break;
+                }
+            }
+            if machine.is_halted() {
+                halted_steps.push(machine.get_steps());
+            }
+        }
+        machine.set_initial_memory(original);
+        machine.reset();
+        halted_steps.sort_unstable();
+        Analysis { runs, step_limit, halted_steps }
+    }
+
+    #[must_use]
+    pub const fn get_runs(&self) -> usize {
+        self.runs
+    }
+
+    /// Get how many runs halted within the step limit.
+    #[must_use]
+    pub fn get_halted_count(&self) -> usize {
+        self.halted_steps.len()
+    }
+
+    /// Get the number of steps of each run which halted, in ascending order.
+    #[must_use]
+    pub fn get_halted_steps(&self) -> &[usize] {
+        &self.halted_steps
+    }
+
+    /// Get the smallest step count which at least `percent`% of the halted runs didn't exceed, or
+    /// [`None`] if no run halted.
+    #[must_use]
+    pub fn percentile(&self, percent: usize) -> Option<usize> {
+        let count = self.halted_steps.len();
+        let rank = (percent.min(100) * count).div_ceil(100).max(1);
+        self.halted_steps.get(rank - 1).copied()
+    }
+
+    /// Get the number of halted runs in each bucket of the histogram, with the first and last step
+    /// counts in the bucket.
+    fn histogram(&self) -> Vec<(usize, usize, usize)> {
+        let (Some(&min), Some(&max)) = (self.halted_steps.first(), self.halted_steps.last()) else {
+            return Vec::new();
+        };
+        let width = (max - min) / BUCKETS + 1;
+        let mut buckets: Vec<(usize, usize, usize)> = (min..=max)
+            .step_by(width)
+            .map(|start| (start, start + width - 1, 0))
+            .collect();
+        for &steps in &self.halted_steps {
+            buckets[(steps - min) / width].2 += 1;
+        }
+        buckets
+    }
+}
+
+impl Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let halted = self.halted_steps.len();
+        let rate = if self.runs == 0 { 0.0 } else { 100.0 * halted as f64 / self.runs as f64 };
+        writeln!(f, "Runs: {}", self.runs)?;
+        writeln!(f, "Halted within {} steps: {halted} ({rate:.1}%)", self.step_limit)?;
+        if halted == 0 {
+            return Ok(());
+        }
+        let mean = self.halted_steps.iter().sum::<usize>() as f64 / halted as f64;
+        let percentile = |percent| self.percentile(percent).unwrap_or_default();
+        writeln!(
+            f,
+            "Steps: min {}, median {}, p90 {}, p99 {}, max {}, mean {mean:.1}",
+            percentile(0),
+            percentile(50),
+            percentile(90),
+            percentile(99),
+            percentile(100),
+        )?;
+        let buckets = self.histogram();
+        let most = buckets.iter().map(|&(_, _, count)| count).max().unwrap_or(1);
+        let labels: Vec<String> = buckets
+            .iter()
+            .map(|(first, last, _)| format!("{first}..={last}"))
+            .collect();
+        let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+        for (label, (_, _, count)) in labels.iter().zip(&buckets) {
+            let bar = "#".repeat((count * BAR_WIDTH).div_ceil(most));
+            writeln!(f, "{label:>label_width$}  {bar} {count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A small, fast pseudorandom number generator, so that the same seed gives the same inputs on
+/// every platform.
+#[derive(Debug, Clone)]
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_in(&mut self, range: &RangeInclusive<u128>) -> u128 {
+        let random = (u128::from(self.next_u64()) << 64) | u128::from(self.next_u64());
+        let span = range.end().saturating_sub(*range.start());
+        if span == u128::MAX {
+            random
+        }
+        else {
+            range.start() + random % (span + 1)
+        }
+    }
+}
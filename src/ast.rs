@@ -0,0 +1,148 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! The syntax tree of a program's source code, for tools which analyse or transform programs
+//! without running them.
+//!
+//! [`parser::parse_ast`](crate::parser::parse_ast) reads source code into a [`Program`], keeping
+//! where everything came from and the comments, and
+//! [`parser::machine_from_ast`](crate::parser::machine_from_ast) builds a
+//! [`Machine`](crate::machine::Machine) from it, which is what
+//! [`parser::parse_str`](crate::parser::parse_str) does in one go. Macros aren't expanded in the
+//! tree, and jumps keep their targets as written, so relative jumps such as `+2` are still
+//! relative.
+//!
+//! # Examples
+//! ```
+//! use remuir::{ ast::ItemKind, parser::parse_ast };
+//!
+//! let source = "registers 0 1\n## Moves r1 into r0.\nloop: decjz r1 +3 # Done?\ninc r0\ngoto loop";
+//! let program = parse_ast(source).unwrap();
+//! assert_eq!(program.items.len(), 4);
+//! let item = &program.items[1];
+//! assert_eq!(item.kind.get_label(), Some("loop"));
+//! assert!(matches!(item.kind, ItemKind::Instruction { .. }));
+//! assert_eq!(item.line, 3);
+//! assert_eq!(item.documentation, vec![String::from("Moves r1 into r0.")]);
+//! assert_eq!(program.comments[1].text, " Done?");
+//! ```
+
+use std::ops::Range;
+
+use crate::{ instruction::Instruction, memory::Memory, requirement::Requirement };
+
+/// Where something is in the source code, in bytes from the start. A leading byte order mark
+/// isn't counted.
+pub type Span = Range<usize>;
+
+/// A whole program, as it's written.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    /// Everything in the program other than comments, in the order it's written.
+    pub items: Vec<Item>,
+    /// Every comment in the program, including `##` documentation, in the order it's written.
+    pub comments: Vec<Comment>,
+}
+
+impl Program {
+    /// Get the registers line, if the program has one.
+    #[must_use]
+    pub fn get_registers(&self) -> Option<&Memory> {
+        self.items.iter().find_map(|item| match &item.kind {
+            ItemKind::Registers(memory) => Some(memory),
+            _ => None,
+        })
+    }
+
+    /// Get the versions of remuir the program requires, from its `#!requires` directives.
+    #[must_use]
+    pub fn get_requirements(&self) -> Vec<&Requirement> {
+        self.items
+            .iter()
+            .filter_map(|item| match &item.kind {
+                ItemKind::Requires(requirement) => Some(requirement),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Get the labels defined by instructions and macro calls, in the order they're written.
+    #[must_use]
+    pub fn get_labels(&self) -> Vec<&str> {
+        self.items.iter().filter_map(|item| item.kind.get_label()).collect()
+    }
+}
+
+/// A line of the program, or a macro definition, which spans several lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub span: Span,
+    /// The line it starts on, counting from 1.
+    pub line: usize,
+    /// The text of the `##` comments right above it, without the `##` and surrounding whitespace.
+    pub documentation: Vec<String>,
+    pub kind: ItemKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemKind {
+    /// The initial registers, such as `registers 0 3`.
+    Registers(Memory),
+    /// An instruction, such as `loop: decjz r1 done`.
+    Instruction { label: Option<String>, instruction: Instruction },
+    /// A call to a macro or a pseudo-instruction, such as `copy r1 r2`.
+    MacroCall { label: Option<String>, name: String, arguments: Vec<String> },
+    /// The lines from `macro NAME PARAMETERS...` to `endmacro`. The body is kept as text, without
+    /// comments or blank lines, since it can only be parsed once its parameters are filled in.
+    MacroDefinition { name: String, parameters: Vec<String>, body: Vec<String> },
+    /// `#!phase LABEL`.
+    Phase(String),
+    /// `#!entry LABEL`.
+    Entry(String),
+    /// `#!export LABEL`.
+    Export(String),
+    /// `#!import LABEL`.
+    Import(String),
+    /// `#!extended`.
+    Extended,
+    /// `#!requires remuir >= 0.3`.
+    Requires(Requirement),
+}
+
+impl ItemKind {
+    /// Get the label defined by an instruction or macro call, if it has one.
+    #[must_use]
+    pub fn get_label(&self) -> Option<&str> {
+        match self {
+            ItemKind::Instruction { label, .. } | ItemKind::MacroCall { label, .. } => {
+                label.as_deref()
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A comment, from a `#` to the end of its line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub span: Span,
+    /// The line it's on, counting from 1.
+    pub line: usize,
+    /// The text after the `#`, or after the `##` of documentation.
+    pub text: String,
+    /// Whether it's `##` documentation.
+    pub is_documentation: bool,
+}
@@ -0,0 +1,206 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Saving debug sessions as they go, so they can be picked up again after a crash or an
+//! accidental Ctrl+D.
+//!
+//! A session is saved as the commands typed into it, which are replayed to restore it, since that
+//! brings back breakpoints, snapshots and everything else as well as the registers. Each program
+//! has its own session file, named after its [`Machine::fingerprint`], so a session is never
+//! replayed on a program which has changed since. After the header, the file has the program's
+//! [`Manifest`], the current line and registers as comments, for anyone reading it, followed by
+//! one command per line:
+//!
+//! ```text
+//! remuir session 1
+//! # remuir 0.1.0
+//! # fingerprint 00c0ffee00c0ffee
+//! # registers 1 0
+//! # optimised no
+//! # strict jumps no
+//! # step limit none
+//! # cost model none
+//! # seed none
+//! # line 2
+//! # registers 1 2
+//! b loop
+//! step
+//! ```
+//!
+//! Quitting with `exit` or `quit` removes the file.
+//!
+//! Sessions can also be saved without `--autosave`, by setting how often to save them in the
+//! config file (see [`Autosave::configured_every`]):
+//!
+//! ```text
+//! # Save debug sessions after every 5 commands.
+//! autosave = 5
+//! ```
+
+use std::{ fs, io, num::NonZeroUsize, path::PathBuf };
+
+use crate::{ machine::Machine, manifest::Manifest };
+
+/// The first line of every session file. The version changes whenever the format does.
+const HEADER: &str = "remuir session 1";
+
+/// The session file of one program, and the commands typed since the session started.
+#[derive(Debug, Clone)]
+pub struct Autosave {
+    path: PathBuf,
+    /// How many commands may be typed before the session is saved again.
+    every: NonZeroUsize,
+    commands: Vec<String>,
+    unsaved: usize,
+}
+
+impl Autosave {
+    /// Save sessions of the given program in the given directory, every `every` commands. The
+    /// directory is created when the session is first saved.
+    #[must_use]
+    pub fn new(directory: PathBuf, machine: &Machine, every: NonZeroUsize) -> Autosave {
+        let path = directory.join(format!("{:016x}.session", machine.fingerprint()));
+        Autosave { path, every, commands: Vec::new(), unsaved: 0 }
+    }
+
+    /// Get the usual directory for sessions, `$XDG_STATE_HOME/remuir/sessions` or else
+    /// `~/.local/state/remuir/sessions`, or [`None`] if there's no home directory.
+    #[must_use]
+    pub fn default_directory() -> Option<PathBuf> {
+        let state_home = std::env::var_os("XDG_STATE_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+            })?;
+        Some(state_home.join("remuir").join("sessions"))
+    }
+
+    /// Get the usual config file, `$XDG_CONFIG_HOME/remuir/config` or else
+    /// `~/.config/remuir/config`, or [`None`] if there's no home directory.
+    #[must_use]
+    pub fn config_file() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("remuir").join("config"))
+    }
+
+    /// Get how often to save sessions from the contents of a config file, which has a setting
+    /// such as `autosave = 5` on each line. `autosave = on` is the same as `autosave = 1`, and
+    /// `autosave = off` or no `autosave` setting at all means sessions aren't saved. Blank lines,
+    /// comments starting with `#` and other settings are ignored. If `autosave` is set more than
+    /// once, the last one counts.
+    ///
+    /// # Errors
+    ///
+    /// * [`String`] - returned when the value of `autosave` isn't `on`, `off` or a positive
+    ///   number of commands.
+    pub fn configured_every(config: &str) -> Result<Option<NonZeroUsize>, String> {
+        let mut every = None;
+        for line in config.lines().map(str::trim) {
+            let Some((key, value)) = line.split_once('=') else { continue };
+            if key.trim() != "autosave" {
+                continue;
+            }
+            every = match value.trim() {
+                "on" => NonZeroUsize::new(1),
+                "off" => None,
+                value => Some(value.parse().map_err(|_| {
+                    format!("autosave should be on, off or a number of commands, not {value:?}")
+                })?),
+            };
+        }
+        Ok(every)
+    }
+
+    /// Get the commands of the last session of this program which wasn't quit, if there's one.
+    #[must_use]
+    pub fn saved_commands(&self) -> Option<Vec<String>> {
+        let contents = fs::read_to_string(&self.path).ok()?;
+        let mut lines = contents.lines();
+        if lines.next() != Some(HEADER) {
+            return None;
+        }
+        let commands: Vec<String> = lines
+            .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+            .map(str::to_string)
+            .collect();
+        (!commands.is_empty()).then_some(commands)
+    }
+
+    /// Remember a command which was typed into the session, and save the session if enough
+    /// commands have been typed since it was last saved.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the session file couldn't be written.
+    pub fn record(&mut self, command: &str, machine: &Machine) -> io::Result<()> {
+        let command = command.trim();
+        if command.is_empty() {
+            return Ok(());
+        }
+        self.commands.push(command.to_string());
+        self.unsaved += 1;
+        if self.unsaved >= self.every.get() {
+            self.save(machine)?;
+        }
+        Ok(())
+    }
+
+    /// Write the session file now.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the directory or the session file couldn't be written.
+    pub fn save(&mut self, machine: &Machine) -> io::Result<()> {
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut contents = format!("{HEADER}\n");
+        for line in Manifest::new(machine).to_string().lines() {
+            contents.push_str(&format!("# {line}\n"));
+        }
+        contents.push_str(&format!(
+            "# line {}\n# {}\n",
+            machine.get_current_line_number(),
+            machine.display_nat_registers(),
+        ));
+        for command in &self.commands {
+            contents.push_str(command);
+            contents.push('\n');
+        }
+        // Write to a temporary file first, so that a crash never leaves half a session.
+        let temporary = self.path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary, contents)?;
+        fs::rename(temporary, &self.path)?;
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    /// Remove the session file, when the session has ended normally.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the file exists but couldn't be removed.
+    pub fn discard(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
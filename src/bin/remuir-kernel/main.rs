@@ -0,0 +1,439 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! A Jupyter kernel for remuir.
+//!
+//! Jupyter starts the kernel with the path of a connection file, which says where to listen and
+//! how to sign messages. Each code cell is run with [`RpcServer::execute`], so a cell is either a
+//! program or a list of REPL commands, and the registers afterwards are shown as a table. An
+//! interrupt stops the running cell as Ctrl+C would in the debugger (see [`remuir::interrupt`]).
+
+#![warn(clippy::all, clippy::pedantic, clippy::nursery)]
+
+mod zmtp;
+
+use std::{
+    fmt::Display,
+    io,
+    process::ExitCode,
+    sync::mpsc,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use remuir::{
+    interrupt,
+    rpc::{Execution, RpcServer},
+};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use zmtp::{Frames, Publisher, Router};
+
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+const PROTOCOL_VERSION: &str = "5.3";
+
+#[derive(Clone, Copy)]
+enum Channel {
+    Shell,
+    Control,
+}
+
+/// A message in the Jupyter wire protocol.
+struct Message {
+    /// The `ZeroMQ` identities of the sender, which replies are sent back to.
+    identities: Frames,
+    header: Value,
+    content: Value,
+}
+
+/// The key messages are signed with, using HMAC-SHA256. Signing is disabled if it's empty.
+#[derive(Clone)]
+struct Key(Vec<u8>);
+
+struct Kernel {
+    key: Key,
+    session: String,
+    messages_sent: usize,
+    execution_count: usize,
+    server: RpcServer,
+    shell: Router,
+    control: Router,
+    iopub: Publisher,
+}
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: remuir-kernel CONNECTION_FILE");
+        return ExitCode::from(2);
+    };
+    match run(&path) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn run(path: &str) -> io::Result<()> {
+    let connection: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let address = |port: &str| {
+        format!(
+            "{}:{}",
+            connection["ip"].as_str().unwrap_or("127.0.0.1"),
+            connection[port].as_u64().unwrap_or(0),
+        )
+    };
+    if connection["transport"].as_str().is_some_and(|transport| transport != "tcp") {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "only TCP is supported"));
+    }
+    let (sender, receiver) = mpsc::channel();
+    let (control_sender, control_receiver) = mpsc::channel();
+    let mut kernel = Kernel {
+        key: Key(connection["key"].as_str().unwrap_or_default().as_bytes().to_vec()),
+        session: new_session(),
+        messages_sent: 0,
+        execution_count: 0,
+        server: RpcServer::default(),
+        shell: Router::bind(&address("shell_port"), Channel::Shell, sender.clone())?,
+        control: Router::bind(&address("control_port"), Channel::Control, control_sender)?,
+        iopub: Publisher::bind(&address("iopub_port"))?,
+    };
+    // Nothing ever asks for input, but clients expect to be able to connect.
+    let (stdin_sender, _stdin_receiver) = mpsc::channel();
+    Router::bind(&address("stdin_port"), (), stdin_sender)?;
+    zmtp::echo(&address("hb_port"))?;
+    // Jupyter interrupts kernels with SIGINT, unless their kernelspec asks for a message instead.
+    interrupt::install_handler();
+    // The messages below are handled one at a time, so an interrupt would only be read once the
+    // cell it's meant to stop had finished. Instead, it's requested as soon as it arrives.
+    let key = kernel.key.clone();
+    thread::spawn(move || {
+        for (channel, frames) in control_receiver {
+            let message = parse(&key, &frames);
+            if message.is_some_and(|message| message.header["msg_type"] == "interrupt_request") {
+                interrupt::request();
+            }
+            if sender.send((channel, frames)).is_err() {
+                return;
+            }
+        }
+    });
+
+    for (channel, frames) in receiver {
+        let Some(message) = parse(&kernel.key, &frames) else {
+            continue;
+        };
+        if !kernel.handle(channel, &message) {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Make a session ID which is unique enough to tell kernels apart.
+fn new_session() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("{nanos:x}-{:x}", std::process::id())
+}
+
+/// The current time in ISO 8601 format, in UTC.
+fn now() -> String {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = time.as_secs();
+    // Convert days since 1970-01-01 to a date, with Howard Hinnant's civil_from_days algorithm.
+    let days = seconds / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z",
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+        time.subsec_micros(),
+    )
+}
+
+/// Registers rendered as an HTML table, with the register names as headings.
+struct RegisterTable<'a>(&'a [String]);
+
+impl Display for RegisterTable<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<table><tr>")?;
+        for i in 0..self.0.len() {
+            write!(f, "<th>r{i}</th>")?;
+        }
+        write!(f, "</tr><tr>")?;
+        for value in self.0 {
+            write!(f, "<td>{value}</td>")?;
+        }
+        write!(f, "</tr></table>")
+    }
+}
+
+impl Key {
+    fn mac(&self, parts: &[&[u8]]) -> Option<Hmac<Sha256>> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).ok()?;
+        for part in parts {
+            mac.update(part);
+        }
+        Some(mac)
+    }
+
+    /// Sign the parts of a message, giving the signature in hex.
+    fn sign(&self, parts: &[&[u8]]) -> String {
+        if self.0.is_empty() {
+            return String::new();
+        }
+        let Some(mac) = self.mac(parts) else {
+            return String::new();
+        };
+        mac.finalize().into_bytes().iter().fold(String::new(), |mut hex, byte| {
+            hex.push(char::from(b"0123456789abcdef"[usize::from(byte >> 4)]));
+            hex.push(char::from(b"0123456789abcdef"[usize::from(byte & 0xF)]));
+            hex
+        })
+    }
+
+    /// Check the signature in hex of the parts of a message, in constant time.
+    fn verify(&self, parts: &[&[u8]], signature: &[u8]) -> bool {
+        if self.0.is_empty() {
+            return signature.is_empty();
+        }
+        let (Some(mac), Some(signature)) = (self.mac(parts), decode_hex(signature)) else {
+            return false;
+        };
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+/// Decode hex digits into bytes, or [`None`] if they aren't hex digits in pairs.
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| {
+            let high = char::from(pair[0]).to_digit(16)?;
+            let low = char::from(pair[1]).to_digit(16)?;
+            u8::try_from(high << 4 | low).ok()
+        })
+        .collect()
+}
+
+/// Split a received message into its parts, checking its signature. Invalid messages are ignored,
+/// as the protocol asks.
+fn parse(key: &Key, frames: &[Vec<u8>]) -> Option<Message> {
+    let delimiter = frames.iter().position(|frame| frame == DELIMITER)?;
+    let (identities, rest) = frames.split_at(delimiter);
+    let [_, signature, header, parent, metadata, content, ..] = rest else {
+        return None;
+    };
+    if !key.verify(&[header, parent, metadata, content], signature) {
+        return None;
+    }
+    Some(Message {
+        identities: identities.to_vec(),
+        header: serde_json::from_slice(header).ok()?,
+        content: serde_json::from_slice(content).ok()?,
+    })
+}
+
+impl Kernel {
+    /// Build the frames of a message to send, in reply to `parent`.
+    fn frames(
+        &mut self,
+        identities: Frames,
+        msg_type: &str,
+        parent: &Value,
+        content: &Value,
+    ) -> Frames {
+        self.messages_sent += 1;
+        let header = json!({
+            "msg_id": format!("{}_{}", self.session, self.messages_sent),
+            "session": self.session,
+            "username": "remuir",
+            "date": now(),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        })
+        .to_string();
+        let parent = parent.to_string();
+        let metadata = String::from("{}");
+        let content = content.to_string();
+        let signature = self.key.sign(&[
+            header.as_bytes(),
+            parent.as_bytes(),
+            metadata.as_bytes(),
+            content.as_bytes(),
+        ]);
+        let mut frames = identities;
+        frames.push(DELIMITER.to_vec());
+        for part in [signature, header, parent, metadata, content] {
+            frames.push(part.into_bytes());
+        }
+        frames
+    }
+
+    fn reply(&mut self, channel: Channel, message: &Message, msg_type: &str, content: &Value) {
+        let frames = self.frames(message.identities.clone(), msg_type, &message.header, content);
+        match channel {
+            Channel::Shell => self.shell.send(&frames),
+            Channel::Control => self.control.send(&frames),
+        }
+    }
+
+    fn publish(&mut self, message: &Message, msg_type: &str, content: &Value) {
+        let topic = format!("kernel.{}.{msg_type}", self.session).into_bytes();
+        let frames = self.frames(vec![topic], msg_type, &message.header, content);
+        self.iopub.send(&frames);
+    }
+
+    /// Handle a request, returning whether the kernel should keep running.
+    fn handle(&mut self, channel: Channel, message: &Message) -> bool {
+        let msg_type = message.header["msg_type"].as_str().unwrap_or_default();
+        let Some(request) = msg_type.strip_suffix("_request") else {
+            return true;
+        };
+        self.publish(message, "status", &json!({ "execution_state": "busy" }));
+        let mut keep_running = true;
+        let reply = match request {
+            "kernel_info" => json!({
+                "status": "ok",
+                "protocol_version": PROTOCOL_VERSION,
+                "implementation": "remuir",
+                "implementation_version": env!("CARGO_PKG_VERSION"),
+                "language_info": {
+                    "name": "remuir",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "mimetype": "text/x-remuir",
+                    "file_extension": ".remuir",
+                },
+                "banner": remuir::text_literals::welcome_repl(),
+                "help_links": [],
+            }),
+            "execute" => self.execute(message),
+            "is_complete" => json!({ "status": "complete" }),
+            "comm_info" => json!({ "status": "ok", "comms": {} }),
+            "history" => json!({ "status": "ok", "history": [] }),
+            // The cell running when the interrupt arrived was already stopped (see `run`), and an
+            // interrupt which arrived between cells mustn't stop the next one.
+            "interrupt" => {
+                interrupt::clear();
+                json!({ "status": "ok" })
+            },
+            "shutdown" => {
+                keep_running = false;
+                let restart = message.content["restart"].as_bool().unwrap_or(false);
+                json!({ "status": "ok", "restart": restart })
+            },
+            _ => {
+                self.publish(message, "status", &json!({ "execution_state": "idle" }));
+                return true;
+            },
+        };
+        self.reply(channel, message, &format!("{request}_reply"), &reply);
+        self.publish(message, "status", &json!({ "execution_state": "idle" }));
+        keep_running
+    }
+
+    fn execute(&mut self, message: &Message) -> Value {
+        let code = message.content["code"].as_str().unwrap_or_default();
+        let silent = message.content["silent"].as_bool().unwrap_or(false);
+        if !silent {
+            self.execution_count += 1;
+        }
+        let count = self.execution_count;
+        self.publish(message, "execute_input", &json!({ "code": code, "execution_count": count }));
+        let Execution { output, registers } = self.server.execute(code);
+        if !silent {
+            if !output.is_empty() {
+                self.publish(message, "stream", &json!({ "name": "stdout", "text": output }));
+            }
+            let data = json!({
+                "text/plain": format!("registers {}", registers.join(" ")),
+                "text/html": RegisterTable(&registers).to_string(),
+            });
+            self.publish(
+                message,
+                "execute_result",
+                &json!({ "execution_count": count, "data": data, "metadata": {} }),
+            );
+        }
+        json!({
+            "status": "ok",
+            "execution_count": count,
+            "user_expressions": {},
+            "payload": [],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test case 2 of RFC 4231, with the data split into parts.
+    const KEY: &[u8] = b"Jefe";
+    const PARTS: [&[u8]; 3] = [b"what do ya ", b"want ", b"for nothing?"];
+    const SIGNATURE: &str = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+
+    fn frames(signature: &str) -> Frames {
+        let mut frames = vec![b"peer".to_vec(), DELIMITER.to_vec(), signature.as_bytes().to_vec()];
+        frames.extend([r#"{"msg_type":"kernel_info_request"}"#, "{}", "{}", "{}"].map(Vec::from));
+        frames
+    }
+
+    #[test]
+    fn signatures_are_hmac_sha256_in_hex() {
+        let key = Key(KEY.to_vec());
+        assert_eq!(key.sign(&PARTS), SIGNATURE);
+        assert!(key.verify(&PARTS, SIGNATURE.as_bytes()));
+        assert!(!key.verify(&PARTS[1..], SIGNATURE.as_bytes()));
+        assert!(!key.verify(&PARTS, &SIGNATURE.as_bytes()[1..]));
+        assert!(!key.verify(&PARTS, SIGNATURE.replace('5', "x").as_bytes()));
+        assert!(!key.verify(&PARTS, b""));
+    }
+
+    #[test]
+    fn empty_keys_disable_signing() {
+        let key = Key(Vec::new());
+        assert_eq!(key.sign(&PARTS), "");
+        assert!(key.verify(&PARTS, b""));
+        assert!(!key.verify(&PARTS, SIGNATURE.as_bytes()));
+    }
+
+    #[test]
+    fn messages_with_bad_signatures_are_ignored() {
+        let key = Key(KEY.to_vec());
+        let signed = frames("");
+        let parts: Vec<&[u8]> = signed[3..].iter().map(Vec::as_slice).collect();
+        let message = parse(&key, &frames(&key.sign(&parts))).unwrap();
+        assert_eq!(message.identities, vec![b"peer".to_vec()]);
+        assert_eq!(message.header["msg_type"], "kernel_info_request");
+        assert!(parse(&key, &frames(SIGNATURE)).is_none());
+        assert!(parse(&key, &frames("")).is_none());
+    }
+}
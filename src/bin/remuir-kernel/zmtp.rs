@@ -0,0 +1,300 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Just enough of ZMTP 3.0, the protocol behind `ZeroMQ`, for a Jupyter kernel.
+//!
+//! Only listening on TCP with the NULL mechanism is supported, which is what Jupyter uses on a
+//! single machine, and only the three socket types a kernel needs:
+//!
+//! * [`Router`] - messages are received with the sender's identity as the first frame, and are
+//!   sent to the peer named by the first frame.
+//! * [`Publisher`] - messages are sent to every peer. Subscriptions are ignored, since Jupyter
+//!   clients subscribe to everything.
+//! * [`echo`] - a REP socket which sends every message straight back, for the heartbeat.
+//!
+//! See <https://rfc.zeromq.org/spec/23/> for the protocol.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc::Sender, Arc, Mutex, PoisonError},
+    thread,
+};
+
+/// A message made of several frames.
+pub type Frames = Vec<Vec<u8>>;
+
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+/// Send our greeting and READY command, and read the peer's. Returns the peer's identity, if it
+/// gave a non-empty one.
+fn handshake(stream: &mut TcpStream, socket_type: &str) -> io::Result<Option<Vec<u8>>> {
+    let mut greeting = [0; 64];
+    greeting[0] = 0xFF;
+    greeting[9] = 0x7F;
+    // Version 3.0.
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    stream.write_all(&greeting)?;
+    let mut peer_greeting = [0; 64];
+    stream.read_exact(&mut peer_greeting)?;
+    if peer_greeting[0] != 0xFF || peer_greeting[9] != 0x7F || peer_greeting[10] < 3 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "peer doesn't speak ZMTP 3"));
+    }
+
+    let mut ready = vec![5];
+    ready.extend_from_slice(b"READY");
+    ready.push(11);
+    ready.extend_from_slice(b"Socket-Type");
+    ready.extend_from_slice(&u32::try_from(socket_type.len()).unwrap_or(0).to_be_bytes());
+    ready.extend_from_slice(socket_type.as_bytes());
+    write_frame(stream, COMMAND, &ready)?;
+
+    let (flags, body) = read_frame(stream)?;
+    if flags & COMMAND == 0 || body.get(1..6) != Some(b"READY") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "expected a READY command"));
+    }
+    // The rest of the command is properties: a 1 byte name length, the name, a 4 byte value
+    // length, and the value.
+    let mut properties = &body[6..];
+    while let Some((&name_length, rest)) = properties.split_first() {
+        let name_length = usize::from(name_length);
+        let name = rest.get(..name_length);
+        let value_length = rest.get(name_length..name_length + 4);
+        let (Some(name), Some(value_length)) = (name, value_length) else {
+            break;
+        };
+        let value_length = u32::from_be_bytes(value_length.try_into().unwrap_or_default()) as usize;
+        let Some(value) = rest.get(name_length + 4..name_length + 4 + value_length) else {
+            break;
+        };
+        if name.eq_ignore_ascii_case(b"Identity") && !value.is_empty() {
+            return Ok(Some(value.to_vec()));
+        }
+        properties = &rest[name_length + 4 + value_length..];
+    }
+    Ok(None)
+}
+
+fn write_frame(stream: &mut impl Write, flags: u8, body: &[u8]) -> io::Result<()> {
+    if let Ok(length) = u8::try_from(body.len()) {
+        stream.write_all(&[flags, length])?;
+    }
+    else {
+        stream.write_all(&[flags | LONG])?;
+        stream.write_all(&(body.len() as u64).to_be_bytes())?;
+    }
+    stream.write_all(body)
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0];
+    stream.read_exact(&mut flags)?;
+    let length = if flags[0] & LONG == 0 {
+        let mut length = [0];
+        stream.read_exact(&mut length)?;
+        usize::from(length[0])
+    }
+    else {
+        let mut length = [0; 8];
+        stream.read_exact(&mut length)?;
+        usize::try_from(u64::from_be_bytes(length))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    };
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body)?;
+    Ok((flags[0], body))
+}
+
+/// Read the next message, skipping any commands.
+fn read_message(stream: &mut impl Read) -> io::Result<Frames> {
+    let mut frames = Vec::new();
+    loop {
+        let (flags, body) = read_frame(stream)?;
+        if flags & COMMAND != 0 {
+            continue;
+        }
+        frames.push(body);
+        if flags & MORE == 0 {
+            return Ok(frames);
+        }
+    }
+}
+
+fn write_message(stream: &mut TcpStream, frames: &[Vec<u8>]) -> io::Result<()> {
+    // Build the whole message first, so it's written in one go.
+    let mut buffer = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        let flags = if i + 1 < frames.len() { MORE } else { 0 };
+        write_frame(&mut buffer, flags, frame)?;
+    }
+    stream.write_all(&buffer)
+}
+
+/// Accept connections on `address` forever, handling each one on its own thread.
+fn listen(
+    address: &str,
+    handle: impl Fn(TcpStream) -> io::Result<()> + Send + Sync + 'static,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let handle = Arc::new(handle);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handle = Arc::clone(&handle);
+            // A broken connection only affects that peer.
+            thread::spawn(move || handle(stream));
+        }
+    });
+    Ok(())
+}
+
+/// A ROUTER socket.
+#[derive(Clone)]
+pub struct Router {
+    peers: Arc<Mutex<HashMap<Vec<u8>, TcpStream>>>,
+}
+
+impl Router {
+    /// Listen on `address`, sending every message received to `incoming` along with `tag`.
+    pub fn bind<T: Clone + Send + Sync + 'static>(
+        address: &str,
+        tag: T,
+        incoming: Sender<(T, Frames)>,
+    ) -> io::Result<Self> {
+        let router = Self { peers: Arc::default() };
+        let peers = Arc::clone(&router.peers);
+        let next_identity = Arc::new(Mutex::new(0_u32));
+        let incoming = Mutex::new(incoming);
+        listen(address, move |mut stream| {
+            let identity = handshake(&mut stream, "ROUTER")?.unwrap_or_else(|| {
+                // Like libzmq, generated identities start with a 0 byte.
+                let mut next = next_identity.lock().unwrap_or_else(PoisonError::into_inner);
+                *next += 1;
+                [&[0][..], &next.to_be_bytes()].concat()
+            });
+            peers
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(identity.clone(), stream.try_clone()?);
+            let incoming = incoming.lock().unwrap_or_else(PoisonError::into_inner).clone();
+            loop {
+                let mut frames = vec![identity.clone()];
+                frames.extend(read_message(&mut stream)?);
+                if incoming.send((tag.clone(), frames)).is_err() {
+                    return Ok(());
+                }
+            }
+        })?;
+        Ok(router)
+    }
+
+    /// Send a message to the peer whose identity is the first frame. Like `ZeroMQ`, messages to
+    /// unknown or disconnected peers are dropped.
+    pub fn send(&self, frames: &[Vec<u8>]) {
+        let Some((identity, frames)) = frames.split_first() else {
+            return;
+        };
+        let mut peers = self.peers.lock().unwrap_or_else(PoisonError::into_inner);
+        if peers.get_mut(identity).is_some_and(|stream| write_message(stream, frames).is_err()) {
+            peers.remove(identity);
+        }
+    }
+}
+
+/// A PUB socket.
+#[derive(Clone)]
+pub struct Publisher {
+    peers: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl Publisher {
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let publisher = Self { peers: Arc::default() };
+        let peers = Arc::clone(&publisher.peers);
+        listen(address, move |mut stream| {
+            handshake(&mut stream, "PUB")?;
+            peers.lock().unwrap_or_else(PoisonError::into_inner).push(stream.try_clone()?);
+            // Read and ignore subscriptions until the peer disconnects.
+            loop {
+                read_message(&mut stream)?;
+            }
+        })?;
+        Ok(publisher)
+    }
+
+    /// Send a message to every peer, forgetting peers which have disconnected.
+    pub fn send(&self, frames: &[Vec<u8>]) {
+        self.peers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain_mut(|stream| write_message(stream, frames).is_ok());
+    }
+}
+
+/// Listen on `address` as a REP socket which sends every message back unchanged.
+pub fn echo(address: &str) -> io::Result<()> {
+    listen(address, |mut stream| {
+        handshake(&mut stream, "REP")?;
+        loop {
+            let frames = read_message(&mut stream)?;
+            write_message(&mut stream, &frames)?;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_and_long_frames() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, MORE, b"hello").unwrap();
+        assert_eq!(bytes, b"\x01\x05hello");
+        let long = vec![7; 300];
+        write_frame(&mut bytes, 0, &long).unwrap();
+        // Bodies over 255 bytes have an 8 byte length.
+        assert_eq!(bytes[7..16], [LONG, 0, 0, 0, 0, 0, 0, 1, 44]);
+        let mut stream = bytes.as_slice();
+        assert_eq!(read_frame(&mut stream).unwrap(), (MORE, b"hello".to_vec()));
+        assert_eq!(read_frame(&mut stream).unwrap(), (LONG, long));
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn messages_skip_commands() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, COMMAND, b"\x04PING").unwrap();
+        write_frame(&mut bytes, MORE, b"first").unwrap();
+        write_frame(&mut bytes, 0, b"second").unwrap();
+        write_frame(&mut bytes, 0, b"next").unwrap();
+        let mut stream = bytes.as_slice();
+        assert_eq!(read_message(&mut stream).unwrap(), vec![b"first".to_vec(), b"second".to_vec()]);
+        assert_eq!(read_message(&mut stream).unwrap(), vec![b"next".to_vec()]);
+        assert!(read_message(&mut stream).is_err());
+    }
+
+    #[test]
+    fn cut_off_frames_are_errors() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, 0, b"hello").unwrap();
+        assert!(read_frame(&mut &bytes[..4]).is_err());
+        assert!(read_frame(&mut &[LONG, 0, 0][..]).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! A compact binary encoding of a program, which can be loaded without parsing any source code.
+//!
+//! Bytecode starts with [`MAGIC`] and the version of the format, [`VERSION`], which changes
+//! whenever the format does. Bytecode written by a later version of remuir is refused with
+//! [`BytecodeError::UnsupportedVersion`] rather than misread. After the header come:
+//!
+//! * a table of every string the program uses (labels and their documentation), so each is only
+//!   stored once,
+//! * the initial registers, each as its number of base 2^128 digits followed by the digits,
+//! * the lines, each as its label (if any), an opcode, and the register and jump target it uses,
+//! * the `#!phase`, `#!entry`, `#!export` and `#!import` directives, and the documentation of
+//!   labels.
+//!
+//! Numbers are stored in unsigned LEB128 (7 bits per byte, with the top bit set on every byte but
+//! the last), so most take a single byte. Jumps are stored already resolved, to a line number,
+//! a label (by its index in the table) or HALT.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{
+    instruction::Instruction,
+    machine::{ Identifier, Line, Machine, MachineEditError },
+    memory::{ Memory, PseudoRegister, Register, RegisterNumber },
+};
+
+/// The bytes every bytecode file starts with.
+pub const MAGIC: &[u8; 4] = b"RMBC";
+/// The version of the format which is written, and the latest which can be read.
+pub const VERSION: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum BytecodeError {
+    #[error("This isn't remuir bytecode, as it doesn't start with \"RMBC\".")]
+    NotBytecode,
+    #[error(
+        "The bytecode is version {version}, but this version of remuir can only read up to version \
+        {VERSION}. Compile the program again, or upgrade remuir.",
+    )]
+    UnsupportedVersion { version: u8 },
+    #[error("The bytecode ends unexpectedly, so it may have been cut short.")]
+    Truncated,
+    #[error("The bytecode is damaged at byte {offset}.")]
+    Corrupt { offset: usize },
+    #[error("The bytecode has a directive for a label which isn't in the program. {0}")]
+    Directive(#[from] MachineEditError),
+}
+
+// Opcodes of the instructions.
+const HALT: u8 = 0;
+const INC: u8 = 1;
+const DECJZ: u8 = 2;
+const DECJNZ: u8 = 3;
+const GOTO: u8 = 4;
+const CALL: u8 = 5;
+const RETURN: u8 = 6;
+const READ: u8 = 7;
+const WRITE: u8 = 8;
+
+// Kinds of registers.
+const NATURAL: u8 = 0;
+const NEGATIVE: u8 = 1;
+const PSEUDO: u8 = 2;
+
+// Kinds of jump targets.
+const TO_HALT: u8 = 0;
+const TO_LINE: u8 = 1;
+const TO_LABEL: u8 = 2;
+
+/// Encode a machine's program and initial registers as bytecode. See [`Machine::to_bytes`].
+#[must_use]
+pub fn encode(machine: &Machine) -> Vec<u8> {
+    let mut strings = StringTable::default();
+    let mut body = Vec::new();
+
+    let memory = machine.get_initial_memory();
+    let registers = memory.get_nat_registers();
+    write_number(&mut body, memory.get_nat_count());
+    for n in 0..memory.get_nat_count() {
+        let limbs = registers.get(n).map_or(&[][..], Register::get_limbs);
+        write_number(&mut body, limbs.len());
+        for limb in limbs {
+            body.extend_from_slice(&limb.to_le_bytes());
+        }
+    }
+
+    let lines = machine.get_lines();
+    write_number(&mut body, lines.len());
+    for line in lines {
+        match line.get_id() {
+            Some(Identifier::Label(label)) => write_number(&mut body, strings.index(label) + 1),
+            _ => write_number(&mut body, 0),
+        }
+        let (opcode, register, target) = match line.get_instruction() {
+            Instruction::HALT => (HALT, None, None),
+            Instruction::INC(register) => (INC, Some(register), None),
+            Instruction::DECJZ(register, target) => (DECJZ, Some(register), Some(target)),
+            Instruction::DECJNZ(register, target) => (DECJNZ, Some(register), Some(target)),
+            Instruction::GOTO(target) => (GOTO, None, Some(target)),
+            Instruction::CALL(target) => (CALL, None, Some(target)),
+            Instruction::RETURN => (RETURN, None, None),
+            Instruction::READ(register) => (READ, Some(register), None),
+            Instruction::WRITE(register) => (WRITE, Some(register), None),
+        };
+        body.push(opcode);
+        if let Some(register) = register {
+            write_register(&mut body, *register);
+        }
+        if let Some(target) = target {
+            write_target(&mut body, &mut strings, target);
+        }
+    }
+
+    for labels in [machine.get_phases(), machine.get_exports(), machine.get_imports()] {
+        write_number(&mut body, labels.len());
+        for label in labels {
+            write_number(&mut body, strings.index(label));
+        }
+    }
+    write_number(&mut body, machine.get_entry().map_or(0, |label| strings.index(label) + 1));
+    let documented: Vec<(&str, &str)> = machine
+        .get_labels()
+        .iter()
+        .filter_map(|(label, _)| Some((label.as_str(), machine.get_label_documentation(label)?)))
+        .collect();
+    write_number(&mut body, documented.len());
+    for (label, documentation) in documented {
+        write_number(&mut body, strings.index(label));
+        write_number(&mut body, strings.index(documentation));
+    }
+
+    let mut bytes = Vec::with_capacity(body.len() + MAGIC.len() + 1);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    write_number(&mut bytes, strings.strings.len());
+    for string in &strings.strings {
+        write_number(&mut bytes, string.len());
+        bytes.extend_from_slice(string.as_bytes());
+    }
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+/// Decode bytecode written by [`encode`] back into a machine. See [`Machine::from_bytes`].
+///
+/// # Errors
+///
+/// See [`BytecodeError`].
+pub fn decode(bytes: &[u8]) -> Result<Machine, BytecodeError> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(BytecodeError::NotBytecode);
+    }
+    let mut reader = Reader { bytes, position: MAGIC.len(), strings: Vec::new() };
+    let version = reader.byte()?;
+    if version > VERSION {
+        return Err(BytecodeError::UnsupportedVersion { version });
+    }
+
+    for _ in 0..reader.number()? {
+        let offset = reader.position;
+        let length = reader.number()?;
+        let string = std::str::from_utf8(reader.take(length)?)
+            .map_err(|_| BytecodeError::Corrupt { offset })?
+            .to_string();
+        reader.strings.push(string);
+    }
+
+    let mut registers = Vec::new();
+    for _ in 0..reader.number()? {
+        let mut limbs = Vec::new();
+        for _ in 0..reader.number()? {
+            let limb = reader.take(16)?.try_into().expect("Exactly 16 bytes were taken.");
+            limbs.push(u128::from_le_bytes(limb));
+        }
+        registers.push(Register::new(&limbs));
+    }
+    let memory = Memory::new_from_slice(&registers);
+
+    let line_count = reader.number()?;
+    let mut lines = Vec::new();
+    for line_number in 0..line_count {
+        let id = reader.optional_string()?.map(Identifier::Label);
+        let offset = reader.position;
+        let instruction = match reader.byte()? {
+            HALT => Instruction::HALT,
+            INC => Instruction::INC(reader.register()?),
+            DECJZ => Instruction::DECJZ(reader.register()?, reader.target()?),
+            DECJNZ => Instruction::DECJNZ(reader.register()?, reader.target()?),
+            GOTO => Instruction::GOTO(reader.target()?),
+            CALL => Instruction::CALL(reader.target()?),
+            RETURN => Instruction::RETURN,
+            READ => Instruction::READ(reader.register()?),
+            WRITE => Instruction::WRITE(reader.register()?),
+            _ => return Err(BytecodeError::Corrupt { offset }),
+        };
+        lines.push(Line::new(line_number, id, instruction));
+    }
+    let mut machine = Machine::new_from_lines(&lines, memory);
+
+    for _ in 0..reader.number()? {
+        machine.add_phase(reader.string()?)?;
+    }
+    for _ in 0..reader.number()? {
+        machine.add_export(reader.string()?)?;
+    }
+    for _ in 0..reader.number()? {
+        machine.add_import(reader.string()?);
+    }
+    if let Some(label) = reader.optional_string()? {
+        machine.set_entry(label)?;
+    }
+    for _ in 0..reader.number()? {
+        let label = reader.string()?;
+        machine.set_label_documentation(label, reader.string()?)?;
+    }
+
+    if reader.position < bytes.len() {
+        return Err(BytecodeError::Corrupt { offset: reader.position });
+    }
+    Ok(machine)
+}
+
+/// The strings of a program being encoded, in the order they were first used.
+#[derive(Default)]
+struct StringTable {
+    strings: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl StringTable {
+    /// Get the index of a string in the table, adding it if it isn't there yet.
+    fn index(&mut self, string: &str) -> usize {
+        if let Some(&index) = self.indices.get(string) {
+            return index;
+        }
+        self.strings.push(string.to_string());
+        self.indices.insert(string.to_string(), self.strings.len() - 1);
+        self.strings.len() - 1
+    }
+}
+
+fn write_number(bytes: &mut Vec<u8>, mut number: usize) {
+    while number >= 0x80 {
+        // Keep the low 7 bits, as the rest are written in the following bytes.
+        #[allow(clippy::cast_possible_truncation)]
+        bytes.push((number as u8 & 0x7f) | 0x80);
+        number >>= 7;
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    bytes.push(number as u8);
+}
+
+fn write_register(bytes: &mut Vec<u8>, register: RegisterNumber) {
+    match register {
+        RegisterNumber::Natural(n) => {
+            bytes.push(NATURAL);
+            write_number(bytes, n);
+        },
+        RegisterNumber::Negative(n) => {
+            bytes.push(NEGATIVE);
+            write_number(bytes, n);
+        },
+        RegisterNumber::Pseudo(PseudoRegister::Steps) => bytes.extend_from_slice(&[PSEUDO, 0]),
+        RegisterNumber::Pseudo(PseudoRegister::Visits) => bytes.extend_from_slice(&[PSEUDO, 1]),
+    }
+}
+
+fn write_target(bytes: &mut Vec<u8>, strings: &mut StringTable, target: &Identifier) {
+    match target {
+        Identifier::Halt => bytes.push(TO_HALT),
+        Identifier::Line(n) => {
+            bytes.push(TO_LINE);
+            write_number(bytes, *n);
+        },
+        Identifier::Label(label) => {
+            bytes.push(TO_LABEL);
+            write_number(bytes, strings.index(label));
+        },
+        Identifier::Relative(_) | Identifier::LabelOffset(..) => unreachable!(),
+    }
+}
+
+/// Reads bytecode from the start, keeping the strings from its table.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    strings: Vec<String>,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], BytecodeError> {
+        let end = self.position.checked_add(count).ok_or(BytecodeError::Truncated)?;
+        let taken = self.bytes.get(self.position..end).ok_or(BytecodeError::Truncated)?;
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn number(&mut self) -> Result<usize, BytecodeError> {
+        let offset = self.position;
+        let mut number: usize = 0;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.byte()?;
+            let bits = usize::from(byte & 0x7f);
+            // Bits shifted past the top would be lost, so the number is too large.
+            if shift > 0 && bits >> (usize::BITS - shift) != 0 {
+                break;
+            }
+            number |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(number);
+            }
+        }
+        Err(BytecodeError::Corrupt { offset })
+    }
+
+    fn string(&mut self) -> Result<String, BytecodeError> {
+        let offset = self.position;
+        let index = self.number()?;
+        self.strings.get(index).cloned().ok_or(BytecodeError::Corrupt { offset })
+    }
+
+    /// Read a string which may be missing, stored as 0 or 1 more than its index.
+    fn optional_string(&mut self) -> Result<Option<String>, BytecodeError> {
+        let offset = self.position;
+        match self.number()? {
+            0 => Ok(None),
+            index => self
+                .strings
+                .get(index - 1)
+                .cloned()
+                .map(Some)
+                .ok_or(BytecodeError::Corrupt { offset }),
+        }
+    }
+
+    fn register(&mut self) -> Result<RegisterNumber, BytecodeError> {
+        let offset = self.position;
+        match self.byte()? {
+            NATURAL => Ok(RegisterNumber::Natural(self.number()?)),
+            NEGATIVE => Ok(RegisterNumber::Negative(self.number()?)),
+            PSEUDO => match self.byte()? {
+                0 => Ok(RegisterNumber::Pseudo(PseudoRegister::Steps)),
+                1 => Ok(RegisterNumber::Pseudo(PseudoRegister::Visits)),
+                _ => Err(BytecodeError::Corrupt { offset }),
+            },
+            _ => Err(BytecodeError::Corrupt { offset }),
+        }
+    }
+
+    /// Read a jump target, which may be any line up to the one after the last.
+    fn target(&mut self) -> Result<Identifier, BytecodeError> {
+        let offset = self.position;
+        match self.byte()? {
+            TO_HALT => Ok(Identifier::Halt),
+            // A jump past the end of the program is valid: it halts, or with strict jumps it's an
+            // invalid jump, so it's kept as it is.
+            TO_LINE => Ok(Identifier::Line(self.number()?)),
+            TO_LABEL => Ok(Identifier::Label(self.string()?)),
+            _ => Err(BytecodeError::Corrupt { offset }),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! A directory of programs which have already been parsed, linked and optimised.
+//!
+//! Each entry is keyed by a hash of everything the program was built from, including the version
+//! of remuir (see [`Cache::key`]), so an entry is never used by a version which would have parsed
+//! the program differently, or rejected it. An entry stores the program's
+//! [`Machine::fingerprint`] followed by its bytecode (see [`crate::bytecode`]), which is much
+//! quicker to load than source code for large generated programs. An entry whose fingerprint
+//! doesn't match what was loaded is ignored, so a damaged entry is never used.
+
+use std::{fs, io, path::PathBuf};
+
+use crate::{
+    machine::{ fnv1a, Machine, FNV_OFFSET_BASIS },
+    requirement,
+};
+
+/// The version of the format of entries, which is part of every key.
+const FORMAT: u8 = 4;
+/// The file extension of entries, so that clearing the cache leaves other files alone.
+const EXTENSION: &str = "rmbc";
+
+#[derive(Debug, Clone)]
+pub struct Cache {
+    directory: PathBuf,
+}
+
+impl Cache {
+    /// Use the given directory for the cache. It's created when the first entry is stored.
+    #[must_use]
+    pub fn new(directory: PathBuf) -> Cache {
+        Cache { directory }
+    }
+
+    /// Get the usual directory for the cache, `$XDG_CACHE_HOME/remuir` or else
+    /// `~/.cache/remuir`, or [`None`] if there's no home directory.
+    #[must_use]
+    pub fn default_directory() -> Option<PathBuf> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(cache_home.join("remuir"))
+    }
+
+    /// Get the key of a program built by this version of remuir from the given source code,
+    /// libraries (names and source code) and, if `optimize` is true, the peephole optimiser.
+    #[must_use]
+    pub fn key(source: &str, libraries: &[(String, String)], optimize: bool) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET_BASIS, &[FORMAT, u8::from(optimize)]);
+        let parts = [requirement::VERSION, source]
+            .into_iter()
+            .chain(libraries.iter().flat_map(|(name, library)| [name.as_str(), library.as_str()]));
+        for part in parts {
+            // Include the length so that moving text from one part to the next changes the key.
+            hash = fnv1a(hash, &part.len().to_le_bytes());
+            hash = fnv1a(hash, part.as_bytes());
+        }
+        hash
+    }
+
+    /// Get the program stored under the given key, or [`None`] if there isn't a usable one.
+    #[must_use]
+    pub fn get(&self, key: u64) -> Option<Machine> {
+        let entry = fs::read(self.path(key)).ok()?;
+        let (fingerprint, bytecode) = entry.split_first_chunk::<8>()?;
+        let machine = Machine::from_bytes(bytecode).ok()?;
+        (machine.fingerprint() == u64::from_le_bytes(*fingerprint)).then_some(machine)
+    }
+
+    /// Store a program under the given key.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the directory or the entry couldn't be written.
+    pub fn put(&self, key: u64, machine: &Machine) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        // Write to a temporary file first, so that another run never reads half an entry.
+        let temporary = self.directory.join(format!("{key:016x}.{}.tmp", std::process::id()));
+        let mut entry = machine.fingerprint().to_le_bytes().to_vec();
+        entry.extend(machine.to_bytes());
+        fs::write(&temporary, entry)?;
+        fs::rename(temporary, self.path(key))
+    }
+
+    /// Remove every entry, returning how many there were.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the directory couldn't be read or an entry couldn't be
+    ///   removed.
+    pub fn clear(&self) -> io::Result<usize> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry?.path();
+            // Entries used to be stored as source code, in ".remuir" files.
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            if matches!(extension, Some(EXTENSION | "remuir")) {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Get the directory the cache is in.
+    #[must_use]
+    pub fn get_directory(&self) -> &PathBuf {
+        &self.directory
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.directory.join(format!("{key:016x}.{EXTENSION}"))
+    }
+}
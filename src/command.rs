@@ -0,0 +1,285 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! Parsing commands typed into the interactive modes.
+//!
+//! A command is a keyword followed by arguments, separated by any amount of whitespace. Keywords
+//! are case-insensitive, but arguments keep their case since labels are case-sensitive. An
+//! argument can be wrapped in double quotes to keep the whitespace inside it, and `\"` and `\\`
+//! can be used inside quotes to write a literal quote or backslash.
+//!
+//! A command can go over more than one line (see [`MultiLine`]). A line ending with `\\` carries
+//! on onto the next, and a line with nothing but a label, such as `loop:`, labels the instruction
+//! on the next line, as it would in a program.
+//!
+//! # Examples
+//! ```
+//! use remuir::command::Command;
+//! let command = Command::parse("BREAK   \"my  label\"").unwrap().unwrap();
+//! assert_eq!(command.keyword, "break");
+//! assert_eq!(command.args, vec![String::from("my  label")]);
+//! ```
+
+use thiserror::Error;
+
+use crate::parser;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CommandParseError {
+    #[error("Missing closing quote (\") in command.")]
+    UnclosedQuote,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Command {
+    /// The first word of the command, in lowercase.
+    pub keyword: String,
+    /// The remaining words of the command, with any quotes removed.
+    pub args: Vec<String>,
+}
+
+impl Command {
+    /// Parse a command, returning [`None`] if the input is blank.
+    ///
+    /// # Errors
+    ///
+    /// * [`CommandParseError::UnclosedQuote`] - returned when a quote is opened but not closed.
+    pub fn parse(input: &str) -> Result<Option<Command>, CommandParseError> {
+        let mut words = split(input)?.into_iter();
+        Ok(words.next().map(|keyword| Command {
+            keyword: keyword.to_lowercase(),
+            args: words.collect(),
+        }))
+    }
+
+    /// Join all arguments with single spaces, for example to get a label containing spaces.
+    #[must_use]
+    pub fn rest(&self) -> String {
+        self.args.join(" ")
+    }
+
+    /// Get the command with a lowercase keyword and single spaces between words, so that it can
+    /// be parsed as a remuir instruction.
+    #[must_use]
+    pub fn normalised(&self) -> String {
+        let mut normalised = self.keyword.clone();
+        for arg in &self.args {
+            normalised.push(' ');
+            normalised.push_str(arg);
+        }
+        normalised
+    }
+}
+
+/// Joins lines of input into a command, for commands which go over more than one line.
+///
+/// # Examples
+/// ```
+/// use remuir::command::MultiLine;
+/// let mut lines = MultiLine::default();
+/// assert_eq!(lines.push("decjz r1 \\"), None);
+/// assert_eq!(lines.push("  done"), Some(String::from("decjz r1 done")));
+/// assert_eq!(lines.push("loop:"), None);
+/// assert_eq!(lines.push("inc r0"), Some(String::from("loop: inc r0")));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MultiLine {
+    pending: String,
+}
+
+impl MultiLine {
+    /// Add a line of input. Returns the whole command, without surrounding whitespace, once it's
+    /// complete, or [`None`] if the next line carries it on. A blank line always completes it.
+    pub fn push(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        let continued = line.strip_suffix('\\');
+        let part = continued.map_or(line, str::trim_end);
+        if !self.pending.is_empty() && !part.is_empty() {
+            self.pending.push(' ');
+        }
+        self.pending.push_str(part);
+        if continued.is_some() || (!line.is_empty() && is_label_definition(&self.pending)) {
+            return None;
+        }
+        Some(std::mem::take(&mut self.pending))
+    }
+
+    /// Check whether a command has been started but not completed.
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Complete the command which has been started, if there is one, such as when the input ends.
+    pub fn finish(&mut self) -> Option<String> {
+        self.is_pending().then(|| std::mem::take(&mut self.pending))
+    }
+}
+
+/// Check whether the input is nothing but a label definition, such as `loop:`.
+fn is_label_definition(input: &str) -> bool {
+    input
+        .strip_suffix(':')
+        .is_some_and(|label| !label.is_empty() && parser::invalid_label_character(label).is_none())
+}
+
+/// Remove the label from the start of an instruction, such as `loop: inc r0`, since there's
+/// nowhere to jump to it from in the interactive modes. Anything else is left as it is.
+///
+/// # Examples
+/// ```
+/// use remuir::command::strip_label;
+/// assert_eq!(strip_label("outer loop: decjz r1 done"), "decjz r1 done");
+/// assert_eq!(strip_label("break lib::loop"), "break lib::loop");
+/// ```
+#[must_use]
+pub fn strip_label(input: &str) -> &str {
+    input
+        .match_indices(':')
+        .map(|(i, _)| (&input[..i], input[i + 1..].trim_start()))
+        .find(|(label, rest)| {
+            let keyword = rest.split_whitespace().next().unwrap_or_default().to_lowercase();
+            parser::INSTRUCTION_NAMES.contains(&keyword.as_str())
+                && !label.trim().is_empty()
+                && parser::invalid_label_character(label.trim()).is_none()
+        })
+        .map_or(input, |(_, rest)| rest)
+}
+
+/// Split the input into words, keeping quoted whitespace.
+fn split(input: &str) -> Result<Vec<String>, CommandParseError> {
+    let mut words: Vec<String> = Vec::new();
+    let mut current = String::new();
+    // Whether a word has been started, so that "" counts as an empty word.
+    let mut in_word = false;
+    let mut in_quotes = false;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_word = true;
+            },
+            '\\' if in_quotes => match chars.next() {
+                Some(escaped @ ('"' | '\\')) => current.push(escaped),
+                Some(other) => {
+                    current.push('\\');
+                    current.push(other);
+                },
+                None => return Err(CommandParseError::UnclosedQuote),
+            },
+            c if c.is_whitespace() && !in_quotes => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            },
+            c => {
+                current.push(c);
+                in_word = true;
+            },
+        }
+    }
+    if in_quotes {
+        return Err(CommandParseError::UnclosedQuote);
+    }
+    if in_word {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(input: &str) -> Vec<String> {
+        Command::parse(input).unwrap().unwrap().args
+    }
+
+    #[test]
+    fn blank_input() {
+        assert_eq!(Command::parse("   \t "), Ok(None));
+    }
+
+    #[test]
+    fn keyword_case_insensitive() {
+        let command = Command::parse("  Break  My Label ").unwrap().unwrap();
+        assert_eq!(command.keyword, "break");
+        assert_eq!(command.rest(), "My Label");
+    }
+
+    #[test]
+    fn quoted_label_keeps_spaces() {
+        assert_eq!(args("b \"loop  two\""), vec!["loop  two"]);
+        assert_eq!(args("b \" padded \" next"), vec![" padded ", "next"]);
+    }
+
+    #[test]
+    fn quotes_inside_word() {
+        assert_eq!(args("b my\" \"label"), vec!["my label"]);
+        assert_eq!(args("b \"\""), vec![""]);
+    }
+
+    #[test]
+    fn escaped_quotes() {
+        assert_eq!(args(r#"b "say \"hi\" \\ there""#), vec![r#"say "hi" \ there"#]);
+    }
+
+    #[test]
+    fn unclosed_quote() {
+        assert_eq!(Command::parse("b \"oops"), Err(CommandParseError::UnclosedQuote));
+    }
+
+    #[test]
+    fn continued_lines() {
+        let mut lines = MultiLine::default();
+        assert_eq!(lines.push("b \\"), None);
+        assert_eq!(lines.push("\\"), None);
+        assert!(lines.is_pending());
+        assert_eq!(lines.push("\"my label\""), Some(String::from("b \"my label\"")));
+        assert!(!lines.is_pending());
+        assert_eq!(lines.finish(), None);
+    }
+
+    #[test]
+    fn label_lines() {
+        let mut lines = MultiLine::default();
+        assert_eq!(lines.push("lib::outer loop:"), None);
+        assert_eq!(lines.push("inc r0"), Some(String::from("lib::outer loop: inc r0")));
+        // A blank line completes a label on its own rather than waiting forever.
+        assert_eq!(lines.push("loop:"), None);
+        assert_eq!(lines.push(""), Some(String::from("loop:")));
+        assert_eq!(lines.push("b:"), None);
+        assert_eq!(lines.finish(), Some(String::from("b:")));
+        assert_eq!(lines.push("info label lib::loop"), Some(String::from("info label lib::loop")));
+    }
+
+    #[test]
+    fn labels_before_instructions() {
+        assert_eq!(strip_label("loop: INC r0"), "INC r0");
+        assert_eq!(strip_label("lib::loop:decjz r0 lib::end"), "decjz r0 lib::end");
+        assert_eq!(strip_label("xref lib::loop"), "xref lib::loop");
+        assert_eq!(strip_label(": inc r0"), ": inc r0");
+    }
+
+    #[test]
+    fn normalised_instruction() {
+        let command = Command::parse("INC    r-2").unwrap().unwrap();
+        assert_eq!(command.normalised(), "inc r-2");
+    }
+}
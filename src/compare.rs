@@ -0,0 +1,241 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Comparing how many steps two programs take on the same inputs.
+//!
+//! Both programs are run on every combination of values in a grid of inputs, such as r1 from 0
+//! to 5 and r2 from 1 to 3. The report is a table of the steps each program took on each input
+//! and which took fewer, followed by how often each won and how many times fewer steps the faster
+//! one took overall, so that an improvement to an algorithm can be measured.
+
+use std::{ fmt::Display, ops::RangeInclusive };
+
+use thiserror::Error;
+
+use crate::{
+    machine::Machine,
+    memory::{ Register, RegisterNumber },
+};
+
+/// The most inputs a grid may have, so that a mistyped range doesn't run for hours.
+pub const MAX_GRID_SIZE: usize = 10_000;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CompareError {
+    #[error("The grid has {size} inputs, but at most {maximum} can be compared.")]
+    GridTooLarge { size: u128, maximum: usize },
+}
+
+/// The steps both programs took on one input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    /// The value of each register in the grid.
+    pub inputs: Vec<u128>,
+    /// The steps each program took, or [`None`] if it didn't halt within the step limit.
+    pub steps: [Option<usize>; 2],
+}
+
+impl Row {
+    /// Get which program took fewer steps, 0 or 1, or [`None`] if they took the same number or
+    /// neither halted. A program which halted beats one which didn't.
+    #[must_use]
+    pub fn winner(&self) -> Option<usize> {
+        match self.steps {
+            [Some(first), Some(second)] if first < second => Some(0),
+            [Some(first), Some(second)] if second < first => Some(1),
+            [Some(_), None] => Some(0),
+            [None, Some(_)] => Some(1),
+            _ => None,
+        }
+    }
+}
+
+/// The step counts of two programs on a grid of inputs, which can be displayed as a report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    names: [String; 2],
+    registers: Vec<usize>,
+    step_limit: usize,
+    rows: Vec<Row>,
+}
+
+impl Comparison {
+    /// Run both machines on every combination of values of the natural registers in `grid`.
+    /// Every other register keeps its initial value in each machine. A run which hasn't halted
+    /// after `step_limit` steps is stopped. Afterwards, the machines are reset with their original
+    /// initial registers. Without a grid, the machines are run once on their own registers.
+    ///
+    /// # Errors
+    ///
+    /// * [`CompareError::GridTooLarge`] - when the grid has more than [`MAX_GRID_SIZE`] inputs.
+    pub fn run(
+        names: [String; 2],
+        machines: [&mut Machine; 2],
+        grid: &[(usize, RangeInclusive<u128>)],
+        step_limit: usize,
+    ) -> Result<Comparison, CompareError> {
+        let size = grid
+            .iter()
+            .map(|(_, range)| range.end().saturating_sub(*range.start()).saturating_add(1))
+            .try_fold(1u128, u128::checked_mul)
+            .unwrap_or(u128::MAX);
+        if size > MAX_GRID_SIZE as u128 {
+            return Err(CompareError::GridTooLarge { size, maximum: MAX_GRID_SIZE });
+        }
+        let [first, second] = machines;
+        let mut inputs: Vec<u128> = grid.iter().map(|(_, range)| *range.start()).collect();
+        let mut rows = Vec::new();
+        for _ in 0..size {
+            let steps = [
+                steps_on(first, grid, &inputs, step_limit),
+                steps_on(second, grid, &inputs, step_limit),
+            ];
+            rows.push(Row { inputs: inputs.clone(), steps });
+            // Count up through the grid, with the last register changing fastest.
+            for (value, (_, range)) in inputs.iter_mut().zip(grid).rev() {
+                if *value < *range.end() {
+                    *value += 1;
+                    break;
+                }
+                *value = *range.start();
+            }
+        }
+        let registers = grid.iter().map(|(register, _)| *register).collect();
+        Ok(Comparison { names, registers, step_limit, rows })
+    }
+
+    #[must_use]
+    pub fn get_rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    /// Get how many inputs each program won on.
+    #[must_use]
+    pub fn get_wins(&self) -> [usize; 2] {
+        let wins = |program| self.rows.iter().filter(|row| row.winner() == Some(program)).count();
+        [wins(0), wins(1)]
+    }
+
+    /// Get the total steps each program took on the inputs where both halted.
+    #[must_use]
+    pub fn get_total_steps(&self) -> [usize; 2] {
+        self.rows
+            .iter()
+            .filter_map(|row| row.steps[0].zip(row.steps[1]))
+            .fold([0, 0], |[first, second], (a, b)| [first + a, second + b])
+    }
+
+    /// Get how many times more steps the first program took than the second in total, on the
+    /// inputs where both halted, so a speedup above 1 means the second program is faster. It's
+    /// [`None`] if there's no such input, or the second program took no steps on them.
+    #[must_use]
+    pub fn get_speedup(&self) -> Option<f64> {
+        let [first, second] = self.get_total_steps();
+        (second > 0).then(|| first as f64 / second as f64)
+    }
+}
+
+/// Run a machine with the grid's registers set to `inputs`, and return how many steps it took to
+/// halt, if it did within the step limit.
+fn steps_on(
+    machine: &mut Machine,
+    grid: &[(usize, RangeInclusive<u128>)],
+    inputs: &[u128],
+    step_limit: usize,
+) -> Option<usize> {
+    let original = machine.get_initial_memory().clone();
+    let mut registers = original.clone();
+    for ((register, _), &value) in grid.iter().zip(inputs) {
+        registers.set_register(RegisterNumber::Natural(*register), Register::from(value));
+    }
+    machine.set_initial_memory(registers);
+    machine.reset();
+    while !machine.is_halted() && machine.get_steps() < step_limit {
+        if !matches!(machine.step(), Ok(None)) {
+            break;
+        }
+    }
+    let steps = machine.is_halted().then(|| machine.get_steps());
+    machine.set_initial_memory(original);
+    machine.reset();
+    steps
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [first, second] = &self.names;
+        let steps = |steps: Option<usize>| {
+            steps.map_or_else(|| format!(">{}", self.step_limit), |steps| steps.to_string())
+        };
+        let mut header: Vec<String> = self.registers.iter().map(|r| format!("r{r}")).collect();
+        header.extend([first.clone(), second.clone(), String::from("winner")]);
+        let table: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let winner = match (row.winner(), row.steps) {
+                    (Some(program), _) => self.names[program].clone(),
+                    (None, [Some(_), Some(_)]) => String::from("tie"),
+                    (None, _) => String::from("-"),
+                };
+                let mut cells: Vec<String> = row.inputs.iter().map(u128::to_string).collect();
+                cells.extend([steps(row.steps[0]), steps(row.steps[1]), winner]);
+                cells
+            })
+            .collect();
+        let lines = || std::iter::once(&header).chain(&table);
+        let widths: Vec<usize> = (0..header.len())
+            .map(|i| lines().map(|cells| cells[i].chars().count()).max().unwrap_or(0))
+            .collect();
+        for cells in lines() {
+            let (winner, counts) = cells.split_last().expect("every row has a winner");
+            let counts: Vec<String> = counts
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{cell:>width$}"))
+                .collect();
+            writeln!(f, "{}  {winner}", counts.join("  "))?;
+        }
+        let [first_wins, second_wins] = self.get_wins();
+        let both_halted = self.rows.iter().filter(|row| row.steps.iter().all(Option::is_some));
+        let ties = both_halted.clone().filter(|row| row.winner().is_none()).count();
+        writeln!(f, "\nWins: {first} {first_wins}, {second} {second_wins}, ties {ties}")?;
+        if both_halted.count() == 0 {
+            return writeln!(f, "There's no input which both programs halted on, so no speedup.");
+        }
+        let [first_total, second_total] = self.get_total_steps();
+        writeln!(
+            f,
+            "Total steps where both halted: {first} {first_total}, {second} {second_total}",
+        )?;
+        let (faster, slower, fewer, more) = if first_total <= second_total {
+            (first, second, first_total, second_total)
+        }
+        else {
+            (second, first, second_total, first_total)
+        };
+        if fewer == more {
+            writeln!(f, "Speedup: none, both took the same number of steps")
+        }
+        else if fewer == 0 {
+            writeln!(f, "Speedup: {faster} took no steps at all")
+        }
+        else {
+            let speedup = more as f64 / fewer as f64;
+            writeln!(f, "Speedup: {faster} took {speedup:.2}x fewer steps than {slower}")
+        }
+    }
+}
@@ -0,0 +1,313 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! An experimental mode where several programs run interleaved over one shared memory.
+//!
+//! Every step, a [`Scheduler`] picks one of the machines which hasn't stopped, and it executes
+//! one instruction on the shared registers. Each instruction is atomic, but a program which checks
+//! a register and changes it later can be interrupted in between, so this is a minimal setting for
+//! demonstrating race conditions and how the result depends on the interleaving.
+//!
+//! Each machine keeps its own position and protected registers, but its own registers are
+//! ignored: everything runs on the memory given to [`Concurrent::new`].
+//!
+//! Rather than following one scheduler, [`Concurrent::explore`] tries every interleaving up to a
+//! number of steps and checks an [`Assertion`] in every state it reaches, like a miniature model
+//! checker.
+
+use std::{ fmt::Display, str::FromStr };
+
+use thiserror::Error;
+
+use crate::{
+    analysis::SplitMix64,
+    machine::{ Machine, TerminationReason },
+    memory::{ Memory, RegisterNumber },
+};
+
+/// How the next machine to step is picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduler {
+    /// Take turns, one step each, skipping machines which have stopped.
+    RoundRobin,
+    /// Pick a machine which hasn't stopped at random. The same seed always gives the same order.
+    Random { seed: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Concurrent {
+    machines: Vec<Machine>,
+    /// Whether each machine has halted, or got stuck on an instruction it refused to execute.
+    stopped: Vec<bool>,
+    memory: Memory,
+    scheduler: Scheduler,
+    rng: SplitMix64,
+    /// The machine whose turn is next, for [`Scheduler::RoundRobin`].
+    next: usize,
+    steps: usize,
+    /// The index of the machine stepped at each step so far.
+    schedule: Vec<usize>,
+}
+
+impl Concurrent {
+    /// Run the given machines, each from its current line, over the given shared memory.
+    #[must_use]
+    pub fn new(machines: Vec<Machine>, memory: Memory, scheduler: Scheduler) -> Concurrent {
+        let stopped = machines.iter().map(Machine::is_halted).collect();
+        let seed = match scheduler {
+            Scheduler::RoundRobin => 0,
+            Scheduler::Random { seed } => seed,
+        };
+        Concurrent {
+            machines,
+            stopped,
+            memory,
+            scheduler,
+            rng: SplitMix64(seed),
+            next: 0,
+            steps: 0,
+            schedule: Vec::new(),
+        }
+    }
+
+    /// Pick a machine and execute one instruction of it on the shared memory. Returns the index of
+    /// the machine which was stepped, or [`None`] if every machine has stopped.
+    pub fn step(&mut self) -> Option<usize> {
+        let running: Vec<usize> = (0..self.machines.len()).filter(|&i| !self.stopped[i]).collect();
+        if running.is_empty() {
+            return None;
+        }
+        let index = match self.scheduler {
+            Scheduler::RoundRobin => {
+                let index = running.iter().copied().find(|&i| i >= self.next).unwrap_or(running[0]);
+                self.next = index + 1;
+                index
+            },
+            Scheduler::Random { .. } => {
+                // The modulo bias is negligible for a handful of machines.
+                let choice = self.rng.next_u64() % running.len() as u64;
+                running[usize::try_from(choice).expect("Less than the number of machines.")]
+            },
+        };
+        self.step_machine(index);
+        Some(index)
+    }
+
+    /// Execute one instruction of the machine with the given index on the shared memory, whatever
+    /// the scheduler would pick. Does nothing if that machine has stopped.
+    ///
+    /// # Panics
+    ///
+    /// * If there's no machine with the given index.
+    pub fn step_machine(&mut self, index: usize) {
+        if self.stopped[index] {
+            return;
+        }
+        let machine = &mut self.machines[index];
+        machine.swap_memory(&mut self.memory);
+        let result = machine.step();
+        let stopped = !matches!(result, Ok(None | Some(TerminationReason::Watchpoint { .. })));
+        machine.swap_memory(&mut self.memory);
+        self.stopped[index] = stopped;
+        self.steps += 1;
+        self.schedule.push(index);
+    }
+
+    /// Step the machines until they've all stopped, for at most `max_steps` steps. Returns whether
+    /// they all stopped.
+    pub fn run(&mut self, max_steps: usize) -> bool {
+        for _ in 0..max_steps {
+            if self.step().is_none() {
+                return true;
+            }
+        }
+        self.is_stopped()
+    }
+
+    /// Check if every machine has stopped.
+    #[must_use]
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.iter().all(|&stopped| stopped)
+    }
+
+    /// Get the shared memory.
+    #[must_use]
+    pub fn get_memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    #[must_use]
+    pub fn get_machines(&self) -> &[Machine] {
+        &self.machines
+    }
+
+    /// Get how many steps have been taken, by all machines together.
+    #[must_use]
+    pub fn get_steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Get the index of the machine which was stepped at each step so far.
+    #[must_use]
+    pub fn get_schedule(&self) -> &[usize] {
+        &self.schedule
+    }
+
+    /// Try every interleaving from the current state for up to `max_steps` more steps, checking
+    /// `holds` on the shared memory in every state reached, including the current one.
+    ///
+    /// Stops at the first state where `holds` is false. The number of interleavings grows
+    /// exponentially with `max_steps`, so keep it small.
+    pub fn explore(&self, max_steps: usize, holds: impl Fn(&Memory) -> bool) -> Exploration {
+        let mut interleavings = 0;
+        let mut complete = true;
+        let mut pending = vec![self.clone()];
+        // A depth-first search, so only one path's worth of states is kept at once.
+        while let Some(state) = pending.pop() {
+            if !holds(&state.memory) {
+                return Exploration::Violated {
+                    schedule: state.schedule[self.schedule.len()..].to_vec(),
+                    memory: state.memory,
+                };
+            }
+            if state.is_stopped() {
+                interleavings += 1;
+                continue;
+            }
+            if state.steps - self.steps == max_steps {
+                interleavings += 1;
+                complete = false;
+                continue;
+            }
+            // Push in reverse so that the interleavings are tried starting with machine 0.
+            for index in (0..state.machines.len()).rev().filter(|&i| !state.stopped[i]) {
+                let mut next = state.clone();
+                next.step_machine(index);
+                pending.push(next);
+            }
+        }
+        Exploration::Held { interleavings, complete }
+    }
+}
+
+/// The result of [`Concurrent::explore`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exploration {
+    /// The check failed after the machines with these indices took a step, in order, leaving the
+    /// shared memory as given.
+    Violated { schedule: Vec<usize>, memory: Memory },
+    /// The check held in every state of every interleaving. `complete` is false if some
+    /// interleavings were cut off by the step limit before every machine stopped.
+    Held { interleavings: usize, complete: bool },
+}
+
+impl Display for Exploration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exploration::Violated { schedule, memory } => {
+                writeln!(f, "The assertion failed after {} steps, with {memory}.", schedule.len())?;
+                let machines: Vec<String> = schedule.iter().map(|i| (i + 1).to_string()).collect();
+                writeln!(f, "Programs stepped: {}", machines.join(" "))
+            },
+            Exploration::Held { interleavings, complete: true } => {
+                writeln!(f, "The assertion held in all {interleavings} interleavings.")
+            },
+            Exploration::Held { interleavings, complete: false } => {
+                writeln!(
+                    f,
+                    "The assertion held in all {interleavings} interleavings, but some were cut \
+                    off before every program halted.",
+                )
+            },
+        }
+    }
+}
+
+/// How an [`Assertion`] compares a register with its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// The operators in the order they must be searched for, so that `<=` isn't read as `<`.
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+}
+
+/// A comparison of one shared register with a number, such as `r0 <= 1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Assertion {
+    pub register: RegisterNumber,
+    pub comparison: Comparison,
+    pub value: u128,
+}
+
+impl Assertion {
+    /// Check the assertion against the given memory.
+    #[must_use]
+    pub fn holds(&self, memory: &Memory) -> bool {
+        // A register too large for a u128 is greater than any value.
+        let Some(actual) = memory.get_value(self.register).to_u128() else {
+            return matches!(
+                self.comparison,
+                Comparison::NotEqual | Comparison::Greater | Comparison::GreaterOrEqual,
+            );
+        };
+        match self.comparison {
+            Comparison::Equal => actual == self.value,
+            Comparison::NotEqual => actual != self.value,
+            Comparison::Less => actual < self.value,
+            Comparison::LessOrEqual => actual <= self.value,
+            Comparison::Greater => actual > self.value,
+            Comparison::GreaterOrEqual => actual >= self.value,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("The assertion {0:?} should compare a register with a number, such as \"r0 <= 1\".")]
+pub struct AssertionParseError(pub String);
+
+impl FromStr for Assertion {
+    type Err = AssertionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || AssertionParseError(s.to_string());
+        let (register, comparison, value) = Comparison::OPERATORS
+            .iter()
+            .find_map(|&(operator, comparison)| {
+                s.split_once(operator).map(|(register, value)| (register, comparison, value))
+            })
+            .ok_or_else(error)?;
+        Ok(Assertion {
+            register: register.trim().parse().map_err(|_| error())?,
+            comparison,
+            value: value.trim().parse().map_err(|_| error())?,
+        })
+    }
+}
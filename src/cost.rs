@@ -0,0 +1,173 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Abstract costs for each kind of instruction.
+//!
+//! Counting steps treats every instruction the same, but the literature uses various conventions,
+//! such as charging more for a jump which is taken or nothing for halting. A [`CostModel`] gives
+//! each kind of instruction its own cost, so the same program can be compared under several.
+
+use std::{ fmt::Display, str::FromStr };
+
+use thiserror::Error;
+
+use crate::instruction::Instruction;
+
+/// The cost of each kind of instruction. Conditional jumps cost differently depending on whether
+/// the jump is taken.
+///
+/// The default model charges 1 for everything, so the total cost is the number of steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostModel {
+    pub inc: u64,
+    pub decjz_taken: u64,
+    pub decjz_not_taken: u64,
+    pub decjnz_taken: u64,
+    pub decjnz_not_taken: u64,
+    pub goto: u64,
+    pub call: u64,
+    pub r#return: u64,
+    pub read: u64,
+    pub write: u64,
+    pub halt: u64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            inc: 1,
+            decjz_taken: 1,
+            decjz_not_taken: 1,
+            decjnz_taken: 1,
+            decjnz_not_taken: 1,
+            goto: 1,
+            call: 1,
+            r#return: 1,
+            read: 1,
+            write: 1,
+            halt: 1,
+        }
+    }
+}
+
+impl CostModel {
+    /// The names of the costs, as written by [`Display`] and read by [`FromStr`].
+    pub const NAMES: [&'static str; 11] = [
+        "inc",
+        "decjz_taken",
+        "decjz_not_taken",
+        "decjnz_taken",
+        "decjnz_not_taken",
+        "goto",
+        "call",
+        "return",
+        "read",
+        "write",
+        "halt",
+    ];
+
+    /// Get the cost of executing an instruction whose register is 0 if `is_zero` is true. The
+    /// value of `is_zero` is ignored for instructions which don't jump conditionally.
+    #[must_use]
+    pub const fn cost(&self, instruction: &Instruction, is_zero: bool) -> u64 {
+        match instruction {
+            Instruction::INC(_) => self.inc,
+            Instruction::DECJZ(..) if is_zero => self.decjz_taken,
+            Instruction::DECJZ(..) => self.decjz_not_taken,
+            Instruction::DECJNZ(..) if is_zero => self.decjnz_not_taken,
+            Instruction::DECJNZ(..) => self.decjnz_taken,
+            Instruction::GOTO(_) => self.goto,
+            Instruction::CALL(_) => self.call,
+            Instruction::RETURN => self.r#return,
+            Instruction::READ(_) => self.read,
+            Instruction::WRITE(_) => self.write,
+            Instruction::HALT => self.halt,
+        }
+    }
+
+    /// Get a mutable reference to the cost with the given name, or [`None`] if there isn't one.
+    fn get_mut(&mut self, name: &str) -> Option<&mut u64> {
+        match name {
+            "inc" => Some(&mut self.inc),
+            "decjz_taken" => Some(&mut self.decjz_taken),
+            "decjz_not_taken" => Some(&mut self.decjz_not_taken),
+            "decjnz_taken" => Some(&mut self.decjnz_taken),
+            "decjnz_not_taken" => Some(&mut self.decjnz_not_taken),
+            "goto" => Some(&mut self.goto),
+            "call" => Some(&mut self.call),
+            "return" => Some(&mut self.r#return),
+            "read" => Some(&mut self.read),
+            "write" => Some(&mut self.write),
+            "halt" => Some(&mut self.halt),
+            _ => None,
+        }
+    }
+}
+
+impl Display for CostModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let costs = [
+            self.inc,
+            self.decjz_taken,
+            self.decjz_not_taken,
+            self.decjnz_taken,
+            self.decjnz_not_taken,
+            self.goto,
+            self.call,
+            self.r#return,
+            self.read,
+            self.write,
+            self.halt,
+        ];
+        let pairs: Vec<String> = CostModel::NAMES
+            .iter()
+            .zip(costs)
+            .map(|(name, cost)| format!("{name}={cost}"))
+            .collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CostModelParseError {
+    #[error("Expected a cost such as \"decjz_taken=2\", but found {0:?}.")]
+    Malformed(String),
+    #[error(
+        "There's no cost called {0:?}. The costs are inc, decjz_taken, decjz_not_taken, \
+        decjnz_taken, decjnz_not_taken, goto, call, return, read, write and halt."
+    )]
+    UnknownName(String),
+}
+
+impl FromStr for CostModel {
+    type Err = CostModelParseError;
+
+    /// Read a comma-separated list of costs such as `inc=1,decjz_taken=2`. Costs which aren't
+    /// given are 1.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut model = CostModel::default();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let malformed = || CostModelParseError::Malformed(pair.to_string());
+            let (name, cost) = pair.split_once('=').ok_or_else(malformed)?;
+            let name = name.trim();
+            let cost = cost.trim().parse().map_err(|_| malformed())?;
+            *model
+                .get_mut(name)
+                .ok_or_else(|| CostModelParseError::UnknownName(name.to_string()))? = cost;
+        }
+        Ok(model)
+    }
+}
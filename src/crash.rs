@@ -0,0 +1,129 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Crash reports for the interactive modes.
+//!
+//! The REPL and debug mode call [`record_command`] before running each command, so that what the
+//! session was doing is known if remuir then panics. The hook set by [`install_hook`] writes it to
+//! a crash report file, with the panic message, and prints a short message saying where the
+//! report is, instead of the usual panic message and backtrace.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    io,
+    panic::{ self, PanicHookInfo },
+    path::{ Path, PathBuf },
+    sync::Mutex,
+};
+
+use crate::{ machine::Machine, manifest::Manifest };
+
+/// How many of the last commands a crash report lists.
+pub const COMMAND_LIMIT: usize = 20;
+
+/// What an interactive session was doing just before its last command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Context {
+    program: String,
+    line: usize,
+    registers: String,
+    manifest: String,
+    /// The last commands, oldest first. The last one is the command which was running.
+    commands: VecDeque<String>,
+}
+
+static CONTEXT: Mutex<Option<Context>> = Mutex::new(None);
+
+/// Remember a command which is about to run on the machine, and the machine as it is now.
+pub fn record_command(command: &str, machine: &Machine) {
+    let mut context = CONTEXT.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+    let context = context.get_or_insert_with(Context::default);
+    context.program = machine.to_source();
+    context.line = machine.get_current_line_number();
+    context.registers = machine.display_nat_registers();
+    context.manifest = Manifest::new(machine).to_string();
+    if context.commands.len() == COMMAND_LIMIT {
+        context.commands.pop_front();
+    }
+    context.commands.push_back(command.to_string());
+}
+
+/// Get the text of a crash report for a panic with the given message, or [`None`] if no command
+/// has been recorded.
+#[must_use]
+pub fn report(message: &str) -> Option<String> {
+    // Don't wait for the lock, in case the panic happened while it was held.
+    let context = match CONTEXT.try_lock() {
+        Ok(context) => context.clone()?,
+        Err(std::sync::TryLockError::Poisoned(poisoned)) => poisoned.into_inner().clone()?,
+        Err(std::sync::TryLockError::WouldBlock) => return None,
+    };
+    let mut report = format!(
+        "remuir {} crashed: {message}\n\nLast commands, oldest first:\n",
+        env!("CARGO_PKG_VERSION"),
+    );
+    for command in &context.commands {
+        report.push_str(&format!("  {command}\n"));
+    }
+    report.push_str(&format!(
+        "\nBefore the last command, the machine was on line {} with {}.\n\nManifest:\n{}\n\
+        Program:\n{}\n",
+        context.line, context.registers, context.manifest, context.program,
+    ));
+    Some(report)
+}
+
+/// Write a crash report for a panic with the given message into a directory, returning its path.
+///
+/// # Errors
+///
+/// * [`io::Error`] - returned when no command has been recorded, or the report couldn't be
+///   written.
+pub fn write_report(message: &str, directory: &Path) -> io::Result<PathBuf> {
+    let report = report(message)
+        .ok_or_else(|| io::Error::other("No command has been recorded."))?;
+    let path = directory.join(format!("remuir-crash-{}.txt", std::process::id()));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// Replace the panic hook with one which writes a crash report to the temporary directory and
+/// prints where it is. Panics before any command has been recorded use the usual hook.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info: &PanicHookInfo| {
+        let payload = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        let message = match info.location() {
+            Some(location) => format!("{payload} (at {location})"),
+            None => payload,
+        };
+        match write_report(&message, &std::env::temp_dir()) {
+            Ok(path) => eprintln!(
+                "\nSorry, remuir crashed unexpectedly: {message}\nA crash report with the program, \
+                registers and last commands was written to {}. Please report this at \
+                https://github.com/ettolrach/remuir/issues and attach the report.",
+                path.display(),
+            ),
+            Err(_) => default_hook(info),
+        }
+    }));
+}
@@ -0,0 +1,241 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Frontends for programs written in other surface syntaxes, such as the [`Urm`] instructions of
+//! some textbooks.
+//!
+//! A [`Dialect`] splits a program into [`Token`]s and parses them into a [`Program`], a list of
+//! remuir instructions with labels. The program is then written out as remuir source code and
+//! parsed as usual, so everything else, from the cache to the debugger, works on programs in any
+//! dialect. Dialects are looked up in a [`Registry`] by name, as with `--dialect`, or by the
+//! extension of a program's file.
+//!
+//! Third parties add a dialect by implementing the trait and registering it:
+//!
+//! ```
+//! use remuir::{
+//!     dialect::{ Dialect, DialectError, Program, Registry, Statement, Token },
+//!     instruction::Instruction,
+//!     memory::{ Memory, RegisterNumber },
+//! };
+//!
+//! /// Programs which only increment, one register number per token.
+//! struct Tally;
+//!
+//! impl Dialect for Tally {
+//!     fn get_name(&self) -> &str {
+//!         "tally"
+//!     }
+//!
+//!     fn parse(&self, tokens: &[Token]) -> Result<Program, DialectError> {
+//!         let mut program = Program::new(Memory::default());
+//!         for token in tokens {
+//!             let register = token.text.parse().map_err(|_| DialectError::Syntax {
+//!                 line: token.line,
+//!                 message: format!("\"{}\" isn't a register number.", token.text),
+//!             })?;
+//!             let instruction = Instruction::INC(RegisterNumber::Natural(register));
+//!             program.statements.push(Statement { label: None, instruction });
+//!         }
+//!         Ok(program)
+//!     }
+//! }
+//!
+//! let mut registry = Registry::default();
+//! registry.register(Tally);
+//! let source = registry.find("tally").unwrap().translate("0 1\n1").unwrap();
+//! assert_eq!(source, "registers 0\ninc r0\ninc r1\ninc r1\n");
+//! ```
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::{
+    instruction::Instruction,
+    memory::Memory,
+    parser::{ self, ParseSourceError },
+    urm::Urm,
+};
+
+/// The name of remuir's own syntax, which isn't in any [`Registry`] since it needs no
+/// translating.
+pub const NATIVE: &str = "remuir";
+
+#[derive(Debug, Error)]
+pub enum DialectError {
+    #[error("Line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("The translated program is invalid!\n{0}")]
+    InvalidSource(#[from] ParseSourceError),
+}
+
+/// A word or symbol of a program, with the line it's on, counting from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub line: usize,
+    pub text: String,
+}
+
+/// A line of a translated program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub label: Option<String>,
+    pub instruction: Instruction,
+}
+
+/// A program in any dialect, as remuir instructions. Jumps should go to the label of a statement
+/// or to [`crate::machine::Identifier::Halt`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub registers: Memory,
+    pub statements: Vec<Statement>,
+}
+
+impl Program {
+    #[must_use]
+    pub fn new(registers: Memory) -> Program {
+        Program { registers, statements: Vec::new() }
+    }
+
+    /// Write the program as remuir source code.
+    #[must_use]
+    pub fn to_source(&self) -> String {
+        let mut source = format!("{}\n", self.registers);
+        for statement in &self.statements {
+            if let Some(label) = &statement.label {
+                source.push_str(&format!("{label}: "));
+            }
+            source.push_str(&format!("{}\n", statement.instruction));
+        }
+        source
+    }
+}
+
+/// Split a program into tokens, which are separated by whitespace. Each of `(`, `)`, `,`, `;` and
+/// `:` is a token of its own.
+#[must_use]
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let mut push = |text: &mut String| {
+            if !text.is_empty() {
+                tokens.push(Token { line: i + 1, text: std::mem::take(text) });
+            }
+        };
+        let mut text = String::new();
+        for c in line.chars() {
+            if c.is_whitespace() {
+                push(&mut text);
+            }
+            else if "(),;:".contains(c) {
+                push(&mut text);
+                push(&mut c.to_string());
+            }
+            else {
+                text.push(c);
+            }
+        }
+        push(&mut text);
+    }
+    tokens
+}
+
+/// A surface syntax for programs.
+pub trait Dialect: Send + Sync {
+    /// The name of the dialect, as given to `--dialect`.
+    fn get_name(&self) -> &str;
+
+    /// The extensions, without a dot, of files holding programs in this dialect.
+    fn get_extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Split a program into tokens. By default, this is done by [`tokenize`].
+    fn tokenize(&self, source: &str) -> Vec<Token> {
+        tokenize(source)
+    }
+
+    /// Parse the tokens of a program.
+    ///
+    /// # Errors
+    ///
+    /// * [`DialectError::Syntax`] - when the program isn't valid in this dialect.
+    fn parse(&self, tokens: &[Token]) -> Result<Program, DialectError>;
+
+    /// Translate a program into remuir source code.
+    ///
+    /// # Errors
+    ///
+    /// * [`DialectError::Syntax`] - when the program isn't valid in this dialect.
+    /// * [`DialectError::InvalidSource`] - when the translation isn't a valid remuir program,
+    ///   such as when it jumps to a label which isn't defined.
+    fn translate(&self, source: &str) -> Result<String, DialectError> {
+        let source = self.parse(&self.tokenize(source))?.to_source();
+        parser::parse_str(&source)?;
+        Ok(source)
+    }
+}
+
+/// The dialects which can be chosen by name or file extension.
+pub struct Registry {
+    dialects: Vec<Box<dyn Dialect>>,
+}
+
+impl Default for Registry {
+    /// A registry of the dialects built into remuir.
+    fn default() -> Self {
+        Registry { dialects: vec![Box::new(Urm)] }
+    }
+}
+
+impl Registry {
+    /// Add a dialect. It replaces any dialect registered before with the same name, and takes
+    /// over its extensions.
+    pub fn register(&mut self, dialect: impl Dialect + 'static) {
+        self.dialects.retain(|other| !other.get_name().eq_ignore_ascii_case(dialect.get_name()));
+        self.dialects.push(Box::new(dialect));
+    }
+
+    /// Find a dialect by its name, in any case.
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<&dyn Dialect> {
+        self.dialects
+            .iter()
+            .rev()
+            .find(|dialect| dialect.get_name().eq_ignore_ascii_case(name))
+            .map(AsRef::as_ref)
+    }
+
+    /// Find the dialect of a file from its extension, if it isn't remuir's own syntax.
+    #[must_use]
+    pub fn for_path(&self, path: &Path) -> Option<&dyn Dialect> {
+        let extension = path.extension()?.to_str()?;
+        self.dialects
+            .iter()
+            .rev()
+            .find(|dialect| {
+                dialect.get_extensions().iter().any(|e| e.eq_ignore_ascii_case(extension))
+            })
+            .map(AsRef::as_ref)
+    }
+
+    /// The names of the registered dialects, starting with [`NATIVE`].
+    #[must_use]
+    pub fn get_names(&self) -> Vec<&str> {
+        std::iter::once(NATIVE).chain(self.dialects.iter().map(|d| d.get_name())).collect()
+    }
+}
@@ -0,0 +1,146 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! Comparing two machines.
+//!
+//! Comparing whole machines with `assert_eq!` gives failures which are very hard to read, so
+//! [`MachineDiff`] only keeps what differs, and displays it one difference per line.
+
+use std::fmt::Display;
+
+use crate::{
+    machine::{ Line, LineNumber, Machine },
+    memory::{ Register, RegisterNumber },
+};
+
+/// The differences between two machines, called the left and the right machine.
+#[derive(Debug, Default, PartialEq)]
+pub struct MachineDiff {
+    /// Lines which differ, where [`None`] means the machine has no line with that number.
+    pub lines: Vec<(LineNumber, Option<Line>, Option<Line>)>,
+    /// Labels which point to different lines, where [`None`] means the label doesn't exist.
+    pub labels: Vec<(String, Option<LineNumber>, Option<LineNumber>)>,
+    /// Registers which hold different values.
+    pub registers: Vec<(RegisterNumber, Register, Register)>,
+    /// The lines the instruction pointers point to, if they differ.
+    pub current_line: Option<(LineNumber, LineNumber)>,
+}
+
+impl MachineDiff {
+    /// Find the differences between two machines.
+    #[must_use]
+    pub fn new(left: &Machine, right: &Machine) -> MachineDiff {
+        let mut diff = MachineDiff::default();
+
+        let (left_lines, right_lines) = (left.get_lines(), right.get_lines());
+        for n in 0..left_lines.len().max(right_lines.len()) {
+            let (l, r) = (left_lines.get(n), right_lines.get(n));
+            if l != r {
+                diff.lines.push((n, l.cloned(), r.cloned()));
+            }
+        }
+
+        for (label, _) in left.get_labels().iter().chain(right.get_labels()) {
+            let (l, r) = (left.get_label_line(label), right.get_label_line(label));
+            if l != r && !diff.labels.iter().any(|(existing, _, _)| existing == label) {
+                diff.labels.push((label.clone(), l, r));
+            }
+        }
+
+        let (left_memory, right_memory) = (left.get_state(), right.get_state());
+        diff.compare_registers(
+            left_memory.get_nat_registers(),
+            right_memory.get_nat_registers(),
+            RegisterNumber::Natural,
+        );
+        diff.compare_registers(
+            left_memory.get_neg_registers(),
+            right_memory.get_neg_registers(),
+            RegisterNumber::Negative,
+        );
+
+        let (l, r) = (left.get_current_line_number(), right.get_current_line_number());
+        if l != r {
+            diff.current_line = Some((l, r));
+        }
+        diff
+    }
+
+    /// Compare one bank of registers, treating missing registers as 0.
+    fn compare_registers(
+        &mut self,
+        left: &[Register],
+        right: &[Register],
+        to_register_number: fn(usize) -> RegisterNumber,
+    ) {
+        let zero = Register::from(0);
+        for n in 0..left.len().max(right.len()) {
+            let l = left.get(n).unwrap_or(&zero);
+            let r = right.get(n).unwrap_or(&zero);
+            if l != r {
+                self.registers.push((to_register_number(n), l.clone(), r.clone()));
+            }
+        }
+    }
+
+    /// Check if the two machines had no differences.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+            && self.labels.is_empty()
+            && self.registers.is_empty()
+            && self.current_line.is_none()
+    }
+}
+
+impl Display for MachineDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn or_missing<T: Display>(value: Option<&T>) -> String {
+            value.map_or_else(|| String::from("(missing)"), ToString::to_string)
+        }
+
+        if self.is_empty() {
+            return writeln!(f, "No differences.");
+        }
+        if !self.lines.is_empty() {
+            writeln!(f, "Lines:")?;
+            for (n, l, r) in &self.lines {
+                let (l, r) = (
+                    or_missing(l.as_ref().map(Line::get_instruction)),
+                    or_missing(r.as_ref().map(Line::get_instruction)),
+                );
+                writeln!(f, "  {n}: {l} != {r}")?;
+            }
+        }
+        if !self.labels.is_empty() {
+            writeln!(f, "Labels:")?;
+            for (label, l, r) in &self.labels {
+                writeln!(f, "  {label}: {} != {}", or_missing(l.as_ref()), or_missing(r.as_ref()))?;
+            }
+        }
+        if !self.registers.is_empty() {
+            writeln!(f, "Registers:")?;
+            for (register, l, r) in &self.registers {
+                writeln!(f, "  {register}: {l} != {r}")?;
+            }
+        }
+        if let Some((l, r)) = self.current_line {
+            writeln!(f, "Current line: {l} != {r}")?;
+        }
+        Ok(())
+    }
+}
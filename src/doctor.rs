@@ -0,0 +1,261 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Reports for filing bugs, from `remuir doctor`.
+//!
+//! A [`Report`] gathers what's needed to make sense of a bug report about a program: the version
+//! of remuir, whether the program parses, its [`Metrics`], its [`Manifest`] and how a sandboxed
+//! run of it, with no input, ends. If remuir crashes while loading or running the program, the
+//! report includes the smallest program found which still crashes in the same way, made by
+//! removing lines from the original for as long as it keeps crashing.
+
+use std::{
+    any::Any,
+    fmt::Display,
+    panic::{ self, AssertUnwindSafe },
+    sync::{ Mutex, PoisonError },
+    time::Duration,
+};
+
+use crate::{
+    io_handler::BufferIo,
+    machine::Machine,
+    manifest::Manifest,
+    metrics::Metrics,
+    sandbox::Sandbox,
+};
+
+/// The caps on each run of the program, so that a report never takes long to make.
+pub const SANDBOX: Sandbox = Sandbox {
+    steps: Some(1_000_000),
+    time: Some(Duration::from_secs(10)),
+    ..Sandbox::SERVER
+};
+
+/// How a run of the program ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program halted after this many steps, with these natural registers.
+    Halted { steps: usize, registers: String },
+    /// The program stopped early, for the given reason, such as the step limit.
+    Stopped(String),
+    /// remuir panicked with the given message.
+    Crashed(String),
+}
+
+/// Held while [`Report::new`] has replaced the panic hook, so that reports made on several
+/// threads at once don't put back each other's hooks.
+static HOOK_LOCK: Mutex<()> = Mutex::new(());
+
+/// A report on a program, which can be displayed for pasting into a bug report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub version: String,
+    /// Why the program couldn't be loaded, if it couldn't.
+    pub parse_error: Option<String>,
+    pub metrics: Option<Metrics>,
+    pub manifest: Option<Manifest>,
+    /// How running the program ended, or [`None`] if it couldn't be loaded.
+    pub outcome: Option<Outcome>,
+    /// The smallest program found which crashes in the same way, if remuir crashed.
+    pub reproduction: Option<String>,
+    /// How many lines the original program has.
+    pub source_lines: usize,
+}
+
+impl Report {
+    /// Load the program with `load` and run it in [`SANDBOX`], noting how each goes. Whether the
+    /// program was optimised can't be known from the machine, so it's given as `optimized`.
+    ///
+    /// While the report is made, panics are caught and the panic hook is replaced with one which
+    /// prints nothing, so that a crash doesn't print a message for every attempt to shrink it.
+    /// Reports are made one at a time, so the hook is always put back once the last one is done.
+    #[must_use]
+    pub fn new<E: Display>(
+        source: &str,
+        optimized: bool,
+        load: impl Fn(&str) -> Result<Machine, E>,
+    ) -> Report {
+        let _lock = HOOK_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        let report = Report::without_hook(source, optimized, &load);
+        panic::set_hook(hook);
+        report
+    }
+
+    fn without_hook<E: Display>(
+        source: &str,
+        optimized: bool,
+        load: &impl Fn(&str) -> Result<Machine, E>,
+    ) -> Report {
+        let mut report = Report {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            parse_error: None,
+            metrics: None,
+            manifest: None,
+            outcome: None,
+            reproduction: None,
+            source_lines: source.lines().count(),
+        };
+        let machine = match panic::catch_unwind(AssertUnwindSafe(|| load(source))) {
+            Ok(Ok(machine)) => machine,
+            Ok(Err(e)) => {
+                report.parse_error = Some(e.to_string());
+                return report;
+            },
+            Err(payload) => {
+                let message = panic_message(payload.as_ref());
+                report.reproduction = Some(minimise(source, |candidate| {
+                    crash(candidate, load).as_ref() == Some(&message)
+                }));
+                report.outcome = Some(Outcome::Crashed(message));
+                return report;
+            },
+        };
+        report.metrics = Some(Metrics::new(&machine));
+        report.manifest = Some(Manifest { optimized, ..Manifest::new(&machine) });
+        let outcome = match panic::catch_unwind(AssertUnwindSafe(|| run(machine))) {
+            Ok(outcome) => outcome,
+            Err(payload) => {
+                let message = panic_message(payload.as_ref());
+                report.reproduction = Some(minimise(source, |candidate| {
+                    crash(candidate, load).as_ref() == Some(&message)
+                }));
+                Outcome::Crashed(message)
+            },
+        };
+        report.outcome = Some(outcome);
+        report
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "remuir {}", self.version)?;
+        match &self.parse_error {
+            Some(error) => writeln!(f, "\nParse: failed\n{error}")?,
+            None if self.metrics.is_none() => writeln!(f, "\nParse: crashed")?,
+            None => writeln!(f, "\nParse: ok, {} lines", self.source_lines)?,
+        }
+        if let Some(metrics) = &self.metrics {
+            write!(f, "\nMetrics:\n{metrics}")?;
+        }
+        if let Some(manifest) = &self.manifest {
+            write!(f, "\nConfiguration:\n{manifest}")?;
+        }
+        match &self.outcome {
+            Some(Outcome::Halted { steps, registers }) => {
+                writeln!(f, "\nRun: halted after {steps} steps with {registers}")?;
+            },
+            Some(Outcome::Stopped(reason)) => writeln!(f, "\nRun: stopped. {reason}")?,
+            Some(Outcome::Crashed(message)) => writeln!(f, "\nCrashed: {message}")?,
+            None => (),
+        }
+        if let Some(reproduction) = &self.reproduction {
+            writeln!(
+                f,
+                "\nSmallest program found which crashes the same way ({} of {} lines):",
+                reproduction.lines().count(),
+                self.source_lines,
+            )?;
+            for line in reproduction.lines() {
+                writeln!(f, "    {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run the machine in [`SANDBOX`] until it halts or stops, with no input.
+fn run(mut machine: Machine) -> Outcome {
+    machine.set_sandbox(SANDBOX);
+    machine.set_io_handler(BufferIo::default());
+    machine.run();
+    if let Some(limit) = machine.get_resource_limit() {
+        Outcome::Stopped(limit.to_string())
+    }
+    else if let Some(e) = machine.get_io_error() {
+        Outcome::Stopped(e.to_string())
+    }
+    else if machine.is_step_limit_reached() && !machine.is_halted() {
+        Outcome::Stopped(format!(
+            "The machine didn't halt within the limit of {} steps.",
+            machine.get_steps(),
+        ))
+    }
+    else if !machine.is_halted() {
+        Outcome::Stopped(format!(
+            "Line {} jumps past the end of the program or to a label which doesn't exist.",
+            machine.get_current_line_number(),
+        ))
+    }
+    else {
+        Outcome::Halted { steps: machine.get_steps(), registers: machine.display_nat_registers() }
+    }
+}
+
+/// Get the panic message of loading and running the program, if it panics.
+fn crash<E>(source: &str, load: &impl Fn(&str) -> Result<Machine, E>) -> Option<String> {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        if let Ok(machine) = load(source) {
+            run(machine);
+        }
+    }))
+    .err()
+    .map(|payload| panic_message(payload.as_ref()))
+}
+
+/// Get the message a panic was started with.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        String::from(*message)
+    }
+    else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    }
+    else {
+        String::from("(no message)")
+    }
+}
+
+/// Remove lines from the source code for as long as `reproduces` still holds, first in large
+/// chunks and then one line at a time, and return what's left.
+#[must_use]
+pub fn minimise(source: &str, reproduces: impl Fn(&str) -> bool) -> String {
+    let mut lines: Vec<&str> = source.lines().collect();
+    let mut chunk = lines.len().div_ceil(2).max(1);
+    loop {
+        let mut removed_any = false;
+        let mut start = 0;
+        while start < lines.len() {
+            let end = (start + chunk).min(lines.len());
+            let candidate: Vec<&str> = [&lines[..start], &lines[end..]].concat();
+            if reproduces(&candidate.join("\n")) {
+                lines = candidate;
+                removed_any = true;
+            }
+            else {
+                start = end;
+            }
+        }
+        if chunk == 1 && !removed_any {
+            break;
+        }
+        chunk = chunk.div_ceil(2);
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,207 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! Publish what a machine is doing while it runs, such as to drive a display of its registers.
+//!
+//! [`run`] runs a machine until it stops, sending an [`Event`] with the registers to a [`Sink`]
+//! at most once every so often, and a last one once the machine stops. Events can be sent over
+//! UDP as [OSC](https://opensoundcontrol.stanford.edu/) messages with [`Osc`], which lighting and
+//! music software understands, or handled by any function with [`Callback`].
+//!
+//! This module is only built with the `emit` feature.
+//!
+//! # Examples
+//! ```
+//! use std::time::Duration;
+//! use remuir::{ emit::{ self, Callback, Event }, parser::parse_str };
+//!
+//! let mut machine = parse_str("registers 3\nloop: decjz r0 HALT\ninc r1\ngoto loop").unwrap();
+//! let mut events = Vec::new();
+//! let mut sink = Callback(|event: &Event| events.push(event.clone()));
+//! emit::run(&mut machine, &mut sink, Duration::ZERO).unwrap();
+//! assert!(events.last().unwrap().halted);
+//! assert_eq!(events.last().unwrap().registers[1].to_string(), "3");
+//! ```
+
+use std::{
+    io,
+    net::{ Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket },
+    time::{ Duration, Instant },
+};
+
+use crate::{ machine::Machine, memory::Register };
+
+/// How many steps [`run`] takes between checks of whether it's time to send an event.
+pub const CHECK_INTERVAL: usize = 1024;
+
+/// The most bytes an OSC message sent by [`Osc`] can have, which is the most a UDP datagram can
+/// carry over IPv4.
+pub const MAX_MESSAGE_LENGTH: usize = 65_507;
+
+/// The state of a machine at some point while it ran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// How many steps the machine had taken.
+    pub steps: usize,
+    /// The values of the natural registers, from r0.
+    pub registers: Vec<Register>,
+    /// Whether the machine had stopped, so this is the last event.
+    pub halted: bool,
+}
+
+impl Event {
+    fn new(machine: &Machine, halted: bool) -> Event {
+        let memory = machine.get_state();
+        let mut registers = memory.get_nat_registers().to_vec();
+        registers.resize(memory.get_nat_count(), Register::from(0));
+        Event { steps: machine.get_steps(), registers, halted }
+    }
+}
+
+/// Somewhere to send events to.
+pub trait Sink {
+    /// Send an event.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the event couldn't be sent, which stops [`run`].
+    fn send(&mut self, event: &Event) -> io::Result<()>;
+}
+
+/// Sends each event to a function.
+pub struct Callback<F>(pub F);
+
+impl<F: FnMut(&Event)> Sink for Callback<F> {
+    fn send(&mut self, event: &Event) -> io::Result<()> {
+        (self.0)(event);
+        Ok(())
+    }
+}
+
+/// Sends each event as an OSC message over UDP. While the machine runs, the message's address is
+/// `/remuir/registers`, and once it stops, it's `/remuir/halted`. Its arguments are the steps
+/// taken, then the value of each natural register from r0.
+///
+/// Numbers are sent as 32-bit integers (`i`) where they fit, otherwise as 64-bit integers (`h`),
+/// and otherwise as strings in decimal (`s`). Registers that don't fit in
+/// [`MAX_MESSAGE_LENGTH`] bytes are left off the end of the message.
+///
+/// Messages that are refused, such as because nothing is listening yet, are dropped rather than
+/// stopping the machine.
+#[derive(Debug)]
+pub struct Osc {
+    socket: UdpSocket,
+}
+
+impl Osc {
+    /// Send messages to the given address, such as `"127.0.0.1:9000"`.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the address can't be resolved, or no socket can be opened.
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Osc> {
+        let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the address didn't resolve to anything")
+        })?;
+        // Bind to the same address family as the target, so IPv6 targets can be reached too.
+        let socket = match address {
+            SocketAddr::V4(_) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?,
+            SocketAddr::V6(_) => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?,
+        };
+        socket.connect(address)?;
+        Ok(Osc { socket })
+    }
+}
+
+impl Sink for Osc {
+    fn send(&mut self, event: &Event) -> io::Result<()> {
+        match self.socket.send(&encode_osc_within(event, MAX_MESSAGE_LENGTH)) {
+            Err(error) if error.kind() != io::ErrorKind::ConnectionRefused => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Encode an event as an OSC message (see [`Osc`]).
+#[must_use]
+pub fn encode_osc(event: &Event) -> Vec<u8> {
+    encode_osc_within(event, usize::MAX)
+}
+
+/// Encode an event as an OSC message, leaving off as many registers from the end as it takes for
+/// the message to be at most `max_length` bytes long. The steps taken are always kept.
+#[must_use]
+pub fn encode_osc_within(event: &Event, max_length: usize) -> Vec<u8> {
+    let address = if event.halted { "/remuir/halted" } else { "/remuir/registers" };
+    let mut message = Vec::new();
+    push_osc_string(&mut message, address);
+    let mut tags = String::from(",");
+    let mut arguments = Vec::new();
+    let mut argument = Vec::new();
+    let steps = Register::from(event.steps as u128);
+    for register in std::iter::once(&steps).chain(&event.registers) {
+        argument.clear();
+        let value = register.to_u128();
+        let tag = if let Some(value) = value.and_then(|value| i32::try_from(value).ok()) {
+            argument.extend_from_slice(&value.to_be_bytes());
+            'i'
+        }
+        else if let Some(value) = value.and_then(|value| i64::try_from(value).ok()) {
+            argument.extend_from_slice(&value.to_be_bytes());
+            'h'
+        }
+        else {
+            push_osc_string(&mut argument, &register.to_string());
+            's'
+        };
+        // The tags are padded to a multiple of 4 after at least one 0 byte.
+        let tags_length = (tags.len() + 1) / 4 * 4 + 4;
+        let length = message.len() + tags_length + arguments.len() + argument.len();
+        if tags.len() > 1 && length > max_length {
+            break;
+        }
+        tags.push(tag);
+        arguments.extend_from_slice(&argument);
+    }
+    push_osc_string(&mut message, &tags);
+    message.extend_from_slice(&arguments);
+    message
+}
+
+/// Append a string as OSC does, ending with at least one 0 byte and padded to a multiple of 4.
+fn push_osc_string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend_from_slice(string.as_bytes());
+    bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+}
+
+/// Run the machine until it stops, sending an event to `sink` straight away, then at most once
+/// every `interval` while it runs, and once more when it stops.
+///
+/// # Errors
+///
+/// * [`io::Error`] - returned when an event couldn't be sent. The machine is left where it was.
+pub fn run(machine: &mut Machine, sink: &mut impl Sink, interval: Duration) -> io::Result<()> {
+    sink.send(&Event::new(machine, false))?;
+    let mut last_sent = Instant::now();
+    while machine.run_steps(CHECK_INTERVAL) == CHECK_INTERVAL {
+        if last_sent.elapsed() >= interval {
+            sink.send(&Event::new(machine, false))?;
+            last_sent = Instant::now();
+        }
+    }
+    sink.send(&Event::new(machine, true))
+}
@@ -0,0 +1,159 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! Known-good example machines, ready to run.
+//!
+//! These are the same programs as in the `examples` directory of the repository, constructed
+//! directly so they can be used without any source code. Every example takes its input in the
+//! initial registers and leaves its result in r0.
+//!
+//! # Examples
+//! ```
+//! let mut machine = remuir::examples::square(7);
+//! machine.run();
+//! assert_eq!(machine.display_nat_registers(), "registers 49");
+//! ```
+
+use crate::{
+    instruction::Instruction,
+    machine::{ Identifier, Line, Machine },
+    memory::{ Memory, Register, RegisterNumber },
+};
+
+/// The name and a short description of every example, in the order they should be listed.
+pub const GALLERY: &[(&str, &str)] = &[
+    ("copy", "Copy r1 into r0, leaving r1 unchanged."),
+    ("add", "Add r1 and r2 into r0, draining r1 and r2."),
+    ("is_even", "Set r0 to 1 if it's even, or 0 if it's odd."),
+    ("square", "Square r0 by repeated addition."),
+];
+
+/// Build the example with the given name from [`GALLERY`], with some sample input, or [`None`] if
+/// there's no example with that name.
+#[must_use]
+pub fn by_name(name: &str) -> Option<Machine> {
+    match name {
+        "copy" => Some(copy(3)),
+        "add" => Some(add(2, 3)),
+        "is_even" => Some(is_even(4)),
+        "square" => Some(square(7)),
+        _ => None,
+    }
+}
+
+/// Build a machine from (label, instruction) pairs, numbering the lines in order.
+fn build(lines: &[(Option<&str>, Instruction)], registers: &[u128]) -> Machine {
+    let lines: Vec<Line> = lines
+        .iter()
+        .enumerate()
+        .map(|(n, (label, instruction))| {
+            Line::new(n, label.map(|l| Identifier::Label(l.to_string())), instruction.clone())
+        })
+        .collect();
+    let memory: Memory = registers.iter().map(|&r| Register::from(r)).collect();
+    Machine::new_from_lines(&lines, memory)
+}
+
+fn inc(register: isize) -> Instruction {
+    Instruction::INC(RegisterNumber::from(register))
+}
+
+fn decjz(register: isize, label: &str) -> Instruction {
+    let id = match label {
+        "HALT" => Identifier::Halt,
+        _ => Identifier::Label(label.to_string()),
+    };
+    Instruction::DECJZ(RegisterNumber::from(register), id)
+}
+
+/// Copy r1 into r0, leaving r1 unchanged.
+#[must_use]
+pub fn copy(value: u128) -> Machine {
+    build(&[
+        (Some("loop1"), decjz(1, "loop2")),
+        (None, inc(0)),
+        (None, inc(-2)),
+        (None, decjz(-1, "loop1")),
+        (Some("loop2"), decjz(-2, "HALT")),
+        (None, inc(1)),
+        (None, decjz(-1, "loop2")),
+    ], &[0, value])
+}
+
+/// Add r1 and r2 into r0, draining r1 and r2.
+#[must_use]
+pub fn add(a: u128, b: u128) -> Machine {
+    build(&[
+        (Some("first"), decjz(1, "second")),
+        (None, inc(0)),
+        (None, decjz(-1, "first")),
+        (Some("second"), decjz(2, "HALT")),
+        (None, inc(0)),
+        (None, decjz(-1, "second")),
+    ], &[0, a, b])
+}
+
+/// Set r0 to 1 if it's even, or 0 if it's odd.
+#[must_use]
+pub fn is_even(value: u128) -> Machine {
+    build(&[
+        (Some("beginning"), decjz(0, "even_halt")),
+        (None, decjz(0, "odd_halt")),
+        (None, decjz(-1, "beginning")),
+        (Some("even_halt"), inc(0)),
+        (None, decjz(-1, "HALT")),
+        (Some("odd_halt"), decjz(-1, "HALT")),
+    ], &[value])
+}
+
+/// Square r0 by repeated addition.
+#[must_use]
+pub fn square(value: u128) -> Machine {
+    build(&[
+        // Copy r0 to r-2 (the counter) and r-4 (the number of additions), using r-9 to restore r0.
+        (Some("1stcopy1"), decjz(0, "1stcopy2")),
+        (None, inc(-2)),
+        (None, inc(-9)),
+        (None, decjz(-1, "1stcopy1")),
+        (Some("1stcopy2"), decjz(-9, "3rdcopy1")),
+        (None, inc(0)),
+        (None, decjz(-1, "1stcopy2")),
+        (Some("3rdcopy1"), decjz(0, "3rdcopy2")),
+        (None, inc(-4)),
+        (None, inc(-9)),
+        (None, decjz(-1, "3rdcopy1")),
+        (Some("3rdcopy2"), decjz(-9, "drain")),
+        (None, inc(0)),
+        (None, decjz(-1, "3rdcopy2")),
+        // Set r0 to 0.
+        (Some("drain"), decjz(0, "addition")),
+        (None, decjz(-1, "drain")),
+        (Some("multiplication"), decjz(-4, "HALT")),
+        // Restore the counter.
+        (Some("multiplication_copy1"), decjz(-2, "multiplication_copy2")),
+        (None, inc(-3)),
+        (None, inc(-9)),
+        (None, decjz(-1, "multiplication_copy1")),
+        (Some("multiplication_copy2"), decjz(-9, "finished_multiplication_copy")),
+        (None, inc(-2)),
+        (None, decjz(-1, "multiplication_copy2")),
+        (Some("finished_multiplication_copy"), decjz(-1, "addition")),
+        (Some("addition"), decjz(-3, "multiplication")),
+        (None, inc(0)),
+        (None, decjz(-1, "addition")),
+    ], &[value])
+}
@@ -0,0 +1,342 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Recognising common idioms and summarising what they compute.
+//!
+//! Where `rZ` is a register which is always 0, the idioms are:
+//!
+//! * A *move* loop, `L: decjz rX E`, some `inc` instructions on other registers, then
+//!   `decjz rZ L` or `goto L`. Each `inc rA` adds `rX` to `rA`, and `rX` ends up as 0. With no
+//!   `inc` instructions, this is a *drain* loop, and with one, it's *addition*.
+//! * A *copy* loop, which moves `rX` into some registers and a scratch register, then moves the
+//!   scratch register back into `rX`. It adds `rX` to the registers and leaves `rX` unchanged, as
+//!   long as the scratch register starts at 0.
+//! * *Multiplication* by repeated addition, `L: decjz rX E` followed by a copy loop of `rY` which
+//!   jumps back to `L`. It adds `rX × rY` to the registers, and `rX` ends up as 0.
+//!
+//! Registers which are only ever used as a GOTO, by `decjz rZ L`, are listed before the listing.
+//!
+//! An idiom is only recognised if nothing jumps into the middle of it. The explanation is rendered
+//! like a [`Listing`](crate::listing::Listing), with a comment summarising each idiom before its
+//! first line. Idioms inside other idioms are indented. The documentation of labels is shown
+//! above them as `##` comments.
+
+use std::fmt::Display;
+
+use crate::{
+    instruction::Instruction,
+    machine::{ Identifier, LineNumber, Machine },
+    memory::RegisterNumber,
+    optimize,
+};
+
+/// What an idiom computes. Each register which is added to is paired with how many times the
+/// source is added to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    /// Set the register to 0.
+    Drain(RegisterNumber),
+    /// Add `from` to each register in `to`, and set `from` to 0.
+    Move { from: RegisterNumber, to: Vec<(RegisterNumber, usize)> },
+    /// Add `from` to each register in `to`, leaving `from` unchanged. `scratch` must start at 0.
+    Copy { from: RegisterNumber, to: Vec<(RegisterNumber, usize)>, scratch: RegisterNumber },
+    /// Add `multiplier × multiplicand` to each register in `to`, and set `multiplier` to 0.
+    /// `scratch` must start at 0.
+    Multiply {
+        multiplier: RegisterNumber,
+        multiplicand: RegisterNumber,
+        to: Vec<(RegisterNumber, usize)>,
+        scratch: RegisterNumber,
+    },
+}
+
+impl Display for Effect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let additions = |to: &[(RegisterNumber, usize)], amount: &str| -> Vec<String> {
+            to.iter()
+                .map(|(register, times)| match times {
+                    1 => format!("{register} += {amount}"),
+                    _ => format!("{register} += {times} × {amount}"),
+                })
+                .collect()
+        };
+        match self {
+            Effect::Drain(register) => write!(f, "{register} := 0 (drain loop)"),
+            Effect::Move { from, to } => {
+                let name = if to.len() == 1 { "addition" } else { "move loop" };
+                let additions = additions(to, &from.to_string()).join("; ");
+                write!(f, "{additions}; {from} := 0 ({name})")
+            },
+            Effect::Copy { from, to, scratch } => {
+                let additions = additions(to, &from.to_string()).join("; ");
+                write!(f, "{additions} (copy loop, using {scratch})")
+            },
+            Effect::Multiply { multiplier, multiplicand, to, scratch } => {
+                let product = format!("{multiplier} × {multiplicand}");
+                let additions = additions(to, &product).join("; ");
+                write!(
+                    f,
+                    "{additions}; {multiplier} := 0 (multiplication by repeated addition, using \
+                    {scratch})",
+                )
+            },
+        }
+    }
+}
+
+/// An idiom found in a program, which covers the lines `start..=end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Idiom {
+    pub start: LineNumber,
+    pub end: LineNumber,
+    pub effect: Effect,
+    /// The idioms it's made of.
+    pub parts: Vec<Idiom>,
+}
+
+/// A machine's program with the idioms found in it.
+pub struct Explanation<'a> {
+    machine: &'a Machine,
+    idioms: Vec<Idiom>,
+    goto_registers: Vec<RegisterNumber>,
+}
+
+impl<'a> Explanation<'a> {
+    /// Find the idioms in a machine's program, from the first line to the last.
+    #[must_use]
+    pub fn new(machine: &'a Machine) -> Explanation<'a> {
+        let finder = Finder::new(machine);
+        let mut idioms = Vec::new();
+        let mut start = 0;
+        while start < machine.get_lines().len() {
+            let found = finder
+                .multiply(start)
+                .or_else(|| finder.copy(start))
+                .or_else(|| finder.simple_loop(start));
+            match found {
+                Some((idiom, _)) => {
+                    start = idiom.end + 1;
+                    idioms.push(idiom);
+                },
+                None => start += 1,
+            }
+        }
+        let goto_registers = optimize::goto_registers(machine.get_lines());
+        Explanation { machine, idioms, goto_registers }
+    }
+
+    /// Get the idioms which aren't part of another idiom, in the order they appear.
+    #[must_use]
+    pub fn get_idioms(&self) -> &[Idiom] {
+        &self.idioms
+    }
+
+    /// Get the registers which are always 0 and only used by DECJZ, so each use is a GOTO.
+    #[must_use]
+    pub fn get_goto_registers(&self) -> &[RegisterNumber] {
+        &self.goto_registers
+    }
+}
+
+impl Display for Explanation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines = self.machine.get_lines();
+        let width = lines.len().saturating_sub(1).to_string().len();
+        // Every idiom with how deeply it's nested, outermost first.
+        let mut comments: Vec<(usize, &Idiom)> = Vec::new();
+        let mut pending: Vec<(usize, &Idiom)> =
+            self.idioms.iter().rev().map(|idiom| (0, idiom)).collect();
+        while let Some((depth, idiom)) = pending.pop() {
+            comments.push((depth, idiom));
+            pending.extend(idiom.parts.iter().rev().map(|part| (depth + 1, part)));
+        }
+        for register in &self.goto_registers {
+            writeln!(
+                f,
+                "{:width$}  # {register} is always 0 and only used by DECJZ, so each use is a goto.",
+                "",
+            )?;
+        }
+        for line in lines {
+            let n = line.get_line_number();
+            for (depth, idiom) in comments.iter().filter(|(_, idiom)| idiom.start == n) {
+                let indent = "  ".repeat(*depth);
+                let range = if idiom.start == idiom.end {
+                    format!("line {}", idiom.start)
+                }
+                else {
+                    format!("lines {}–{}", idiom.start, idiom.end)
+                };
+                writeln!(f, "{:width$}  # {indent}{range}: {}", "", idiom.effect)?;
+            }
+            let label = match line.get_id() {
+                Some(Identifier::Label(label)) => Some(label),
+                _ => None,
+            };
+            let documentation = label.and_then(|label| self.machine.get_label_documentation(label));
+            for line in documentation.unwrap_or_default().lines() {
+                writeln!(f, "{}", format!("{:width$}  ## {line}", "").trim_end())?;
+            }
+            write!(f, "{n:>width$}  ")?;
+            if let Some(label) = label {
+                write!(f, "{label}: ")?;
+            }
+            writeln!(f, "{}", line.get_instruction())?;
+        }
+        Ok(())
+    }
+}
+
+/// Matches idioms starting at a given line. Each match gives the idiom and the line it exits to,
+/// where the line after the last one means HALT.
+struct Finder<'a> {
+    machine: &'a Machine,
+    zero: Vec<RegisterNumber>,
+}
+
+impl<'a> Finder<'a> {
+    fn new(machine: &'a Machine) -> Finder<'a> {
+        let zero = optimize::always_zero_registers(machine.get_lines());
+        Finder { machine, zero }
+    }
+
+    fn instruction(&self, line: LineNumber) -> Option<&Instruction> {
+        self.machine.get_lines().get(line).map(|line| line.get_instruction())
+    }
+
+    fn resolve(&self, target: &Identifier) -> LineNumber {
+        let halt = self.machine.get_lines().len();
+        match target {
+            Identifier::Line(n) => *n,
+            Identifier::Label(label) => self.machine.get_label_line(label).unwrap_or(halt),
+            Identifier::Halt => halt,
+            Identifier::Relative(_) | Identifier::LabelOffset(..) => unreachable!(),
+        }
+    }
+
+    /// Get the register and target of a DECJZ on a register which isn't always 0.
+    fn branch(&self, line: LineNumber) -> Option<(RegisterNumber, LineNumber)> {
+        match self.instruction(line)? {
+            Instruction::DECJZ(register, target) if !self.zero.contains(register) => {
+                Some((*register, self.resolve(target)))
+            },
+            _ => None,
+        }
+    }
+
+    /// Check whether the line is an unconditional jump to `target`.
+    fn is_goto(&self, line: LineNumber, target: LineNumber) -> bool {
+        match self.instruction(line) {
+            Some(Instruction::DECJZ(register, to)) if self.zero.contains(register) => {
+                self.resolve(to) == target
+            },
+            Some(Instruction::GOTO(to)) => self.resolve(to) == target,
+            _ => false,
+        }
+    }
+
+    /// Check that nothing outside the lines `start..=end` jumps into them, other than to `start`,
+    /// and that execution doesn't start inside them.
+    fn is_only_entered_at_top(&self, start: LineNumber, end: LineNumber) -> bool {
+        let inside = |line: LineNumber| start < line && line <= end;
+        !inside(self.machine.get_entry_line())
+            && self.machine.get_lines().iter().all(|line| {
+                inside(line.get_line_number())
+                    || line.get_line_number() == start
+                    || line
+                        .get_instruction()
+                        .get_jump_target()
+                        .is_none_or(|target| !inside(self.resolve(target)))
+            })
+    }
+
+    /// A drain or move loop starting at `start`.
+    fn simple_loop(&self, start: LineNumber) -> Option<(Idiom, LineNumber)> {
+        let (from, exit) = self.branch(start)?;
+        let mut to: Vec<(RegisterNumber, usize)> = Vec::new();
+        let mut end = start + 1;
+        while let Some(Instruction::INC(register)) = self.instruction(end) {
+            if *register == from {
+                return None;
+            }
+            match to.iter_mut().find(|(added, _)| added == register) {
+                Some((_, times)) => *times += 1,
+                None => to.push((*register, 1)),
+            }
+            end += 1;
+        }
+        if !self.is_goto(end, start) || !self.is_only_entered_at_top(start, end) {
+            return None;
+        }
+        let effect = if to.is_empty() { Effect::Drain(from) } else { Effect::Move { from, to } };
+        Some((Idiom { start, end, effect, parts: Vec::new() }, exit))
+    }
+
+    /// A copy loop starting at `start`: a move into the targets and a scratch register, followed
+    /// by a move from the scratch register back.
+    fn copy(&self, start: LineNumber) -> Option<(Idiom, LineNumber)> {
+        let (first, first_exit) = self.simple_loop(start)?;
+        let Effect::Move { from, to } = &first.effect else { return None };
+        if first_exit != first.end + 1 {
+            return None;
+        }
+        let (second, exit) = self.simple_loop(first_exit)?;
+        let Effect::Move { from: scratch, to: restored } = &second.effect else { return None };
+        if restored.as_slice() != [(*from, 1)] || !to.contains(&(*scratch, 1)) {
+            return None;
+        }
+        let end = second.end;
+        if !self.is_only_entered_at_top(start, end) {
+            return None;
+        }
+        let effect = Effect::Copy {
+            from: *from,
+            to: to.iter().copied().filter(|(register, _)| register != scratch).collect(),
+            scratch: *scratch,
+        };
+        Some((Idiom { start, end, effect, parts: vec![first, second] }, exit))
+    }
+
+    /// Multiplication starting at `start`: a branch on the multiplier, then a copy loop of the
+    /// multiplicand which goes back to the branch.
+    fn multiply(&self, start: LineNumber) -> Option<(Idiom, LineNumber)> {
+        let (multiplier, exit) = self.branch(start)?;
+        let (copy, copy_exit) = self.copy(start + 1)?;
+        let Effect::Copy { from: multiplicand, to, scratch } = &copy.effect else { return None };
+        let end = if copy_exit == start {
+            copy.end
+        }
+        else if copy_exit == copy.end + 1 && self.is_goto(copy_exit, start) {
+            copy_exit
+        }
+        else {
+            return None;
+        };
+        let uses_multiplier = *multiplicand == multiplier
+            || *scratch == multiplier
+            || to.iter().any(|(register, _)| *register == multiplier);
+        if uses_multiplier || !self.is_only_entered_at_top(start, end) {
+            return None;
+        }
+        let effect = Effect::Multiply {
+            multiplier,
+            multiplicand: *multiplicand,
+            to: to.clone(),
+            scratch: *scratch,
+        };
+        Some((Idiom { start, end, effect, parts: vec![copy] }, exit))
+    }
+}
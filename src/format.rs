@@ -0,0 +1,159 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! Formatting source code in one consistent style, for the `fmt` command.
+//!
+//! The formatted program computes the same thing as the original, with the same labels, comments
+//! and documentation. Its style is:
+//!
+//! * Instructions and macro calls are lined up in one column, after the longest label, so that
+//!   the labels stand out on the left.
+//! * Each instruction, directive and registers line is written with single spaces between its
+//!   words, and without spaces at either end.
+//! * Comments stay on their lines. A comment after code is one space after it, and a line which
+//!   is only a comment starts in the first column.
+//! * The bodies of macros are indented by four spaces.
+//! * Runs of blank lines become a single blank line, and the file ends with one line break.
+//!
+//! # Examples
+//! ```
+//! use remuir::parser::format_source;
+//!
+//! let source = "registers  0 3\n\n\nloop: decjz r1   done # Done?\ninc r0\ngoto loop\ndone:halt";
+//! assert_eq!(format_source(source).unwrap(), "registers 0 3
+//!
+//! loop: decjz r1 done # Done?
+//!       inc r0
+//!       goto loop
+//! done: halt
+//! ");
+//! ```
+
+use crate::{
+    ast::{ Item, ItemKind },
+    parser::{ self, ParseSourceError },
+    vecmap::VecMap,
+};
+
+/// How far the bodies of macros are indented.
+const MACRO_INDENT: &str = "    ";
+
+/// Format a program's source code. See the [module documentation](self) for the style.
+///
+/// # Errors
+///
+/// The same as [`parser::parse_ast`], since only source code which parses can be formatted.
+pub fn format_source(input: &str) -> Result<String, ParseSourceError> {
+    let input = input.strip_prefix('\u{FEFF}').unwrap_or(input);
+    let program = parser::parse_ast(input)?;
+    let source_lines: Vec<&str> = input.lines().collect();
+    let mut comments = VecMap::default();
+    for comment in &program.comments {
+        comments.update(comment.line, input[comment.span.clone()].trim_end());
+    }
+    let width = program
+        .get_labels()
+        .iter()
+        .map(|label| label.chars().count() + 2)
+        .max()
+        .unwrap_or(0);
+
+    let mut lines: Vec<String> = Vec::new();
+    // Source lines are counted from 1.
+    let mut next = 1;
+    for item in &program.items {
+        // Lines with only comments, or nothing, before the item.
+        for line in next..item.line {
+            lines.push(comments.get(&line).map_or_else(String::new, ToString::to_string));
+        }
+        let last = item.line + input[item.span.clone()].matches('\n').count();
+        let mut formatted = format_item(item, width, &source_lines[item.line - 1..last]);
+        for (line, text) in (item.line..=last).zip(&mut formatted) {
+            if let Some(comment) = comments.get(&line) {
+                if text.is_empty() {
+                    text.push_str(comment);
+                }
+                else {
+                    *text = format!("{text} {comment}");
+                }
+            }
+        }
+        lines.extend(formatted);
+        next = last + 1;
+    }
+    for line in next..=source_lines.len() {
+        lines.push(comments.get(&line).map_or_else(String::new, ToString::to_string));
+    }
+
+    let mut output = String::new();
+    let mut previous_blank = true;
+    for line in &lines {
+        let blank = line.is_empty();
+        if !(blank && previous_blank) {
+            output.push_str(line);
+            output.push('\n');
+        }
+        previous_blank = blank;
+    }
+    if output.ends_with("\n\n") {
+        output.pop();
+    }
+    Ok(output)
+}
+
+/// Format an item, which is written on the given lines, giving one line for each of them. The
+/// instructions are lined up after `width` characters.
+fn format_item(item: &Item, width: usize, source_lines: &[&str]) -> Vec<String> {
+    let aligned = |label: &Option<String>, rest: String| match label {
+        Some(label) => format!("{:width$}{rest}", format!("{label}: ")),
+        None => format!("{:width$}{rest}", ""),
+    };
+    let line = match &item.kind {
+        ItemKind::Registers(_) => words(source_lines[0]),
+        ItemKind::Instruction { label, instruction } => aligned(label, instruction.to_string()),
+        ItemKind::MacroCall { label, name, arguments } => {
+            let call = std::iter::once(name).chain(arguments).cloned().collect::<Vec<_>>();
+            aligned(label, call.join(" "))
+        },
+        ItemKind::MacroDefinition { name, parameters, .. } => {
+            let header = std::iter::once(name).chain(parameters).cloned().collect::<Vec<_>>();
+            let mut lines = vec![format!("macro {}", header.join(" "))];
+            // The body is kept as written, apart from the indentation, since its lines are only
+            // parsed once the parameters have been filled in.
+            for body_line in &source_lines[1..source_lines.len() - 1] {
+                let code = body_line.split('#').next().unwrap_or_default().trim();
+                let indented =
+                    if code.is_empty() { String::new() } else { format!("{MACRO_INDENT}{code}") };
+                lines.push(indented);
+            }
+            lines.push(String::from("endmacro"));
+            return lines;
+        },
+        ItemKind::Phase(label) => format!("#!phase {label}"),
+        ItemKind::Entry(label) => format!("#!entry {label}"),
+        ItemKind::Export(label) => format!("#!export {label}"),
+        ItemKind::Import(label) => format!("#!import {label}"),
+        ItemKind::Extended => String::from("#!extended"),
+        ItemKind::Requires(requirement) => format!("#!requires {requirement}"),
+    };
+    vec![line]
+}
+
+/// Join the words of a line with single spaces.
+fn words(line: &str) -> String {
+    line.split('#').next().unwrap_or_default().split_whitespace().collect::<Vec<_>>().join(" ")
+}
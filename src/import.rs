@@ -0,0 +1,271 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Importing register machines written as transition tables in CSV.
+//!
+//! Some textbooks give machines as tables, with one row per state:
+//!
+//! ```text
+//! state,instruction,register,next,target
+//! q0,decjz,1,,q2
+//! q1,inc,0,q0,
+//! q2,decjz,2,halt,halt
+//! ```
+//!
+//! The first row names the columns, which can be in any order, and other columns are ignored.
+//! The columns are:
+//!
+//! * `state` - the name of the state, which becomes a label. May be empty.
+//! * `instruction` - `inc` (or `+`), `decjz` (or `dec` or `-`) or `decjnz` (or `jnz`), in any
+//!   case.
+//! * `register` - the register, such as `r1` or just `1`.
+//! * `next` - the state to go to afterwards, or `halt`. If it's empty, or the column is missing,
+//!   the next row is executed next.
+//! * `target` - the state a DECJZ goes to if the register is 0, or a DECJNZ goes to if it isn't,
+//!   or `halt`.
+//!
+//! Different column names can be used with a [`ColumnMapping`]. Since remuir always goes on to the
+//! next line, a `next` state which isn't the next row becomes a jump using a negative register
+//! which the table doesn't use.
+
+use thiserror::Error;
+
+use crate::{
+    memory::{ Memory, RegisterNumber },
+    parser::{ self, ParseSourceError },
+};
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("The table is empty.")]
+    Empty,
+    #[error("The table has no \"{0}\" column.")]
+    MissingColumn(String),
+    #[error("Row {row} has an unknown instruction \"{value}\". Use inc, decjz or decjnz.")]
+    UnknownInstruction { row: usize, value: String },
+    #[error("Row {row} has an invalid register \"{value}\".")]
+    InvalidRegister { row: usize, value: String },
+    #[error("Row {row} is a conditional jump, but has no target.")]
+    MissingTarget { row: usize },
+    #[error("Row {row} goes to the state \"{state}\", which isn't in the table.")]
+    UnknownState { row: usize, state: String },
+    #[error("Row {row} has the state \"{state}\", which is already used or isn't a valid label.")]
+    InvalidState { row: usize, state: String },
+    #[error("Row {row} has an unterminated quote.")]
+    UnterminatedQuote { row: usize },
+    #[error("The imported program is invalid!\n{0}")]
+    InvalidSource(#[from] ParseSourceError),
+}
+
+/// The names of the columns in a table. See the [module documentation](self) for what each one
+/// means.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMapping {
+    pub state: String,
+    pub instruction: String,
+    pub register: String,
+    pub next: String,
+    pub target: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            state: String::from("state"),
+            instruction: String::from("instruction"),
+            register: String::from("register"),
+            next: String::from("next"),
+            target: String::from("target"),
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Rename a column, where `field` is one of "state", "instruction", "register", "next" and
+    /// "target". Returns `false` if there's no such field.
+    pub fn set(&mut self, field: &str, name: &str) -> bool {
+        let column = match field {
+            "state" => &mut self.state,
+            "instruction" => &mut self.instruction,
+            "register" => &mut self.register,
+            "next" => &mut self.next,
+            "target" => &mut self.target,
+            _ => return false,
+        };
+        *column = String::from(name);
+        true
+    }
+}
+
+/// The instruction of a row, without its register or target.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Inc,
+    Decjz,
+    Decjnz,
+}
+
+/// One row of a table, with the row number it came from (counting the header as row 1).
+struct Row {
+    number: usize,
+    state: String,
+    operation: Operation,
+    register: RegisterNumber,
+    next: String,
+    target: String,
+}
+
+/// Convert a CSV transition table into remuir source code, starting with the given registers.
+///
+/// # Errors
+///
+/// * [`ImportError`] - returned when the table is malformed, with the row at fault where there is
+///   one.
+pub fn import_csv(
+    csv: &str,
+    columns: &ColumnMapping,
+    registers: &Memory,
+) -> Result<String, ImportError> {
+    let mut lines = csv
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or(ImportError::Empty)?;
+    let header = split_row(header, 1)?;
+    let find = |name: &str| header.iter().position(|column| column.eq_ignore_ascii_case(name));
+    let require = |name: &str| {
+        find(name).ok_or_else(|| ImportError::MissingColumn(name.to_string()))
+    };
+    let state_column = find(&columns.state);
+    let instruction_column = require(&columns.instruction)?;
+    let register_column = require(&columns.register)?;
+    let next_column = find(&columns.next);
+    let target_column = find(&columns.target);
+
+    let mut rows: Vec<Row> = Vec::new();
+    for (number, line) in lines {
+        let fields = split_row(line, number)?;
+        let field = |column: Option<usize>| {
+            column.and_then(|i| fields.get(i)).map_or("", |field| field.trim()).to_string()
+        };
+        let instruction = field(Some(instruction_column));
+        let operation = match instruction.to_lowercase().as_str() {
+            "inc" | "+" => Operation::Inc,
+            "decjz" | "dec" | "-" => Operation::Decjz,
+            "decjnz" | "jnz" => Operation::Decjnz,
+            _ => return Err(ImportError::UnknownInstruction { row: number, value: instruction }),
+        };
+        let register = field(Some(register_column));
+        let register = if register.starts_with('r') {
+            register.parse::<RegisterNumber>()
+        }
+        else {
+            format!("r{register}").parse::<RegisterNumber>()
+        }
+        .map_err(|_| ImportError::InvalidRegister { row: number, value: register })?;
+        let row = Row {
+            number,
+            state: field(state_column),
+            operation,
+            register,
+            next: field(next_column),
+            target: field(target_column),
+        };
+        let is_valid_label = !row.state.eq_ignore_ascii_case("halt")
+            && !row.state.contains(':')
+            && parser::invalid_label_character(&row.state).is_none();
+        if !row.state.is_empty()
+            && (!is_valid_label || rows.iter().any(|other| other.state == row.state))
+        {
+            return Err(ImportError::InvalidState { row: number, state: row.state });
+        }
+        if row.operation != Operation::Inc && row.target.is_empty() {
+            return Err(ImportError::MissingTarget { row: number });
+        }
+        rows.push(row);
+    }
+    if rows.is_empty() {
+        return Err(ImportError::Empty);
+    }
+
+    for row in &rows {
+        for state in [&row.next, &row.target] {
+            let known = state.is_empty()
+                || state.eq_ignore_ascii_case("halt")
+                || rows.iter().any(|other| &other.state == state);
+            if !known {
+                return Err(ImportError::UnknownState { row: row.number, state: state.clone() });
+            }
+        }
+    }
+
+    // Jumps to a row other than the next one use a register which is never incremented.
+    let mut goto = 1;
+    while rows.iter().any(|row| row.register == RegisterNumber::Negative(goto)) {
+        goto += 1;
+    }
+    let mut source = format!("{registers}\n");
+    for (i, row) in rows.iter().enumerate() {
+        if !row.state.is_empty() {
+            source.push_str(&format!("{}: ", row.state));
+        }
+        let target = identifier(&row.target);
+        match row.operation {
+            Operation::Inc => source.push_str(&format!("inc {}\n", row.register)),
+            Operation::Decjz => source.push_str(&format!("decjz {} {target}\n", row.register)),
+            Operation::Decjnz => source.push_str(&format!("decjnz {} {target}\n", row.register)),
+        }
+        let next_state = rows.get(i + 1).map(|next| next.state.as_str());
+        let falls_through = row.next.is_empty()
+            || Some(row.next.as_str()) == next_state
+            || (next_state.is_none() && row.next.eq_ignore_ascii_case("halt"));
+        if !falls_through {
+            source.push_str(&format!("decjz r-{goto} {}\n", identifier(&row.next)));
+        }
+    }
+    parser::parse_str(&source)?;
+    Ok(source)
+}
+
+/// Write a state as a label to jump to.
+fn identifier(state: &str) -> &str {
+    if state.eq_ignore_ascii_case("halt") { "HALT" } else { state }
+}
+
+/// Split a row of a CSV file into its fields. Fields may be quoted, and quotes in quoted fields
+/// are doubled.
+fn split_row(line: &str, row: usize) -> Result<Vec<String>, ImportError> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().expect("there is always a field");
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(ImportError::UnterminatedQuote { row });
+    }
+    Ok(fields)
+}
@@ -25,10 +25,31 @@ use crate::{
 #[derive(Debug, PartialEq, Clone)]
 pub enum Instruction {
     INC(RegisterNumber),
-    DECJZ(RegisterNumber, Identifier)
+    DECJZ(RegisterNumber, Identifier),
+    /// Decrement the register and jump if it isn't 0, or go on to the next line if it is. This is
+    /// the other way around to DECJZ, as some courses define it.
+    DECJNZ(RegisterNumber, Identifier),
+    /// Jump to the label unconditionally, without needing a register which is always 0.
+    GOTO(Identifier),
+    /// Jump to the label like GOTO, and remember the next line so that RETURN goes back to it.
+    CALL(Identifier),
+    /// Go back to the line after the most recent CALL which hasn't returned yet, or halt if every
+    /// CALL has returned.
+    RETURN,
+    /// Set the register to the next value of the machine's input (see [`crate::io_handler`]).
+    READ(RegisterNumber),
+    /// Output the value of the register, leaving it unchanged.
+    WRITE(RegisterNumber),
+    /// Stop the machine, like jumping to `HALT` with a register which is always 0.
+    HALT,
 }
 
 impl Instruction {
+    /// Execute the instruction on the memory, and return where it jumps to, if anywhere.
+    ///
+    /// The call stack is kept by [`crate::machine::Machine`], so on its own, RETURN halts as if
+    /// there were nothing to return to. So is the input and output, so on their own, READ and WRITE
+    /// do nothing.
     pub fn execute(&self, memory: &mut Memory) -> Option<Identifier> {
         match self {
             Instruction::INC(register) => {
@@ -40,9 +61,69 @@ impl Instruction {
                 }
                 memory.dec(*register);
             },
+            Instruction::DECJNZ(register, ident_to_jump_to) => {
+                if !memory.is_zero(*register) {
+                    memory.dec(*register);
+                    return Some(ident_to_jump_to.clone());
+                }
+            },
+            Instruction::GOTO(ident_to_jump_to) | Instruction::CALL(ident_to_jump_to) => {
+                return Some(ident_to_jump_to.clone());
+            },
+            Instruction::RETURN | Instruction::HALT => return Some(Identifier::Halt),
+            Instruction::READ(_) | Instruction::WRITE(_) => (),
         }
         None
     }
+
+    /// Get the register which this instruction reads or modifies, if it uses one.
+    #[must_use]
+    pub const fn get_register(&self) -> Option<RegisterNumber> {
+        match self {
+            Instruction::INC(register)
+            | Instruction::DECJZ(register, _)
+            | Instruction::DECJNZ(register, _)
+            | Instruction::READ(register)
+            | Instruction::WRITE(register) => Some(*register),
+            Instruction::GOTO(_)
+            | Instruction::CALL(_)
+            | Instruction::RETURN
+            | Instruction::HALT => None,
+        }
+    }
+
+    /// Get the identifier which this instruction may jump to, if it can jump at all.
+    ///
+    /// Where RETURN goes is only known while running, so it's treated like HALT, as neither goes
+    /// on to the next line.
+    #[must_use]
+    pub const fn get_jump_target(&self) -> Option<&Identifier> {
+        match self {
+            Instruction::INC(_) | Instruction::READ(_) | Instruction::WRITE(_) => None,
+            Instruction::DECJZ(_, ident)
+            | Instruction::DECJNZ(_, ident)
+            | Instruction::GOTO(ident)
+            | Instruction::CALL(ident) => Some(ident),
+            Instruction::RETURN | Instruction::HALT => Some(&Identifier::Halt),
+        }
+    }
+
+    /// Get a copy of this instruction which jumps to `target` instead. Instructions which can't
+    /// jump anywhere but HALT are copied unchanged.
+    #[must_use]
+    pub fn with_jump_target(&self, target: Identifier) -> Instruction {
+        match self {
+            Instruction::DECJZ(register, _) => Instruction::DECJZ(*register, target),
+            Instruction::DECJNZ(register, _) => Instruction::DECJNZ(*register, target),
+            Instruction::GOTO(_) => Instruction::GOTO(target),
+            Instruction::CALL(_) => Instruction::CALL(target),
+            Instruction::INC(_)
+            | Instruction::READ(_)
+            | Instruction::WRITE(_)
+            | Instruction::RETURN
+            | Instruction::HALT => self.clone(),
+        }
+    }
 }
 
 impl Display for Instruction {
@@ -50,6 +131,13 @@ impl Display for Instruction {
         match self {
             Self::INC(num) => write!(f, "inc {num}"),
             Self::DECJZ(num, id) => write!(f, "decjz {num} {id}"),
+            Self::DECJNZ(num, id) => write!(f, "decjnz {num} {id}"),
+            Self::GOTO(id) => write!(f, "goto {id}"),
+            Self::CALL(id) => write!(f, "call {id}"),
+            Self::RETURN => write!(f, "return"),
+            Self::READ(num) => write!(f, "read {num}"),
+            Self::WRITE(num) => write!(f, "write {num}"),
+            Self::HALT => write!(f, "halt"),
         }
     }
 }
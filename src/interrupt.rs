@@ -0,0 +1,55 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Stopping a long run with Ctrl+C without losing the machine's state.
+//!
+//! Once [`install_handler`] has been called, Ctrl+C no longer ends remuir. Instead, it sets a flag
+//! which [`Machine::debug`](crate::machine::Machine::debug) checks before every step, stopping
+//! with [`TerminationReason::Interrupted`](crate::machine::TerminationReason::Interrupted) so
+//! that the debugger gets control back. The flag can also be set with [`request`], such as by a
+//! front end with its own way of interrupting.
+//!
+//! Since this flag is shared by the whole process, an embedder running several machines at once
+//! can instead give each run its own flag with
+//! [`Machine::run_with_cancel`](crate::machine::Machine::run_with_cancel).
+
+use std::sync::{ atomic::{ AtomicBool, Ordering }, OnceLock };
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Ask the running machine to stop at the next step.
+pub fn request() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Check whether an interrupt was requested, clearing the request.
+pub fn take() -> bool {
+    // Loading first keeps the check cheap when there's no request, as there usually isn't.
+    INTERRUPTED.load(Ordering::Relaxed) && INTERRUPTED.swap(false, Ordering::Relaxed)
+}
+
+/// Forget any interrupt which was requested before now, such as Ctrl+C pressed at a prompt.
+pub fn clear() {
+    INTERRUPTED.store(false, Ordering::Relaxed);
+}
+
+/// Make Ctrl+C (SIGINT on Unix) request an interrupt instead of ending the process. Returns
+/// whether the handler is installed, which it can't be if something else in the process already
+/// handles Ctrl+C. Installing it again does nothing.
+pub fn install_handler() -> bool {
+    static INSTALLED: OnceLock<bool> = OnceLock::new();
+    *INSTALLED.get_or_init(|| ctrlc::set_handler(request).is_ok())
+}
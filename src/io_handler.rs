@@ -0,0 +1,218 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Input and output for the `read` and `write` instructions.
+//!
+//! A [`crate::machine::Machine`] carries them out through an [`IoHandler`]. By default, this is
+//! [`StdIo`], which reads one natural number per line from STDIN and writes one per line to
+//! STDERR, since STDOUT is kept for the final registers. [`LineIo`] reads from somewhere else,
+//! such as a file, when STDIN holds the program or the commands of a debug session. Frontends
+//! without a terminal, and tests, can give the machine a [`BufferIo`] instead with
+//! [`crate::machine::Machine::set_io_handler`]:
+//!
+//! ```
+//! use remuir::{ io_handler::BufferIo, memory::Register, parser::parse_str };
+//!
+//! let mut machine = parse_str("registers 0\nread r1\ninc r1\nwrite r1").unwrap();
+//! let io = BufferIo::new([Register::from(4)]);
+//! machine.set_io_handler(io.clone());
+//! machine.run();
+//! assert_eq!(io.get_output(), [Register::from(5)]);
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    io::{ self, BufRead, Write },
+    sync::{ Arc, Mutex, MutexGuard, PoisonError },
+};
+
+use thiserror::Error;
+
+use crate::memory::Register;
+
+/// Where `read` instructions get their values from and `write` instructions send them to.
+pub trait IoHandler: Debug + Send {
+    /// Get the value for a `read` instruction.
+    ///
+    /// # Errors
+    ///
+    /// * [`IoError::EndOfInput`] - returned when there's nothing left to read.
+    /// * [`IoError::InvalidInput`] - returned when the input isn't a natural number.
+    /// * [`IoError::Failed`] - returned when reading fails.
+    fn read(&mut self) -> Result<Register, IoError>;
+
+    /// Output the value of a `write` instruction.
+    ///
+    /// # Errors
+    ///
+    /// * [`IoError::Failed`] - returned when writing fails.
+    fn write(&mut self, value: &Register) -> Result<(), IoError>;
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum IoError {
+    #[error("There was nothing left to read.")]
+    EndOfInput,
+    #[error("Couldn't read {0:?} as a natural number.")]
+    InvalidInput(String),
+    #[error("Input or output failed: {0}")]
+    Failed(String),
+}
+
+impl From<io::Error> for IoError {
+    fn from(value: io::Error) -> Self {
+        IoError::Failed(value.to_string())
+    }
+}
+
+/// Reads from STDIN and writes to STDERR, one natural number per line. Only the final registers
+/// may be printed to STDOUT, so that the output of a run can be piped into another program.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdIo;
+
+impl IoHandler for StdIo {
+    fn read(&mut self) -> Result<Register, IoError> {
+        read_line(&mut io::stdin().lock())
+    }
+
+    fn write(&mut self, value: &Register) -> Result<(), IoError> {
+        writeln!(io::stderr(), "{value}")?;
+        Ok(())
+    }
+}
+
+/// Reads one natural number per line from any reader, and writes one per line to STDERR, like
+/// [`StdIo`].
+#[derive(Debug)]
+pub struct LineIo<R> {
+    input: R,
+}
+
+impl<R: BufRead> LineIo<R> {
+    #[must_use]
+    pub const fn new(input: R) -> LineIo<R> {
+        LineIo { input }
+    }
+}
+
+impl<R: BufRead + Debug + Send> IoHandler for LineIo<R> {
+    fn read(&mut self) -> Result<Register, IoError> {
+        read_line(&mut self.input)
+    }
+
+    fn write(&mut self, value: &Register) -> Result<(), IoError> {
+        StdIo.write(value)
+    }
+}
+
+/// Read a natural number from the next line of `input`.
+fn read_line(input: &mut impl BufRead) -> Result<Register, IoError> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(IoError::EndOfInput);
+    }
+    let line = line.trim();
+    line.parse::<u128>()
+        .map(Register::from)
+        .map_err(|_| IoError::InvalidInput(line.to_string()))
+}
+
+/// Reads from and writes to buffers in memory. Clones share the same buffers, so one can be kept
+/// to look at the output after giving another to a machine.
+#[derive(Debug, Default, Clone)]
+pub struct BufferIo(Arc<Mutex<Buffers>>);
+
+#[derive(Debug, Default)]
+struct Buffers {
+    input: VecDeque<Register>,
+    output: Vec<Register>,
+}
+
+impl BufferIo {
+    /// Make buffers which give the given values to `read` instructions, in order.
+    #[must_use]
+    pub fn new(input: impl IntoIterator<Item = Register>) -> BufferIo {
+        let buffers = Buffers { input: input.into_iter().collect(), output: Vec::new() };
+        BufferIo(Arc::new(Mutex::new(buffers)))
+    }
+
+    /// Add a value to the end of the input.
+    pub fn push_input(&self, value: Register) {
+        self.lock().input.push_back(value);
+    }
+
+    /// Get the values which have been written so far, oldest first.
+    #[must_use]
+    pub fn get_output(&self) -> Vec<Register> {
+        self.lock().output.clone()
+    }
+
+    /// Get the values which have been written since this was last called, oldest first, and
+    /// forget them.
+    #[must_use]
+    pub fn take_output(&self) -> Vec<Register> {
+        std::mem::take(&mut self.lock().output)
+    }
+
+    /// Get the values which are still to be read.
+    #[must_use]
+    pub fn get_remaining_input(&self) -> Vec<Register> {
+        self.lock().input.iter().cloned().collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Buffers> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl IoHandler for BufferIo {
+    fn read(&mut self) -> Result<Register, IoError> {
+        self.lock().input.pop_front().ok_or(IoError::EndOfInput)
+    }
+
+    fn write(&mut self, value: &Register) -> Result<(), IoError> {
+        self.lock().output.push(value.clone());
+        Ok(())
+    }
+}
+
+/// The handler of a machine. Copies of a machine share their handler, and the handler doesn't
+/// count towards whether two machines are equal, since it isn't part of the program.
+#[derive(Debug, Clone)]
+pub(crate) struct SharedHandler(Arc<Mutex<dyn IoHandler>>);
+
+impl SharedHandler {
+    pub(crate) fn new(handler: impl IoHandler + 'static) -> SharedHandler {
+        SharedHandler(Arc::new(Mutex::new(handler)))
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, dyn IoHandler + 'static> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for SharedHandler {
+    fn default() -> Self {
+        SharedHandler::new(StdIo)
+    }
+}
+
+impl PartialEq for SharedHandler {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
@@ -14,8 +14,56 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 
+pub mod analysis;
+pub mod ast;
+pub mod autosave;
+pub mod bytecode;
+pub mod cache;
+pub mod command;
+pub mod compare;
+pub mod concurrent;
+pub mod cost;
+pub mod crash;
+pub mod dialect;
+pub mod diff;
+pub mod doctor;
+#[cfg(feature = "emit")]
+pub mod emit;
+pub mod examples;
+pub mod explain;
+pub mod format;
+pub mod link;
+pub mod lint;
+pub mod listing;
+pub mod lockstep;
 pub mod machine;
+pub mod manifest;
 pub mod memory;
+pub mod metrics;
+pub mod obfuscate;
 pub mod parser;
+pub mod pressure;
+pub mod requirement;
 pub mod vecmap;
+pub mod import;
 pub mod instruction;
+pub mod interrupt;
+pub mod io_handler;
+pub mod optimize;
+pub mod roundtrip;
+pub mod rpc;
+pub mod runaway;
+pub mod sandbox;
+pub mod slice;
+pub mod specialize;
+pub mod suggest;
+pub mod test_block;
+pub mod testing;
+pub mod text_literals;
+pub mod timeline;
+pub mod trace;
+pub mod transcript;
+pub mod tui;
+pub mod urm;
+pub mod watch;
+pub mod xref;
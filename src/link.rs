@@ -0,0 +1,207 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Linking several programs into one, with the labels of each library kept in its own scope.
+//!
+//! The main program keeps its labels as they are, and each library's labels are renamed to
+//! `library::label`, so library code can't collide with labels of the main program or of other
+//! libraries. Inside a library, an unqualified reference such as `loop` means the library's own
+//! `loop` if it has one. Any program may jump into a library with a qualified reference such as
+//! `library::loop`. The libraries are placed after the main program, so execution starts at the
+//! first line of the main program, and the initial registers are those of the main program.
+//!
+//! Programs can say which of their labels are meant to be used by others. Once a program has an
+//! `#!export LABEL` directive, other programs can only jump to the labels it exports. Once a
+//! program has an `#!import LABEL` directive, such as `#!import maths::add`, it can only jump to
+//! the labels of other programs which it imports, and each import must be a label another program
+//! exports. Programs without these directives can use and be used freely.
+
+use thiserror::Error;
+
+use crate::{
+    machine::{ Identifier, Line, LineNumber, Machine },
+    parser,
+};
+
+/// The separator between a scope and a label, as in `library::label`.
+pub const SCOPE_SEPARATOR: &str = "::";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LinkError {
+    #[error("Library name {0:?} is invalid. It must be a label without \"::\", such as \"maths\".")]
+    InvalidLibraryName(String),
+    #[error("Label {label:?} is defined more than once.")]
+    DuplicateLabel { label: String },
+    #[error("Cannot find label {label:?}, which line {line} of {scope} jumps to.")]
+    LabelNotFound { label: String, scope: String, line: LineNumber },
+    #[error("Label {label:?} is used by {scope}, but isn't exported by the file which defines it.")]
+    NotExported { label: String, scope: String },
+    #[error("Line {line} of {scope} jumps to {label:?}, which isn't imported. Add \"#!import {label}\" to allow this.")]
+    NotImported { label: String, scope: String, line: LineNumber },
+    #[error("Label {label:?} is imported by {scope}, but no file defines it.")]
+    ImportNotFound { label: String, scope: String },
+}
+
+/// Qualify a label with the given scope.
+#[must_use]
+pub fn qualify(scope: &str, label: &str) -> String {
+    format!("{scope}{SCOPE_SEPARATOR}{label}")
+}
+
+/// Link a main program with some named libraries, giving one machine.
+///
+/// # Errors
+///
+/// * [`LinkError::InvalidLibraryName`] - returned when a library's name couldn't be used as a
+///   scope.
+/// * [`LinkError::DuplicateLabel`] - returned when two labels would have the same name once
+///   scoped, such as when the main program defines `library::loop` itself.
+/// * [`LinkError::LabelNotFound`] - returned when a reference doesn't resolve to any label.
+/// * [`LinkError::NotExported`] - returned when a program uses a label of another program which
+///   has exports, but not that label.
+/// * [`LinkError::NotImported`] - returned when a program with imports jumps to a label of another
+///   program which it doesn't import.
+/// * [`LinkError::ImportNotFound`] - returned when an imported label isn't defined.
+pub fn link(main: &Machine, libraries: &[(String, Machine)]) -> Result<Machine, LinkError> {
+    for (name, _) in libraries {
+        if name.contains(':') || parser::invalid_label_character(name).is_some() {
+            return Err(LinkError::InvalidLibraryName(name.clone()));
+        }
+    }
+
+    let modules = std::iter::once((None, main))
+        .chain(libraries.iter().map(|(name, machine)| (Some(name.as_str()), machine)));
+
+    // First pass: give every line its scoped label and new line number.
+    let mut lines: Vec<Line> = Vec::new();
+    let mut offsets: Vec<LineNumber> = Vec::new();
+    let mut defined: Vec<String> = Vec::new();
+    // The index of the module which defines each label.
+    let mut owners: Vec<usize> = Vec::new();
+    for (module, (scope, machine)) in modules.clone().enumerate() {
+        offsets.push(lines.len());
+        for line in machine.get_lines() {
+            let id = match line.get_id() {
+                Some(Identifier::Label(label)) => {
+                    let label = scope.map_or_else(|| label.clone(), |scope| qualify(scope, label));
+                    if defined.contains(&label) {
+                        return Err(LinkError::DuplicateLabel { label });
+                    }
+                    defined.push(label.clone());
+                    owners.push(module);
+                    Some(Identifier::Label(label))
+                },
+                _ => None,
+            };
+            lines.push(Line::new(lines.len(), id, line.get_instruction().clone()));
+        }
+    }
+
+    // The labels each module exports, scoped like the labels they name.
+    let exports: Vec<Vec<String>> = modules
+        .clone()
+        .map(|(scope, machine)| {
+            let exports = machine.get_exports().iter();
+            exports.map(|label| scope.map_or_else(|| label.clone(), |s| qualify(s, label)))
+                .collect()
+        })
+        .collect();
+    let owner = |label: &str| defined.iter().position(|d| d == label).map(|i| owners[i]);
+    // Check that a label which `module` uses is exported by the module which defines it.
+    let is_exported = |owner: usize, label: &str| {
+        exports[owner].is_empty() || exports[owner].iter().any(|export| export == label)
+    };
+    let check_export = |label: &str, module: usize, scope: &str| match owner(label) {
+        Some(owner) if owner != module && !is_exported(owner, label) => {
+            let (label, scope) = (label.to_string(), scope.to_string());
+            Err(LinkError::NotExported { label, scope })
+        },
+        _ => Ok(()),
+    };
+    for (module, (scope, machine)) in modules.clone().enumerate() {
+        let scope = scope.unwrap_or("the main program");
+        for label in machine.get_imports() {
+            if owner(label).is_none() {
+                let (label, scope) = (label.clone(), scope.to_string());
+                return Err(LinkError::ImportNotFound { label, scope });
+            }
+            check_export(label, module, scope)?;
+        }
+    }
+
+    // Second pass: resolve every jump target in its module's scope.
+    for (module, ((scope, machine), offset)) in modules.clone().zip(&offsets).enumerate() {
+        for line in machine.get_lines() {
+            let Some(target) = line.get_instruction().get_jump_target() else { continue };
+            let target = match target {
+                Identifier::Label(label) => {
+                    let scoped = scope
+                        .map(|scope| qualify(scope, label))
+                        .filter(|scoped| defined.contains(scoped));
+                    let name = scope.unwrap_or("the main program");
+                    match scoped {
+                        Some(scoped) => Identifier::Label(scoped),
+                        None if defined.contains(label) => {
+                            check_export(label, module, name)?;
+                            let imports = machine.get_imports();
+                            let imported = imports.is_empty() || imports.contains(label);
+                            if owner(label) != Some(module) && !imported {
+                                return Err(LinkError::NotImported {
+                                    label: label.clone(),
+                                    scope: name.to_string(),
+                                    line: line.get_line_number(),
+                                });
+                            }
+                            Identifier::Label(label.clone())
+                        },
+                        None => return Err(LinkError::LabelNotFound {
+                            label: label.clone(),
+                            scope: name.to_string(),
+                            line: line.get_line_number(),
+                        }),
+                    }
+                },
+                Identifier::Line(n) if *n >= machine.get_lines().len() => Identifier::Halt,
+                Identifier::Line(n) => Identifier::Line(n + offset),
+                Identifier::Halt => Identifier::Halt,
+                Identifier::Relative(_) | Identifier::LabelOffset(..) => unreachable!(),
+            };
+            let n = line.get_line_number() + offset;
+            let id = lines[n].get_id().cloned();
+            lines[n] = Line::new(n, id, line.get_instruction().with_jump_target(target));
+        }
+    }
+
+    let mut linked = Machine::new_from_lines(&lines, main.get_initial_memory().clone());
+    for (scope, machine) in modules {
+        for phase in machine.get_phases() {
+            let phase = scope.map_or_else(|| phase.clone(), |scope| qualify(scope, phase));
+            linked.add_phase(phase).expect("Every phase label was linked.");
+        }
+        for (label, _) in machine.get_labels() {
+            if let Some(documentation) = machine.get_label_documentation(label) {
+                let label = scope.map_or_else(|| label.clone(), |scope| qualify(scope, label));
+                linked
+                    .set_label_documentation(label, documentation.to_string())
+                    .expect("Every label was linked.");
+            }
+        }
+    }
+    if let Some(entry) = main.get_entry() {
+        linked.set_entry(entry.to_string()).expect("Every label of the main program was linked.");
+    }
+    Ok(linked)
+}
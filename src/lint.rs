@@ -0,0 +1,189 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Warnings about programs which parse but are probably wrong, for the `check` command.
+//!
+//! The warnings are:
+//!
+//! * An *unused label*, which nothing jumps to, and which isn't the entry point, a phase or an
+//!   export.
+//! * *Unreachable lines*, which can never be executed starting from the entry point. Registers
+//!   which are always 0 are taken into account, so the line after `decjz r-1 L` is unreachable
+//!   unless something jumps to it.
+//! * A register which is *decremented but never incremented* or read into, so it's always 0.
+//!   Registers given by the registers line are left out, since they're inputs, and so are negative
+//!   registers which are only used by DECJZ, since that's the usual way of writing a GOTO.
+
+use thiserror::Error;
+
+use crate::{
+    instruction::Instruction,
+    machine::{ Identifier, LineNumber, Machine },
+    memory::RegisterNumber,
+    optimize,
+    slice,
+};
+
+/// A warning about a program. Its lines are lines of source code, counting from 1 (see
+/// [`Machine::get_source_line`]).
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum Warning {
+    #[error("The label {label:?} on line {line} is never jumped to.")]
+    UnusedLabel { label: String, line: LineNumber },
+    #[error("{} can never be reached.", lines_text(*.first, *.last))]
+    Unreachable { first: LineNumber, last: LineNumber },
+    #[error(
+        "Register {register} is decremented on line {line} but never incremented, so it's always 0."
+    )]
+    NeverIncremented { register: RegisterNumber, line: LineNumber },
+}
+
+/// Describe a range of lines.
+fn lines_text(first: LineNumber, last: LineNumber) -> String {
+    if first == last {
+        format!("Line {first}")
+    }
+    else {
+        format!("Lines {first} to {last}")
+    }
+}
+
+/// Find everything in a machine's program which is probably a mistake, in the order of the lines
+/// it's on.
+///
+/// # Examples
+/// ```
+/// use remuir::{ lint::{ lint, Warning }, parser::parse_str };
+///
+/// let machine = parse_str("registers 0 2\nloop: decjz r1 done\ngoto done\ninc r0\ndone: halt")
+///     .unwrap();
+/// assert_eq!(lint(&machine), vec![
+///     Warning::UnusedLabel { label: String::from("loop"), line: 2 },
+///     Warning::Unreachable { first: 4, last: 4 },
+/// ]);
+/// ```
+#[must_use]
+pub fn lint(machine: &Machine) -> Vec<Warning> {
+    let mut warnings: Vec<(LineNumber, Warning)> = Vec::new();
+    warnings.extend(unused_labels(machine).into_iter().map(|w| (warning_line(&w), w)));
+    warnings.extend(unreachable_lines(machine).into_iter().map(|w| (warning_line(&w), w)));
+    warnings.extend(never_incremented(machine).into_iter().map(|w| (warning_line(&w), w)));
+    // Sorting is stable, so warnings on the same line keep the order above.
+    warnings.sort_by_key(|(line, _)| *line);
+    warnings.into_iter().map(|(_, warning)| warning).collect()
+}
+
+/// Get the line a warning is about, or the first one if there's more than one.
+const fn warning_line(warning: &Warning) -> LineNumber {
+    match warning {
+        Warning::UnusedLabel { line, .. } | Warning::NeverIncremented { line, .. } => *line,
+        Warning::Unreachable { first, .. } => *first,
+    }
+}
+
+fn unused_labels(machine: &Machine) -> Vec<Warning> {
+    let xref = machine.get_cross_reference();
+    machine
+        .get_lines()
+        .iter()
+        .filter_map(|line| match line.get_id() {
+            Some(id @ Identifier::Label(label))
+                if xref.jumps_to(id).is_empty()
+                    && xref.jumps_after(id).is_empty()
+                    && machine.get_entry() != Some(label.as_str())
+                    && !machine.get_phases().contains(label)
+                    && !machine.get_exports().contains(label) =>
+            {
+                let line = machine.get_source_line(line.get_line_number());
+                Some(Warning::UnusedLabel { label: label.clone(), line })
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn unreachable_lines(machine: &Machine) -> Vec<Warning> {
+    let line_count = machine.get_lines().len();
+    if line_count == 0 {
+        return Vec::new();
+    }
+    let zero = optimize::always_zero_registers(machine.get_lines());
+    let successors = slice::successors(machine, &zero);
+    let mut reached = vec![false; line_count];
+    let mut to_visit = vec![machine.get_entry_line()];
+    while let Some(n) = to_visit.pop() {
+        if n >= line_count || reached[n] {
+            continue;
+        }
+        reached[n] = true;
+        to_visit.extend(&successors[n]);
+    }
+    let mut warnings = Vec::new();
+    let mut n = 0;
+    while n < line_count {
+        if reached[n] {
+            n += 1;
+            continue;
+        }
+        let first = n;
+        while n < line_count && !reached[n] {
+            n += 1;
+        }
+        warnings.push(Warning::Unreachable {
+            first: machine.get_source_line(first),
+            last: machine.get_source_line(n - 1),
+        });
+    }
+    warnings
+}
+
+fn never_incremented(machine: &Machine) -> Vec<Warning> {
+    let lines = machine.get_lines();
+    let inputs = machine.get_initial_memory().get_nat_count();
+    let mut warnings: Vec<Warning> = Vec::new();
+    for line in lines {
+        let (Instruction::DECJZ(register, _) | Instruction::DECJNZ(register, _)) =
+            line.get_instruction()
+        else {
+            continue;
+        };
+        let register = *register;
+        let is_input = matches!(register, RegisterNumber::Natural(n) if n < inputs);
+        let is_goto = matches!(register, RegisterNumber::Negative(_))
+            && lines.iter().all(|l| match l.get_instruction() {
+                Instruction::DECJZ(..) => true,
+                instruction => instruction.get_register() != Some(register),
+            });
+        let is_incremented = lines.iter().any(|l| match l.get_instruction() {
+            Instruction::INC(r) | Instruction::READ(r) => *r == register,
+            _ => false,
+        });
+        let is_reported = warnings.iter().any(|warning| {
+            matches!(warning, Warning::NeverIncremented { register: r, .. } if *r == register)
+        });
+        if is_input
+            || is_goto
+            || is_incremented
+            || is_reported
+            || matches!(register, RegisterNumber::Pseudo(_))
+        {
+            continue;
+        }
+        let line = machine.get_source_line(line.get_line_number());
+        warnings.push(Warning::NeverIncremented { register, line });
+    }
+    warnings
+}
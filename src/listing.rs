@@ -0,0 +1,96 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! Rendering a program as an annotated listing.
+//!
+//! Each line is shown with its line number and label, and marked with `=>` if the instruction
+//! pointer points to it and `*` if it has a breakpoint. For example:
+//!
+//! ```text
+//!    0  loop: decjz r1 HALT
+//! =>*1  decjz r0 HALT
+//!    2  decjz r2 loop
+//! ```
+//!
+//! Jumps to a line number, which come from relative jumps such as `+2`, are shown as a number of
+//! lines after the nearest label above the line, such as `loop+2`, where there is one.
+
+use std::fmt::Display;
+
+use crate::{
+    instruction::Instruction,
+    machine::{ Identifier, Machine },
+};
+
+/// An annotated listing of a machine's program, which is rendered using [`Display`].
+pub struct Listing<'a> {
+    machine: &'a Machine,
+}
+
+impl<'a> Listing<'a> {
+    #[must_use]
+    pub const fn new(machine: &'a Machine) -> Listing<'a> {
+        Listing { machine }
+    }
+}
+
+impl Display for Listing<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines = self.machine.get_lines();
+        let current = self.machine.get_current_line_number();
+        let width = lines.len().saturating_sub(1).to_string().len();
+        for line in lines {
+            let n = line.get_line_number();
+            let arrow = if n == current { "=>" } else { "  " };
+            let breakpoint = if self.machine.get_breakpoints().contains(&n) { "*" } else { " " };
+            write!(f, "{arrow}{breakpoint}{n:>width$}  ")?;
+            if let Some(Identifier::Label(label)) = line.get_id() {
+                write!(f, "{label}: ")?;
+            }
+            writeln!(f, "{}", labelled_instruction(self.machine, line.get_instruction()))?;
+        }
+        if self.machine.is_halted() {
+            writeln!(f, "=> HALT")?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the instruction with a jump to a line number written as a number of lines after the nearest
+/// label at or above that line, if there is one.
+fn labelled_instruction(machine: &Machine, instruction: &Instruction) -> Instruction {
+    let Some(&Identifier::Line(target)) = instruction.get_jump_target() else {
+        return instruction.clone();
+    };
+    let lines = machine.get_lines();
+    let labelled = lines[..lines.len().min(target + 1)]
+        .iter()
+        .rev()
+        .find_map(|line| match line.get_id() {
+            Some(Identifier::Label(label)) => Some((label, line.get_line_number())),
+            _ => None,
+        });
+    match labelled {
+        Some((label, line)) if line == target => {
+            instruction.with_jump_target(Identifier::Label(label.clone()))
+        },
+        Some((label, line)) => {
+            instruction.with_jump_target(Identifier::LabelOffset(label.clone(), target - line))
+        },
+        None => instruction.clone(),
+    }
+}
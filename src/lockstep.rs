@@ -0,0 +1,102 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Running two machines side by side to find where their behaviour first differs.
+//!
+//! This is used to check a transformed program, such as one from [`crate::optimize`], against the
+//! original. Only what a user could observe is compared: the natural registers, and whether each
+//! machine has stopped. Line numbers and negative registers may differ freely.
+
+use std::fmt::Display;
+
+use crate::{
+    machine::{ Machine, TerminationReason },
+    memory::{ Register, RegisterNumber },
+};
+
+/// How two machines compared when stepped together.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lockstep {
+    /// Both machines stopped after the same number of steps, with the same registers.
+    Agreed { steps: usize },
+    /// Neither machine stopped within the step limit, and they never differed.
+    StepLimitReached { steps: usize },
+    /// The machines differed after the given number of steps.
+    Diverged {
+        steps: usize,
+        /// Natural registers which hold different values.
+        registers: Vec<(RegisterNumber, Register, Register)>,
+        /// Whether each machine had stopped.
+        stopped: (bool, bool),
+    },
+}
+
+impl Lockstep {
+    /// Step both machines together from their current state, for at most `max_steps` steps, and
+    /// stop at the first step after which they differ.
+    ///
+    /// A machine stops when it halts, or when it would modify a protected register.
+    pub fn run(left: &mut Machine, right: &mut Machine, max_steps: usize) -> Lockstep {
+        let mut stopped = (left.is_halted(), right.is_halted());
+        for steps in 0..=max_steps {
+            let registers = left.get_state().differing_registers(right.get_state());
+            if !registers.is_empty() || stopped.0 != stopped.1 {
+                return Lockstep::Diverged { steps, registers, stopped };
+            }
+            if stopped.0 && stopped.1 {
+                return Lockstep::Agreed { steps };
+            }
+            if steps == max_steps {
+                break;
+            }
+            stopped = (step(left), step(right));
+        }
+        Lockstep::StepLimitReached { steps: max_steps }
+    }
+
+    /// Check if the machines never differed.
+    #[must_use]
+    pub const fn is_agreement(&self) -> bool {
+        !matches!(self, Lockstep::Diverged { .. })
+    }
+}
+
+/// Take a step, and return whether the machine has stopped. Watchpoints don't stop it.
+fn step(machine: &mut Machine) -> bool {
+    !matches!(machine.step(), Ok(None | Some(TerminationReason::Watchpoint { .. })))
+}
+
+impl Display for Lockstep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = |stopped: bool| if stopped { "stopped" } else { "running" };
+        match self {
+            Lockstep::Agreed { steps } => writeln!(f, "Both machines stopped after {steps} steps."),
+            Lockstep::StepLimitReached { steps } => {
+                writeln!(f, "No differences within {steps} steps.")
+            },
+            Lockstep::Diverged { steps, registers, stopped } => {
+                writeln!(f, "Machines differ after {steps} steps.")?;
+                if stopped.0 != stopped.1 {
+                    writeln!(f, "  left is {}, right is {}", state(stopped.0), state(stopped.1))?;
+                }
+                for (register, l, r) in registers {
+                    writeln!(f, "  {register}: {l} != {r}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
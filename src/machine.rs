@@ -14,15 +14,49 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 
-use std::{ convert::Infallible, fmt::Display, str::FromStr };
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    fmt::Display,
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::atomic::{ AtomicBool, Ordering },
+    time::Instant,
+};
 use thiserror::Error;
 
-use crate::{ instruction::Instruction, memory::{Memory, RegisterNumber}, vecmap::VecMap };
+use crate::{
+    bytecode::{ self, BytecodeError },
+    cost::CostModel,
+    diff::MachineDiff,
+    explain::Explanation,
+    instruction::Instruction,
+    interrupt,
+    io_handler::{ IoError, IoHandler, SharedHandler },
+    listing::Listing,
+    lockstep::Lockstep,
+    memory::{DecrementZeroError, Memory, Register, RegisterNumber, RegisterValue},
+    metrics::Metrics,
+    sandbox::{ self, ResourceLimit, Sandbox },
+    slice::Slice,
+    suggest,
+    vecmap::VecMap,
+    xref::CrossReference,
+};
+
+/// How many steps [`Machine::run_with_cancel`] takes between checks of its cancellation flag.
+pub const CANCEL_CHECK_INTERVAL: usize = 1024;
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum Identifier {
     Label(String),
     Line(LineNumber),
+    /// A jump by a number of lines from the line it's on, such as `+3` or `-2`. Machines replace
+    /// these with [`Identifier::Line`] when they're constructed.
+    Relative(isize),
+    /// A jump to a number of lines after a label, such as `loop+2`. Machines replace these with
+    /// [`Identifier::Line`] when they're constructed.
+    LabelOffset(String, usize),
     Halt,
 }
 
@@ -30,6 +64,14 @@ impl FromStr for Identifier {
     type Err = Infallible;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Labels can't have a "+" in them, so "loop+2" is always a number of lines after a label.
+        if let Some((label, offset)) = s.rsplit_once('+') {
+            let is_number = !offset.is_empty() && offset.bytes().all(|byte| byte.is_ascii_digit());
+            if !label.is_empty() && is_number {
+                let offset = offset.parse().unwrap_or(usize::MAX);
+                return Ok(Identifier::LabelOffset(label.to_string(), offset));
+            }
+        }
         match s.to_uppercase().as_str() {
             "HALT" => Ok(Identifier::Halt),
             _ => Ok(Identifier::Label(String::from(s))),
@@ -42,12 +84,36 @@ impl Display for Identifier {
         match self {
             Self::Label(s) => write!(f, "{s}"),
             Self::Line(n) => write!(f, "{n}"),
+            Self::Relative(offset) => write!(f, "{offset:+}"),
+            Self::LabelOffset(label, offset) => write!(f, "{label}+{offset}"),
             Self::Halt => write!(f, "HALT"),
         }
     }
 }
 
-type LineNumber = usize;
+pub type LineNumber = usize;
+
+/// The hash of no bytes, to start a 64-bit FNV-1a hash from.
+pub(crate) const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continue a 64-bit FNV-1a hash with the given bytes.
+pub(crate) fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+}
+
+/// Get the register the instruction would modify, if any.
+fn modified_register(instruction: &Instruction, memory: &mut Memory) -> Option<RegisterNumber> {
+    let register = instruction.get_register()?;
+    match instruction {
+        // Pseudo-registers can't be modified.
+        _ if matches!(register, RegisterNumber::Pseudo(_)) => None,
+        // DECJZ and DECJNZ don't modify the register when it's 0, and WRITE never does.
+        Instruction::DECJZ(..) | Instruction::DECJNZ(..) if memory.is_zero(register) => None,
+        Instruction::WRITE(_) => None,
+        _ => Some(register),
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Line {
@@ -63,6 +129,38 @@ impl Line {
     pub fn change_id(&mut self, new_id: Option<Identifier>) {
         self.id = new_id;
     }
+
+    #[must_use]
+    pub const fn get_line_number(&self) -> LineNumber {
+        self.line_number
+    }
+
+    #[must_use]
+    pub const fn get_id(&self) -> Option<&Identifier> {
+        self.id.as_ref()
+    }
+
+    #[must_use]
+    pub const fn get_instruction(&self) -> &Instruction {
+        &self.instruction
+    }
+
+    /// Get the instruction with a jump to a line number written as a relative jump, which is how
+    /// it's written in source code.
+    fn relative_instruction(&self) -> Instruction {
+        match self.instruction.get_jump_target() {
+            Some(&Identifier::Line(n)) => {
+                let offset = if n >= self.line_number {
+                    isize::try_from(n - self.line_number).unwrap_or(isize::MAX)
+                }
+                else {
+                    -isize::try_from(self.line_number - n).unwrap_or(isize::MAX)
+                };
+                self.instruction.with_jump_target(Identifier::Relative(offset))
+            },
+            _ => self.instruction.clone(),
+        }
+    }
 }
 
 impl Display for Line {
@@ -70,7 +168,9 @@ impl Display for Line {
         match &self.id {
             Some(Identifier::Label(label)) => write!(f, "{}    {}: {}", self.line_number, label, self.instruction),
             Some(Identifier::Line(_)) => write!(f, "{}    {}", self.line_number, self.instruction),
-            Some(Identifier::Halt) => unreachable!(),
+            Some(Identifier::Halt | Identifier::Relative(_) | Identifier::LabelOffset(..)) => {
+                unreachable!()
+            },
             None => write!(f, "{}    {}", self.line_number, self.instruction),
         }
     }
@@ -83,19 +183,54 @@ pub enum MachineEditError {
         label: String,
         line: usize,
     },
-    #[error("Cannot find label {label:?} in the code!")]
-    LabelNotFound { label: String },
+    #[error(
+        "Cannot find label {label:?} in the code!{}",
+        suggest::did_you_mean(.suggestion.as_deref()),
+    )]
+    LabelNotFound {
+        label: String,
+        /// The closest label which does exist, if one is close enough to be a likely typo.
+        suggestion: Option<String>,
+    },
+    #[error(
+        "There's no snapshot called {name:?}.{}",
+        suggest::did_you_mean(.suggestion.as_deref()),
+    )]
+    SnapshotNotFound { name: String, suggestion: Option<String> },
     #[error("Cannot go to line number {line_num}! Last line of the machine is {last_line}.")]
     LineNumberTooBig { line_num: usize, last_line: usize },
 }
 
+/// A label which is defined on more than one line, with the lines of source code of two of its
+/// definitions in order, counting from 1 (see [`Machine::get_source_line`]). Jumps to it go to the
+/// last definition.
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+#[error("Label {label:?} is defined on line {first_line} and again on line {second_line}.")]
+pub struct DuplicateLabel {
+    pub label: String,
+    pub first_line: usize,
+    pub second_line: usize,
+}
+
 #[derive(Debug, Error)]
 pub enum RuntimeError {
     #[error("Cannot execute a step, the machine has already halted.")]
     Halted,
+    #[error("The machine didn't halt within the limit of {limit} steps.")]
+    StepLimitReached { limit: usize },
+    #[error("Stopped before modifying protected register {0}.")]
+    ProtectedRegister(RegisterNumber),
+    #[error("Line {0} jumps past the end of the program or to a label which doesn't exist.")]
+    InvalidJump(LineNumber),
+    #[error("{0}")]
+    DecrementedZero(#[from] DecrementZeroError),
+    #[error("{0}")]
+    ResourceLimit(#[from] ResourceLimit),
+    #[error("{0}")]
+    Io(#[from] IoError),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum TerminationReason {
     /// A breakpoint was reached.
     Breakpoint,
@@ -103,6 +238,55 @@ pub enum TerminationReason {
     Empty,
     /// The program halted successfully.
     Halted,
+    /// The next instruction would have modified a protected register, so it wasn't executed.
+    ProtectedRegister(RegisterNumber),
+    /// The instruction on this line would have jumped outside the program while jumps are strict
+    /// (see [`Machine::set_strict_jumps`]), so it wasn't executed.
+    InvalidJump(LineNumber),
+    /// The last instruction changed a register with a watchpoint.
+    Watchpoint { register: RegisterNumber, old: Register, new: Register },
+    /// The run was interrupted, such as with Ctrl+C (see [`crate::interrupt`]), before the
+    /// instruction on the current line.
+    Interrupted,
+    /// The step limit (see [`Machine::set_step_limit`]) was reached before the instruction on the
+    /// current line.
+    StepLimitReached,
+    /// The instruction on the current line would have gone over a cap of the machine's sandbox
+    /// (see [`Machine::set_sandbox`]), so it wasn't executed.
+    ResourceLimit(ResourceLimit),
+    /// The READ or WRITE instruction on the current line failed (see
+    /// [`Machine::set_io_handler`]), so it wasn't executed.
+    Io(IoError),
+}
+
+/// How many times a register was used by each kind of instruction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterHeat {
+    /// How many times the register was checked for zero, by DECJZ or DECJNZ.
+    pub reads: usize,
+    pub increments: usize,
+    pub decrements: usize,
+}
+
+impl RegisterHeat {
+    /// Get how many times the register was used at all. A check for zero followed by a decrement
+    /// counts as two uses.
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.reads + self.increments + self.decrements
+    }
+}
+
+impl std::ops::Add for RegisterHeat {
+    type Output = RegisterHeat;
+
+    fn add(self, other: RegisterHeat) -> RegisterHeat {
+        RegisterHeat {
+            reads: self.reads + other.reads,
+            increments: self.increments + other.increments,
+            decrements: self.decrements + other.decrements,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -111,48 +295,188 @@ pub enum BreakpointToggle {
     Removed,
 }
 
-#[derive(Debug, PartialEq, Default)]
+#[derive(Debug, Clone, Copy)]
+pub enum ProtectionToggle {
+    Protected,
+    Unprotected,
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
 pub struct Machine {
     lines: Vec<Line>,
     current_line: LineNumber,
     initial_memory: Memory,
     memory: Memory,
     labels: VecMap<String, LineNumber>,
+    /// Labels which are defined more than once, with the lines of the program they're defined on.
+    duplicate_labels: Vec<(String, LineNumber, LineNumber)>,
     breakpoints: Vec<usize>,
+    /// Breakpoints which only stop on the given execution of their line.
+    breakpoint_hit_targets: VecMap<LineNumber, usize>,
+    /// How many times each line with a breakpoint has been executed since the last reset.
+    breakpoint_hits: VecMap<LineNumber, usize>,
+    /// The breakpoint which [`Machine::debug`] last stopped at, so it can be moved past.
+    resume_line: Option<LineNumber>,
+    /// The most recently executed lines, oldest first, if they're being recorded.
+    history: VecDeque<LineNumber>,
+    history_limit: Option<usize>,
+    /// The lines each CALL which hasn't returned yet will return to, oldest first.
+    call_stack: Vec<LineNumber>,
+    protected_registers: Vec<RegisterNumber>,
+    watchpoints: Vec<RegisterNumber>,
+    /// Whether jumping outside the program is an error rather than a way to halt.
+    strict_jumps: bool,
+    xref: CrossReference,
+    steps: usize,
+    /// The caps on the resources the machine may use, including its step limit.
+    sandbox: Sandbox,
+    /// When the current execution started, for the sandbox's time limit.
+    execution_started: Option<Instant>,
+    /// The cap of the sandbox which stopped the machine last, until it's reset.
+    resource_limit: Option<ResourceLimit>,
+    /// Where READ and WRITE get and send values.
+    io: SharedHandler,
+    /// The error of the READ or WRITE which stopped the machine last, until it's reset.
+    io_error: Option<IoError>,
+    compaction_interval: Option<NonZeroUsize>,
+    phases: Vec<String>,
+    entry: Option<String>,
+    /// The documentation of labels, from the `##` comments above them.
+    label_documentation: VecMap<String, String>,
+    /// The labels other programs may jump to when linked, or every label if there are none.
+    exports: Vec<String>,
+    /// The labels of other programs this program jumps to when linked.
+    imports: Vec<String>,
+    current_phase: Option<String>,
+    phase_steps: VecMap<Option<String>, usize>,
+    /// Whether to count how each register is used, which costs a little time on every step.
+    counts_register_heat: bool,
+    register_heat: VecMap<RegisterNumber, RegisterHeat>,
+    cost_model: Option<CostModel>,
+    cost: u64,
+    phase_costs: VecMap<Option<String>, u64>,
+    /// Copies of the registers saved under a name, which resets leave alone.
+    snapshots: VecMap<String, Memory>,
+    /// How many times each line has been executed, which is only counted if the program reads
+    /// `r!visits` or `r!steps` (see [`crate::memory::PseudoRegister`]).
+    line_visits: Option<Vec<usize>>,
+    source_lines: SourceLines,
+}
+
+/// The line of source code each line of a machine came from, counting from 1, if it was parsed.
+/// They're only for messages about the program, so machines which differ only in them are equal.
+#[derive(Debug, Default, Clone)]
+struct SourceLines(Vec<usize>);
+
+impl PartialEq for SourceLines {
+    fn eq(&self, _: &SourceLines) -> bool {
+        true
+    }
 }
 
 impl Machine {
     // Constructors.
 
-    /// Construct a new machine from a slice of [`Line`]s.
+    /// Construct a new machine from a slice of [`Line`]s. Relative jumps and jumps to a number of
+    /// lines after a label are resolved to the lines they go to, or to HALT if that would be before
+    /// the first line or the label doesn't exist.
     #[must_use]
     pub fn new_from_lines(lines_slice: &[Line], memory: Memory) -> Machine {
-        let mut lines_vec: Vec<Line> = Vec::from(lines_slice);
+        let mut lines_vec: Vec<Line> = lines_slice
+            .iter()
+            .map(|line| match line.instruction.get_jump_target() {
+                Some(&Identifier::Relative(offset)) => {
+                    let target = line.line_number
+                        .checked_add_signed(offset)
+                        .map_or(Identifier::Halt, Identifier::Line);
+                    Line { instruction: line.instruction.with_jump_target(target), ..line.clone() }
+                },
+                _ => line.clone(),
+            })
+            .collect();
         let mut labels_map = VecMap::default();
-        // Create a map of labels.
+        let mut duplicate_labels = Vec::new();
+        // Create a map of labels, where the last definition of a label wins.
         for l in &lines_vec {
             if let Some(Identifier::Label(s)) = &l.id {
+                if let Some(&first) = labels_map.get(s) {
+                    duplicate_labels.push((s.clone(), first, l.line_number));
+                }
                 labels_map.update(s.to_string(), l.line_number);
             }
         }
-        // Now replace all labels in DECJZ instructions with line numbers to speed up jumps.
-        for l in &mut lines_vec {
-            if let Instruction::DECJZ(_, Identifier::Label(s)) = &l.instruction {
-                if let Some(new_num) = labels_map.get(s) {
-                    l.change_id(Some(Identifier::Line(*new_num)));
-                }
+        let mut label_offsets = Vec::new();
+        for line in &mut lines_vec {
+            let target = line.instruction.get_jump_target();
+            if let Some(Identifier::LabelOffset(label, offset)) = target {
+                label_offsets.push((label.clone(), line.line_number));
+                let target = labels_map
+                    .get(label)
+                    .map_or(Identifier::Halt, |&n| Identifier::Line(n.saturating_add(*offset)));
+                line.instruction = line.instruction.with_jump_target(target);
             }
         }
+        let mut xref = CrossReference::new_from_lines(&lines_vec);
+        for (label, line_number) in &label_offsets {
+            xref.add_label_offset(label, *line_number);
+        }
+        let uses_pseudo_registers = lines_vec
+            .iter()
+            .any(|line| matches!(line.instruction.get_register(), Some(RegisterNumber::Pseudo(_))));
+        let line_visits = uses_pseudo_registers.then(|| vec![0; lines_vec.len()]);
         Machine {
             lines: lines_vec,
             current_line: 0,
             initial_memory: memory.clone(),
             memory,
             labels: labels_map,
+            duplicate_labels,
             breakpoints: Vec::new(),
+            breakpoint_hit_targets: VecMap::default(),
+            breakpoint_hits: VecMap::default(),
+            resume_line: None,
+            history: VecDeque::new(),
+            history_limit: None,
+            call_stack: Vec::new(),
+            protected_registers: Vec::new(),
+            watchpoints: Vec::new(),
+            strict_jumps: false,
+            xref,
+            steps: 0,
+            sandbox: Sandbox::UNLIMITED,
+            execution_started: None,
+            resource_limit: None,
+            io: SharedHandler::default(),
+            io_error: None,
+            compaction_interval: None,
+            phases: Vec::new(),
+            entry: None,
+            label_documentation: VecMap::default(),
+            exports: Vec::new(),
+            imports: Vec::new(),
+            current_phase: None,
+            phase_steps: VecMap::default(),
+            counts_register_heat: false,
+            register_heat: VecMap::default(),
+            cost_model: None,
+            cost: 0,
+            phase_costs: VecMap::default(),
+            snapshots: VecMap::default(),
+            line_visits,
+            source_lines: SourceLines::default(),
         }
     }
 
+    /// Construct a machine from bytecode written by [`Machine::to_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// * [`BytecodeError`] - returned when the bytes aren't bytecode, are from a later version of
+    ///   the format, or are damaged.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Machine, BytecodeError> {
+        bytecode::decode(bytes)
+    }
+
     // Editing.
 
     /// Add a breakpoint if one hasn't been added already, or remove it otherwise.
@@ -160,26 +484,56 @@ impl Machine {
     /// # Errors
     /// 
     /// * [`MachineEditError::LabelNotFound`] - returned when the specified label doesn't exist in
-    /// the code and couldn't be found.
+    ///   the code and couldn't be found.
     /// * [`MachineEditError::LineNumberTooBig`] - returned when the line number given is larger
-    /// than the last line number.
+    ///   than the last line number.
     pub fn toggle_breakpoint(&mut self, id: &Identifier) -> Result<BreakpointToggle, MachineEditError> {
-        match id {
-            Identifier::Label(s) => {
-                if let Some(n) = self.labels.get(s) {
-                    if self.breakpoints.contains(n) {
-                        self.breakpoints.retain(|x| x != n);
-                        Ok(BreakpointToggle::Removed)
-                    }
-                    else {
-                        self.breakpoints.push(*n);
-                        Ok(BreakpointToggle::Added)
-                    }
-                }
-                else {
-                    Err(MachineEditError::LabelNotFound { label: s.to_owned() })
-                }
+        let n = self.breakpoint_line(id)?;
+        match self.breakpoints.binary_search(&n) {
+            Ok(i) => {
+                self.breakpoints.remove(i);
+                self.breakpoint_hit_targets.remove(&n);
+                self.breakpoint_hits.remove(&n);
+                Ok(BreakpointToggle::Removed)
+            },
+            Err(i) => {
+                self.breakpoints.insert(i, n);
+                Ok(BreakpointToggle::Added)
             },
+        }
+    }
+
+    /// Add a breakpoint which only stops the machine on the `hit`th execution of its line since
+    /// the last reset, counting from 1. If the line already has a breakpoint, it's changed to
+    /// this one.
+    ///
+    /// # Errors
+    ///
+    /// * [`MachineEditError::LabelNotFound`] - returned when the specified label doesn't exist in
+    ///   the code and couldn't be found.
+    /// * [`MachineEditError::LineNumberTooBig`] - returned when the line number given is larger
+    ///   than the last line number.
+    pub fn set_breakpoint_on_hit(
+        &mut self,
+        id: &Identifier,
+        hit: usize,
+    ) -> Result<(), MachineEditError> {
+        let n = self.breakpoint_line(id)?;
+        if let Err(i) = self.breakpoints.binary_search(&n) {
+            self.breakpoints.insert(i, n);
+        }
+        self.breakpoint_hit_targets.update(n, hit);
+        Ok(())
+    }
+
+    /// Find the line a breakpoint would be attached to. A relative identifier counts from the
+    /// current line.
+    fn breakpoint_line(&self, id: &Identifier) -> Result<LineNumber, MachineEditError> {
+        match id {
+            Identifier::Label(s) => self.labels
+                .get(s)
+                .copied()
+                .ok_or_else(|| self.label_not_found(s.to_owned())),
             Identifier::Line(n) => {
                 if self.lines.get(*n).is_none() {
                     return Err(MachineEditError::LineNumberTooBig {
@@ -187,19 +541,161 @@ impl Machine {
                         last_line: self.lines.len()
                     });
                 }
-                if self.breakpoints.contains(n) {
-                    self.breakpoints.retain(|x| x != n);
-                    Ok(BreakpointToggle::Removed)
-                }
-                else {
-                    self.breakpoints.push(*n);
-                    Ok(BreakpointToggle::Added)
-                }
+                Ok(*n)
+            },
+            Identifier::Relative(offset) => {
+                let n = self.current_line.checked_add_signed(*offset).unwrap_or(self.lines.len());
+                self.breakpoint_line(&Identifier::Line(n))
+            },
+            Identifier::LabelOffset(label, offset) => {
+                let n = self.breakpoint_line(&Identifier::Label(label.clone()))?;
+                self.breakpoint_line(&Identifier::Line(n.saturating_add(*offset)))
             },
             Identifier::Halt => unreachable!(),
         }
     }
 
+    /// Mark a register as read-only if it isn't already, or make it writable again otherwise.
+    ///
+    /// While a register is protected, the machine will stop before executing any instruction which
+    /// would modify it.
+    pub fn toggle_protection(&mut self, register: RegisterNumber) -> ProtectionToggle {
+        match self.protected_registers.binary_search(&register) {
+            Ok(i) => {
+                self.protected_registers.remove(i);
+                ProtectionToggle::Unprotected
+            },
+            Err(i) => {
+                self.protected_registers.insert(i, register);
+                ProtectionToggle::Protected
+            },
+        }
+    }
+
+    /// Add a watchpoint to a register if it doesn't have one, or remove it otherwise.
+    ///
+    /// While a register has a watchpoint, [`Machine::debug`] and [`Machine::step`] stop after any
+    /// instruction which changes it, giving its old and new values.
+    pub fn toggle_watchpoint(&mut self, register: RegisterNumber) -> BreakpointToggle {
+        match self.watchpoints.binary_search(&register) {
+            Ok(i) => {
+                self.watchpoints.remove(i);
+                BreakpointToggle::Removed
+            },
+            Err(i) => {
+                self.watchpoints.insert(i, register);
+                BreakpointToggle::Added
+            },
+        }
+    }
+
+    /// Mark a label as the start of a phase, so that the steps spent from it until the next phase
+    /// are counted separately.
+    ///
+    /// # Errors
+    ///
+    /// * [`MachineEditError::LabelNotFound`] - returned when the specified label doesn't exist in
+    ///   the code and couldn't be found.
+    pub fn add_phase(&mut self, label: String) -> Result<(), MachineEditError> {
+        if self.labels.get(&label).is_none() {
+            return Err(self.label_not_found(label));
+        }
+        if !self.phases.contains(&label) {
+            self.phases.push(label);
+        }
+        Ok(())
+    }
+
+    /// Start execution at the given label instead of the first line, including after a reset. If
+    /// the machine hasn't taken any steps yet, it's also moved to the label straight away.
+    ///
+    /// # Errors
+    ///
+    /// * [`MachineEditError::LabelNotFound`] - returned when the specified label doesn't exist in
+    ///   the code and couldn't be found.
+    pub fn set_entry(&mut self, label: String) -> Result<(), MachineEditError> {
+        let Some(&line) = self.labels.get(&label) else {
+            return Err(self.label_not_found(label));
+        };
+        if self.steps == 0 && self.current_line == self.get_entry_line() {
+            self.current_line = line;
+        }
+        self.entry = Some(label);
+        Ok(())
+    }
+
+    /// Attach documentation to a label, such as the `##` comments above it in the source code.
+    /// Its lines are separated by `\n`.
+    ///
+    /// # Errors
+    ///
+    /// * [`MachineEditError::LabelNotFound`] - returned when the specified label doesn't exist in
+    ///   the code and couldn't be found.
+    pub fn set_label_documentation(
+        &mut self,
+        label: String,
+        documentation: String,
+    ) -> Result<(), MachineEditError> {
+        if self.labels.get(&label).is_none() {
+            return Err(self.label_not_found(label));
+        }
+        self.label_documentation.update(label, documentation);
+        Ok(())
+    }
+
+    /// Record the line of source code each line came from, counting from 1, for messages about
+    /// the program.
+    pub fn set_source_lines(&mut self, source_lines: Vec<usize>) {
+        self.source_lines = SourceLines(source_lines);
+    }
+
+    /// Get the line of source code the given line came from, counting from 1. Lines which weren't
+    /// parsed from source code, such as those of a linked or optimised program, are counted from 1
+    /// instead.
+    #[must_use]
+    pub fn get_source_line(&self, line: LineNumber) -> usize {
+        self.source_lines.0.get(line).copied().unwrap_or(line + 1)
+    }
+
+    /// Mark a label as one which other programs may jump to when they're linked together (see
+    /// [`crate::link`]). Once a label is exported, the labels which aren't can't be used by other
+    /// programs.
+    ///
+    /// # Errors
+    ///
+    /// * [`MachineEditError::LabelNotFound`] - returned when the specified label doesn't exist in
+    ///   the code and couldn't be found.
+    pub fn add_export(&mut self, label: String) -> Result<(), MachineEditError> {
+        if self.labels.get(&label).is_none() {
+            return Err(self.label_not_found(label));
+        }
+        if !self.exports.contains(&label) {
+            self.exports.push(label);
+        }
+        Ok(())
+    }
+
+    /// Declare that the program jumps to a label of another program it's linked with, such as
+    /// `maths::add`. Once a label is imported, jumps to other programs' labels which aren't
+    /// imported are an error when linking.
+    pub fn add_import(&mut self, label: String) {
+        if !self.imports.contains(&label) {
+            self.imports.push(label);
+        }
+    }
+
+    /// Get the defined label closest to `label`, if one is close enough to be a likely typo.
+    #[must_use]
+    pub fn closest_label(&self, label: &str) -> Option<String> {
+        suggest::closest(label, self.get_labels().iter().map(|(defined, _)| defined.as_str()))
+            .map(str::to_string)
+    }
+
+    fn label_not_found(&self, label: String) -> MachineEditError {
+        let suggestion = self.closest_label(&label);
+        MachineEditError::LabelNotFound { label, suggestion }
+    }
+
     /// Try to add a new label to a given line number.
     /// 
     /// # Errors
@@ -212,18 +708,20 @@ impl Machine {
                 line: *actual_line_number,
             })
         }
+        self.xref.add_label(&Identifier::Label(label.clone()), line_number);
         self.labels.update(label, line_number);
         Ok(())
     }
 
-    /// Set the instruction pointer to a given identifier.
+    /// Set the instruction pointer to a given identifier. A relative identifier counts from the
+    /// current line.
     /// 
     /// # Errors
     /// 
     /// * [`MachineEditError::LabelNotFound`] - returned when the specified label doesn't exist in
-    /// the code and couldn't be found.
+    ///   the code and couldn't be found.
     /// * [`MachineEditError::LineNumberTooBig`] - returned when the line number given is larger
-    /// than the last line number.
+    ///   than the last line number.
     pub fn go_to_identifier(&mut self, id: &Identifier) -> Result<(), MachineEditError> {
         match id {
             Identifier::Halt => {
@@ -242,68 +740,452 @@ impl Machine {
                     })
                 }
             },
+            Identifier::Relative(offset) => {
+                let n = self.current_line.checked_add_signed(*offset).unwrap_or(self.lines.len());
+                self.go_to_identifier(&Identifier::Line(n))
+            },
+            Identifier::LabelOffset(label, offset) => {
+                let n = match self.labels.get(label) {
+                    Some(&n) => n.saturating_add(*offset),
+                    None => return Err(self.label_not_found(label.to_owned())),
+                };
+                self.go_to_identifier(&Identifier::Line(n))
+            },
             Identifier::Label(s) => { 
                 self.current_line = match self.labels.get(s) {
                     Some(&n) => n,
-                    None => return Err(MachineEditError::LabelNotFound { label: s.to_owned() }),
+                    None => return Err(self.label_not_found(s.to_owned())),
                 };
                 Ok(())
             },
         }
     }
 
+    /// Choose whether jumping past the end of the program or to a label which doesn't exist is an
+    /// error. By default such jumps halt the machine. When strict, the machine stops before the
+    /// jump instead (see [`TerminationReason::InvalidJump`]), and only [`Instruction::HALT`] or
+    /// running off the last line halts it.
+    pub fn set_strict_jumps(&mut self, strict: bool) {
+        self.strict_jumps = strict;
+    }
+
+    /// Get whether invalid jumps are errors (see [`Machine::set_strict_jumps`]).
+    #[must_use]
+    pub const fn is_strict_jumps(&self) -> bool {
+        self.strict_jumps
+    }
+
+    /// Start or stop counting how many times each register is read, incremented and decremented
+    /// (see [`Machine::get_register_heat`]).
+    pub fn set_register_heat(&mut self, enabled: bool) {
+        self.counts_register_heat = enabled;
+    }
+
+    /// Start adding up the cost of each step under the given model (see [`Machine::get_cost`]), or
+    /// stop if [`None`].
+    pub fn set_cost_model(&mut self, model: Option<CostModel>) {
+        self.cost_model = model;
+    }
+
+    /// Set the most steps [`Machine::run`] and [`Machine::call`] may take before giving up, or
+    /// [`None`] for no limit.
+    pub fn set_step_limit(&mut self, limit: Option<usize>) {
+        self.sandbox.steps = limit;
+    }
+
+    /// Get the most steps the machine may take, if it's limited (see [`Machine::set_step_limit`]).
+    #[must_use]
+    pub const fn get_step_limit(&self) -> Option<usize> {
+        self.sandbox.steps
+    }
+
+    /// Set the caps on the resources the machine may use. This replaces the step limit with the
+    /// sandbox's (see [`Machine::set_step_limit`]).
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = sandbox;
+    }
+
+    /// Get the caps on the resources the machine may use.
+    #[must_use]
+    pub const fn get_sandbox(&self) -> &Sandbox {
+        &self.sandbox
+    }
+
+    /// Get the cap of the sandbox which stopped the machine, if one has since it was last reset.
+    #[must_use]
+    pub const fn get_resource_limit(&self) -> Option<ResourceLimit> {
+        self.resource_limit
+    }
+
+    /// Set where READ instructions get values from and WRITE instructions send them, instead of
+    /// STDIN and STDOUT (see [`crate::io_handler`]). Copies of the machine made afterwards share
+    /// the handler.
+    pub fn set_io_handler(&mut self, handler: impl IoHandler + 'static) {
+        self.io = SharedHandler::new(handler);
+    }
+
+    /// Get the error of the READ or WRITE instruction which stopped the machine, if one has since
+    /// it was last reset.
+    #[must_use]
+    pub fn get_io_error(&self) -> Option<&IoError> {
+        self.io_error.as_ref()
+    }
+
+    /// Start timing an execution for the sandbox's time limit. [`Machine::debug`],
+    /// [`Machine::run`] and [`Machine::call`] do this themselves unless an execution has already
+    /// started, and stop timing once they return. So this is only needed by frontends which call
+    /// [`Machine::step`] in a loop of their own, or whose runs should all count as one execution.
+    pub fn start_execution(&mut self) {
+        self.execution_started = Some(Instant::now());
+    }
+
+    /// Run `f` as one execution for the sandbox's time limit, unless an execution has already
+    /// started.
+    fn within_execution<T>(&mut self, f: impl FnOnce(&mut Machine) -> T) -> T {
+        let starts = self.execution_started.is_none();
+        if starts {
+            self.start_execution();
+        }
+        let result = f(self);
+        if starts {
+            self.execution_started = None;
+        }
+        result
+    }
+
+    /// Check whether the machine has taken as many steps as its step limit allows.
+    #[must_use]
+    pub fn is_step_limit_reached(&self) -> bool {
+        self.sandbox.steps.is_some_and(|limit| self.steps >= limit)
+    }
+
+    /// Compact the memory (see [`Memory::compact`]) every `interval` steps, or never if [`None`].
+    pub fn set_compaction_interval(&mut self, interval: Option<NonZeroUsize>) {
+        self.compaction_interval = interval;
+    }
+
+    /// Record up to `limit` of the most recently executed lines (see [`Machine::get_history`]), or
+    /// stop recording if [`None`].
+    pub fn set_history_limit(&mut self, limit: Option<usize>) {
+        self.history_limit = limit;
+        match limit {
+            Some(limit) => {
+                while self.history.len() > limit {
+                    self.history.pop_front();
+                }
+            },
+            None => self.history.clear(),
+        }
+    }
+
+    /// Replace the initial memory, which the machine goes back to when it's reset.
+    pub fn set_initial_memory(&mut self, new_memory: Memory) {
+        self.initial_memory = new_memory;
+    }
+
     /// Replace the current memory with the given memory.
     pub fn replace_memory(&mut self, new_memory: Memory) {
         self.memory = new_memory;
     }
 
+    /// Save a copy of the registers under a name, replacing any snapshot with the same name.
+    pub fn save_snapshot(&mut self, name: String) {
+        self.snapshots.update(name, self.memory.clone());
+    }
+
+    /// Get the registers saved under a name with [`Machine::save_snapshot`].
+    ///
+    /// # Errors
+    ///
+    /// * [`MachineEditError::SnapshotNotFound`] - returned when no snapshot has the name.
+    pub fn get_snapshot(&self, name: &str) -> Result<&Memory, MachineEditError> {
+        self.snapshots.get(&name.to_string()).ok_or_else(|| {
+            let names = self.snapshots.keys();
+            let suggestion = suggest::closest(name, names.iter().map(|name| name.as_str()))
+                .map(str::to_string);
+            MachineEditError::SnapshotNotFound { name: name.to_string(), suggestion }
+        })
+    }
+
+    /// Replace the registers with those saved under a name.
+    ///
+    /// # Errors
+    ///
+    /// * [`MachineEditError::SnapshotNotFound`] - returned when no snapshot has the name.
+    pub fn restore_snapshot(&mut self, name: &str) -> Result<(), MachineEditError> {
+        self.memory = self.get_snapshot(name)?.clone();
+        Ok(())
+    }
+
+    /// Replace the call stack, such as to undo a CALL or RETURN (see
+    /// [`Machine::get_call_stack`]).
+    pub fn replace_call_stack(&mut self, call_stack: Vec<LineNumber>) {
+        self.call_stack = call_stack;
+    }
+
+    /// Swap the current memory with the given memory, so that the machine can run on memory
+    /// which is shared with other machines.
+    pub fn swap_memory(&mut self, memory: &mut Memory) {
+        std::mem::swap(&mut self.memory, memory);
+    }
+
     /// Resets the state of the machine by returning the memory to its initial state and setting
     /// the instruction pointer to the first instruction line.
     pub fn reset(&mut self) {
         self.memory = self.initial_memory.clone();
-        self.current_line = 0;
+        self.current_line = self.get_entry_line();
+        self.breakpoint_hits = VecMap::default();
+        self.resume_line = None;
+        self.history.clear();
+        self.call_stack.clear();
+        self.resource_limit = None;
+        self.io_error = None;
+        self.steps = 0;
+        self.current_phase = None;
+        self.phase_steps = VecMap::default();
+        self.register_heat = VecMap::default();
+        self.cost = 0;
+        self.phase_costs = VecMap::default();
+        if let Some(visits) = &mut self.line_visits {
+            visits.fill(0);
+        }
     }
 
     // Execution.
 
-    /// Run the machine until a breakpoint is reached or until it halts.
+    /// Run the machine until a breakpoint is reached, until it halts, or until the step limit (see
+    /// [`Machine::set_step_limit`]) or another cap of its sandbox is reached.
     /// 
     /// # Errors
     /// 
     /// * [`RuntimeError::Halted`] - returned when trying to run when the machine has halted.
     pub fn debug(&mut self) -> Result<TerminationReason, RuntimeError> {
+        self.within_execution(Machine::debug_until_stopped)
+    }
+
+    /// Run the machine like [`Machine::debug`], within an execution which has already started.
+    fn debug_until_stopped(&mut self) -> Result<TerminationReason, RuntimeError> {
         if self.lines.is_empty() {
             return Ok(TerminationReason::Empty);
         }
-        while self.current_line < self.lines.len()
-            && !self.breakpoints.contains(&self.current_line)
-        {
-            self.step_unchecked();
+        if self.current_line >= self.lines.len() {
+            return Err(RuntimeError::Halted);
+        }
+        // Move past the breakpoint the machine last stopped at, rather than stopping there again.
+        if self.resume_line == Some(self.current_line) {
+            if let Some(reason @ (TerminationReason::ProtectedRegister(_)
+                | TerminationReason::InvalidJump(_)
+                | TerminationReason::Watchpoint { .. }
+                | TerminationReason::ResourceLimit(_)
+                | TerminationReason::Io(_))) = self.step_unchecked()
+            {
+                return Ok(reason);
+            }
+        }
+        while self.current_line < self.lines.len() && !self.stops_at(self.current_line) {
+            if interrupt::take() {
+                return Ok(TerminationReason::Interrupted);
+            }
+            if self.is_step_limit_reached() {
+                return Ok(TerminationReason::StepLimitReached);
+            }
+            if let Some(reason @ (TerminationReason::ProtectedRegister(_)
+                | TerminationReason::InvalidJump(_)
+                | TerminationReason::Watchpoint { .. }
+                | TerminationReason::ResourceLimit(_)
+                | TerminationReason::Io(_))) = self.step_unchecked()
+            {
+                return Ok(reason);
+            }
         }
         if self.current_line >= self.lines.len() {
             Ok(TerminationReason::Halted)
         }
         else {
+            self.resume_line = Some(self.current_line);
             Ok(TerminationReason::Breakpoint)
         }
 
     }
 
+    /// Check whether [`Machine::debug`] should stop before executing the given line.
+    fn stops_at(&self, line: LineNumber) -> bool {
+        if !self.breakpoints.contains(&line) {
+            return false;
+        }
+        self.breakpoint_hit_targets
+            .get(&line)
+            .is_none_or(|&hit| self.get_breakpoint_hits(line) + 1 == hit)
+    }
+
+    /// Run the program like a function, using the usual convention of taking inputs in r1 to rn and
+    /// leaving the output in r0.
+    ///
+    /// The machine is reset and every register is set to 0 apart from the inputs. The program is
+    /// then run until it halts, or until the step limit (see [`Machine::set_step_limit`]) or
+    /// another cap of its sandbox is reached. The final values of the natural registers are
+    /// returned, starting with r0.
+    ///
+    /// # Errors
+    ///
+    /// * [`RuntimeError::StepLimitReached`] - returned when the machine doesn't halt in time.
+    /// * [`RuntimeError::ProtectedRegister`] - returned when the program would modify a protected
+    ///   register.
+    /// * [`RuntimeError::InvalidJump`] - returned when jumps are strict and the program would jump
+    ///   outside itself.
+    /// * [`RuntimeError::ResourceLimit`] - returned when the program would go over another cap of
+    ///   the machine's sandbox.
+    /// * [`RuntimeError::Io`] - returned when a READ or WRITE instruction fails.
+    pub fn call(&mut self, inputs: &[u128]) -> Result<Vec<RegisterValue>, RuntimeError> {
+        self.reset();
+        let mut registers = vec![Register::from(0)];
+        registers.extend(inputs.iter().map(|&input| Register::from(input)));
+        self.memory = Memory::new_from_slice(&registers);
+        self.within_execution(Machine::call_until_halted)
+    }
+
+    /// Run the machine for [`Machine::call`] once its inputs are set.
+    fn call_until_halted(&mut self) -> Result<Vec<RegisterValue>, RuntimeError> {
+        while !self.is_halted() {
+            if let Some(limit) = self.sandbox.steps {
+                if self.steps >= limit {
+                    return Err(RuntimeError::StepLimitReached { limit });
+                }
+            }
+            match self.step_unchecked() {
+                Some(TerminationReason::ProtectedRegister(register)) => {
+                    return Err(RuntimeError::ProtectedRegister(register));
+                },
+                Some(TerminationReason::InvalidJump(line)) => {
+                    return Err(RuntimeError::InvalidJump(line));
+                },
+                Some(TerminationReason::ResourceLimit(limit)) => return Err(limit.into()),
+                Some(TerminationReason::Io(e)) => return Err(e.into()),
+                _ => (),
+            }
+        }
+        let mut registers = Vec::from(self.memory.get_nat_registers());
+        registers.resize(self.memory.get_nat_count(), Register::from(0));
+        Ok(registers)
+    }
+
+    /// Decrement the given register by 1, trapping instead of changing it if it's zero.
+    ///
+    /// # Errors
+    ///
+    /// * [`RuntimeError::DecrementedZero`] - returned if the register is zero.
+    pub fn dec(&mut self, register: RegisterNumber) -> Result<(), RuntimeError> {
+        Ok(self.memory.checked_dec(register)?)
+    }
+
     /// Execute the given instruction.
     pub fn execute(&mut self, instruction: Instruction) -> Option<Identifier> {
         instruction.execute(&mut self.memory)
     }
 
+    /// Execute the given instruction like [`Machine::execute`], but as a step of its own which
+    /// keeps to the machine's sandbox, as the REPL does, so that running many instructions one by
+    /// one can't take forever or use up the host's memory. The step counts towards the step limit
+    /// (see [`Machine::set_step_limit`]), and an instruction which would go over a cap of the
+    /// sandbox or modify a protected register isn't executed.
+    ///
+    /// # Errors
+    ///
+    /// * [`RuntimeError::StepLimitReached`] - returned when the step limit has been reached.
+    /// * [`RuntimeError::ProtectedRegister`] - returned when the instruction would modify a
+    ///   protected register.
+    /// * [`RuntimeError::ResourceLimit`] - returned when the instruction would go over a cap of
+    ///   the sandbox.
+    pub fn execute_step(
+        &mut self,
+        instruction: Instruction,
+    ) -> Result<Option<Identifier>, RuntimeError> {
+        if let Some(limit) = self.sandbox.steps.filter(|&limit| self.steps >= limit) {
+            return Err(RuntimeError::StepLimitReached { limit });
+        }
+        if !self.protected_registers.is_empty() {
+            if let Some(register) = modified_register(&instruction, &mut self.memory) {
+                if self.protected_registers.contains(&register) {
+                    return Err(RuntimeError::ProtectedRegister(register));
+                }
+            }
+        }
+        if let Some(limit) = self.exceeded_resource_limit(&instruction) {
+            self.resource_limit = Some(limit);
+            return Err(RuntimeError::ResourceLimit(limit));
+        }
+        self.steps += 1;
+        Ok(instruction.execute(&mut self.memory))
+    }
+
     /// Run the machine until it halts.
     /// 
-    /// This will start running from whatever the current instruction is.
+    /// This will start running from whatever the current instruction is. If a protected register
+    /// would be modified, a strict jump would leave the program, or the step limit (see
+    /// [`Machine::set_step_limit`]) or another cap of the sandbox is reached, the machine stops
+    /// early without halting.
     pub fn run(&mut self) {
-        if self.lines.is_empty() {
-            return;
+        self.run_steps(usize::MAX);
+    }
+
+    /// Run the machine like [`Machine::run`], but take at most `count` steps. Returns how many
+    /// steps were taken, so the machine stopped early if it's fewer than `count`.
+    pub fn run_steps(&mut self, count: usize) -> usize {
+        let start = self.steps;
+        let mut end = start.saturating_add(count);
+        if let Some(limit) = self.sandbox.steps {
+            end = end.min(limit);
         }
-        while self.current_line < self.lines.len() {
-            self.step_unchecked();
+        self.within_execution(|machine| {
+            while machine.current_line < machine.lines.len() && machine.steps < end {
+                if let Some(
+                    TerminationReason::ProtectedRegister(_)
+                    | TerminationReason::InvalidJump(_)
+                    | TerminationReason::ResourceLimit(_)
+                    | TerminationReason::Io(_),
+                ) = machine.step_unchecked()
+                {
+                    break;
+                }
+            }
+        });
+        self.steps - start
+    }
+
+    /// Run the machine like [`Machine::run`], but stop early once `cancel` has been set, such as
+    /// by another thread. The flag is checked every [`CANCEL_CHECK_INTERVAL`] steps, and left as
+    /// it was, so a caller reusing it should clear it before running again.
+    ///
+    /// Returns why the machine stopped: [`TerminationReason::Interrupted`] if it was cancelled,
+    /// or otherwise the same reasons as [`Machine::debug`] apart from breakpoints, which are
+    /// ignored.
+    pub fn run_with_cancel(&mut self, cancel: &AtomicBool) -> TerminationReason {
+        if self.lines.is_empty() {
+            return TerminationReason::Empty;
         }
+        self.within_execution(|machine| {
+            let mut until_check = 0;
+            while machine.current_line < machine.lines.len() {
+                if until_check == 0 {
+                    if cancel.load(Ordering::Relaxed) {
+                        return TerminationReason::Interrupted;
+                    }
+                    until_check = CANCEL_CHECK_INTERVAL;
+                }
+                until_check -= 1;
+                if machine.is_step_limit_reached() {
+                    return TerminationReason::StepLimitReached;
+                }
+                if let Some(reason @ (TerminationReason::ProtectedRegister(_)
+                    | TerminationReason::InvalidJump(_)
+                    | TerminationReason::ResourceLimit(_)
+                    | TerminationReason::Io(_))) = machine.step_unchecked()
+                {
+                    return reason;
+                }
+            }
+            TerminationReason::Halted
+        })
     }
 
     /// Run the current line of code, or in other words, take a "step".
@@ -315,17 +1197,96 @@ impl Machine {
         if self.current_line >= self.lines.len() {
             return Err(RuntimeError::Halted)
         }
-        // Execute the current instruction.
-        match self.lines[self.current_line]
-            .instruction
-            .execute(&mut self.memory)
-        {
-            Some(ident) => {
-                self.go_to_identifier(&ident).unwrap();
-            },
-            None => {
-                self.current_line += 1;
+        if let Some(visits) = &self.line_visits {
+            self.memory.set_pseudo_registers(self.steps, visits[self.current_line]);
+        }
+        // Refuse to execute the current instruction if it would modify a protected register.
+        if let Some(register) = self.modifies_protected_register() {
+            return Ok(Some(TerminationReason::ProtectedRegister(register)))
+        }
+        // Refuse to jump outside the program if jumps are strict.
+        if self.strict_jumps && self.jumps_outside_program() {
+            return Ok(Some(TerminationReason::InvalidJump(self.current_line)))
+        }
+        // Refuse to go over the caps of the sandbox.
+        let instruction = &self.lines[self.current_line].instruction;
+        if let Some(limit) = self.exceeded_resource_limit(instruction) {
+            self.resource_limit = Some(limit);
+            return Ok(Some(TerminationReason::ResourceLimit(limit)))
+        }
+        // Read or write before the step is counted, so that a failure leaves the machine as it was.
+        let input = match self.exchange() {
+            Ok(input) => input,
+            Err(e) => {
+                self.io_error = Some(e.clone());
+                return Ok(Some(TerminationReason::Io(e)))
             },
+        };
+        self.count_step();
+        let line = self.current_line;
+        if let Some(visits) = &mut self.line_visits {
+            visits[line] += 1;
+        }
+        self.resume_line = None;
+        if let Some(limit) = self.history_limit {
+            if self.history.len() == limit {
+                self.history.pop_front();
+            }
+            if limit > 0 {
+                self.history.push_back(line);
+            }
+        }
+        if self.breakpoints.contains(&line) {
+            self.breakpoint_hits.update_with_fn(line, &0, |n| n + 1);
+        }
+        if self.counts_register_heat {
+            self.count_register_heat();
+        }
+        if let Some(model) = self.cost_model {
+            self.count_cost(&model);
+        }
+        let watched = if self.watchpoints.is_empty() {
+            None
+        } else {
+            self.modified_register()
+                .filter(|register| self.watchpoints.contains(register))
+                .map(|register| (register, self.memory.get_value(register)))
+        };
+        // Execute the current instruction.
+        let instruction = &self.lines[self.current_line].instruction;
+        if let Instruction::CALL(_) = instruction {
+            self.call_stack.push(self.current_line + 1);
+        }
+        if let (Instruction::READ(register), Some(value)) = (instruction, input) {
+            self.memory.set_register(*register, value);
+        }
+        if let Instruction::RETURN = instruction {
+            // Returning when every CALL has returned halts the machine.
+            self.current_line = self.call_stack.pop().unwrap_or(self.lines.len());
+        }
+        else {
+            match instruction.execute(&mut self.memory) {
+                Some(ident) => {
+                    // A jump to a line or label which doesn't exist halts the machine.
+                    if self.go_to_identifier(&ident).is_err() {
+                        self.current_line = self.lines.len();
+                    }
+                },
+                None => {
+                    self.current_line += 1;
+                },
+            }
+        }
+        if let Some(interval) = self.compaction_interval {
+            if self.steps % interval == 0 {
+                self.memory.compact();
+            }
+        }
+        // Audit the register internals after every step in debug builds.
+        debug_assert!(self.memory.is_normalised(), "Register invariants broken by line {line}.");
+        if let Some((register, old)) = watched {
+            let new = self.memory.get_value(register);
+            return Ok(Some(TerminationReason::Watchpoint { register, old, new }))
         }
         if self.current_line >= self.lines.len() {
             return Ok(Some(TerminationReason::Halted))
@@ -349,41 +1310,450 @@ impl Machine {
 
     /// Run the current line of code, or in other words, take a "step". Does not check if the
     /// machine has halted.
-    fn step_unchecked(&mut self) {
-        self.step().unwrap();
+    fn step_unchecked(&mut self) -> Option<TerminationReason> {
+        self.step().unwrap()
+    }
+
+    /// Count the step about to be taken, both in total and towards the current phase.
+    fn count_step(&mut self) {
+        self.steps += 1;
+        if self.phases.is_empty() {
+            return;
+        }
+        if let Some(Identifier::Label(label)) = &self.lines[self.current_line].id {
+            if self.phases.contains(label) {
+                self.current_phase = Some(label.clone());
+            }
+        }
+        self.phase_steps.update_with_fn(self.current_phase.clone(), &0, |n| n + 1);
+    }
+
+    /// Carry out the input or output of the current instruction, if it has any, and return the
+    /// value read by READ.
+    fn exchange(&self) -> Result<Option<Register>, IoError> {
+        match self.lines[self.current_line].instruction {
+            Instruction::READ(_) => self.io.lock().read().map(Some),
+            Instruction::WRITE(register) => {
+                self.io.lock().write(&self.memory.get_value(register)).map(|()| None)
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Check whether the instruction would go over a cap of the sandbox, apart from the step
+    /// limit, and if so, return the cap.
+    fn exceeded_resource_limit(&self, instruction: &Instruction) -> Option<ResourceLimit> {
+        if let Some(limit) = self.sandbox.registers {
+            let nat_count = self.memory.get_nat_count();
+            let neg_count = self.memory.get_neg_registers().len();
+            let count = match instruction.get_register() {
+                Some(RegisterNumber::Natural(n)) => nat_count.max(n + 1) + neg_count,
+                Some(RegisterNumber::Negative(n)) => nat_count + neg_count.max(n + 1),
+                Some(RegisterNumber::Pseudo(_)) | None => nat_count + neg_count,
+            };
+            if count > limit {
+                return Some(ResourceLimit::Registers(limit));
+            }
+        }
+        if let (Some(limit), Instruction::CALL(_)) = (self.sandbox.calls, instruction) {
+            if self.call_stack.len() >= limit {
+                return Some(ResourceLimit::Calls(limit));
+            }
+        }
+        if !self.steps.is_multiple_of(sandbox::CHECK_INTERVAL) {
+            return None;
+        }
+        if let Some(limit) = self.sandbox.limbs {
+            if self.memory.get_limb_count() > limit {
+                return Some(ResourceLimit::Limbs(limit));
+            }
+        }
+        let elapsed = self.execution_started.map(|started| started.elapsed());
+        match (self.sandbox.time, elapsed) {
+            (Some(limit), Some(elapsed)) if elapsed > limit => Some(ResourceLimit::Time(limit)),
+            _ => None,
+        }
+    }
+
+    /// Check whether the current instruction would modify a protected register, and if so, return
+    /// that register.
+    fn modifies_protected_register(&mut self) -> Option<RegisterNumber> {
+        // Most programs protect nothing, so don't work out the modified register for them.
+        if self.protected_registers.is_empty() {
+            return None;
+        }
+        self.modified_register().filter(|register| self.protected_registers.contains(register))
+    }
+
+    /// Get the register the current instruction would modify, if any.
+    fn modified_register(&mut self) -> Option<RegisterNumber> {
+        modified_register(&self.lines[self.current_line].instruction, &mut self.memory)
+    }
+
+    /// Count how the current instruction is about to use its register.
+    fn count_register_heat(&mut self) {
+        let instruction = &self.lines[self.current_line].instruction;
+        let Some(register) = instruction.get_register() else {
+            return;
+        };
+        let heat = match instruction {
+            Instruction::INC(_) => RegisterHeat { increments: 1, ..RegisterHeat::default() },
+            Instruction::WRITE(_) => RegisterHeat { reads: 1, ..RegisterHeat::default() },
+            // The register's value comes from outside, so it's neither read nor changed by a step.
+            Instruction::READ(_) => RegisterHeat::default(),
+            _ => RegisterHeat {
+                reads: 1,
+                increments: 0,
+                decrements: usize::from(!self.memory.is_zero(register)),
+            },
+        };
+        self.register_heat
+            .update_with_fn(register, &RegisterHeat::default(), |total| *total + heat);
+    }
+
+    /// Add the cost of the current instruction under the given model.
+    fn count_cost(&mut self, model: &CostModel) {
+        let instruction = &self.lines[self.current_line].instruction;
+        let is_zero =
+            instruction.get_register().is_some_and(|register| self.memory.is_zero(register));
+        let cost = model.cost(instruction, is_zero);
+        self.cost = self.cost.saturating_add(cost);
+        if !self.phases.is_empty() {
+            // The step has already been counted, so the current phase is up to date.
+            self.phase_costs
+                .update_with_fn(self.current_phase.clone(), &0, |total| total.saturating_add(cost));
+        }
+    }
+
+    /// Check whether the current instruction would jump to a line past the end of the program or
+    /// to a label which doesn't exist. Jumping to [`Identifier::Halt`] is always allowed.
+    fn jumps_outside_program(&mut self) -> bool {
+        let instruction = &self.lines[self.current_line].instruction;
+        let jumps = match instruction {
+            Instruction::DECJZ(register, _) => self.memory.is_zero(*register),
+            Instruction::DECJNZ(register, _) => !self.memory.is_zero(*register),
+            Instruction::GOTO(_) | Instruction::CALL(_) => true,
+            Instruction::INC(_)
+            | Instruction::READ(_)
+            | Instruction::WRITE(_)
+            | Instruction::RETURN
+            | Instruction::HALT => false,
+        };
+        match instruction.get_jump_target() {
+            Some(Identifier::Line(n)) => jumps && *n >= self.lines.len(),
+            Some(Identifier::Label(label)) => jumps && self.labels.get(label).is_none(),
+            Some(Identifier::Halt) | None => false,
+            Some(Identifier::Relative(_) | Identifier::LabelOffset(..)) => unreachable!(),
+        }
     }
 
     // Getting state.
 
     /// Get a string representation of the state of the (natural) registers.
-    /// 
-    /// # Panics
-    /// 
-    /// * If the value of any register is larger than 2^128 - 1, then this will panic!
     #[must_use]
     pub fn display_nat_registers(&self) -> String {
         format!("{}", self.memory)
     }
 
     /// Get a string representation of the state of a specific register.
-    /// 
-    /// # Panics
-    /// 
-    /// * If the value of any register is larger than 2^128 - 1, then this will panic!
     pub fn display_register(&self, register_number: RegisterNumber) -> String {
         self.memory.get_register(register_number)
     }
 
     /// Get the state of all registers.
-    /// 
-    /// # Panics
-    /// 
-    /// * If the value of any register is larger than 2^128 - 1, then this will panic!
     #[must_use]
     pub fn get_state(&self) -> &Memory {
         &self.memory
     }
 
+    /// Get the state of all registers when the machine was constructed.
+    #[must_use]
+    pub const fn get_initial_memory(&self) -> &Memory {
+        &self.initial_memory
+    }
+
+    /// Get every label and the line number it points to, in the order the labels are first
+    /// defined.
+    #[must_use]
+    pub fn get_labels(&self) -> &[(String, LineNumber)] {
+        &self.labels.vec
+    }
+
+    /// Get the labels which are defined on more than one line, pairing each extra definition with
+    /// the one before it.
+    #[must_use]
+    pub fn get_duplicate_labels(&self) -> Vec<DuplicateLabel> {
+        self.duplicate_labels
+            .iter()
+            .map(|(label, first, second)| DuplicateLabel {
+                label: label.clone(),
+                first_line: self.get_source_line(*first),
+                second_line: self.get_source_line(*second),
+            })
+            .collect()
+    }
+
+    /// Get the line number which a label points to, if the label exists.
+    #[must_use]
+    pub fn get_label_line(&self, label: &str) -> Option<LineNumber> {
+        self.labels.get(&label.to_string()).copied()
+    }
+
+    /// Get the documentation of a label, if it has any.
+    #[must_use]
+    pub fn get_label_documentation(&self, label: &str) -> Option<&str> {
+        self.label_documentation.get(&label.to_string()).map(String::as_str)
+    }
+
+    /// Get the source code of the program, starting with its initial registers.
+    ///
+    /// Parsing the result gives back an equivalent machine. Comments and blank lines from the
+    /// original source code aren't kept, except for the documentation of labels.
+    #[must_use]
+    pub fn to_source(&self) -> String {
+        format!("{}\n{}", self.initial_memory, self.program_source())
+    }
+
+    /// Encode the program, its directives and its initial registers as compact bytecode (see
+    /// [`crate::bytecode`]), which [`Machine::from_bytes`] loads without parsing any source code.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bytecode::encode(self)
+    }
+
+    /// Get a stable hash of the program, which ignores its initial registers, comments and
+    /// formatting.
+    ///
+    /// Two machines have the same fingerprint exactly when their instructions, labels, label
+    /// documentation and directives are the same, so it can be used to tell whether a program has
+    /// changed. The hash (64-bit FNV-1a) doesn't depend on the platform or the version of Rust.
+    #[must_use]
+    pub fn fingerprint(&self) -> u64 {
+        fnv1a(FNV_OFFSET_BASIS, self.program_source().as_bytes())
+    }
+
+    /// Get the source code of the program without its registers line.
+    fn program_source(&self) -> String {
+        let mut source = String::new();
+        for phase in &self.phases {
+            source.push_str(&format!("\n#!phase {phase}"));
+        }
+        if let Some(entry) = &self.entry {
+            source.push_str(&format!("\n#!entry {entry}"));
+        }
+        for export in &self.exports {
+            source.push_str(&format!("\n#!export {export}"));
+        }
+        for import in &self.imports {
+            source.push_str(&format!("\n#!import {import}"));
+        }
+        if self.line_visits.is_some() {
+            source.push_str("\n#!extended");
+        }
+        for line in &self.lines {
+            match &line.id {
+                Some(Identifier::Label(label)) => {
+                    let documentation = self.get_label_documentation(label).unwrap_or_default();
+                    for line in documentation.lines() {
+                        source.push_str(format!("\n## {line}").trim_end());
+                    }
+                    source.push_str(&format!("\n{label}: {}", line.relative_instruction()));
+                },
+                _ => source.push_str(&format!("\n{}", line.relative_instruction())),
+            }
+        }
+        source
+    }
+
+    /// Get all lines of the program.
+    #[must_use]
+    pub fn get_lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Get the index of where each label and register is referenced in the program.
+    #[must_use]
+    pub const fn get_cross_reference(&self) -> &CrossReference {
+        &self.xref
+    }
+
+    /// Get the number of steps taken since the machine was constructed or last reset.
+    #[must_use]
+    pub const fn get_steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Get the labels which have been marked as the start of a phase.
+    #[must_use]
+    pub fn get_phases(&self) -> &[String] {
+        &self.phases
+    }
+
+    /// Get the label execution starts at, if it isn't the first line.
+    #[must_use]
+    pub fn get_entry(&self) -> Option<&str> {
+        self.entry.as_deref()
+    }
+
+    /// Get the labels which other programs may jump to when linked. If there are none, every label
+    /// may be used.
+    #[must_use]
+    pub fn get_exports(&self) -> &[String] {
+        &self.exports
+    }
+
+    /// Get the labels of other programs which this program declares it jumps to when linked.
+    #[must_use]
+    pub fn get_imports(&self) -> &[String] {
+        &self.imports
+    }
+
+    /// Get the line number execution starts at.
+    #[must_use]
+    pub fn get_entry_line(&self) -> LineNumber {
+        self.entry
+            .as_ref()
+            .and_then(|label| self.labels.get(label))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Get the number of steps spent in each phase, in the order the phases were first entered.
+    ///
+    /// Steps taken before reaching the first phase are counted under [`None`].
+    #[must_use]
+    pub fn get_phase_steps(&self) -> &[(Option<String>, usize)] {
+        &self.phase_steps.vec
+    }
+
+    /// Get the cost of the steps spent in each phase under the model given to
+    /// [`Machine::set_cost_model`], in the same order as [`Machine::get_phase_steps`]. Empty if
+    /// there's no cost model.
+    #[must_use]
+    pub fn get_phase_costs(&self) -> &[(Option<String>, u64)] {
+        &self.phase_costs.vec
+    }
+
+    /// Get how each register has been used since the last reset, hottest (most used) first, while
+    /// counting is enabled with [`Machine::set_register_heat`]. Registers which are equally hot
+    /// are in ascending order.
+    #[must_use]
+    pub fn get_register_heat(&self) -> Vec<(RegisterNumber, RegisterHeat)> {
+        let mut heat = self.register_heat.vec.clone();
+        heat.sort_by_key(|&(register, heat)| (std::cmp::Reverse(heat.total()), register));
+        heat
+    }
+
+    /// Get the model costs are counted with, if there is one.
+    #[must_use]
+    pub const fn get_cost_model(&self) -> Option<&CostModel> {
+        self.cost_model.as_ref()
+    }
+
+    /// Get the total cost of the steps taken since the last reset, under the model given to
+    /// [`Machine::set_cost_model`], or 0 if there isn't one.
+    #[must_use]
+    pub const fn get_cost(&self) -> u64 {
+        self.cost
+    }
+
+    /// Get the registers which have a watchpoint, in ascending order.
+    #[must_use]
+    pub fn get_watchpoints(&self) -> &[RegisterNumber] {
+        &self.watchpoints
+    }
+
+    /// Get the line numbers which have a breakpoint, in ascending order.
+    #[must_use]
+    pub fn get_breakpoints(&self) -> &[usize] {
+        &self.breakpoints
+    }
+
+    /// Get the most recently executed lines since the last reset, oldest first. Lines are only
+    /// recorded after a limit is set with [`Machine::set_history_limit`].
+    #[must_use]
+    pub const fn get_history(&self) -> &VecDeque<LineNumber> {
+        &self.history
+    }
+
+    /// Get the lines which the CALLs that haven't returned yet will return to, oldest first.
+    #[must_use]
+    pub fn get_call_stack(&self) -> &[LineNumber] {
+        &self.call_stack
+    }
+
+    /// Get the most recent jumps in the history, most recent first, as pairs of the line which
+    /// jumped and the line it jumped to. Jumping past the last line (halting) is included.
+    #[must_use]
+    pub fn get_backtrace(&self) -> Vec<(LineNumber, LineNumber)> {
+        let next_lines = self.history.iter().skip(1).chain(std::iter::once(&self.current_line));
+        let mut jumps: Vec<(LineNumber, LineNumber)> = self.history
+            .iter()
+            .zip(next_lines)
+            .filter(|&(&from, &to)| to != from + 1 || to == self.lines.len())
+            .map(|(&from, &to)| (from, to))
+            .collect();
+        jumps.reverse();
+        jumps
+    }
+
+    /// Describe a line briefly, such as "line 3 (loop)", or "HALT" if it's past the end of the
+    /// program.
+    #[must_use]
+    pub fn describe_line(&self, n: LineNumber) -> String {
+        self.lines.get(n).map_or_else(
+            || String::from("HALT"),
+            |line| match line.get_id() {
+                Some(Identifier::Label(label)) => format!("line {n} ({label})"),
+                _ => format!("line {n}"),
+            },
+        )
+    }
+
+    /// Get how many times the line with the given breakpoint has been executed since the last
+    /// reset.
+    #[must_use]
+    pub fn get_breakpoint_hits(&self, line: LineNumber) -> usize {
+        self.breakpoint_hits.get(&line).copied().unwrap_or(0)
+    }
+
+    /// Get which execution of its line the given breakpoint stops on, if it doesn't stop on every
+    /// execution.
+    #[must_use]
+    pub fn get_breakpoint_hit_target(&self, line: LineNumber) -> Option<usize> {
+        self.breakpoint_hit_targets.get(&line).copied()
+    }
+
+    /// Find what differs between this machine and another one.
+    #[must_use]
+    pub fn diff(&self, other: &Machine) -> MachineDiff {
+        MachineDiff::new(self, other)
+    }
+
+    /// Step this machine and another together until their natural registers differ, or whether
+    /// they've stopped differs, giving up after `max_steps` steps. See [`Lockstep`].
+    pub fn lockstep(&mut self, other: &mut Machine, max_steps: usize) -> Lockstep {
+        Lockstep::run(self, other, max_steps)
+    }
+
+    /// Find static metrics about the program, such as how many instructions of each type it has.
+    #[must_use]
+    pub fn metrics(&self) -> Metrics {
+        Metrics::new(self)
+    }
+
+    /// Find the lines which can affect the given register's final value. See [`Slice`].
+    #[must_use]
+    pub fn slice(&self, register: RegisterNumber) -> Slice<'_> {
+        Slice::new(self, register)
+    }
+
+    /// Find the common idioms in the program and what they compute. See [`Explanation`].
+    #[must_use]
+    pub fn explain(&self) -> Explanation<'_> {
+        Explanation::new(self)
+    }
+
     /// Get the current line number which the instruction pointer is pointing to.
     #[must_use]
     pub fn get_current_line_number(&self) -> usize {
@@ -403,3 +1773,11 @@ impl Machine {
         &self.lines[self.current_line]
     }
 }
+
+/// Displays the current registers followed by an annotated [`Listing`] of the program.
+impl Display for Machine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.memory)?;
+        write!(f, "{}", Listing::new(self))
+    }
+}
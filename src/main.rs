@@ -16,82 +16,1126 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 
 #![warn(clippy::all, clippy::pedantic, clippy::nursery)]
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-use std::io::{self, Read, Write,};
+use std::{
+    fs::File,
+    io::{self, BufReader, IsTerminal, Read, Write,},
+    num::{NonZeroU32, NonZeroUsize},
+    path::{ Path, PathBuf },
+    time::Duration,
+};
 
-use remuir::{machine::Machine, parser};
+use std::ops::RangeInclusive;
 
-mod text_literals;
-mod tui;
-
-use tui::{printers, Mode, RemuirError};
-#[allow(clippy::wildcard_imports)]
-use text_literals::*;
+use remuir::{
+    analysis::Analysis,
+    autosave::Autosave,
+    cache::Cache,
+    compare::Comparison,
+    concurrent::{ Assertion, Concurrent, Scheduler },
+    cost::CostModel,
+    doctor::Report,
+    crash,
+    dialect::{ Dialect, Registry },
+    examples,
+    import::{ self, ColumnMapping },
+    io_handler::{ BufferIo, LineIo },
+    interrupt,
+    link,
+    machine::{ Machine, RuntimeError },
+    manifest::Manifest,
+    obfuscate,
+    memory::{ Register, RegisterNumber },
+    optimize,
+    parser,
+    pressure::RegisterPressure,
+    rpc,
+    runaway::RunawaySummary,
+    sandbox::Sandbox,
+    specialize,
+    test_block::{ self, TestOutcome },
+    timeline::Timeline,
+    trace::{ SampledTrace, TraceConfig },
+    tui::{self, RemuirError, Terminal},
+    watch::TestWatcher,
+};
+#[cfg(feature = "emit")]
+use remuir::emit;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
+#[allow(clippy::struct_excessive_bools)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short, long)]
     repl: bool,
     #[arg(short, long)]
-    debug: Option<std::path::PathBuf>,
+    debug: Option<PathBuf>,
+    /// Run the given source code instead of reading a program from STDIN, which leaves STDIN for
+    /// `read` instructions. Lines may be separated by newlines or by "\n", as in
+    /// "registers 2 3\ninc r0".
+    #[arg(short, long, value_name = "PROGRAM", conflicts_with_all = ["repl", "debug"])]
+    eval: Option<String>,
+    /// Run a program compiled with "remuir compile" instead of reading one from STDIN, which
+    /// skips parsing it.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "eval", "repl", "debug", "specialize", "link", "permissive", "dialect",
+        ],
+    )]
+    bytecode: Option<PathBuf>,
+    /// Instead of running the program, print a version specialised for a known register value,
+    /// given as e.g. "r2=5". May be used more than once.
+    #[arg(long, value_name = "REGISTER=VALUE", value_parser = parse_known_register)]
+    specialize: Vec<(RegisterNumber, u128)>,
+    /// Remove redundant instructions from the program before running or specialising it.
+    #[arg(short = 'O', long)]
+    optimize: bool,
+    /// Write a timeline of the execution to the given file, in the Chrome trace-event format.
+    #[arg(long, value_name = "PATH")]
+    timeline: Option<PathBuf>,
+    /// Link a library into the program. Its labels are scoped by its file name, so a library
+    /// in "maths.remuir" is jumped into with e.g. `maths::add`. May be used more than once.
+    #[arg(long, value_name = "PATH")]
+    link: Vec<PathBuf>,
+    /// Free registers which are 0 at the end of memory every N steps, which saves memory on long
+    /// runs. The output is unchanged.
+    #[arg(long, value_name = "N")]
+    compact_every: Option<NonZeroUsize>,
+    /// Allow labels to be defined more than once, and jumps to labels which aren't defined,
+    /// printing a warning instead of failing. Jumps go to the last definition, or halt.
+    #[arg(long)]
+    permissive: bool,
+    /// Read programs written in another syntax, such as a textbook's. Without it, a file's
+    /// dialect is chosen by its extension, and a program on STDIN is in remuir's own syntax.
+    #[arg(long, value_name = "NAME", value_parser = parse_dialect)]
+    dialect: Option<String>,
+    /// Treat a jump past the end of the program, or to a label which doesn't exist, as an error
+    /// reporting the offending line, rather than as a way to halt.
+    #[arg(long)]
+    strict_jumps: bool,
+    /// Keep parsed, linked and optimised programs in a cache in ~/.cache/remuir, and load them
+    /// from there when the same program is run again. Ignored with --permissive.
+    #[arg(long)]
+    cache: bool,
+    /// With --debug, save the session in ~/.local/state/remuir/sessions after every so many
+    /// commands (1 by default), and offer to restore it if it wasn't quit. Overrides the autosave
+    /// setting of ~/.config/remuir/config.
+    #[arg(
+        long,
+        value_name = "COMMANDS",
+        num_args = 0..=1,
+        default_missing_value = "1",
+        requires = "debug",
+    )]
+    autosave: Option<NonZeroUsize>,
+    /// Run slowly, redrawing a status line with the current line and registers on STDERR after
+    /// every step. Useful for demonstrations.
+    #[arg(long, conflicts_with = "timeline")]
+    live: bool,
+    /// Sample the line about to be executed every N steps, and print a report of where time was
+    /// spent to STDERR. Much cheaper than --timeline for long runs.
+    #[arg(long, value_name = "N", conflicts_with_all = ["timeline", "live"])]
+    sample_every: Option<NonZeroUsize>,
+    /// Count how many times each register is read, incremented and decremented, and print the
+    /// hottest ones to STDERR.
+    #[arg(long)]
+    heat: bool,
+    /// Add up the cost of every step and print it to STDERR with the number of steps. Each kind
+    /// of instruction costs 1 unless given otherwise, as in `decjz_taken=2,halt=0`. The costs are
+    /// `inc`, `decjz_taken`, `decjz_not_taken`, `decjnz_taken`, `decjnz_not_taken`, `goto`,
+    /// `call`, `return` and `halt`.
+    /// The cost of each phase is printed too, and shown by the `phases` command with --debug.
+    #[arg(long, value_name = "COSTS", num_args = 0..=1, default_missing_value = "")]
+    cost: Option<CostModel>,
+    /// While sampling, also count every jump to the given label. May be used more than once.
+    #[arg(long, value_name = "LABEL", conflicts_with_all = ["timeline", "live"])]
+    sample_label: Vec<String>,
+    /// Give up if the program hasn't halted after this many steps.
+    #[arg(
+        long,
+        value_name = "STEPS",
+        conflicts_with_all = ["timeline", "live", "sample_every", "sample_label"],
+    )]
+    max_steps: Option<usize>,
+    /// Give up if the program is still running after this many seconds, such as 2.5.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    max_time: Option<Duration>,
+    /// Give up before the program uses more than this many registers, natural and negative.
+    #[arg(long, value_name = "N")]
+    max_registers: Option<usize>,
+    /// Give up if the registers take up more than this many limbs of 128 bits (16 bytes) in total.
+    #[arg(long, value_name = "N")]
+    max_limbs: Option<usize>,
+    /// Give up before the program has more than this many calls waiting to return.
+    #[arg(long, value_name = "N")]
+    max_calls: Option<usize>,
+    /// Read the values for `read` instructions from this file, one per line. STDIN holds the
+    /// program, or the commands with --debug, so without this or --eval there's nothing to read.
+    #[arg(long, value_name = "PATH")]
+    input: Option<PathBuf>,
+    /// Redraw a status line on STDERR about twice a second with the steps taken, the steps per
+    /// second and, with --max-steps, roughly how long until the limit is reached. Useful for long
+    /// runs, which otherwise look like they've hung.
+    #[arg(long, conflicts_with_all = ["timeline", "live", "sample_every", "sample_label"])]
+    progress: bool,
+    /// Count the nonzero registers and find the highest nonzero one every N steps (every step by
+    /// default), and print them as sparklines to STDERR, showing how much space the program uses.
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "1",
+        conflicts_with_all = ["timeline", "live", "sample_every", "sample_label", "progress"],
+    )]
+    pressure: Option<NonZeroUsize>,
+    /// With --pressure, also write every sample to the given file as CSV.
+    #[arg(long, value_name = "PATH", requires = "pressure")]
+    pressure_csv: Option<PathBuf>,
+    /// Send the steps taken and the registers to the given address, such as "127.0.0.1:9000", as
+    /// OSC messages over UDP while the program runs, such as to drive a display.
+    #[cfg(feature = "emit")]
+    #[arg(
+        long,
+        value_name = "HOST:PORT",
+        conflicts_with_all = [
+            "timeline", "live", "sample_every", "sample_label", "progress", "pressure",
+        ],
+    )]
+    emit_osc: Option<String>,
+    /// How many milliseconds to wait at least between messages with --emit-osc.
+    #[cfg(feature = "emit")]
+    #[arg(long, value_name = "MS", default_value_t = 100, requires = "emit_osc")]
+    emit_every: u64,
+    /// How many steps to take per second with --live.
+    #[arg(
+        long,
+        value_name = "STEPS",
+        default_value_t = NonZeroU32::new(10).unwrap(),
+        requires = "live",
+    )]
+    speed: NonZeroU32,
+}
+
+impl Cli {
+    /// Get the caps on the resources a run may use, from --max-steps, --max-time, --max-registers,
+    /// --max-limbs and --max-calls.
+    const fn sandbox(&self) -> Sandbox {
+        Sandbox {
+            steps: self.max_steps,
+            time: self.max_time,
+            registers: self.max_registers,
+            limbs: self.max_limbs,
+            calls: self.max_calls,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that a program parses (and links), and optionally analyse it.
+    Check {
+        path: PathBuf,
+        /// Print only the lines which can affect the given register's final value.
+        #[arg(long, value_name = "REGISTER")]
+        slice: Option<RegisterNumber>,
+        /// Fail if there are any warnings, such as unused labels or unreachable lines.
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+    /// Run a program many times on random inputs, and report how many steps it took.
+    Analyze {
+        path: PathBuf,
+        /// How many times to run the program.
+        #[arg(long, value_name = "RUNS", default_value_t = 100)]
+        random: usize,
+        /// The seed for the random inputs. The same seed always gives the same inputs.
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Set a natural register to a random value in the given inclusive range on every run,
+        /// given as e.g. "r1=0..20". May be used more than once. Other registers keep their
+        /// values from the registers line.
+        #[arg(long, value_name = "REGISTER=LOW..HIGH", value_parser = parse_register_range)]
+        range: Vec<(usize, RangeInclusive<u128>)>,
+        /// Stop a run which hasn't halted after this many steps.
+        #[arg(long, value_name = "STEPS", default_value_t = 100_000)]
+        step_limit: usize,
+    },
+    /// Run two programs on every combination of inputs in a grid, and print a table of the steps
+    /// each took and which was faster, with how many times fewer steps the faster one took
+    /// overall.
+    Compare {
+        first: PathBuf,
+        second: PathBuf,
+        /// Set a natural register to every value in the given inclusive range, as in "r1=0..5".
+        /// May be used more than once, in which case every combination of values is run. Other
+        /// registers keep their values from each program's registers line.
+        #[arg(long, value_name = "REGISTER=LOW..HIGH", value_parser = parse_register_range)]
+        inputs: Vec<(usize, RangeInclusive<u128>)>,
+        /// Stop a run which hasn't halted after this many steps.
+        #[arg(long, value_name = "STEPS", default_value_t = 100_000)]
+        step_limit: usize,
+    },
+    /// (Experimental) Run programs interleaved over shared registers, starting with the first
+    /// program's registers line, and print the registers once they've all halted.
+    Concurrent {
+        #[arg(required = true, num_args = 2..)]
+        paths: Vec<PathBuf>,
+        /// Pick the next program to step at random with this seed, instead of taking turns.
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Give up after this many steps in total.
+        #[arg(long, value_name = "STEPS", default_value_t = 100_000)]
+        step_limit: usize,
+        /// Instead of running once, try every interleaving of up to this many steps and report
+        /// whether any breaks the assertion given with --assert.
+        #[arg(long, value_name = "STEPS", conflicts_with = "seed")]
+        explore: Option<usize>,
+        /// What --explore checks in every state, given as e.g. "r0 <= 1". Without it, --explore
+        /// only counts the interleavings.
+        #[arg(long = "assert", value_name = "CONDITION", requires = "explore")]
+        assertion: Option<Assertion>,
+    },
+    /// Run the test blocks in a program's source code, such as
+    /// `#test adds { registers 2 3 / expect registers 0 5 }`.
+    Test {
+        /// A program, or with --watch, a directory of programs.
+        path: PathBuf,
+        /// Keep running, and rerun the tests of each program whenever it changes, printing which
+        /// tests were fixed or broken.
+        #[arg(short, long)]
+        watch: bool,
+        /// How often --watch checks for changes, in milliseconds.
+        #[arg(long, value_name = "MILLISECONDS", default_value_t = 500, requires = "watch")]
+        interval: u64,
+    },
+    /// Print a report on a program to attach to a bug report: the version, whether it parses,
+    /// its metrics and configuration, and how a short run of it ends. If remuir crashes, the
+    /// report ends with the smallest program found which still crashes.
+    Doctor {
+        path: PathBuf,
+    },
+    /// Print a program's listing, with a comment summarising each common idiom found in it, such
+    /// as a loop which adds one register to another.
+    Explain {
+        path: PathBuf,
+    },
+    /// Print a program which computes the same result but is harder to read: its labels are
+    /// renamed, its blocks are shuffled and its comments are removed.
+    Obfuscate {
+        path: PathBuf,
+        /// The seed for shuffling and naming. The same seed always gives the same program.
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Print a program's source code in one consistent style, with the instructions lined up
+    /// after the labels, single spaces between words and the comments kept where they are.
+    Fmt {
+        path: PathBuf,
+        /// Overwrite the file with the formatted source code instead of printing it.
+        #[arg(long, conflicts_with = "check")]
+        write: bool,
+        /// Print nothing, and fail if the file isn't formatted already.
+        #[arg(long)]
+        check: bool,
+    },
+    /// Compile a program into compact bytecode, which "remuir --bytecode" runs without parsing it.
+    /// With -O, the program is optimised first.
+    Compile {
+        path: PathBuf,
+        /// Where to write the bytecode. By default, it's the program's path with the extension
+        /// ".rmbc".
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Convert a transition table in CSV into source code, and print it.
+    Import {
+        path: PathBuf,
+        /// Use a different column name for a field, given as e.g. "state=Label". The fields are
+        /// state, instruction, register, next and target. May be used more than once.
+        #[arg(long, value_name = "FIELD=NAME", value_parser = parse_column)]
+        column: Vec<(String, String)>,
+        /// The initial registers of the imported program.
+        #[arg(long, value_name = "NUMBERS", default_value = "0")]
+        registers: String,
+    },
+    /// Drive a machine with JSON-RPC 2.0 requests on STDIN, one per line. See the rpc module for
+    /// the available methods.
+    Rpc {
+        /// Send the negative registers as "scratch" alongside the natural registers, for programs
+        /// which leave their results there.
+        #[arg(long)]
+        include_scratch: bool,
+    },
+    /// List, show or run the bundled example programs.
+    Examples {
+        #[command(subcommand)]
+        command: ExamplesCommand,
+    },
+    /// Manage the cache of parsed, linked and optimised programs.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand, Clone, Copy)]
+enum CacheCommand {
+    /// Remove every cached program.
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum ExamplesCommand {
+    /// List the names of the examples, with a short description of each.
+    List,
+    /// Print the source code of an example.
+    Show { name: String },
+    /// Run an example and print its registers. The inputs replace the example's registers,
+    /// starting from r0, like a registers line.
+    Run {
+        name: String,
+        inputs: Vec<u128>,
+    },
+}
+
+fn parse_known_register(s: &str) -> Result<(RegisterNumber, u128), String> {
+    let Some((register, value)) = s.split_once('=') else {
+        return Err(String::from("expected a register and value such as \"r2=5\""));
+    };
+    let register = register.trim().parse::<RegisterNumber>().map_err(|e| e.to_string())?;
+    let value = value.trim().parse::<u128>().map_err(|e| e.to_string())?;
+    Ok((register, value))
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds = s.trim().parse::<f64>().map_err(|e| e.to_string())?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+fn parse_register_range(s: &str) -> Result<(usize, RangeInclusive<u128>), String> {
+    let usage = || String::from("expected a register and range such as \"r1=0..20\"");
+    let (register, range) = s.split_once('=').ok_or_else(usage)?;
+    let RegisterNumber::Natural(register) = register.trim().parse::<RegisterNumber>()
+        .map_err(|e| e.to_string())?
+    else {
+        return Err(String::from("only natural registers can be given random values"));
+    };
+    let (low, high) = range.split_once("..").ok_or_else(usage)?;
+    let low = low.trim().parse::<u128>().map_err(|e| e.to_string())?;
+    let high = high.trim().trim_start_matches('=').parse::<u128>().map_err(|e| e.to_string())?;
+    if low > high {
+        return Err(format!("the range {low}..{high} is empty"));
+    }
+    Ok((register, low..=high))
+}
+
+fn parse_dialect(s: &str) -> Result<String, String> {
+    let names = Registry::default().get_names().join(", ");
+    if names.split(", ").any(|name| name.eq_ignore_ascii_case(s.trim())) {
+        Ok(s.trim().to_string())
+    }
+    else {
+        Err(format!("there is no dialect called \"{}\". The dialects are {names}", s.trim()))
+    }
+}
+
+fn parse_column(s: &str) -> Result<(String, String), String> {
+    let Some((field, name)) = s.split_once('=') else {
+        return Err(String::from("expected a field and column name such as \"state=Label\""));
+    };
+    if !ColumnMapping::default().set(field.trim(), name.trim()) {
+        return Err(format!("there is no field called \"{}\"", field.trim()));
+    }
+    Ok((field.trim().to_string(), name.trim().to_string()))
 }
 
 fn main() -> tui::ExitStatus {
     let cli = Cli::parse();
-    if cli.repl {
-        tui::ExitStatus::from(repl())
+    let dialects = Registry::default();
+    let loader = Loader {
+        libraries: &cli.link,
+        permissive: cli.permissive,
+        strict_jumps: cli.strict_jumps,
+        dialects: &dialects,
+        dialect: cli.dialect.as_deref(),
+        eval: cli.eval.as_deref(),
+        bytecode: cli.bytecode.as_deref(),
+    };
+    if let Some(command) = cli.command {
+        match command {
+            Command::Check { path, slice, deny_warnings } => {
+                tui::ExitStatus::from(check(path, slice, deny_warnings, &loader))
+            },
+            Command::Analyze { path, random, seed, range, step_limit } => {
+                tui::ExitStatus::from(analyze(&path, random, seed, &range, step_limit, &loader))
+            },
+            Command::Compare { first, second, inputs, step_limit } => {
+                tui::ExitStatus::from(compare(&first, &second, &inputs, step_limit, &loader))
+            },
+            Command::Concurrent { paths, explore: Some(steps), assertion, .. } => {
+                tui::ExitStatus::from(explore_interleavings(&paths, steps, assertion, &loader))
+            },
+            Command::Concurrent { paths, seed, step_limit, .. } => {
+                tui::ExitStatus::from(concurrent(&paths, seed, step_limit, &loader))
+            },
+            Command::Test { path, watch: true, interval } => {
+                tui::ExitStatus::from(watch_tests(path, interval, &loader))
+            },
+            Command::Test { path, .. } => tui::ExitStatus::from(test(&path, &loader)),
+            Command::Doctor { path } => {
+                tui::ExitStatus::from(doctor(&path, cli.optimize, &loader))
+            },
+            Command::Explain { path } => tui::ExitStatus::from(explain(&path, &loader)),
+            Command::Obfuscate { path, seed } => {
+                tui::ExitStatus::from(obfuscate(&path, seed, &loader))
+            },
+            Command::Fmt { path, write, check } => tui::ExitStatus::from(fmt(&path, write, check)),
+            Command::Compile { path, output } => {
+                tui::ExitStatus::from(compile(&path, output, cli.optimize, &loader))
+            },
+            Command::Import { path, column, registers } => {
+                tui::ExitStatus::from(import(&path, &column, &registers))
+            },
+            Command::Rpc { include_scratch } => tui::ExitStatus::from(rpc(include_scratch)),
+            Command::Examples { command } => tui::ExitStatus::from(examples(command)),
+            Command::Cache { command } => tui::ExitStatus::from(cache(command)),
+        }
+    }
+    else if cli.repl {
+        crash::install_hook();
+        tui::ExitStatus::from(repl(cli.sandbox()))
     }
     else if let Some(path) = cli.debug {
-        tui::ExitStatus::from(debug(path))
+        crash::install_hook();
+        interrupt::install_handler();
+        tui::ExitStatus::from(debug(&path, &loader, cli.cost, cli.autosave, cli.input.as_ref()))
+    }
+    else if !cli.specialize.is_empty() {
+        tui::ExitStatus::from(specialize(&cli.specialize, cli.optimize, &loader))
     }
     else {
-        tui::ExitStatus::from(run())
+        let recording = Recording::choose(&cli);
+        // Warnings about duplicate labels are only printed when the program is parsed.
+        let cache = if cli.cache && !cli.permissive {
+            Cache::default_directory().map(Cache::new)
+        }
+        else {
+            None
+        };
+        tui::ExitStatus::from(
+            run(
+                cli.optimize,
+                recording,
+                &loader,
+                cache.as_ref(),
+                cli.compact_every,
+                cli.sandbox(),
+                cli.heat,
+                cli.cost,
+                cli.input.as_ref(),
+            )
+        )
+    }
+}
+
+/// How many registers --heat reports.
+const HOTTEST_REGISTERS: usize = 10;
+
+/// How to turn the source code of a program into a machine.
+struct Loader<'a> {
+    /// Libraries to link the program with.
+    libraries: &'a [PathBuf],
+    /// Whether labels may be defined more than once, with a warning.
+    permissive: bool,
+    /// Whether jumping outside the program is an error.
+    strict_jumps: bool,
+    /// The dialects which programs may be written in.
+    dialects: &'a Registry,
+    /// The dialect every program is written in, if it isn't chosen by file extension.
+    dialect: Option<&'a str>,
+    /// The program given with --eval, which is read instead of the one on STDIN.
+    eval: Option<&'a str>,
+    /// The compiled program given with --bytecode, which is loaded instead of the one on STDIN.
+    bytecode: Option<&'a Path>,
+}
+
+impl Loader<'_> {
+    /// Find the dialect a program is written in, or `None` if it's remuir's own syntax.
+    fn dialect(&self, path: Option<&Path>) -> Option<&dyn Dialect> {
+        self.dialect.map_or_else(
+            || path.and_then(|path| self.dialects.for_path(path)),
+            |name| self.dialects.find(name),
+        )
+    }
+
+    /// Translate a program from its dialect into remuir source code.
+    fn translate(&self, source: String, path: Option<&Path>) -> Result<String, RemuirError> {
+        match self.dialect(path) {
+            Some(dialect) => Ok(dialect.translate(&source)?),
+            None => Ok(source),
+        }
+    }
+
+    /// Read the program given with --eval, or otherwise the one on STDIN, as remuir source code.
+    fn read_program(&self) -> Result<String, RemuirError> {
+        let source = if let Some(source) = self.eval {
+            source.replace("\\n", "\n")
+        }
+        else {
+            let mut buffer = String::new();
+            io::stdin().read_to_string(&mut buffer)?;
+            buffer
+        };
+        self.translate(source, None)
+    }
+
+    /// Read a program from a file as remuir source code.
+    fn read(&self, path: impl AsRef<Path>) -> Result<String, RemuirError> {
+        let path = path.as_ref();
+        self.translate(std::fs::read_to_string(path)?, Some(path))
+    }
+
+    /// Parse a program and link it with the libraries, if there are any.
+    fn load(&self, source: &str) -> Result<Machine, RemuirError> {
+        let machine = if self.permissive {
+            let machine = parser::parse_str_permissive(source)?;
+            for duplicate in machine.get_duplicate_labels() {
+                eprintln!("Warning: {duplicate} Jumps to it go to the last definition.");
+            }
+            for undefined in parser::find_undefined_labels(&machine) {
+                eprintln!("Warning: {undefined} The jump halts the machine.");
+            }
+            machine
+        }
+        else {
+            parser::parse_str(source)?
+        };
+        let mut machine = if self.libraries.is_empty() {
+            machine
+        }
+        else {
+            self.link(&machine)?
+        };
+        machine.set_strict_jumps(self.strict_jumps);
+        Ok(machine)
+    }
+
+    /// Link a program with the libraries.
+    fn link(&self, machine: &Machine) -> Result<Machine, RemuirError> {
+        let mut linked_libraries = Vec::new();
+        for (name, source) in self.read_libraries()? {
+            linked_libraries.push((name, parser::parse_library_str(&source)?));
+        }
+        Ok(link::link(machine, &linked_libraries)?)
+    }
+
+    /// Read the name and source code of each library.
+    fn read_libraries(&self) -> io::Result<Vec<(String, String)>> {
+        self.libraries
+            .iter()
+            .map(|path| {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                Ok((name, std::fs::read_to_string(path)?))
+            })
+            .collect()
+    }
+}
+
+/// A way of watching a program while it's run, other than just printing its registers at the end.
+enum Recording {
+    /// Write a timeline to the given file.
+    Timeline(PathBuf),
+    /// Redraw a status line after every step, taking the given number of steps per second.
+    Live(NonZeroU32),
+    /// Print a report of sampled lines and jumps to STDERR.
+    Sample(TraceConfig),
+    /// Redraw a status line on STDERR every so often, with the steps taken and the steps per
+    /// second.
+    Progress,
+    /// Print sparklines of the register pressure, sampled every so many steps, to STDERR, and
+    /// write the samples as CSV to the given file if there is one.
+    Pressure { interval: NonZeroUsize, csv: Option<PathBuf> },
+    /// Send the registers as OSC messages to the given address, at most once every interval.
+    #[cfg(feature = "emit")]
+    Emit { address: String, interval: Duration },
+}
+
+impl Recording {
+    /// Choose how to watch the program from the command line arguments, if at all.
+    fn choose(cli: &Cli) -> Option<Self> {
+        if let Some(path) = &cli.timeline {
+            return Some(Self::Timeline(path.clone()));
+        }
+        #[cfg(feature = "emit")]
+        if let Some(address) = &cli.emit_osc {
+            let interval = Duration::from_millis(cli.emit_every);
+            return Some(Self::Emit { address: address.clone(), interval });
+        }
+        if cli.live {
+            Some(Self::Live(cli.speed))
+        }
+        else if cli.sample_every.is_some() || !cli.sample_label.is_empty() {
+            let labels = cli.sample_label.clone();
+            Some(Self::Sample(TraceConfig { interval: cli.sample_every, labels }))
+        }
+        else if cli.progress {
+            Some(Self::Progress)
+        }
+        else {
+            cli.pressure.map(|interval| Self::Pressure { interval, csv: cli.pressure_csv.clone() })
+        }
+    }
+}
+
+/// How often --progress redraws its status line.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Load the program to run, from bytecode, the cache, or by parsing (and caching) its source code.
+fn load_program(
+    optimize: bool,
+    loader: &Loader,
+    cache: Option<&Cache>,
+) -> Result<Machine, RemuirError> {
+    if let Some(path) = loader.bytecode {
+        let program = Machine::from_bytes(&std::fs::read(path)?)?;
+        return Ok(if optimize { optimize::peephole(&program) } else { program });
     }
+    let buffer = loader.read_program()?;
+    let key = match cache {
+        Some(_) => Some(Cache::key(&buffer, &loader.read_libraries()?, optimize)),
+        None => None,
+    };
+    if let Some(program) = cache.zip(key).and_then(|(cache, key)| cache.get(key)) {
+        return Ok(program);
+    }
+    let mut program = loader.load(&buffer)?;
+    if optimize {
+        program = optimize::peephole(&program);
+    }
+    if let Some((cache, key)) = cache.zip(key) {
+        if let Err(e) = cache.put(key, &program) {
+            let directory = cache.get_directory().display();
+            eprintln!("Warning: couldn't write to the cache in {directory}. {e}");
+        }
+    }
+    Ok(program)
 }
 
-fn run() -> io::Result<()> {
-    let mut buffer = String::new();
-    io::stdin().read_to_string(&mut buffer)?;
-    let mut program = parser::parse_str(&buffer).unwrap();
-    program.run();
+#[allow(clippy::too_many_arguments)]
+fn run(
+    optimize: bool,
+    recording: Option<Recording>,
+    loader: &Loader,
+    cache: Option<&Cache>,
+    compact_every: Option<NonZeroUsize>,
+    sandbox: Sandbox,
+    heat: bool,
+    cost: Option<CostModel>,
+    input: Option<&PathBuf>,
+) -> Result<(), RemuirError> {
+    let mut program = load_program(optimize, loader, cache)?;
+    program.set_strict_jumps(loader.strict_jumps);
+    program.set_compaction_interval(compact_every);
+    program.set_sandbox(sandbox);
+    if let Some(path) = input {
+        program.set_io_handler(LineIo::new(BufReader::new(File::open(path)?)));
+    }
+    if sandbox.steps.is_some() {
+        // Keep the recent history for the summary printed if the limit is reached.
+        program.set_history_limit(Some(tui::HISTORY_LIMIT));
+    }
+    program.set_register_heat(heat);
+    program.set_cost_model(cost);
+    let mut trace = None;
+    let mut pressure = None;
+    // Status lines are only redrawn in place on a terminal, such as not when STDERR is a log file.
+    let terminal = Terminal::detect(io::stderr().is_terminal());
+    program.start_execution();
+    match recording {
+        Some(Recording::Timeline(path)) => {
+            let timeline = Timeline::record(&mut program);
+            let manifest = Manifest { optimized: optimize, ..Manifest::new(&program) };
+            let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+            timeline.write_trace_json(&mut out, &manifest)?;
+        },
+        Some(Recording::Live(speed)) => {
+            // Only the registers may be printed to STDOUT.
+            tui::live(&mut program, speed, &mut io::stderr(), terminal)?;
+        },
+        Some(Recording::Sample(config)) => {
+            trace = Some(SampledTrace::record(&mut program, &config));
+        },
+        Some(Recording::Progress) => {
+            // Only the registers may be printed to STDOUT.
+            tui::progress(&mut program, PROGRESS_INTERVAL, &mut io::stderr(), terminal)?;
+        },
+        Some(Recording::Pressure { interval, csv }) => {
+            let recorded = RegisterPressure::record(&mut program, interval);
+            if let Some(path) = csv {
+                recorded.write_csv(&mut io::BufWriter::new(File::create(path)?))?;
+            }
+            pressure = Some(recorded);
+        },
+        #[cfg(feature = "emit")]
+        Some(Recording::Emit { address, interval }) => {
+            emit::run(&mut program, &mut emit::Osc::connect(address)?, interval)?;
+        },
+        None => program.run(),
+    }
+    if let Some(limit) = program.get_resource_limit() {
+        return Err(RuntimeError::ResourceLimit(limit).into());
+    }
+    if let Some(e) = program.get_io_error() {
+        return Err(RuntimeError::Io(e.clone()).into());
+    }
+    if let Some(limit) = sandbox.steps {
+        if !program.is_halted() && program.is_step_limit_reached() {
+            // Only the registers may be printed to STDOUT.
+            write!(io::stderr(), "{}", RunawaySummary::new(&program))?;
+            return Err(RuntimeError::StepLimitReached { limit }.into());
+        }
+    }
+    // With strict jumps, a machine which stopped without halting was about to jump outside the
+    // program.
+    if !program.is_halted() {
+        return Err(RuntimeError::InvalidJump(program.get_current_line_number()).into());
+    }
     let output = program.display_nat_registers();
     println!("{output}");
+    // Only the registers may be printed to STDOUT.
+    if !program.get_phases().is_empty() {
+        tui::phase_report(&mut io::stderr(), &program)?;
+    }
+    if let Some(trace) = trace {
+        write!(io::stderr(), "{trace}")?;
+    }
+    if let Some(pressure) = pressure {
+        write!(io::stderr(), "{pressure}")?;
+    }
+    if heat {
+        tui::heat_report(&mut io::stderr(), &program, HOTTEST_REGISTERS)?;
+    }
+    if let Some(model) = cost {
+        tui::cost_report(&mut io::stderr(), &program, &model)?;
+    }
     Ok(())
 }
 
-fn repl() -> Result<(), RemuirError> {
-    writeln!(io::stdout(), "{}", welcome_repl())?;
-    let mut machine = Machine::default();
-    let mut mode = Mode::Repl;
+fn specialize(
+    known: &[(RegisterNumber, u128)],
+    optimize: bool,
+    loader: &Loader,
+) -> Result<(), RemuirError> {
+    let machine = loader.load(&loader.read_program()?)?;
+    let mut specialized = specialize::specialize(&machine, known)?;
+    if optimize {
+        specialized = optimize::peephole(&specialized);
+    }
+    writeln!(io::stdout(), "{}", specialized.to_source())?;
+    Ok(())
+}
 
-    loop {
-        writeln!(io::stdout(), "\n{}", machine.display_nat_registers())?;
-        write!(io::stdout(), "remuir> ")?;
-        io::stdout().flush()?;
-        let mut line = String::new();
-        let bytes = io::stdin().read_line(&mut line)?;
-        let input = line.trim();
+fn check(
+    path: PathBuf,
+    slice: Option<RegisterNumber>,
+    deny_warnings: bool,
+    loader: &Loader,
+) -> Result<(), RemuirError> {
+    let source = loader.read(path)?;
+    // Report every error at once, unless labels defined more than once aren't errors.
+    if !loader.permissive {
+        if let Err(mut errors) = parser::parse_str_all_errors(&source) {
+            return Err(match errors.len() {
+                1 => RemuirError::from(errors.remove(0)),
+                _ => RemuirError::InvalidSyntaxMany(errors),
+            });
+        }
+    }
+    let machine = loader.load(&source)?;
+    let warnings = parser::lint(&machine);
+    for warning in &warnings {
+        writeln!(io::stdout(), "Warning: {warning}")?;
+    }
+    if let Some(register) = slice {
+        write!(io::stdout(), "{}", machine.slice(register))?;
+    }
+    if deny_warnings && !warnings.is_empty() {
+        return Err(RemuirError::Warnings(warnings.len()));
+    }
+    Ok(())
+}
+
+fn analyze(
+    path: &PathBuf,
+    runs: usize,
+    seed: u64,
+    ranges: &[(usize, RangeInclusive<u128>)],
+    step_limit: usize,
+    loader: &Loader,
+) -> Result<(), RemuirError> {
+    let mut machine = loader.load(&loader.read(path)?)?;
+    let analysis = Analysis::sample(&mut machine, ranges, runs, seed, step_limit);
+    write!(io::stdout(), "{analysis}")?;
+    let manifest = Manifest {
+        step_limit: Some(step_limit),
+        seed: Some(seed),
+        ..Manifest::new(&machine)
+    };
+    write!(io::stdout(), "\nManifest:\n{manifest}")?;
+    Ok(())
+}
+
+fn rpc(include_scratch: bool) -> io::Result<()> {
+    // Ctrl+C stops a long "run" rather than the server.
+    interrupt::install_handler();
+    let mut server = rpc::RpcServer::default();
+    server.set_include_scratch(include_scratch);
+    server.serve(&mut io::stdin().lock(), &mut io::stdout())
+}
+
+fn compare(
+    first: &Path,
+    second: &Path,
+    inputs: &[(usize, RangeInclusive<u128>)],
+    step_limit: usize,
+    loader: &Loader,
+) -> Result<(), RemuirError> {
+    let mut first_machine = loader.load(&loader.read(first)?)?;
+    let mut second_machine = loader.load(&loader.read(second)?)?;
+    let names = [first.display().to_string(), second.display().to_string()];
+    let machines = [&mut first_machine, &mut second_machine];
+    let comparison = Comparison::run(names, machines, inputs, step_limit)?;
+    write!(io::stdout(), "{comparison}")?;
+    Ok(())
+}
+
+fn load_concurrent(
+    paths: &[PathBuf],
+    scheduler: Scheduler,
+    loader: &Loader,
+) -> Result<Concurrent, RemuirError> {
+    let mut machines = Vec::new();
+    for path in paths {
+        machines.push(loader.load(&loader.read(path)?)?);
+    }
+    let memory = machines[0].get_initial_memory().clone();
+    Ok(Concurrent::new(machines, memory, scheduler))
+}
+
+fn concurrent(
+    paths: &[PathBuf],
+    seed: Option<u64>,
+    step_limit: usize,
+    loader: &Loader,
+) -> Result<(), RemuirError> {
+    let scheduler = seed.map_or(Scheduler::RoundRobin, |seed| Scheduler::Random { seed });
+    let mut concurrent = load_concurrent(paths, scheduler, loader)?;
+    if !concurrent.run(step_limit) {
+        eprintln!("Warning: not every program halted within {step_limit} steps.");
+    }
+    println!("{}", concurrent.get_memory());
+    Ok(())
+}
+
+fn explore_interleavings(
+    paths: &[PathBuf],
+    steps: usize,
+    assertion: Option<Assertion>,
+    loader: &Loader,
+) -> Result<(), RemuirError> {
+    let concurrent = load_concurrent(paths, Scheduler::RoundRobin, loader)?;
+    let exploration = concurrent
+        .explore(steps, |memory| assertion.is_none_or(|assertion| assertion.holds(memory)));
+    write!(io::stdout(), "{exploration}")?;
+    Ok(())
+}
 
-        // Handle EOF/Ctrl+D.
-        if bytes == 0 {
-            printers::goodbye()?;
-            break;
+fn test(path: &PathBuf, loader: &Loader) -> Result<(), RemuirError> {
+    let source = loader.read(path)?;
+    let tests = test_block::find_tests(&source)?;
+    let mut machine = loader.load(&source)?;
+    // Tests may be run on programs which are being graded, so they get the same caps as servers.
+    machine.set_sandbox(Sandbox::SERVER);
+    if tests.is_empty() {
+        writeln!(io::stdout(), "No tests found.")?;
+        return Ok(());
+    }
+    let mut failed = 0;
+    for test in &tests {
+        let outcome = test.run(&machine);
+        if outcome != TestOutcome::Passed {
+            failed += 1;
+        }
+        writeln!(io::stdout(), "test {} ... {outcome}", test.name)?;
+    }
+    if failed > 0 {
+        return Err(RemuirError::TestsFailed { failed, total: tests.len() });
+    }
+    writeln!(io::stdout(), "All {} tests passed.", tests.len())?;
+    Ok(())
+}
+
+/// Rerun the tests of every program under `path` when it changes, until interrupted.
+fn watch_tests(path: PathBuf, interval: u64, loader: &Loader) -> Result<(), RemuirError> {
+    writeln!(io::stdout(), "Watching {} for changes. Press Ctrl-C to stop.", path.display())?;
+    let mut watcher = TestWatcher::new(path);
+    loop {
+        let load = |source: &str| {
+            let mut machine = loader.load(source)?;
+            machine.set_sandbox(Sandbox::SERVER);
+            Ok(machine)
+        };
+        if watcher.poll(load, &mut io::stdout())? {
+            writeln!(io::stdout())?;
         }
+        std::thread::sleep(Duration::from_millis(interval));
+    }
+}
+
+fn doctor(path: &PathBuf, optimize: bool, loader: &Loader) -> Result<(), RemuirError> {
+    let source = loader.read(path)?;
+    let report = Report::new(&source, optimize, |source| {
+        let machine = loader.load(source)?;
+        Ok::<_, RemuirError>(if optimize { optimize::peephole(&machine) } else { machine })
+    });
+    write!(io::stdout(), "{report}")?;
+    Ok(())
+}
+
+fn explain(path: &PathBuf, loader: &Loader) -> Result<(), RemuirError> {
+    let machine = loader.load(&loader.read(path)?)?;
+    write!(io::stdout(), "{}", machine.explain())?;
+    Ok(())
+}
+
+fn obfuscate(path: &PathBuf, seed: u64, loader: &Loader) -> Result<(), RemuirError> {
+    let machine = loader.load(&loader.read(path)?)?;
+    writeln!(io::stdout(), "{}", obfuscate::obfuscate(&machine, seed).to_source())?;
+    Ok(())
+}
 
-        // Handle the command and decide whether to keep looping or not.
-        match tui::command(input, &mut machine, &mut mode)? {
-            tui::ReplState::KeepLooping => continue,
-            tui::ReplState::Stop => break,
+fn fmt(path: &PathBuf, write: bool, check: bool) -> Result<(), RemuirError> {
+    let source = std::fs::read_to_string(path)?;
+    let formatted = parser::format_source(&source)?;
+    if check {
+        if formatted != source {
+            return Err(RemuirError::Unformatted(path.clone()));
+        }
+    }
+    else if write {
+        if formatted != source {
+            std::fs::write(path, formatted)?;
         }
     }
+    else {
+        write!(io::stdout(), "{formatted}")?;
+    }
+    Ok(())
+}
+
+fn compile(
+    path: &PathBuf,
+    output: Option<PathBuf>,
+    optimize: bool,
+    loader: &Loader,
+) -> Result<(), RemuirError> {
+    let mut machine = loader.load(&loader.read(path)?)?;
+    if optimize {
+        machine = optimize::peephole(&machine);
+    }
+    std::fs::write(output.unwrap_or_else(|| path.with_extension("rmbc")), machine.to_bytes())?;
+    Ok(())
+}
+
+fn import(
+    path: &PathBuf,
+    columns: &[(String, String)],
+    registers: &str,
+) -> Result<(), RemuirError> {
+    let mut mapping = ColumnMapping::default();
+    for (field, name) in columns {
+        mapping.set(field, name);
+    }
+    let registers = parser::parse_register_line(&format!("registers {registers}"))?;
+    let source = import::import_csv(&std::fs::read_to_string(path)?, &mapping, &registers)?;
+    write!(io::stdout(), "{source}")?;
+    Ok(())
+}
+
+fn examples(command: ExamplesCommand) -> Result<(), RemuirError> {
+    let find = |name: String| examples::by_name(&name).ok_or(RemuirError::UnknownExample(name));
+    match command {
+        ExamplesCommand::List => {
+            let width = examples::GALLERY.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            for (name, description) in examples::GALLERY {
+                writeln!(io::stdout(), "{name:width$}  {description}")?;
+            }
+        },
+        ExamplesCommand::Show { name } => {
+            writeln!(io::stdout(), "{}", find(name)?.to_source())?;
+        },
+        ExamplesCommand::Run { name, inputs } => {
+            let mut machine = find(name)?;
+            if !inputs.is_empty() {
+                machine.set_initial_memory(inputs.into_iter().map(Register::from).collect());
+                machine.reset();
+            }
+            machine.run();
+            writeln!(io::stdout(), "{}", machine.display_nat_registers())?;
+        },
+    }
+    Ok(())
+}
+
+fn cache(command: CacheCommand) -> Result<(), RemuirError> {
+    match command {
+        CacheCommand::Clear => {
+            let Some(directory) = Cache::default_directory() else {
+                writeln!(io::stdout(), "There's no cache, since there's no home directory.")?;
+                return Ok(());
+            };
+            let removed = Cache::new(directory).clear()?;
+            writeln!(io::stdout(), "Removed {removed} cached program(s).")?;
+        },
+    }
     Ok(())
 }
 
-fn debug(path: std::path::PathBuf) -> Result<(), RemuirError> {
-    writeln!(io::stdout(), "{}", welcome_debug())?;
-    
+fn repl(sandbox: Sandbox) -> Result<(), RemuirError> {
+    // Without any caps, a mistyped register such as r99999999999 could use up the host's memory.
+    let sandbox = if sandbox == Sandbox::UNLIMITED { Sandbox::SERVER } else { sandbox };
+    let terminal = session_terminal();
+    tui::repl_in_sandbox(&mut io::stdin().lock(), &mut io::stdout(), sandbox, terminal)
+}
+
+/// Leave out prompts and escape codes when the REPL or debugger is driven by a script, such as
+/// with STDIN or STDOUT piped, or from a dumb terminal.
+fn session_terminal() -> Terminal {
+    Terminal::detect(io::stdin().is_terminal() && io::stdout().is_terminal())
+}
+
+/// Read how often to autosave debug sessions from the config file, if there's one. A bad value is
+/// only a warning, after which sessions aren't saved.
+fn configured_autosave() -> Option<NonZeroUsize> {
+    let config = std::fs::read_to_string(Autosave::config_file()?).ok()?;
+    Autosave::configured_every(&config).unwrap_or_else(|e| {
+        eprintln!("Warning: ignoring the config file, {e}.");
+        None
+    })
+}
+
+fn debug(
+    path: &Path,
+    loader: &Loader,
+    cost: Option<CostModel>,
+    autosave: Option<NonZeroUsize>,
+    input: Option<&PathBuf>,
+) -> Result<(), RemuirError> {
     let source_code: String = match std::fs::read_to_string(path) {
         Ok(s) => s,
         Err(e) => {
@@ -99,33 +1143,26 @@ fn debug(path: std::path::PathBuf) -> Result<(), RemuirError> {
             return Err(RemuirError::IOError(e));
         },
     };
-    let mut machine = parser::parse_str(&source_code)?;
-    let mut mode = Mode::Debug { previous_line: None, previous_memory: None };
-
-    loop {
-        writeln!(io::stdout(), "\n{}", machine.display_nat_registers())?;
-        if machine.is_halted() {
-            writeln!(io::stdout(), "Next line:\nNone (machine halted).")?;
-        }
-        else {
-            writeln!(io::stdout(), "Next line:\n{}", machine.peek_next_line())?;
-        }
-        printers::print_prompt()?;
-        let mut line = String::new();
-        let bytes = io::stdin().read_line(&mut line)?;
-        let input = line.trim();
-
-        // Handle EOF/Ctrl+D.
-        if bytes == 0 {
-            printers::goodbye()?;
-            break;
-        }
-
-        // Handle the command and decide whether to keep looping or not.
-        match tui::command(input, &mut machine, &mut mode)? {
-            tui::ReplState::KeepLooping => continue,
-            tui::ReplState::Stop => break,
+    let mut machine = loader.load(&loader.translate(source_code, Some(path))?)?;
+    machine.set_cost_model(cost);
+    // STDIN has the commands, so it can't be read by the program too.
+    match input {
+        Some(path) => machine.set_io_handler(LineIo::new(BufReader::new(File::open(path)?))),
+        None => machine.set_io_handler(BufferIo::default()),
+    }
+    let autosave = autosave.or_else(configured_autosave).and_then(|every| {
+        let directory = Autosave::default_directory();
+        if directory.is_none() {
+            eprintln!("Warning: the session can't be autosaved, there's no home directory.");
         }
+        Some(Autosave::new(directory?, &machine, every))
+    });
+    let terminal = session_terminal();
+    let (mut input, mut out) = (io::stdin().lock(), io::stdout());
+    match autosave {
+        Some(autosave) => {
+            tui::debug_with_autosave(machine, autosave, &mut input, &mut out, terminal)
+        },
+        None => tui::debug(machine, &mut input, &mut out, terminal),
     }
-    Ok(())
 }
@@ -0,0 +1,93 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Reproducibility manifests, which say exactly what produced a report.
+//!
+//! Reports and exports (timelines, step limit summaries, crash reports, saved sessions and
+//! analyses) include a [`Manifest`] with the version of remuir, the [`Machine::fingerprint`] of
+//! the program, its initial registers and everything configured which changes how it runs, so
+//! that whoever reads the report can run the same program in the same way again.
+
+use std::fmt::Display;
+
+use serde_json::{ json, Value };
+
+use crate::machine::Machine;
+
+/// What's needed to reproduce a run of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub version: String,
+    pub fingerprint: u64,
+    /// The initial registers, such as `registers 1 2`.
+    pub registers: String,
+    pub optimized: bool,
+    pub strict_jumps: bool,
+    pub step_limit: Option<usize>,
+    /// The cost model, in the form given to `--cost`.
+    pub cost_model: Option<String>,
+    /// The seed of any random choices, such as the inputs of an analysis.
+    pub seed: Option<u64>,
+}
+
+impl Manifest {
+    /// Describe how the machine is set up to run. Whether it was optimised and the seed aren't
+    /// known from the machine, so they have to be set afterwards if there are any.
+    #[must_use]
+    pub fn new(machine: &Machine) -> Manifest {
+        Manifest {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            fingerprint: machine.fingerprint(),
+            registers: machine.get_initial_memory().to_string(),
+            optimized: false,
+            strict_jumps: machine.is_strict_jumps(),
+            step_limit: machine.get_step_limit(),
+            cost_model: machine.get_cost_model().map(ToString::to_string),
+            seed: None,
+        }
+    }
+
+    /// Get the manifest as a JSON object, such as `{"version": "0.1.0", "fingerprint":
+    /// "00c0ffee00c0ffee", "registers": "registers 1 2", "optimized": false, "strictJumps":
+    /// false, "stepLimit": null, "costModel": null, "seed": null}`.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": self.version,
+            "fingerprint": format!("{:016x}", self.fingerprint),
+            "registers": self.registers,
+            "optimized": self.optimized,
+            "strictJumps": self.strict_jumps,
+            "stepLimit": self.step_limit,
+            "costModel": self.cost_model,
+            "seed": self.seed,
+        })
+    }
+}
+
+impl Display for Manifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_none = |value: Option<String>| value.unwrap_or_else(|| String::from("none"));
+        writeln!(f, "remuir {}", self.version)?;
+        writeln!(f, "fingerprint {:016x}", self.fingerprint)?;
+        writeln!(f, "{}", self.registers)?;
+        writeln!(f, "optimised {}", if self.optimized { "yes" } else { "no" })?;
+        writeln!(f, "strict jumps {}", if self.strict_jumps { "yes" } else { "no" })?;
+        writeln!(f, "step limit {}", or_none(self.step_limit.map(|limit| limit.to_string())))?;
+        writeln!(f, "cost model {}", or_none(self.cost_model.clone()))?;
+        writeln!(f, "seed {}", or_none(self.seed.map(|seed| seed.to_string())))
+    }
+}
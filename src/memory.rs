@@ -14,7 +14,7 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 
-use std::{fmt::Display, str::FromStr};
+use std::{ fmt::Display, num::ParseIntError, ops::{ Add, Mul }, str::FromStr };
 
 use thiserror::Error;
 
@@ -24,16 +24,30 @@ use thiserror::Error;
 /// stored, but is realistically limited by what the operating system will allow.
 // This vector represents a little endian number of base 2^128.
 // So, 2^128 + 73 is vec![73, 1]
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct Register (Vec<u128>);
 
 impl Register {
     /// Create a new register using a slice of a little-endian encoded base 2^128 number.
     /// 
-    /// So, 2^128 + 73 could be constructed via `Register::new(&[73, 1])`.
+    /// So, 2^128 + 73 could be constructed via `Register::new(&[73, 1])`. Leading zero digits are
+    /// removed.
     #[must_use]
     pub fn new(registers: &[u128]) -> Register {
-        Register(Vec::from(registers))
+        let length = registers.iter().rposition(|&d| d != 0).map_or(0, |i| i + 1);
+        Register(Vec::from(&registers[..length]))
+    }
+
+    /// Check that the register has no leading zero digits. 0 itself may be stored with no digits
+    /// or a single zero digit.
+    ///
+    /// Every operation keeps this invariant, and checks it in debug builds.
+    #[must_use]
+    pub fn is_normalised(&self) -> bool {
+        match self.0.last() {
+            Some(0) => self.0.len() == 1,
+            _ => true,
+        }
     }
 
     /// Increment the register by 1.
@@ -57,10 +71,13 @@ impl Register {
         if !assigned {
             self.0.push(1);
         }
+        debug_assert!(self.is_normalised(), "inc left leading zero digits in {:?}.", self.0);
     }
 
     /// Decrement the register by 1.
     pub fn dec(&mut self) {
+        // Decrementing 0 would wrap around, since the loop below never finds a digit to decrease.
+        debug_assert!(!self.is_zero(), "Tried to decrement a register which is already 0.");
         // A similar principal to inc() is used here.
         let mut decreased = false;
         // For each 0, set it to u128::MAX and decrease the last digit.
@@ -82,6 +99,81 @@ impl Register {
         if decreased && self.0.last().expect("Register always has at least one digit") == &0 {
             self.0.pop();
         }
+        debug_assert!(self.is_normalised(), "dec left leading zero digits in {:?}.", self.0);
+    }
+
+    /// Get how many limbs (digits of base 2^128) the register is stored with.
+    #[must_use]
+    pub fn get_limb_count(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Get the little-endian base 2^128 digits of the register, without any leading zero digits
+    /// except that 0 may be a single zero digit.
+    #[must_use]
+    pub fn get_limbs(&self) -> &[u128] {
+        &self.0
+    }
+
+    /// Get how many bits the register's value needs, which is 0 for 0.
+    #[must_use]
+    pub fn get_bit_count(&self) -> u64 {
+        match self.0.last() {
+            Some(&last) if last != 0 => {
+                (self.0.len() as u64 - 1) * 128 + u64::from(u128::BITS - last.leading_zeros())
+            },
+            _ => 0,
+        }
+    }
+
+    /// Subtract another register's value from this one, or get [`None`] if the result would be
+    /// negative.
+    #[must_use]
+    pub fn checked_sub(&self, other: &Register) -> Option<Register> {
+        if other.0.len() > self.0.len() && other.0[self.0.len()..].iter().any(|&d| d != 0) {
+            return None;
+        }
+        let mut digits = Vec::with_capacity(self.0.len());
+        let mut borrow = false;
+        for (i, &digit) in self.0.iter().enumerate() {
+            let (difference, first) = digit.overflowing_sub(other.digit(i));
+            let (difference, second) = difference.overflowing_sub(u128::from(borrow));
+            digits.push(difference);
+            borrow = first || second;
+        }
+        (!borrow).then(|| Register::new(&digits))
+    }
+
+    /// Raise the register's value to a power, by repeated squaring.
+    #[must_use]
+    pub fn pow(&self, mut exponent: u64) -> Register {
+        let mut result = Register::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    /// Get a digit of base 2^128, where digits past the most significant are 0.
+    fn digit(&self, index: usize) -> u128 {
+        self.0.get(index).copied().unwrap_or(0)
+    }
+
+    /// Get the value of the register as a u128, or [`None`] if it's larger than 2^128 - 1.
+    #[must_use]
+    pub fn to_u128(&self) -> Option<u128> {
+        match self.0.len() {
+            0 => Some(0),
+            1 => Some(self.0[0]),
+            _ => None,
+        }
     }
 
     /// Check if the register's value is 0.
@@ -107,41 +199,229 @@ impl Register {
     }
 }
 
+// 0 may be represented with or without a digit, so compare without any leading zeros.
+impl PartialEq for Register {
+    fn eq(&self, other: &Self) -> bool {
+        fn significant(digits: &[u128]) -> &[u128] {
+            let length = digits.iter().rposition(|&d| d != 0).map_or(0, |i| i + 1);
+            &digits[..length]
+        }
+        significant(&self.0) == significant(&other.0)
+    }
+}
+
+impl Add for &Register {
+    type Output = Register;
+
+    fn add(self, other: &Register) -> Register {
+        let length = self.0.len().max(other.0.len());
+        let mut digits = Vec::with_capacity(length + 1);
+        let mut carry = false;
+        for i in 0..length {
+            let (sum, first) = self.digit(i).overflowing_add(other.digit(i));
+            let (sum, second) = sum.overflowing_add(u128::from(carry));
+            digits.push(sum);
+            carry = first || second;
+        }
+        digits.push(u128::from(carry));
+        Register::new(&digits)
+    }
+}
+
+impl Mul for &Register {
+    type Output = Register;
+
+    // Multiply in base 2^64, so that each product of two digits, plus the carries, fits in a u128.
+    #[allow(clippy::cast_possible_truncation)]
+    fn mul(self, other: &Register) -> Register {
+        let halves = |digits: &[u128]| -> Vec<u64> {
+            digits.iter().flat_map(|&d| [d as u64, (d >> 64) as u64]).collect()
+        };
+        let (left, right) = (halves(&self.0), halves(&other.0));
+        let mut product = vec![0_u64; left.len() + right.len()];
+        for (i, &a) in left.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &b) in right.iter().enumerate() {
+                let current = u128::from(a) * u128::from(b) + u128::from(product[i + j]) + carry;
+                product[i + j] = current as u64;
+                carry = current >> 64;
+            }
+            product[i + right.len()] = carry as u64;
+        }
+        let digits: Vec<u128> = product
+            .chunks(2)
+            .map(|pair| u128::from(pair[0]) | u128::from(pair.get(1).copied().unwrap_or(0)) << 64)
+            .collect();
+        Register::new(&digits)
+    }
+}
+
+/// Parse a natural number written in decimal, of any size.
+impl FromStr for Register {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // 10^38 is the largest power of 10 which fits in a u128.
+        const DIGITS: usize = 38;
+        if s.is_empty() || !s.is_ascii() {
+            return s.parse::<u128>().map(Register::from);
+        }
+        let mut value = Register::from(0);
+        let first = match s.len() % DIGITS {
+            0 => DIGITS,
+            length => length,
+        };
+        let mut start = 0;
+        for end in (first..=s.len()).step_by(DIGITS) {
+            let chunk = &s[start..end];
+            let scale = Register::from(10_u128.pow(u32::try_from(chunk.len()).unwrap_or(0)));
+            value = &(&value * &scale) + &Register::from(chunk.parse::<u128>()?);
+            start = end;
+        }
+        Ok(value)
+    }
+}
+
+/// The value held by a register.
+pub type RegisterValue = Register;
+
+impl Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(n) = self.to_u128() {
+            return write!(f, "{n}");
+        }
+        // Split each digit into two base 2^64 digits (most significant first), so that the
+        // remainder of dividing by 10^19 fits alongside a digit in a u128.
+        const CHUNK: u128 = 10_000_000_000_000_000_000;
+        let mut digits: Vec<u128> = self.0
+            .iter()
+            .rev()
+            .flat_map(|&d| [d >> 64, d & u128::from(u64::MAX)])
+            .collect();
+        let mut chunks: Vec<u128> = Vec::new();
+        while digits.iter().any(|&d| d != 0) {
+            let mut remainder = 0;
+            for digit in &mut digits {
+                let current = (remainder << 64) | *digit;
+                *digit = current / CHUNK;
+                remainder = current % CHUNK;
+            }
+            chunks.push(remainder);
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap_or(&0))?;
+        for chunk in chunks {
+            write!(f, "{chunk:019}")?;
+        }
+        Ok(())
+    }
+}
+
 impl From<u128> for Register {
     fn from(value: u128) -> Self {
         Register(vec![value])
     }
 }
 
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Cannot decrement {0}, its value is already 0.")]
+pub struct DecrementZeroError(pub RegisterNumber);
+
 #[derive(Error, Debug, Clone)]
 pub enum RegisterParseError {
     #[error("The register number wasn't a valid integer!")]
     NotInt(#[from] std::num::ParseIntError),
     #[error("Missing character 'r' before register number.")]
     MissingR,
+    #[error(
+        "There is no pseudo-register \"r!{name}\". The pseudo-registers are r!steps and r!visits.",
+    )]
+    UnknownPseudoRegister { name: String },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum RegisterNumber {
     Negative(usize),
     Natural(usize),
+    /// A read-only register whose value is kept by the machine (see [`PseudoRegister`]).
+    Pseudo(PseudoRegister),
+}
+
+/// A counter kept by the machine which programs can read like a register, written `r!` followed
+/// by its name. Incrementing and decrementing a pseudo-register does nothing, so `decjz r!steps`
+/// only jumps if it's 0, and otherwise carries on to the next line.
+///
+/// Pseudo-registers can only be used in programs with the `#!extended` directive.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum PseudoRegister {
+    /// `r!steps`, how many steps were taken before the current one.
+    Steps,
+    /// `r!visits`, how many times the current line was executed before. On a labelled line, this
+    /// counts how many times the label has been reached.
+    Visits,
+}
+
+impl PseudoRegister {
+    /// The name of every pseudo-register, as written after `r!`.
+    pub const NAMES: [&'static str; 2] = ["steps", "visits"];
+
+    #[must_use]
+    pub const fn get_name(self) -> &'static str {
+        match self {
+            PseudoRegister::Steps => "steps",
+            PseudoRegister::Visits => "visits",
+        }
+    }
+}
+
+/// Natural registers come first, in ascending order, then negative registers in the order `r-1`,
+/// `r-2` and so on, then pseudo-registers. This is the order registers are listed in everywhere.
+impl Ord for RegisterNumber {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        use RegisterNumber as Rnum;
+        let rank = |register: &RegisterNumber| match register {
+            Rnum::Natural(_) => 0,
+            Rnum::Negative(_) => 1,
+            Rnum::Pseudo(_) => 2,
+        };
+        match (self, other) {
+            (Rnum::Natural(a), Rnum::Natural(b)) | (Rnum::Negative(a), Rnum::Negative(b)) => {
+                a.cmp(b)
+            },
+            (Rnum::Pseudo(a), Rnum::Pseudo(b)) => a.cmp(b),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+impl PartialOrd for RegisterNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl FromStr for RegisterNumber {
     type Err = RegisterParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if &s[0..1] != "r" {
+        let Some(number) = s.strip_prefix('r') else {
             return Err(RegisterParseError::MissingR)
+        };
+        if let Some(name) = number.strip_prefix('!') {
+            match name {
+                "steps" => Ok(Self::Pseudo(PseudoRegister::Steps)),
+                "visits" => Ok(Self::Pseudo(PseudoRegister::Visits)),
+                _ => Err(RegisterParseError::UnknownPseudoRegister { name: name.to_string() }),
+            }
         }
-        if &s[1..2] == "-" {
-            match (s[2..s.len()]).parse::<usize>() {
+        else if let Some(negative) = number.strip_prefix('-') {
+            match negative.parse::<usize>() {
                 Ok(num) => Ok(Self::Negative(num)),
                 Err(e) => Err(RegisterParseError::NotInt(e)),
             }
         }
         else {
-            match (s[1..s.len()]).parse::<usize>() {
+            match number.parse::<usize>() {
                 Ok(num) => Ok(Self::Natural(num)),
                 Err(e) => Err(RegisterParseError::NotInt(e)),
             }
@@ -165,14 +445,37 @@ impl Display for RegisterNumber {
         match self {
             RegisterNumber::Natural(n) => write!(f, "r{}", *n),
             RegisterNumber::Negative(n) => write!(f, "r-{}", *n),
+            RegisterNumber::Pseudo(register) => write!(f, "r!{}", register.get_name()),
         }
     }
 }
 
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone)]
 pub struct Memory {
     nat_registers: Vec<Register>,
     neg_registers: Vec<Register>,
+    /// How many natural registers had been created when the memory was last compacted. Trailing
+    /// zero registers are removed by compacting, but are still shown when displaying the memory.
+    nat_count: usize,
+    /// The value of `r!steps` (see [`PseudoRegister`]), which the machine sets before each step.
+    steps: usize,
+    /// The value of `r!visits`, which the machine sets before each step.
+    visits: usize,
+}
+
+// Registers removed by compacting are 0, so compare as if they were still there. Pseudo-registers
+// belong to the machine rather than the program's state, so they aren't compared.
+impl PartialEq for Memory {
+    fn eq(&self, other: &Self) -> bool {
+        fn same(left: &[Register], right: &[Register]) -> bool {
+            let zero = Register::from(0);
+            (0..left.len().max(right.len()))
+                .all(|n| left.get(n).unwrap_or(&zero) == right.get(n).unwrap_or(&zero))
+        }
+        self.get_nat_count() == other.get_nat_count()
+            && same(&self.nat_registers, &other.nat_registers)
+            && same(&self.neg_registers, &other.neg_registers)
+    }
 }
 
 impl Memory {
@@ -180,7 +483,55 @@ impl Memory {
     /// is the 0th register, etc.)
     #[must_use]
     pub fn new_from_slice(registers: &[Register]) -> Memory {
-        Memory { nat_registers: Vec::from(registers), neg_registers: Vec::new() }
+        Memory { nat_registers: Vec::from(registers), ..Memory::default() }
+    }
+
+    /// Set the values of the pseudo-registers (see [`PseudoRegister`]).
+    pub fn set_pseudo_registers(&mut self, steps: usize, visits: usize) {
+        self.steps = steps;
+        self.visits = visits;
+    }
+
+    /// Get the value of a pseudo-register.
+    #[must_use]
+    pub const fn get_pseudo_register(&self, register: PseudoRegister) -> usize {
+        match register {
+            PseudoRegister::Steps => self.steps,
+            PseudoRegister::Visits => self.visits,
+        }
+    }
+
+    /// Free trailing registers which are 0, in both the natural and negative registers.
+    ///
+    /// No register changes number, and the removed registers still count as accessed, so the
+    /// memory is displayed in the same way as before.
+    pub fn compact(&mut self) {
+        self.nat_count = self.get_nat_count();
+        for registers in [&mut self.nat_registers, &mut self.neg_registers] {
+            let length = registers.iter().rposition(|r| !r.is_zero()).map_or(0, |i| i + 1);
+            registers.truncate(length);
+            registers.shrink_to_fit();
+        }
+    }
+
+    /// Get how many natural registers have been accessed, including any removed by
+    /// [`Memory::compact`].
+    #[must_use]
+    pub fn get_nat_count(&self) -> usize {
+        self.nat_registers.len().max(self.nat_count)
+    }
+
+    /// Get how many registers, natural and negative, are stored.
+    #[must_use]
+    pub fn get_register_count(&self) -> usize {
+        self.nat_registers.len() + self.neg_registers.len()
+    }
+
+    /// Get how many limbs (see [`Register::get_limb_count`]) every register together is stored
+    /// with.
+    #[must_use]
+    pub fn get_limb_count(&self) -> usize {
+        self.nat_registers.iter().chain(&self.neg_registers).map(Register::get_limb_count).sum()
     }
 
     /// Initialise new registers with the value 0 up to the given register number.
@@ -196,10 +547,27 @@ impl Memory {
                     self.neg_registers.push(Register::from(0));
                 }
             },
+            RegisterNumber::Pseudo(_) => (),
         }
     }
 
-    /// Increment the given register by 1.
+    /// Set the given register to a value, creating any registers before it if necessary.
+    /// Pseudo-registers are read-only, so setting one does nothing.
+    pub fn set_register(&mut self, register_number: RegisterNumber, value: Register) {
+        match register_number {
+            RegisterNumber::Natural(n) => {
+                self.create_new_registers(RegisterNumber::Natural(n + 1));
+                self.nat_registers[n] = value;
+            },
+            RegisterNumber::Negative(n) => {
+                self.create_new_registers(RegisterNumber::Negative(n + 1));
+                self.neg_registers[n] = value;
+            },
+            RegisterNumber::Pseudo(_) => (),
+        }
+    }
+
+    /// Increment the given register by 1, unless it's a pseudo-register.
     pub fn inc(&mut self, register_number: RegisterNumber) {
         match register_number {
             RegisterNumber::Natural(n) => {
@@ -220,22 +588,47 @@ impl Memory {
                     self.neg_registers[n].inc();
                 }
             },
+            RegisterNumber::Pseudo(_) => (),
         }
     }
     
-    /// Decrement the given register by 1.
+    /// Decrement the given register by 1, unless it's a pseudo-register.
     /// 
     /// # Panics
     /// 
-    /// * This function assumes that the register isn't zero!
+    /// * This function assumes that the register isn't zero! Use [`Memory::checked_dec`] if it
+    ///   might be.
     pub fn dec(&mut self, register_number: RegisterNumber) {
         match register_number {
             RegisterNumber::Natural(n) => self.nat_registers[n].dec(),
             RegisterNumber::Negative(n) => self.neg_registers[n].dec(),
+            RegisterNumber::Pseudo(_) => (),
         }
 
     }
 
+    /// Check that every register has no leading zero digits (see [`Register::is_normalised`]).
+    #[must_use]
+    pub fn is_normalised(&self) -> bool {
+        self.nat_registers.iter().chain(&self.neg_registers).all(Register::is_normalised)
+    }
+
+    /// Decrement the given register by 1, unless it's zero.
+    ///
+    /// # Errors
+    ///
+    /// * [`DecrementZeroError`] - returned, leaving the register unchanged, if it's zero.
+    pub fn checked_dec(
+        &mut self,
+        register_number: RegisterNumber,
+    ) -> Result<(), DecrementZeroError> {
+        if self.is_zero(register_number) {
+            return Err(DecrementZeroError(register_number));
+        }
+        self.dec(register_number);
+        Ok(())
+    }
+
     /// Check if the given register's value is 0.
     #[must_use]
     pub fn is_zero(&mut self, register_number: RegisterNumber) -> bool {
@@ -268,9 +661,23 @@ impl Memory {
                     true
                 }
             },
+            RegisterNumber::Pseudo(register) => self.get_pseudo_register(register) == 0,
         }
     }
 
+    /// Get all (natural) registers which are stored. After [`Memory::compact`], there may be fewer
+    /// of these than [`Memory::get_nat_count`], in which case the rest are 0.
+    #[must_use]
+    pub fn get_nat_registers(&self) -> &[Register] {
+        &self.nat_registers
+    }
+
+    /// Get all negative registers, where the 0th element is r-0, the 1st is r-1, and so on.
+    #[must_use]
+    pub fn get_neg_registers(&self) -> &[Register] {
+        &self.neg_registers
+    }
+
     /// Get the current value of all (natural) registers as u128 numbers.
     /// 
     /// # Panics
@@ -282,47 +689,61 @@ impl Memory {
         for reg in &self.nat_registers[..] {
             to_return.push(reg.get_u128());
         }
+        to_return.resize(self.get_nat_count(), 0);
         to_return
     }
 
-    /// Get the current value of a register as a u128.
-    /// 
-    /// # Panics
-    /// 
-    /// * If the value of any register is larger than 2^128 - 1, then this will panic!
+    /// Get the current value of a register. Registers which haven't been used yet are 0.
     #[must_use]
-    pub fn get_register(&self, register_number: RegisterNumber) -> String {
-        match register_number {
-            RegisterNumber::Natural(n) => {
-                self.nat_registers.get(n).map_or(
-                    "0".to_string(),
-                    |register| register.get_u128().to_string(),
-                )
+    pub fn get_value(&self, register_number: RegisterNumber) -> Register {
+        let register = match register_number {
+            RegisterNumber::Natural(n) => self.nat_registers.get(n),
+            RegisterNumber::Negative(n) => self.neg_registers.get(n),
+            RegisterNumber::Pseudo(register) => {
+                return Register::from(self.get_pseudo_register(register) as u128);
             },
-            RegisterNumber::Negative(n) => {
-                self.neg_registers.get(n).map_or(
-                    "0".to_string(),
-                    |register| register.get_u128().to_string(),
-                )
-            },
-        }
+        };
+        register.cloned().unwrap_or_else(|| Register::from(0))
+    }
+
+    /// Get the natural registers which hold different values in `other`, with the value in each,
+    /// treating registers which haven't been used as 0.
+    #[must_use]
+    pub fn differing_registers(&self, other: &Memory) -> Vec<(RegisterNumber, Register, Register)> {
+        (0..self.get_nat_count().max(other.get_nat_count()))
+            .map(RegisterNumber::Natural)
+            .filter_map(|register| {
+                let (left, right) = (self.get_value(register), other.get_value(register));
+                (left != right).then_some((register, left, right))
+            })
+            .collect()
+    }
+
+    /// Get the current value of a register in decimal.
+    #[must_use]
+    pub fn get_register(&self, register_number: RegisterNumber) -> String {
+        self.get_value(register_number).to_string()
     }
 }
 
 impl FromIterator<Register> for Memory {
     fn from_iter<T: IntoIterator<Item = Register>>(iter: T) -> Self {
-        Memory { nat_registers: Vec::from_iter(iter), neg_registers: Vec::new() }
+        Memory { nat_registers: Vec::from_iter(iter), ..Memory::default() }
     }
 }
 
 impl Display for Memory {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("registers")?;
-        if self.nat_registers.is_empty() {
+        if self.get_nat_count() == 0 {
             return f.write_str(" 0");
         }
         for r in &self.nat_registers {
-            f.write_fmt(format_args!(" {}", r.get_u128()))?;
+            f.write_fmt(format_args!(" {r}"))?;
+        }
+        // Registers removed by compacting.
+        for _ in self.nat_registers.len()..self.get_nat_count() {
+            f.write_str(" 0")?;
         }
         Ok(())
     }
@@ -331,6 +752,37 @@ impl Display for Memory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn compact_test() {
+        let mut memory = Memory::new_from_slice(&[Register::from(0), Register::from(2)]);
+        memory.create_new_registers(RegisterNumber::Natural(5));
+        memory.create_new_registers(RegisterNumber::Negative(3));
+        let before = memory.clone();
+        memory.compact();
+        assert_eq!(memory.get_nat_registers().len(), 2);
+        assert!(memory.get_neg_registers().is_empty());
+        assert_eq!(memory.get_nat_count(), 5);
+        assert_eq!(memory.to_string(), "registers 0 2 0 0 0");
+        assert_eq!(memory, before);
+        memory.inc(RegisterNumber::Natural(3));
+        assert_eq!(memory.to_string(), "registers 0 2 0 1 0");
+    }
+
+    #[test]
+    fn checked_dec_test() {
+        let mut memory = Memory::new_from_slice(&[Register::from(1)]);
+        assert!(memory.checked_dec(RegisterNumber::Natural(0)).is_ok());
+        assert_eq!(
+            memory.checked_dec(RegisterNumber::Natural(0)),
+            Err(DecrementZeroError(RegisterNumber::Natural(0))),
+        );
+        assert_eq!(
+            memory.checked_dec(RegisterNumber::Natural(5)),
+            Err(DecrementZeroError(RegisterNumber::Natural(5))),
+        );
+        assert_eq!(memory.get_nat_registers_as_u128(), vec![0, 0, 0, 0, 0, 0]);
+    }
+
     #[test]
     fn inc_from_max_test() {
         let mut reg = Register::new(&[u128::MAX][..]);
@@ -352,10 +804,35 @@ mod tests {
         assert_eq!(reg, Register::new(&[u128::MAX]));
     }
 
+    #[test]
+    fn display_larger_than_u128() {
+        // 2^128 + 73
+        let reg = Register::new(&[73, 1]);
+        assert_eq!(reg.to_string(), "340282366920938463463374607431768211529");
+    }
+
     #[test]
     fn is_zero_test() {
         let reg = Register::new(&[]);
         let mut mem = Memory::new_from_slice(&[reg]);
         assert!(mem.is_zero(RegisterNumber::Natural(0)))
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn arithmetic_test() {
+        let max = Register::from(u128::MAX);
+        let one = Register::from(1);
+        assert_eq!(&max + &one, Register::new(&[0, 1]));
+        assert_eq!(Register::new(&[0, 1]).checked_sub(&one), Some(max.clone()));
+        assert_eq!(one.checked_sub(&Register::new(&[0, 1])), None);
+        assert_eq!(&max * &max, Register::new(&[1, u128::MAX - 1]));
+        assert_eq!(Register::from(2).pow(128), Register::new(&[0, 1]));
+        assert_eq!(Register::from(7).pow(0), one);
+        assert_eq!(Register::new(&[0, 1]).get_bit_count(), 129);
+        assert_eq!(Register::from(0).get_bit_count(), 0);
+        let parsed: Register = "340282366920938463463374607431768211456".parse().unwrap();
+        assert_eq!(parsed, Register::new(&[0, 1]));
+        assert_eq!(parsed.to_string(), "340282366920938463463374607431768211456");
+        assert!("12a".parse::<Register>().is_err());
+    }
+}
@@ -0,0 +1,167 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Static metrics about a program, found without running it.
+
+use std::fmt::Display;
+
+use crate::{
+    instruction::Instruction,
+    machine::{ Identifier, Machine },
+    memory::RegisterNumber,
+};
+
+/// Static metrics about a machine's program, which can be displayed as a short report.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metrics {
+    /// The total number of instructions.
+    pub lines: usize,
+    pub inc_count: usize,
+    pub decjz_count: usize,
+    pub decjnz_count: usize,
+    pub goto_count: usize,
+    pub call_count: usize,
+    pub return_count: usize,
+    pub read_count: usize,
+    pub write_count: usize,
+    pub halt_count: usize,
+    pub labels: usize,
+    /// The highest natural register used, or [`None`] if no natural register is used.
+    pub max_natural_register: Option<usize>,
+    /// The highest negative register used, or [`None`] if no negative register is used.
+    pub max_negative_register: Option<usize>,
+    /// The cyclomatic complexity of the control flow graph, which is one more than the number of
+    /// DECJZ instructions which can go to two different lines.
+    pub cyclomatic_complexity: usize,
+}
+
+impl Metrics {
+    /// Find the metrics of a machine's program.
+    #[must_use]
+    pub fn new(machine: &Machine) -> Metrics {
+        let lines = machine.get_lines();
+        let mut metrics = Metrics {
+            lines: lines.len(),
+            labels: machine.get_labels().len(),
+            cyclomatic_complexity: 1,
+            ..Metrics::default()
+        };
+        for line in lines {
+            let branch_target = match line.get_instruction() {
+                Instruction::INC(_) => {
+                    metrics.inc_count += 1;
+                    None
+                },
+                Instruction::DECJZ(_, target) => {
+                    metrics.decjz_count += 1;
+                    Some(target)
+                },
+                Instruction::DECJNZ(_, target) => {
+                    metrics.decjnz_count += 1;
+                    Some(target)
+                },
+                // GOTO only ever goes to one line, so it doesn't add to the complexity.
+                Instruction::GOTO(_) => {
+                    metrics.goto_count += 1;
+                    None
+                },
+                // Neither do CALL and RETURN, which don't depend on any register.
+                Instruction::CALL(_) => {
+                    metrics.call_count += 1;
+                    None
+                },
+                Instruction::RETURN => {
+                    metrics.return_count += 1;
+                    None
+                },
+                Instruction::READ(_) => {
+                    metrics.read_count += 1;
+                    None
+                },
+                Instruction::WRITE(_) => {
+                    metrics.write_count += 1;
+                    None
+                },
+                Instruction::HALT => {
+                    metrics.halt_count += 1;
+                    None
+                },
+            };
+            if let Some(target) = branch_target {
+                let target_line = match target {
+                    Identifier::Line(n) => Some(*n),
+                    Identifier::Label(label) => machine.get_label_line(label),
+                    Identifier::Halt => Some(lines.len()),
+                    Identifier::Relative(_) | Identifier::LabelOffset(..) => unreachable!(),
+                };
+                if target_line != Some(line.get_line_number() + 1) {
+                    metrics.cyclomatic_complexity += 1;
+                }
+            }
+            match line.get_instruction().get_register() {
+                Some(RegisterNumber::Natural(n)) => {
+                    metrics.max_natural_register = metrics.max_natural_register.max(Some(n));
+                },
+                Some(RegisterNumber::Negative(n)) => {
+                    metrics.max_negative_register = metrics.max_negative_register.max(Some(n));
+                },
+                Some(RegisterNumber::Pseudo(_)) | None => (),
+            }
+        }
+        metrics
+    }
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let register = |max: Option<usize>, prefix: &str| {
+            max.map_or_else(|| String::from("none"), |n| format!("{prefix}{n}"))
+        };
+        write!(
+            f,
+            "Instructions: {} ({} inc, {} decjz",
+            self.lines,
+            self.inc_count,
+            self.decjz_count,
+        )?;
+        if self.decjnz_count > 0 {
+            write!(f, ", {} decjnz", self.decjnz_count)?;
+        }
+        if self.goto_count > 0 {
+            write!(f, ", {} goto", self.goto_count)?;
+        }
+        if self.call_count > 0 {
+            write!(f, ", {} call", self.call_count)?;
+        }
+        if self.return_count > 0 {
+            write!(f, ", {} return", self.return_count)?;
+        }
+        if self.read_count > 0 {
+            write!(f, ", {} read", self.read_count)?;
+        }
+        if self.write_count > 0 {
+            write!(f, ", {} write", self.write_count)?;
+        }
+        if self.halt_count > 0 {
+            write!(f, ", {} halt", self.halt_count)?;
+        }
+        writeln!(f, ")")?;
+        writeln!(f, "Labels: {}", self.labels)?;
+        writeln!(f, "Highest natural register: {}", register(self.max_natural_register, "r"))?;
+        writeln!(f, "Highest negative register: {}", register(self.max_negative_register, "r-"))?;
+        writeln!(f, "Cyclomatic complexity: {}", self.cyclomatic_complexity)
+    }
+}
@@ -0,0 +1,180 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Making a program harder to read without changing what it computes.
+//!
+//! [`obfuscate`] splits the program into basic blocks: straight-line runs of instructions which
+//! are only entered at the top and only jump at the bottom. The block execution starts in stays
+//! first, and the rest are shuffled. A block which used to fall through into the next one gets an
+//! unconditional jump (`decjz rZ L`, where `rZ` is always 0) if that block no longer follows it.
+//! Every label is replaced by an opaque name, and labels which nothing jumps to are dropped.
+//!
+//! The result always computes the same registers, but it may take a few more steps because of the
+//! added jumps. Comments are never kept, and neither are `#!phase` directives, since phase labels
+//! describe the program. Jumps to labels which don't exist are replaced by jumps to `HALT`, which
+//! is what they did.
+
+use crate::{
+    analysis::SplitMix64,
+    instruction::Instruction,
+    machine::{ Identifier, Line, LineNumber, Machine },
+    memory::RegisterNumber,
+    optimize,
+};
+
+/// Obfuscate a machine's program. The same seed always gives the same program.
+#[must_use]
+pub fn obfuscate(machine: &Machine, seed: u64) -> Machine {
+    let lines = machine.get_lines();
+    let memory = machine.get_initial_memory().clone();
+    if lines.is_empty() {
+        return Machine::new_from_lines(&[], memory);
+    }
+    let mut rng = SplitMix64(seed);
+    let resolve = |target: &Identifier| match target {
+        Identifier::Halt => None,
+        Identifier::Line(n) => (*n < lines.len()).then_some(*n),
+        Identifier::Label(label) => machine.get_label_line(label),
+        Identifier::Relative(_) | Identifier::LabelOffset(..) => unreachable!(),
+    };
+    let blocks = basic_blocks(lines, machine.get_entry_line(), resolve);
+    // Keep the block execution starts in first, so that no #!entry directive is needed.
+    let entry = blocks
+        .iter()
+        .position(|&(start, _)| start == machine.get_entry_line())
+        .expect("Execution starts at the top of a block.");
+    let mut order: Vec<usize> = (0..blocks.len()).filter(|&i| i != entry).collect();
+    // A Fisher-Yates shuffle.
+    for i in (1..order.len()).rev() {
+        let j = usize::try_from(rng.next_u64() % (i as u64 + 1)).expect("At most i.");
+        order.swap(i, j);
+    }
+    order.insert(0, entry);
+    let zero_registers = optimize::always_zero_registers(lines);
+    let zero = zero_register(lines, &zero_registers);
+
+    // Work out which blocks need a label: those jumped to, and those which are fallen into but
+    // no longer follow the block which falls into them.
+    let mut targets: Vec<LineNumber> = lines
+        .iter()
+        .filter_map(|line| line.get_instruction().get_jump_target().and_then(&resolve))
+        .collect();
+    let mut gotos: Vec<Option<Option<LineNumber>>> = vec![None; order.len()];
+    for (position, &block) in order.iter().enumerate() {
+        let (_, end) = blocks[block];
+        // Blocks which end by halting, returning or jumping unconditionally never fall through.
+        // Those ending with CALL do once it returns, so they're followed by a jump if need be.
+        match lines[end - 1].get_instruction() {
+            Instruction::HALT | Instruction::RETURN | Instruction::GOTO(_) => continue,
+            Instruction::DECJZ(register, _) if zero_registers.contains(register) => continue,
+            _ => (),
+        }
+        // Falling off the end of the program halts.
+        let successor = (end < lines.len()).then_some(end);
+        let next_start = order.get(position + 1).map(|&next| blocks[next].0);
+        if successor != next_start {
+            gotos[position] = Some(successor);
+            targets.extend(successor);
+        }
+    }
+    let mut names: Vec<(LineNumber, String)> = Vec::new();
+    for target in targets {
+        if names.iter().all(|(line, _)| *line != target) {
+            let name = opaque_name(&mut rng, &names);
+            names.push((target, name));
+        }
+    }
+    let label = |line: Option<LineNumber>| match line {
+        Some(line) => Identifier::Label(
+            names
+                .iter()
+                .find(|(named, _)| *named == line)
+                .map(|(_, name)| name.clone())
+                .expect("Every target has a name."),
+        ),
+        None => Identifier::Halt,
+    };
+
+    let mut obfuscated: Vec<Line> = Vec::new();
+    for (position, &block) in order.iter().enumerate() {
+        let (start, end) = blocks[block];
+        for (line_number, line) in lines.iter().enumerate().take(end).skip(start) {
+            let id = names
+                .iter()
+                .any(|(named, _)| *named == line_number)
+                .then(|| label(Some(line_number)));
+            let instruction = match line.get_instruction().get_jump_target() {
+                Some(target) => line.get_instruction().with_jump_target(label(resolve(target))),
+                None => line.get_instruction().clone(),
+            };
+            obfuscated.push(Line::new(obfuscated.len(), id, instruction));
+        }
+        if let Some(successor) = gotos[position] {
+            let goto = Instruction::DECJZ(zero, label(successor));
+            obfuscated.push(Line::new(obfuscated.len(), None, goto));
+        }
+    }
+    Machine::new_from_lines(&obfuscated, memory)
+}
+
+/// Split the lines into basic blocks, given as the range of line numbers `start..end` of each, in
+/// program order. A block starts at the first line, at `entry`, at every line which is jumped to
+/// and after every line which can jump.
+fn basic_blocks(
+    lines: &[Line],
+    entry: LineNumber,
+    resolve: impl Fn(&Identifier) -> Option<LineNumber>,
+) -> Vec<(LineNumber, LineNumber)> {
+    let mut starts = vec![0, entry];
+    for (line_number, line) in lines.iter().enumerate() {
+        if let Some(target) = line.get_instruction().get_jump_target() {
+            starts.extend(resolve(target));
+            starts.push(line_number + 1);
+        }
+    }
+    starts.retain(|&start| start < lines.len());
+    starts.sort_unstable();
+    starts.dedup();
+    let ends = starts.iter().skip(1).copied().chain(std::iter::once(lines.len()));
+    starts.iter().copied().zip(ends).collect()
+}
+
+/// Find a register which is always 0, preferring one the program already uses.
+fn zero_register(lines: &[Line], zero_registers: &[RegisterNumber]) -> RegisterNumber {
+    if let Some(&register) = zero_registers.first() {
+        return register;
+    }
+    let highest = lines
+        .iter()
+        .filter_map(|line| match line.get_instruction().get_register() {
+            Some(RegisterNumber::Negative(n)) => Some(n),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    RegisterNumber::Negative(highest + 1)
+}
+
+/// Make up a label which doesn't give anything away and isn't already in use.
+fn opaque_name(rng: &mut SplitMix64, names: &[(LineNumber, String)]) -> String {
+    loop {
+        // More digits than needed, so that a retry is rare.
+        let name = format!("l{:06x}", rng.next_u64() & 0xFF_FFFF);
+        if names.iter().all(|(_, used)| *used != name) {
+            return name;
+        }
+    }
+}
@@ -0,0 +1,222 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! A peephole optimiser which removes redundant instructions.
+//!
+//! Generated code is often full of small patterns which do nothing, such as incrementing a
+//! register just to decrement it on the next line. [`peephole`] repeatedly finds and removes these
+//! until none are left. The following patterns are recognised, where `rZ` is a register which is
+//! always 0:
+//!
+//! * `decjz rZ L` where `L` is the next line - it neither changes memory nor jumps anywhere new.
+//! * `inc rX` followed by `decjz rX L` which nothing else jumps to - the DECJZ never jumps and
+//!   undoes the increment.
+//! * `inc rX` followed by a loop which drains `rX` (`L: decjz rX M` then `decjz rZ L`) - the
+//!   increment is immediately thrown away.
+//!
+//! Once none are left, every `decjz rZ L` which is left is replaced with `goto L`, which jumps
+//! without checking a register at all.
+
+use crate::{
+    instruction::Instruction,
+    machine::{ Identifier, Line, LineNumber, Machine },
+    memory::RegisterNumber,
+};
+
+/// Find every register which is always 0, that is, every negative register which is never
+/// incremented or read into.
+#[must_use]
+pub fn always_zero_registers(lines: &[Line]) -> Vec<RegisterNumber> {
+    let mut registers: Vec<RegisterNumber> = Vec::new();
+    for line in lines {
+        let register = line.get_instruction().get_register();
+        if let Some(register @ RegisterNumber::Negative(_)) = register {
+            let is_incremented = lines
+                .iter()
+                .any(|l| {
+                    let instruction = l.get_instruction();
+                    instruction == &Instruction::INC(register)
+                        || instruction == &Instruction::READ(register)
+                });
+            if !is_incremented && !registers.contains(&register) {
+                registers.push(register);
+            }
+        }
+    }
+    registers
+}
+
+/// Find every register which is used as a GOTO, that is, every register which is always 0 and
+/// which is only ever used by DECJZ, like `r-1` in `decjz r-1 loop`.
+#[must_use]
+pub fn goto_registers(lines: &[Line]) -> Vec<RegisterNumber> {
+    let mut registers = always_zero_registers(lines);
+    registers.retain(|register| {
+        lines.iter().all(|line| match line.get_instruction() {
+            Instruction::DECJZ(_, _) => true,
+            instruction => instruction.get_register() != Some(*register),
+        })
+    });
+    registers
+}
+
+/// Remove redundant instructions from a machine's program.
+///
+/// The returned machine computes the same result as the original, starting from the same initial
+/// registers.
+#[must_use]
+pub fn peephole(machine: &Machine) -> Machine {
+    let mut lines: Vec<Line> = Vec::from(machine.get_lines());
+    let zero_registers = always_zero_registers(&lines);
+    let mut entry = machine.get_entry().map(|label| Identifier::Label(label.to_string()));
+    while let Some(to_remove) = find_redundant(&lines, &zero_registers, entry.as_ref()) {
+        // Remove from the back so the earlier index stays correct.
+        for &i in to_remove.iter().rev() {
+            remove_line(&mut lines, i, &mut entry);
+        }
+    }
+    for line in &mut lines {
+        if let Instruction::DECJZ(register, target) = line.get_instruction() {
+            if zero_registers.contains(register) {
+                let goto = Instruction::GOTO(target.clone());
+                *line = Line::new(line.get_line_number(), line.get_id().cloned(), goto);
+            }
+        }
+    }
+    let mut optimized = Machine::new_from_lines(&lines, machine.get_initial_memory().clone());
+    for phase in machine.get_phases() {
+        // A phase whose label was on a removed line can't be kept.
+        let _ = optimized.add_phase(phase.clone());
+    }
+    for (label, _) in machine.get_labels() {
+        if let Some(documentation) = machine.get_label_documentation(label) {
+            let _ = optimized.set_label_documentation(label.clone(), documentation.to_string());
+        }
+    }
+    if let Some(Identifier::Label(entry)) = entry {
+        optimized.set_entry(entry).expect("The entry point was kept up to date.");
+    }
+    optimized
+}
+
+/// Find the line number a jump target points to, where HALT is just past the last line.
+fn resolve(lines: &[Line], target: &Identifier) -> Option<LineNumber> {
+    match target {
+        Identifier::Halt => Some(lines.len()),
+        Identifier::Line(n) => Some(*n),
+        Identifier::Label(_) => lines.iter().position(|line| line.get_id() == Some(target)),
+        Identifier::Relative(_) | Identifier::LabelOffset(..) => unreachable!(),
+    }
+}
+
+/// Check whether any instruction could jump to the given line, or execution could start there.
+fn is_jump_target(lines: &[Line], line_number: LineNumber, entry: Option<&Identifier>) -> bool {
+    let is_entry = entry.is_some_and(|entry| resolve(lines, entry) == Some(line_number));
+    is_entry || lines.iter().any(|line| {
+        line.get_instruction()
+            .get_jump_target()
+            .is_some_and(|target| resolve(lines, target) == Some(line_number))
+    })
+}
+
+/// Find the first set of lines which can be removed, in ascending order.
+fn find_redundant(
+    lines: &[Line],
+    zero_registers: &[RegisterNumber],
+    entry: Option<&Identifier>,
+) -> Option<Vec<LineNumber>> {
+    let is_goto = |line: &Line, to: LineNumber| match line.get_instruction() {
+        Instruction::DECJZ(register, target) => {
+            zero_registers.contains(register) && resolve(lines, target) == Some(to)
+        },
+        Instruction::GOTO(target) => resolve(lines, target) == Some(to),
+        Instruction::INC(_)
+        | Instruction::READ(_)
+        | Instruction::WRITE(_)
+        | Instruction::DECJNZ(..)
+        | Instruction::CALL(_)
+        | Instruction::RETURN
+        | Instruction::HALT => false,
+    };
+    // Removing the entry point along with every line after it would leave nowhere to start.
+    let entry_line = entry.and_then(|entry| resolve(lines, entry));
+    let keeps_entry = |first: LineNumber, last: LineNumber| {
+        entry_line.is_none_or(|n| n < first || n > last || last + 1 < lines.len())
+    };
+    for (i, line) in lines.iter().enumerate() {
+        if is_goto(line, i + 1) && keeps_entry(i, i) {
+            return Some(vec![i]);
+        }
+        let Instruction::INC(register) = line.get_instruction() else { continue };
+        let Some(next) = lines.get(i + 1) else { continue };
+        if let Instruction::DECJZ(next_register, _) = next.get_instruction() {
+            let removable = !is_jump_target(lines, i + 1, entry) && keeps_entry(i, i + 1);
+            if next_register == register && removable {
+                return Some(vec![i, i + 1]);
+            }
+            let drains = next_register == register
+                && lines.get(i + 2).is_some_and(|after| is_goto(after, i + 1));
+            if drains {
+                return Some(vec![i]);
+            }
+        }
+    }
+    None
+}
+
+/// Remove a line from a program, keeping every jump pointing at the same instruction.
+///
+/// If the removed line has a label, jumps to it go to the line which takes its place instead.
+/// Jumps to a line number, which relative jumps and jumps to a label plus an offset have become,
+/// are shifted along with the lines after the removed one.
+fn remove_line(lines: &mut Vec<Line>, line_number: LineNumber, entry: &mut Option<Identifier>) {
+    let removed = lines.remove(line_number);
+    for line in lines.iter_mut() {
+        // Jumps to the removed line itself now land on the line which takes its place.
+        if let Some(&Identifier::Line(n)) = line.get_instruction().get_jump_target() {
+            if n > line_number {
+                let instruction = line.get_instruction().with_jump_target(Identifier::Line(n - 1));
+                *line = Line::new(line.get_line_number(), line.get_id().cloned(), instruction);
+            }
+        }
+    }
+    if let Some(old) = removed.get_id() {
+        let new = match lines.get_mut(line_number) {
+            Some(next) => {
+                if next.get_id().is_none() {
+                    next.change_id(Some(old.clone()));
+                }
+                next.get_id().cloned().expect("Label was just set.")
+            },
+            // Jumping past the end of the program halts it.
+            None => Identifier::Halt,
+        };
+        if entry.as_ref() == Some(old) {
+            *entry = Some(new.clone());
+        }
+        for line in lines.iter_mut() {
+            if line.get_instruction().get_jump_target() == Some(old) {
+                let instruction = line.get_instruction().with_jump_target(new.clone());
+                *line = Line::new(line.get_line_number(), line.get_id().cloned(), instruction);
+            }
+        }
+    }
+    // Renumber the remaining lines.
+    for (n, line) in lines.iter_mut().enumerate() {
+        *line = Line::new(n, line.get_id().cloned(), line.get_instruction().clone());
+    }
+}
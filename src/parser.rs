@@ -14,37 +14,374 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 
-use pest::Parser;
+use std::{ fmt::Display, ops::Range };
+
+use pest::{
+    error::{ ErrorVariant, LineColLocation },
+    iterators::Pair,
+    Parser,
+};
 use pest_derive::Parser;
 use thiserror::Error;
 
 use crate::{
+    ast,
+    dialect::{ Dialect, DialectError },
     instruction::Instruction,
-    memory::{ Memory, Register, RegisterNumber },
-    machine::{ Identifier, Line, Machine },
+    link::SCOPE_SEPARATOR,
+    memory::{ Memory, PseudoRegister, Register, RegisterNumber },
+    machine::{ DuplicateLabel, Identifier, Line, Machine, MachineEditError },
+    requirement::{ self, Comparison, Requirement },
+    suggest,
+    vecmap::VecMap,
 };
 
+/// Warnings about programs which parse but are probably wrong (see [`crate::lint`]).
+pub use crate::format::format_source;
+pub use crate::lint::{ lint, Warning };
+
 #[derive(Parser)]
 #[grammar = "syntax.pest"]
 pub struct RemuirParser;
 
+/// Limits on the size of a program, for services which parse source code they don't trust, so a
+/// pathological program can't use up all their memory. Registers are stored in a vector, so a
+/// single `inc r1000000000` would otherwise allocate gigabytes.
+///
+/// Macros can expand a short program into a great many lines, so the number of lines is checked
+/// after expanding them. A few nested macros can also call each other billions of times without
+/// adding a line, so the number of expansions and how deeply they're nested are limited too.
+/// Source code can't include other files (libraries are linked by the caller, see
+/// [`crate::link`]), so macros are the only thing which nests.
+///
+/// Between them, these bound the time and memory parsing can take, whatever the source code is.
+/// They don't bound running the program, which is what [`crate::sandbox`] is for.
+///
+/// # Examples
+/// ```
+/// use remuir::parser::{ parse_str_with_limits, ParseLimits, ParseSourceError };
+///
+/// let limits = ParseLimits { max_lines: 1, ..ParseLimits::default() };
+/// assert!(matches!(
+///     parse_str_with_limits("registers 0\ninc r0\ninc r0", &limits),
+///     Err(ParseSourceError::TooManyLines { maximum: 1 }),
+/// ));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// The longest source code allowed, in bytes.
+    pub max_source_length: usize,
+    /// The most instruction lines allowed, after expanding macros.
+    pub max_lines: usize,
+    /// The longest label allowed, in characters.
+    pub max_label_length: usize,
+    /// The largest register number allowed, such as 3 for `r3` or `r-3`. This also limits how
+    /// many values the registers line can have.
+    pub max_register: usize,
+    /// The most macro and pseudo-instruction calls which may be expanded, counting calls made by
+    /// other macros. Macros which expand to no lines at all can't be caught by `max_lines`.
+    pub max_expansions: usize,
+    /// How deeply macros may call other macros.
+    pub max_macro_depth: usize,
+}
+
+impl ParseLimits {
+    /// No limits at all, which is what [`parse_str`] uses.
+    pub const UNLIMITED: ParseLimits = ParseLimits {
+        max_source_length: usize::MAX,
+        max_lines: usize::MAX,
+        max_label_length: usize::MAX,
+        max_register: usize::MAX,
+        max_expansions: usize::MAX,
+        max_macro_depth: usize::MAX,
+    };
+}
+
+impl Default for ParseLimits {
+    /// Limits which are generous for any program written by hand.
+    fn default() -> Self {
+        ParseLimits {
+            max_source_length: 1 << 20,
+            max_lines: 10_000,
+            max_label_length: 256,
+            max_register: 1_000,
+            max_expansions: 10_000,
+            max_macro_depth: 32,
+        }
+    }
+}
+
+/// The number of bits a value in the registers line may have, so that a value such as `9^9^9`
+/// can't use up all the memory. Each step of working out a value must also fit.
+pub const MAX_REGISTER_VALUE_BITS: u64 = 65_536;
+
+/// Parse a register initialisation line, such as `registers 1 2 3`. Values can be arithmetic on
+/// natural numbers, such as `registers 2^64 10*3 0`, and may be larger than 2^128.
+///
+/// # Errors
+///
+/// * [`ParseSourceError::InvalidRegisterValue`] - when one of the values isn't a natural number.
+/// * [`ParseSourceError::NegativeRegisterValue`] - when one of the values is below 0, such as
+///   `2-3`.
+/// * [`ParseSourceError::RegisterValueTooLarge`] - when one of the values needs more than
+///   [`MAX_REGISTER_VALUE_BITS`] bits.
+/// * [`ParseSourceError::SyntaxError`] - when the line doesn't start with `registers`, or has no
+///   values.
 pub fn parse_register_line(s: &str) -> Result<Memory, ParseSourceError> {
-    let register_line = RemuirParser::parse(Rule::register_line, s)
-        ?
-        .next()
-        .expect("Can't fail.");
+    let register_line = match RemuirParser::parse(Rule::register_line, s) {
+        Ok(mut pairs) => pairs.next().expect("Can't fail."),
+        Err(e) => return Err(find_invalid_register_value(s).unwrap_or_else(|| e.into())),
+    };
+    // Pest stops at the first value which isn't a number, so make sure nothing was left over.
+    if register_line.as_span().end() < s.trim_end().len() {
+        return Err(find_invalid_register_value(s).unwrap_or_else(|| {
+            ParseSourceError::InvalidRegisterValue {
+                value: s[register_line.as_span().end()..].trim().to_string(),
+                position: register_line.clone().into_inner().count() + 1,
+            }
+        }));
+    }
+
+    register_line
+        .into_inner()
+        .map(|value| {
+            let text = value.as_str();
+            evaluate_register_value(value.clone()).map_err(|error| match error {
+                ValueError::Negative => {
+                    ParseSourceError::NegativeRegisterValue { value: text.to_string() }
+                },
+                ValueError::TooLarge => {
+                    ParseSourceError::RegisterValueTooLarge { value: text.to_string() }
+                },
+            })
+        })
+        .collect::<Result<Memory, ParseSourceError>>()
+}
+
+/// Why a value in the registers line can't be used.
+enum ValueError {
+    Negative,
+    TooLarge,
+}
+
+/// Work out a value in the registers line, such as `2^64`. `^` is applied first, from right to
+/// left, then `*`, then `+` and `-`, from left to right.
+fn evaluate_register_value(value: Pair<Rule>) -> Result<Register, ValueError> {
+    let checked = |register: Register| {
+        if register.get_bit_count() > MAX_REGISTER_VALUE_BITS {
+            Err(ValueError::TooLarge)
+        }
+        else {
+            Ok(register)
+        }
+    };
+    let mut operands = Vec::new();
+    let mut operators = Vec::new();
+    for part in value.into_inner() {
+        match part.as_rule() {
+            Rule::register_value_number => {
+                // The number of digits is checked first, so that reading a huge number is quick.
+                if part.as_str().len() as u64 > MAX_REGISTER_VALUE_BITS / 3 + 1 {
+                    return Err(ValueError::TooLarge);
+                }
+                operands.push(checked(part.as_str().parse().expect("Guaranteed by Pest."))?);
+            },
+            Rule::register_value => operands.push(evaluate_register_value(part)?),
+            _ => operators.push(part.as_str()),
+        }
+    }
+    for i in (0..operators.len()).rev() {
+        if operators[i] == "^" {
+            let exponent = operands.remove(i + 1);
+            operands[i] = checked(power(&operands[i], &exponent)?)?;
+            operators.remove(i);
+        }
+    }
+    let mut i = 0;
+    while i < operators.len() {
+        if operators[i] == "*" {
+            let right = operands.remove(i + 1);
+            operands[i] = checked(&operands[i] * &right)?;
+            operators.remove(i);
+        }
+        else {
+            i += 1;
+        }
+    }
+    let mut operands = operands.into_iter();
+    let mut result = operands.next().expect("Guaranteed by Pest.");
+    for (operator, operand) in operators.into_iter().zip(operands) {
+        result = match operator {
+            "+" => checked(&result + &operand)?,
+            _ => result.checked_sub(&operand).ok_or(ValueError::Negative)?,
+        };
+    }
+    Ok(result)
+}
+
+/// Raise `base` to the power of `exponent`, without working out powers which would be too large.
+fn power(base: &Register, exponent: &Register) -> Result<Register, ValueError> {
+    // 0 and 1 stay the same, apart from to the power of 0.
+    if base.get_bit_count() <= 1 {
+        return Ok(if exponent.get_bit_count() == 0 { Register::from(1) } else { base.clone() });
+    }
+    // The result is at least 2^((bits - 1) * exponent).
+    let exponent = exponent
+        .to_u128()
+        .and_then(|exponent| u64::try_from(exponent).ok())
+        .filter(|&exponent| {
+            (base.get_bit_count() - 1).saturating_mul(exponent) <= MAX_REGISTER_VALUE_BITS
+        })
+        .ok_or(ValueError::TooLarge)?;
+    Ok(base.pow(exponent))
+}
+
+/// Find the first value in a register initialisation line which isn't a natural number.
+fn find_invalid_register_value(s: &str) -> Option<ParseSourceError> {
+    let mut words = s.split_whitespace();
+    if words.next() != Some("registers") {
+        return None;
+    }
+    words
+        .enumerate()
+        .find(|(_, word)| !is_register_value(word))
+        .map(|(i, word)| ParseSourceError::InvalidRegisterValue {
+            value: word.to_string(),
+            position: i + 1,
+        })
+}
 
-    Ok(
-        register_line
-            // Turn into an iterator of Pest Pairs.
-            .into_inner()
-            // Each rule will be the register initial value, so use a map to make them u128s.
-            .map(
-                |r| r.as_str().parse::<u128>().expect("Assume r < 2^128.")
-            )
-            .map(Register::from)
-            .collect::<Memory>()
-    )
+/// Check whether a word of a registers line is a valid value, such as `3` or `2^64`.
+fn is_register_value(word: &str) -> bool {
+    RemuirParser::parse(Rule::whole_register_value, word).is_ok()
+}
+
+/// Explain why the first line of a program which looks like a registers line isn't one, such as
+/// `Registers 1 2 3` or `registers 1,2,3`, suggesting what it should be.
+fn diagnose_register_line(input: &str) -> Option<ParseSourceError> {
+    let line = input
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+    let mut words = line.split(|c: char| c.is_whitespace() || c == ',' || c == ';');
+    let keyword = words.next()?;
+    let values: Vec<&str> = words.filter(|word| !word.is_empty()).collect();
+    let name = keyword.trim_end_matches(':');
+    if !matches!(name.to_lowercase().as_str(), "registers" | "register" | "regs" | "reg") {
+        return None;
+    }
+    if !values.iter().all(|value| is_register_value(value)) {
+        // Only the values are wrong, which is reported more precisely elsewhere.
+        return if keyword == "registers" { find_invalid_register_value(line) } else { None };
+    }
+
+    let mut problems: Vec<&str> = Vec::new();
+    if name != "registers" {
+        problems.push("The line must start with \"registers\", in lower case.");
+    }
+    if keyword != name {
+        problems.push("There shouldn't be a colon after \"registers\".");
+    }
+    if line.contains([',', ';']) {
+        problems.push("Values must be separated by spaces, not commas or semicolons.");
+    }
+    if values.is_empty() {
+        problems.push("At least one value must be given.");
+    }
+    if problems.is_empty() {
+        return None;
+    }
+    let suggestion = if values.is_empty() {
+        String::from("registers 0")
+    }
+    else {
+        format!("registers {}", values.join(" "))
+    };
+    Some(ParseSourceError::MalformedRegisterLine {
+        line: line.to_string(),
+        problem: problems.join(" "),
+        suggestion,
+    })
+}
+
+/// Check whether `label` can be used as a label, giving the first character which can't be used in
+/// one, if any. Labels are words made of letters, digits, marks, `_` and `-` from any script,
+/// separated by spaces, and `::` separates a scope from a label, as in `library::loop`. Returns
+/// [`None`] if the label is valid.
+///
+/// A label with no invalid characters can still be invalid if it's empty, or has a misplaced `:`
+/// or space, in which case that character (or `:` for an empty label) is returned.
+#[must_use]
+pub fn invalid_label_character(label: &str) -> Option<char> {
+    if RemuirParser::parse(Rule::whole_label, label).is_ok() {
+        return None;
+    }
+    let mut buffer = [0; 4];
+    label
+        .chars()
+        .find(|&c| {
+            !matches!(c, ':' | ' ' | '\t')
+                && RemuirParser::parse(Rule::single_label_char, c.encode_utf8(&mut buffer)).is_err()
+        })
+        .or_else(|| label.chars().find(|c| matches!(c, ':' | ' ' | '\t')))
+        .or(Some(':'))
+}
+
+/// Find the first label in some source code which has a character that can't be used in labels,
+/// so it can be reported more precisely than a syntax error.
+fn find_invalid_label(input: &str) -> Option<ParseSourceError> {
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with("registers") {
+            continue;
+        }
+        let line = line.split('#').next().unwrap_or_default();
+        // The line label is whatever comes before the colon which is followed by the instruction.
+        let instruction_start = line
+            .match_indices(':')
+            .map(|(colon, _)| colon + 1)
+            .find(|&start| {
+                let rest = line[start..].trim_start();
+                let names = [
+                    "inc", "decjz", "decjnz", "goto", "call", "return", "read", "write", "many",
+                    "halt",
+                ];
+                names.iter().any(|name| {
+                    rest.strip_prefix(name).is_some_and(|rest| {
+                        rest.is_empty() || rest.starts_with(char::is_whitespace)
+                    })
+                })
+            });
+        let mut labels = Vec::new();
+        if let Some(start) = instruction_start {
+            labels.push(line[..start - 1].trim());
+        }
+        let instruction = line[instruction_start.unwrap_or(0)..].trim();
+        let arguments = instruction
+            .strip_prefix("decjz")
+            .or_else(|| instruction.strip_prefix("decjnz"));
+        if let Some(arguments) = arguments {
+            let mut arguments = arguments.trim_start().splitn(2, char::is_whitespace);
+            arguments.next();
+            labels.push(arguments.next().unwrap_or_default().trim());
+        }
+        else if let Some(target) = instruction
+            .strip_prefix("goto ")
+            .or_else(|| instruction.strip_prefix("call "))
+        {
+            labels.push(target.trim());
+        }
+        for label in labels.into_iter().filter(|label| !label.is_empty()) {
+            if let Some(character) = invalid_label_character(label) {
+                return Some(ParseSourceError::InvalidLabel {
+                    label: label.to_string(),
+                    character,
+                    line: i + 1,
+                });
+            }
+        }
+    }
+    None
 }
 
 #[must_use]
@@ -55,10 +392,28 @@ fn parse_label(s: &str) -> Identifier {
     }
 }
 
+/// Parse where a jump goes, which is either a label, a relative jump or a number of lines after a
+/// label. A relative jump too large to count goes as far as it can, which is past the end of the
+/// program or before its start.
+fn parse_target(pair: &Pair<Rule>) -> Identifier {
+    let s = pair.as_str();
+    match pair.as_rule() {
+        Rule::relative_target => {
+            let farthest = if s.starts_with('-') { isize::MIN } else { isize::MAX };
+            Identifier::Relative(s.parse().unwrap_or(farthest))
+        },
+        Rule::label_offset_target => {
+            let (label, offset) = s.rsplit_once('+').expect("Guaranteed by Pest.");
+            Identifier::LabelOffset(label.to_string(), offset.parse().unwrap_or(usize::MAX))
+        },
+        _ => parse_label(s),
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ParseSourceError {
-    #[error("Syntax error: invalid machine source code. {0}")]
-    SyntaxError(#[from] Box<pest::error::Error<Rule>>),
+    #[error("{0}")]
+    SyntaxError(Box<SyntaxError>),
     #[error("Too many arguments specified. Got {received} but {instruction} expects {expected}.")]
     TooManyArgument {
         received: usize,
@@ -73,11 +428,264 @@ pub enum ParseSourceError {
     },
     #[error("No initial registers provided. Please make the first line \"registers 0\" if this is intentional.")]
     NoInitialRegisters,
+    #[error("The registers line {line:?} is malformed. {problem} It should look like {suggestion:?}.")]
+    MalformedRegisterLine { line: String, problem: String, suggestion: String },
+    #[error("Invalid register value {value:?} (value number {position}). Register values must be natural numbers, or arithmetic on them without spaces, such as \"registers 3 0 2^64\".")]
+    InvalidRegisterValue { value: String, position: usize },
+    #[error("Register value {value} is too large. Values of 2^{MAX_REGISTER_VALUE_BITS} or more aren't supported.")]
+    RegisterValueTooLarge { value: String },
+    #[error("Register value {value} is negative. Registers can only hold natural numbers.")]
+    NegativeRegisterValue { value: String },
+    #[error("Invalid character {character:?} in the label {label:?} on line {line}. Labels can only contain letters, digits, spaces, \"_\" and \"-\", with \"::\" between a scope and a label.")]
+    InvalidLabel { label: String, character: char, line: usize },
+    #[error("The source code is {length} bytes long, but at most {maximum} are allowed.")]
+    SourceTooLong { length: usize, maximum: usize },
+    #[error("The program has more than {maximum} lines.")]
+    TooManyLines { maximum: usize },
+    #[error("The label {label:?} is longer than {maximum} characters.")]
+    LabelTooLong { label: String, maximum: usize },
+    #[error("Register {register} is too large. Registers up to r{maximum} and r-{maximum} are allowed.")]
+    RegisterTooLarge { register: RegisterNumber, maximum: usize },
+    #[error("{0} Each label can only be defined once.")]
+    DuplicateLabel(DuplicateLabel),
+    #[error(
+        "Cannot mark {label:?} as a phase, no line has that label.{}",
+        suggest::did_you_mean(.suggestion.as_deref()),
+    )]
+    PhaseLabelNotFound { label: String, suggestion: Option<String> },
+    #[error(
+        "Cannot start execution at {label:?}, no line has that label.{}",
+        suggest::did_you_mean(.suggestion.as_deref()),
+    )]
+    EntryLabelNotFound { label: String, suggestion: Option<String> },
+    #[error("More than one entry point given. There can only be one \"#!entry\" directive.")]
+    MultipleEntries,
+    #[error(
+        "Cannot export {label:?}, no line has that label.{}",
+        suggest::did_you_mean(.suggestion.as_deref()),
+    )]
+    ExportLabelNotFound { label: String, suggestion: Option<String> },
+    #[error(
+        "There is no pseudo-register \"r!{name}\".{}",
+        suggest::did_you_mean(.suggestion.as_deref()),
+    )]
+    UnknownPseudoRegister { name: String, suggestion: Option<String> },
+    #[error("{0} is a pseudo-register, which can only be read.")]
+    ReadOnlyRegister(RegisterNumber),
+    #[error("Line {line} uses the pseudo-register {register}, which needs the \"#!extended\" directive.")]
+    NotExtended { register: RegisterNumber, line: usize },
+    #[error("Line {line} says the program needs {requirement}, but this is remuir {}.", requirement::VERSION)]
+    UnmetRequirement { requirement: Requirement, line: usize },
+    #[error("The directive {text:?} on line {line} isn't a valid requirement. It should look like \"#!requires remuir >= 0.3\".")]
+    InvalidRequirement { text: String, line: usize },
+    #[error("The jump {offset:+} on line {line} goes before the start of the program.")]
+    JumpBeforeStart { offset: isize, line: usize },
+    #[error("The jump to {label}+{offset} on line {line} goes past the end of the program.")]
+    JumpPastEnd { label: String, offset: usize, line: usize },
+    #[error(
+        "Line {line} jumps to {label:?}, but no line has that label.{}",
+        suggest::did_you_mean(.suggestion.as_deref()),
+    )]
+    UndefinedLabel { label: String, line: usize, suggestion: Option<String> },
+    #[error(
+        "Unknown instruction or macro {name:?}.{} Macros must be defined before they're used.",
+        suggest::did_you_mean(.suggestion.as_deref()),
+    )]
+    UnknownMacro { name: String, suggestion: Option<String> },
+    #[error("The macro {name:?} is defined more than once.")]
+    DuplicateMacro { name: String },
+    #[error("The macro {name:?} calls itself, so it would never stop expanding.")]
+    RecursiveMacro { name: String },
+    #[error("The program expands more than {maximum} macros.")]
+    TooManyExpansions { maximum: usize },
+    #[error("The macro {name:?} is called from more than {maximum} nested macros.")]
+    MacroTooDeep { name: String, maximum: usize },
+    #[error("{instruction} only works on registers, but was given {argument:?}.")]
+    ExpectedRegister { argument: String, instruction: String },
+    #[error("Unknown instruction {word:?}.{}", suggest::did_you_mean(*.suggestion))]
+    UnknownInstruction { word: String, span: Range<usize>, suggestion: Option<&'static str> },
 }
 
 impl From<pest::error::Error<Rule>> for ParseSourceError {
     fn from(value: pest::error::Error<Rule>) -> Self {
-        ParseSourceError::SyntaxError(Box::new(value))
+        ParseSourceError::SyntaxError(Box::new(SyntaxError::from(value)))
+    }
+}
+
+/// Where a syntax error is in the source code, and a hint about how to fix it. It's shown with
+/// the offending line and a caret under the bad token:
+///
+/// ```text
+/// Syntax error on line 2, column 5: expected a register, such as "r0".
+///   2 | inc x
+///     |     ^
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// The line the error is on, counting from 1.
+    pub line: usize,
+    /// The column the bad token starts at, counting characters from 1.
+    pub column: usize,
+    /// The text of the line the error is on.
+    pub source_line: String,
+    /// How many characters the bad token has, which is at least 1.
+    pub length: usize,
+    /// What was expected instead, or what's wrong.
+    pub hint: String,
+}
+
+impl SyntaxError {
+    /// Make an error at a column of a line, with the bad token being the word which starts
+    /// there, or the next character if it's whitespace.
+    fn at(line: usize, column: usize, source_line: &str, hint: String) -> SyntaxError {
+        let length = source_line
+            .chars()
+            .skip(column.saturating_sub(1))
+            .take_while(|c| !c.is_whitespace())
+            .count()
+            .max(1);
+        SyntaxError { line, column, source_line: source_line.to_string(), length, hint }
+    }
+
+    /// Find where a line which should be an instruction stops being one.
+    fn in_instruction_line(line: usize, source_line: &str) -> Option<SyntaxError> {
+        let trimmed = source_line.trim_start();
+        let indent = source_line.chars().count() - trimmed.chars().count();
+        let (column, hint) = match RemuirParser::parse(Rule::instruction_line, trimmed) {
+            Err(e) => {
+                if let Some(column) = find_bad_register(trimmed) {
+                    let column = indent + column;
+                    return Some(SyntaxError::at(line, column, source_line, register_hint()));
+                }
+                let LineColLocation::Pos((_, column)) = e.line_col else { return None };
+                let ErrorVariant::ParsingError { positives, .. } = &e.variant else { return None };
+                (column, hint(positives))
+            },
+            Ok(mut pairs) => {
+                let end = pairs.next()?.as_span().end();
+                let rest = &trimmed[end..];
+                if rest.trim().is_empty() || rest.trim_start().starts_with('#') {
+                    return None;
+                }
+                let skipped = rest.len() - rest.trim_start().len();
+                let column = trimmed[..end + skipped].chars().count() + 1;
+                (column, String::from("each line can only have one instruction"))
+            },
+        };
+        Some(SyntaxError::at(line, indent + column, source_line, hint))
+    }
+}
+
+impl From<pest::error::Error<Rule>> for SyntaxError {
+    fn from(error: pest::error::Error<Rule>) -> Self {
+        let (LineColLocation::Pos((line, column)) | LineColLocation::Span((line, column), _)) =
+            error.line_col;
+        // Pest shows the line break at the end of the line as a symbol, such as "␊".
+        let source_line = error.line().trim_end_matches(['\r', '\n', '␍', '␊']);
+        let positives = match &error.variant {
+            ErrorVariant::ParsingError { positives, .. } => positives.as_slice(),
+            ErrorVariant::CustomError { .. } => &[],
+        };
+        let word = source_line.split_whitespace().next().unwrap_or_default();
+        if positives.contains(&Rule::instruction_line) {
+            // An error at the start of a line only says that it isn't any kind of line, so look
+            // for what's wrong with it as an instruction.
+            let hint = match word {
+                "macro" => "a macro definition must end with a line saying \"endmacro\"",
+                "endmacro" => "there's no \"macro\" line before this \"endmacro\"",
+                _ if word.starts_with("#!") => {
+                    "expected a directive, such as \"#!phase loop\", since comments can't start \
+                    with \"#!\" and a letter"
+                },
+                _ => "",
+            };
+            if !hint.is_empty() {
+                return SyntaxError::at(line, column, source_line, hint.to_string());
+            }
+            if let Some(error) = SyntaxError::in_instruction_line(line, source_line) {
+                return error;
+            }
+        }
+        // Pest only reports which instruction it tried, not that its register was wrong.
+        if let Some(register_column) = find_bad_register(source_line.trim_start()) {
+            let indent = source_line.chars().count() - source_line.trim_start().chars().count();
+            return SyntaxError::at(line, indent + register_column, source_line, register_hint());
+        }
+        SyntaxError::at(line, column, source_line, hint(positives))
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let number = self.line.to_string();
+        let margin = " ".repeat(number.len());
+        // Tabs are kept under the caret so that it lines up however wide they're shown.
+        let indent: String = self
+            .source_line
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        writeln!(
+            f,
+            "Syntax error on line {}, column {}: {}.",
+            self.line,
+            self.column,
+            self.hint,
+        )?;
+        writeln!(f, "  {number} | {}", self.source_line)?;
+        write!(f, "  {margin} | {indent}{}", "^".repeat(self.length))
+    }
+}
+
+/// Find the column of the register argument of an instruction which isn't a register, or the
+/// column just after the instruction if its register is missing. The line mustn't start with
+/// whitespace.
+fn find_bad_register(line: &str) -> Option<usize> {
+    let start = line_label(line).map_or(0, |label| label.len() + 1);
+    let rest = &line[start..];
+    let instruction_start = start + rest.len() - rest.trim_start().len();
+    let instruction = line[instruction_start..].split('#').next().unwrap_or_default();
+    let mut words = instruction.split_whitespace();
+    let name = words.next()?;
+    if !matches!(name, "inc" | "dec" | "decjz" | "decjnz" | "read" | "write" | "many") {
+        return None;
+    }
+    let column = |byte: usize| line[..byte].chars().count() + 1;
+    let Some(register) = words.next() else {
+        return Some(column(instruction_start + name.len()));
+    };
+    if RemuirParser::parse(Rule::whole_register, register).is_ok() {
+        return None;
+    }
+    let offset = instruction.find(register).expect("The register is in the instruction.");
+    Some(column(instruction_start + offset))
+}
+
+fn register_hint() -> String {
+    String::from("expected a register, such as \"r0\"")
+}
+
+/// Describe what the parser expected, from the rules it tried.
+fn hint(positives: &[Rule]) -> String {
+    let expects = |rules: &[Rule]| positives.iter().any(|rule| rules.contains(rule));
+    if expects(&[Rule::pos_register_num, Rule::neg_register_num, Rule::pseudo_register_name]) {
+        register_hint()
+    }
+    else if expects(&[Rule::reference_label, Rule::relative_target]) {
+        String::from("expected a label to jump to, or a relative jump such as \"+3\"")
+    }
+    else if expects(&[Rule::register_num]) {
+        String::from("expected a natural number")
+    }
+    else if expects(&[Rule::instruction_line, Rule::instruction, Rule::line_label]) {
+        String::from("expected an instruction, such as \"inc r0\"")
+    }
+    else if positives == [Rule::EOI] {
+        String::from("expected the end of the line")
+    }
+    else {
+        String::from("this isn't valid source code")
     }
 }
 
@@ -95,27 +703,51 @@ pub fn parse_inc(s: &str) -> Result<Instruction, ParseSourceError> {
         .next()
         .unwrap();
 
-    let reg_num: RegisterNumber = match inc.as_rule() {
-        Rule::pos_register_num => RegisterNumber::Natural(
-            inc
-                .as_str()
-                .parse()
-                .expect("Guaranteed by Pest.")
-        ),
-        Rule::neg_register_num => RegisterNumber::Negative(
-            inc
-                .as_str()
-                .parse()
-                .expect("Guaranteed by Pest.")
-        ),
-        _ => unreachable!(),
-    };
+    let reg_num = parse_register(&inc)?;
+    if let RegisterNumber::Pseudo(_) = reg_num {
+        return Err(ParseSourceError::ReadOnlyRegister(reg_num));
+    }
     Ok(Instruction::INC(reg_num))
 }
 
 pub fn parse_decjz(s: &str) -> Result<Instruction, ParseSourceError> {
+    let (register, label) = parse_jump(Rule::decjz, s)?;
+    Ok(Instruction::DECJZ(register, label))
+}
+
+/// Parse a decrement and jump if not zero line.
+///
+/// # Errors
+///
+/// * [`ParseSourceError::SyntaxError`] - when there's a syntax error in the source code.
+pub fn parse_decjnz(s: &str) -> Result<Instruction, ParseSourceError> {
+    let (register, label) = parse_jump(Rule::decjnz, s)?;
+    Ok(Instruction::DECJNZ(register, label))
+}
+
+/// Read a register from the rule Pest matched it with.
+fn parse_register(pair: &Pair<Rule>) -> Result<RegisterNumber, ParseSourceError> {
+    let number = || pair.as_str().parse().expect("Guaranteed by Pest.");
+    match pair.as_rule() {
+        Rule::pos_register_num => Ok(RegisterNumber::Natural(number())),
+        Rule::neg_register_num => Ok(RegisterNumber::Negative(number())),
+        Rule::pseudo_register_name => {
+            let name = pair.as_str();
+            format!("r!{name}").parse().map_err(|_| ParseSourceError::UnknownPseudoRegister {
+                name: name.to_string(),
+                suggestion: suggest::closest(name, PseudoRegister::NAMES)
+                    .map(|closest| format!("r!{closest}")),
+            })
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Parse the register and label of a conditional jump, either [`Rule::decjz`] or
+/// [`Rule::decjnz`].
+fn parse_jump(rule: Rule, s: &str) -> Result<(RegisterNumber, Identifier), ParseSourceError> {
     use RegisterNumber as Rnum;
-    let decjz = RemuirParser::parse(Rule::decjz, s)
+    let decjz = RemuirParser::parse(rule, s)
         ?
         .next()
         .unwrap();
@@ -125,14 +757,17 @@ pub fn parse_decjz(s: &str) -> Result<Instruction, ParseSourceError> {
 
     for rule in decjz.into_inner() {
         match rule.as_rule() {
-            Rule::pos_register_num => final_register_number = Rnum::Natural(rule.as_str().parse().unwrap()),
-            Rule::neg_register_num => final_register_number = Rnum::Negative(rule.as_str().parse().unwrap()),
-            Rule::reference_label => final_label = parse_label(rule.as_str()),
+            Rule::pos_register_num | Rule::neg_register_num | Rule::pseudo_register_name => {
+                final_register_number = parse_register(&rule)?;
+            },
+            Rule::reference_label | Rule::relative_target | Rule::label_offset_target => {
+                final_label = parse_target(&rule);
+            },
             _ => unreachable!(),
         }
     }
     
-    Ok(Instruction::DECJZ(final_register_number, final_label))
+    Ok((final_register_number, final_label))
 }
 
 pub fn parse_instruction_line(s: &str, line_num: usize) -> Result<Line, ParseSourceError> {
@@ -160,6 +795,32 @@ pub fn parse_instruction_line(s: &str, line_num: usize) -> Result<Line, ParseSou
                     Rule::decjz => {
                         instruction = parse_decjz(instruction_part.as_str())?;
                     },
+                    Rule::decjnz => {
+                        instruction = parse_decjnz(instruction_part.as_str())?;
+                    },
+                    Rule::goto => {
+                        let target = instruction_part.into_inner().next().unwrap();
+                        instruction = Instruction::GOTO(parse_target(&target));
+                    },
+                    Rule::call => {
+                        let target = instruction_part.into_inner().next().unwrap();
+                        instruction = Instruction::CALL(parse_target(&target));
+                    },
+                    Rule::return_instruction => instruction = Instruction::RETURN,
+                    Rule::read => {
+                        let operand = instruction_part.into_inner().next().unwrap();
+                        let register = parse_register(&operand)?;
+                        if let RegisterNumber::Pseudo(_) = register {
+                            return Err(ParseSourceError::ReadOnlyRegister(register));
+                        }
+                        instruction = Instruction::READ(register);
+                    },
+                    Rule::write => {
+                        let operand = instruction_part.into_inner().next().unwrap();
+                        let register = parse_register(&operand)?;
+                        instruction = Instruction::WRITE(register);
+                    },
+                    Rule::halt => instruction = Instruction::HALT,
                     _ => unreachable!(),
                 }
             },
@@ -171,43 +832,1090 @@ pub fn parse_instruction_line(s: &str, line_num: usize) -> Result<Line, ParseSou
 }
 
 /// Parse a register machine source code and return a [`Machine`] struct if the source code is
-/// valid.
+/// valid. Lines may end with `\n` or `\r\n`, and a leading byte order mark is ignored.
 /// 
 /// # Errors
 /// 
 /// * [`ParseSourceError::SyntaxError`] - when there's a syntax error in the source code.
-/// * [`ParseSourceError::NoInitialRegisters`] - when a machine doesn't have an initial registers.
-/// line.
+/// * [`ParseSourceError::NoInitialRegisters`] - when a machine doesn't have an initial registers
+///   line.
+/// * [`ParseSourceError::MalformedRegisterLine`] - when the first line looks like a registers line
+///   but isn't quite one, such as `Registers 1 2 3` or `registers 1,2,3`.
+/// * [`ParseSourceError::InvalidRegisterValue`] - when a value in the registers line isn't a
+///   natural number.
+/// * [`ParseSourceError::InvalidLabel`] - when a label has a character which can't be used in
+///   labels.
+/// * [`ParseSourceError::DuplicateLabel`] - when a label is defined on more than one line.
+/// * [`ParseSourceError::UndefinedLabel`] - when an instruction jumps to a label which isn't
+///   defined. Labels with a scope, such as `library::loop`, are checked when linking instead.
+/// * [`ParseSourceError::JumpPastEnd`] - when a jump to a number of lines after a label, such as
+///   `loop+2`, goes further than just past the last line.
+/// * [`ParseSourceError::PhaseLabelNotFound`] - when a phase directive names a label which doesn't
+///   exist.
+/// * [`ParseSourceError::EntryLabelNotFound`] - when an entry directive names a label which doesn't
+///   exist.
+/// * [`ParseSourceError::MultipleEntries`] - when there's more than one entry directive.
+/// * [`ParseSourceError::ExportLabelNotFound`] - when an export directive names a label which
+///   doesn't exist.
+/// * [`ParseSourceError::UnknownPseudoRegister`] - when a pseudo-register, such as `r!steps`,
+///   doesn't exist.
+/// * [`ParseSourceError::ReadOnlyRegister`] - when a pseudo-register is incremented.
+/// * [`ParseSourceError::NotExtended`] - when a pseudo-register is used without the `#!extended`
+///   directive.
+/// * [`ParseSourceError::UnknownMacro`] - when a line uses an instruction or macro which doesn't
+///   exist.
+/// * [`ParseSourceError::DuplicateMacro`] - when a macro is defined more than once.
+/// * [`ParseSourceError::RecursiveMacro`] - when a macro calls itself.
+/// * [`ParseSourceError::TooFewArguments`] and [`ParseSourceError::TooManyArgument`] - when a
+///   macro or pseudo-instruction, such as `copy`, is given the wrong number of arguments.
+/// * [`ParseSourceError::ExpectedRegister`] - when a pseudo-instruction is given something other
+///   than a register.
 pub fn parse_str(input: &str) -> Result<Machine, ParseSourceError> {
-    use ParseSourceError as PSErr;
+    parse_str_with_limits(input, &ParseLimits::UNLIMITED)
+}
+
+/// Parse a register machine's source code like [`parse_str`], but carry on past errors to find
+/// as many as it can in one go. After an error on a line, that line is left out, keeping its label
+/// if it has one, and the rest is parsed again. This stops at the first error which can't be
+/// traced to a line, such as a missing registers line, since it may only be there because of the
+/// lines left out.
+///
+/// # Errors
+///
+/// Every error found, in the order of the lines they're on, with any error which isn't on a line
+/// last. They're the same errors as [`parse_str`] returns.
+///
+/// # Examples
+/// ```
+/// use remuir::parser::{ parse_str_all_errors, ParseSourceError };
+///
+/// let errors = parse_str_all_errors("registers 0\ninc x\ndecjz r0\ninc r0").unwrap_err();
+/// assert_eq!(errors.len(), 2);
+/// assert!(matches!(&errors[1], ParseSourceError::SyntaxError(error) if error.line == 3));
+/// ```
+pub fn parse_str_all_errors(input: &str) -> Result<Machine, Vec<ParseSourceError>> {
+    let mut lines: Vec<String> = input.lines().map(str::to_string).collect();
+    let mut errors: Vec<(Option<usize>, ParseSourceError)> = Vec::new();
+    loop {
+        let error = match parse_str(&lines.join("\n")) {
+            Ok(machine) if errors.is_empty() => return Ok(machine),
+            Ok(_) => break,
+            Err(error) => error,
+        };
+        let line = error_line(&error, &lines);
+        let repeated = errors.iter().any(|(found, _)| line.is_some() && *found == line);
+        if !repeated {
+            errors.push((line, error));
+        }
+        match line {
+            Some(line) if !repeated => {
+                let label = line_label(lines[line - 1].trim())
+                    .filter(|label| invalid_label_character(label).is_none());
+                lines[line - 1] = label.map(|label| format!("{label}: halt")).unwrap_or_default();
+            },
+            _ => break,
+        }
+    }
+    errors.sort_by_key(|(line, _)| line.unwrap_or(usize::MAX));
+    Err(errors.into_iter().map(|(_, error)| error).collect())
+}
+
+/// Find the line of the source code an error is on, counting from 1, if it can be told.
+fn error_line(error: &ParseSourceError, lines: &[String]) -> Option<usize> {
+    match error {
+        // Errors in a macro's body are numbered from the start of the body, so only trust the
+        // line number if the line matches.
+        ParseSourceError::SyntaxError(error) => lines
+            .get(error.line.checked_sub(1)?)
+            .is_some_and(|line| *line == error.source_line)
+            .then_some(error.line),
+        ParseSourceError::InvalidLabel { line, .. } => Some(*line),
+        ParseSourceError::UnknownMacro { name, .. } => lines
+            .iter()
+            .position(|line| {
+                let line = line.trim();
+                let start = line_label(line).map_or(0, |label| label.len() + 1);
+                line[start..].split_whitespace().next() == Some(name.as_str())
+            })
+            .map(|i| i + 1),
+        _ => None,
+    }
+}
+
+/// Parse a register machine's source code like [`parse_str`], but allow labels to be defined more
+/// than once, in which case jumps go to the last definition. The duplicates can be found with
+/// [`Machine::get_duplicate_labels`], so they can be shown as warnings.
+///
+/// # Errors
+///
+/// The same as [`parse_str`], except for [`ParseSourceError::DuplicateLabel`] and
+/// [`ParseSourceError::UndefinedLabel`].
+pub fn parse_str_permissive(input: &str) -> Result<Machine, ParseSourceError> {
+    parse_file(input, Err(ParseSourceError::NoInitialRegisters), &ParseLimits::UNLIMITED, true)
+}
+
+/// Parse a register machine's source code like [`parse_str`], but fail if it's larger than
+/// `limits` allows.
+///
+/// # Errors
+///
+/// The same as [`parse_str`], and also:
+///
+/// * [`ParseSourceError::SourceTooLong`] - when the source code is too long.
+/// * [`ParseSourceError::TooManyLines`] - when there are too many instruction lines.
+/// * [`ParseSourceError::LabelTooLong`] - when a label is too long.
+/// * [`ParseSourceError::RegisterTooLarge`] - when a register number is too large, or the
+///   registers line has too many values.
+/// * [`ParseSourceError::TooManyExpansions`] - when too many macros are expanded.
+/// * [`ParseSourceError::MacroTooDeep`] - when macros call each other too deeply.
+pub fn parse_str_with_limits(
+    input: &str,
+    limits: &ParseLimits,
+) -> Result<Machine, ParseSourceError> {
+    parse_file(input, Err(ParseSourceError::NoInitialRegisters), limits, false)
+}
+
+/// Parse the source code of a library to be linked with a main program (see [`crate::link`]).
+/// Unlike [`parse_str`], the registers line may be left out, since only the main program's
+/// registers are used.
+///
+/// # Errors
+///
+/// * [`ParseSourceError::SyntaxError`] - when there's a syntax error in the source code.
+/// * [`ParseSourceError::DuplicateLabel`] - when a label is defined on more than one line.
+/// * [`ParseSourceError::PhaseLabelNotFound`] - when a phase directive names a label which doesn't
+///   exist.
+/// * [`ParseSourceError::EntryLabelNotFound`] - when an entry directive names a label which doesn't
+///   exist.
+/// * [`ParseSourceError::MultipleEntries`] - when there's more than one entry directive.
+/// * [`ParseSourceError::ExportLabelNotFound`] - when an export directive names a label which
+///   doesn't exist.
+pub fn parse_library_str(input: &str) -> Result<Machine, ParseSourceError> {
+    parse_file(input, Ok(Memory::default()), &ParseLimits::UNLIMITED, false)
+}
+
+/// Parse a program written in another dialect, such as [`crate::urm::Urm`], by translating it
+/// into remuir source code first.
+///
+/// # Errors
+///
+/// * [`DialectError::Syntax`] - when the program isn't valid in the dialect.
+/// * [`DialectError::InvalidSource`] - when the translation can't be parsed, with any error of
+///   [`parse_str`].
+pub fn parse_str_dialect(input: &str, dialect: &dyn Dialect) -> Result<Machine, DialectError> {
+    Ok(parse_str(&dialect.translate(input)?)?)
+}
+
+/// Check that a line of a program is within `limits`.
+fn check_line_limits(line: &Line, limits: &ParseLimits) -> Result<(), ParseSourceError> {
+    let instruction = line.get_instruction();
+    if let Some(
+        register @ (RegisterNumber::Natural(number) | RegisterNumber::Negative(number))
+    ) = instruction.get_register() {
+        if number > limits.max_register {
+            return Err(ParseSourceError::RegisterTooLarge {
+                register,
+                maximum: limits.max_register,
+            });
+        }
+    }
+    for id in [line.get_id(), instruction.get_jump_target()].into_iter().flatten() {
+        if let Identifier::Label(label) = id {
+            if label.chars().count() > limits.max_label_length {
+                return Err(ParseSourceError::LabelTooLong {
+                    label: label.clone(),
+                    maximum: limits.max_label_length,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Explain why source code which doesn't parse is wrong, if there's a more helpful reason than a
+/// syntax error. `needs_registers` is whether the source code should start with a registers line.
+fn diagnose(input: &str, needs_registers: bool) -> Option<ParseSourceError> {
+    if needs_registers {
+        if let Some(error) = diagnose_register_line(input) {
+            return Some(error);
+        }
+        if !input.trim().starts_with("registers ") {
+            return Some(ParseSourceError::NoInitialRegisters);
+        }
+    }
+    find_invalid_label(input)
+}
+
+/// A macro, defined by the lines from `macro NAME PARAMETERS...` to `endmacro`.
+struct Macro {
+    parameters: Vec<String>,
+    /// The lines between the `macro` and `endmacro` lines, without comments.
+    body: Vec<String>,
+}
+
+/// Collects the lines of a program, expanding each macro call into the lines of the macro's body.
+///
+/// Labels defined in a macro's body are local to each expansion, by renaming them to
+/// `NAME::N::LABEL` in the Nth expansion. Jumps to `end` in a body go to the line after the
+/// expansion, or halt if there isn't one.
+struct Expander<'a> {
+    limits: &'a ParseLimits,
+    /// A register the program doesn't use, for pseudo-instructions to keep values in. It's 0
+    /// before and after each of them.
+    scratch: RegisterNumber,
+    macros: VecMap<String, Macro>,
+    lines: Vec<Line>,
+    /// How many macro calls have been expanded.
+    expansions: usize,
+    /// Labels for the next line, such as the label of a macro call, or the `end` of an expansion.
+    pending: Vec<String>,
+    /// Labels which are jumped to by another name, because their line already has a label.
+    aliases: VecMap<String, Identifier>,
+    /// The macros being expanded, innermost last.
+    expanding: Vec<String>,
+}
+
+impl<'a> Expander<'a> {
+    fn new(limits: &'a ParseLimits, scratch: RegisterNumber) -> Expander<'a> {
+        Expander {
+            limits,
+            scratch,
+            macros: VecMap::default(),
+            lines: Vec::new(),
+            expansions: 0,
+            pending: Vec::new(),
+            aliases: VecMap::default(),
+            expanding: Vec::new(),
+        }
+    }
+
+    fn define(
+        &mut self,
+        name: &str,
+        parameters: &[String],
+        body: &[String],
+    ) -> Result<(), ParseSourceError> {
+        if self.macros.get(&name.to_string()).is_some() {
+            return Err(ParseSourceError::DuplicateMacro { name: name.to_string() });
+        }
+        let (parameters, body) = (parameters.to_vec(), body.to_vec());
+        self.macros.update(name.to_string(), Macro { parameters, body });
+        Ok(())
+    }
+
+    /// Add an instruction line, or the lines a macro call expands to, from a macro's body.
+    fn add(&mut self, line: Pair<Rule>) -> Result<(), ParseSourceError> {
+        if line.as_rule() == Rule::macro_call {
+            let (label, name, arguments) = parse_macro_call(line);
+            return self.call(label.as_deref(), &name, &arguments);
+        }
+        let line = parse_instruction_line(line.as_str(), self.lines.len())?;
+        self.push(line.get_id().cloned(), line.get_instruction().clone())
+    }
+
+    /// Add a line, giving it the pending labels.
+    fn push(
+        &mut self,
+        id: Option<Identifier>,
+        instruction: Instruction,
+    ) -> Result<(), ParseSourceError> {
+        if self.lines.len() >= self.limits.max_lines {
+            return Err(ParseSourceError::TooManyLines { maximum: self.limits.max_lines });
+        }
+        let mut pending = std::mem::take(&mut self.pending).into_iter();
+        let id = match id {
+            Some(Identifier::Label(label)) => Some(label),
+            _ => pending.next(),
+        };
+        for label in pending {
+            let id = id.clone().expect("Only empty when nothing is pending.");
+            self.aliases.update(label, Identifier::Label(id));
+        }
+        let line = Line::new(self.lines.len(), id.map(Identifier::Label), instruction);
+        check_line_limits(&line, self.limits)?;
+        self.lines.push(line);
+        Ok(())
+    }
+
+    /// Add the lines a call to a macro or pseudo-instruction expands to.
+    fn call(
+        &mut self,
+        label: Option<&str>,
+        name: &str,
+        arguments: &[String],
+    ) -> Result<(), ParseSourceError> {
+        let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
+        // Macros the program defines take precedence over pseudo-instructions of the same name.
+        let pseudo_instruction;
+        let definition = match self.macros.get(&name.to_string()) {
+            Some(definition) => definition,
+            None => match lower_pseudo_instruction(name, &arguments, &self.scratch)? {
+                Some(lowered) => {
+                    pseudo_instruction = lowered;
+                    &pseudo_instruction
+                },
+                None => {
+                    let macros = self.macros.keys();
+                    // The REPL can't jump, read or write, so these are only instructions in
+                    // programs.
+                    let names = INSTRUCTION_NAMES
+                        .into_iter()
+                        .chain(["goto", "call", "return", "read", "write"])
+                        .chain(PSEUDO_INSTRUCTION_NAMES)
+                        .chain(macros.iter().map(|n| n.as_str()));
+                    return Err(ParseSourceError::UnknownMacro {
+                        name: name.to_string(),
+                        suggestion: suggest::closest(name, names).map(ToString::to_string),
+                    });
+                },
+            },
+        };
+        if self.expanding.iter().any(|expanding| expanding == name) {
+            return Err(ParseSourceError::RecursiveMacro { name: name.to_string() });
+        }
+        check_argument_count(name, arguments.len(), definition.parameters.len())?;
+        if self.expansions >= self.limits.max_expansions {
+            return Err(ParseSourceError::TooManyExpansions { maximum: self.limits.max_expansions });
+        }
+        if self.expanding.len() >= self.limits.max_macro_depth {
+            return Err(ParseSourceError::MacroTooDeep {
+                name: name.to_string(),
+                maximum: self.limits.max_macro_depth,
+            });
+        }
+        self.expansions += 1;
+        let prefix = format!("{name}::{}::", self.expansions);
+        let locals: Vec<&str> = definition.body.iter().filter_map(|l| line_label(l)).collect();
+        let mut ends = false;
+        let body: Vec<String> = definition.body
+            .iter()
+            .map(|line| instantiate(line, |word| {
+                if let Some(i) = definition.parameters.iter().position(|p| p == word) {
+                    arguments[i].to_string()
+                }
+                else if locals.contains(&word) || word == "end" {
+                    ends |= word == "end";
+                    format!("{prefix}{word}")
+                }
+                else {
+                    word.to_string()
+                }
+            }))
+            .collect();
+        self.pending.extend(label.map(str::to_string));
+        self.expanding.push(name.to_string());
+        for line in &body {
+            let mut parsed = RemuirParser::parse(Rule::body_line, line)?;
+            let line = parsed
+                .next()
+                .and_then(|line| line.into_inner().next())
+                .expect("Guaranteed by Pest.");
+            self.add(line)?;
+        }
+        self.expanding.pop();
+        if ends {
+            self.pending.push(format!("{prefix}end"));
+        }
+        Ok(())
+    }
+
+    /// Get the label which a label stands for, if it ended up on a line with another label.
+    fn resolve(&self, label: String) -> String {
+        match self.aliases.get(&label) {
+            Some(Identifier::Label(alias)) => alias.clone(),
+            _ => label,
+        }
+    }
+
+    /// Get the lines of the program, with every jump going to the label it stands for. Labels
+    /// which never got a line, because nothing came after them, halt.
+    fn finish(&mut self) -> Vec<Line> {
+        for label in std::mem::take(&mut self.pending) {
+            self.aliases.update(label, Identifier::Halt);
+        }
+        let mut lines = std::mem::take(&mut self.lines);
+        for line in &mut lines {
+            let instruction = line.get_instruction();
+            let target = match instruction.get_jump_target() {
+                Some(Identifier::Label(label)) => self.aliases.get(label).cloned(),
+                Some(Identifier::LabelOffset(label, offset)) => match self.aliases.get(label) {
+                    Some(Identifier::Label(alias)) => {
+                        Some(Identifier::LabelOffset(alias.clone(), *offset))
+                    },
+                    // The label is past the last line, and so is anything after it.
+                    Some(_) => Some(Identifier::Halt),
+                    None => None,
+                },
+                _ => None,
+            };
+            if let Some(target) = target {
+                *line = Line::new(
+                    line.get_line_number(),
+                    line.get_id().cloned(),
+                    instruction.with_jump_target(target),
+                );
+            }
+        }
+        lines
+    }
+}
+
+/// Get the label, name and arguments of a macro call.
+fn parse_macro_call(call: Pair<Rule>) -> (Option<String>, String, Vec<String>) {
+    let mut label = None;
+    let mut name = String::new();
+    let mut arguments = Vec::new();
+    for part in call.into_inner() {
+        match part.as_rule() {
+            Rule::line_label => label = part.as_str().strip_suffix(':').map(str::to_string),
+            Rule::macro_name => name = part.as_str().to_string(),
+            Rule::macro_argument => arguments.push(part.as_str().to_string()),
+            _ => unreachable!(),
+        }
+    }
+    (label, name, arguments)
+}
+
+/// Check that an instruction or macro was given as many arguments as it expects.
+fn check_argument_count(
+    name: &str,
+    received: usize,
+    expected: usize,
+) -> Result<(), ParseSourceError> {
+    let instruction = name.to_string();
+    if received < expected {
+        Err(ParseSourceError::TooFewArguments { received, instruction, expected })
+    }
+    else if received > expected {
+        Err(ParseSourceError::TooManyArgument { received, instruction, expected })
+    }
+    else {
+        Ok(())
+    }
+}
+
+/// The pseudo-instructions which are lowered to INC, DECJZ and DECJNZ when parsing.
+pub const PSEUDO_INSTRUCTION_NAMES: [&str; 3] = ["copy", "add", "zero"];
+
+/// Get the lines a pseudo-instruction stands for, as a macro with the parameters `a` and `b`, or
+/// [`None`] if `name` isn't a pseudo-instruction. `copy a b` sets `b` to `a`, `add a b` adds `a`
+/// to `b` and `zero a` sets `a` to 0. `a` keeps its value, by moving it to `scratch` and back.
+fn lower_pseudo_instruction(
+    name: &str,
+    arguments: &[&str],
+    scratch: &RegisterNumber,
+) -> Result<Option<Macro>, ParseSourceError> {
+    let expected = match name {
+        "copy" | "add" => 2,
+        "zero" => 1,
+        _ => return Ok(None),
+    };
+    check_argument_count(name, arguments.len(), expected)?;
+    let mut registers = Vec::new();
+    for argument in arguments {
+        let register = RemuirParser::parse(Rule::whole_register, argument)
+            .ok()
+            .and_then(|mut pairs| pairs.next()?.into_inner().next())
+            .ok_or_else(|| ParseSourceError::ExpectedRegister {
+                argument: argument.to_string(),
+                instruction: name.to_string(),
+            })?;
+        let register = parse_register(&register)?;
+        if let RegisterNumber::Pseudo(_) = register {
+            return Err(ParseSourceError::ReadOnlyRegister(register));
+        }
+        registers.push(register);
+    }
+    let same = registers.len() == 2 && registers[0] == registers[1];
+    // `a` is moved to `s` while counting in `b`, then moved back.
+    let body: &[&str] = match (name, same) {
+        ("zero", _) => &["clear: decjnz a clear"],
+        ("copy", true) => &[],
+        ("copy", false) => &[
+            "clear: decjnz b clear",
+            "decjz a restore",
+            "move: inc b",
+            "inc s",
+            "decjnz a move",
+            "restore: decjz s end",
+            "back: inc a",
+            "decjnz s back",
+        ],
+        (_, false) => &[
+            "decjz a restore",
+            "move: inc b",
+            "inc s",
+            "decjnz a move",
+            "restore: decjz s end",
+            "back: inc a",
+            "decjnz s back",
+        ],
+        (_, true) => &[
+            "decjz a restore",
+            "move: inc s",
+            "decjnz a move",
+            "restore: decjz s end",
+            "back: inc a",
+            "inc a",
+            "decjnz s back",
+        ],
+    };
+    let scratch = scratch.to_string();
+    let body = body
+        .iter()
+        .map(|line| instantiate(line, |word| {
+            if word == "s" { scratch.clone() } else { word.to_string() }
+        }))
+        .collect();
+    let parameters = ["a", "b"][..expected].iter().map(ToString::to_string).collect();
+    Ok(Some(Macro { parameters, body }))
+}
+
+/// Get a negative register which isn't used in the program, for pseudo-instructions to use.
+fn find_scratch_register(program: &ast::Program) -> RegisterNumber {
+    let largest_in = |text: &str| {
+        text.match_indices("r-")
+            .filter_map(|(i, _)| {
+                let digits = &text[i + 2..];
+                let length = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+                digits[..length].parse::<usize>().ok()
+            })
+            .max()
+    };
+    let largest = program.items
+        .iter()
+        .filter_map(|item| match &item.kind {
+            ast::ItemKind::Instruction { instruction, .. } => match instruction.get_register() {
+                Some(RegisterNumber::Negative(n)) => Some(n),
+                _ => None,
+            },
+            ast::ItemKind::MacroCall { arguments: text, .. }
+            | ast::ItemKind::MacroDefinition { body: text, .. } => {
+                text.iter().filter_map(|text| largest_in(text)).max()
+            },
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    RegisterNumber::Negative(largest.saturating_add(1))
+}
+
+/// Get the label defined at the start of a line, if there is one.
+fn line_label(line: &str) -> Option<&str> {
+    let label = RemuirParser::parse(Rule::line_label, line).ok()?.next()?;
+    label.as_str().strip_suffix(':')
+}
+
+/// Fill in a line of a macro's body, passing each label and operand to `replace`.
+fn instantiate(line: &str, mut replace: impl FnMut(&str) -> String) -> String {
+    let label = line_label(line);
+    let rest = label.map_or(line, |label| line[label.len() + 1..].trim_start());
+    let mut words = rest.split_whitespace();
+    let operation = words.next().unwrap_or_default();
+    let mut operands: Vec<String> = Vec::new();
+    if let "decjz" | "decjnz" | "goto" | "call" = operation {
+        // The target of a jump can have spaces in it.
+        if let "decjz" | "decjnz" = operation {
+            operands.extend(words.next().map(&mut replace));
+        }
+        let target = words.collect::<Vec<&str>>().join(" ");
+        // Only the label of a jump such as "loop+2" is replaced.
+        let (target, offset) = match target.rsplit_once('+') {
+            Some((label, offset))
+                if !label.is_empty()
+                    && !offset.is_empty()
+                    && offset.bytes().all(|byte| byte.is_ascii_digit()) =>
+            {
+                (label.to_string(), format!("+{offset}"))
+            },
+            _ => (target, String::new()),
+        };
+        if !target.is_empty() {
+            operands.push(replace(&target) + &offset);
+        }
+    }
+    else {
+        operands.extend(words.map(&mut replace));
+    }
+    let mut source = label.map(|label| format!("{}: ", replace(label))).unwrap_or_default();
+    source.push_str(operation);
+    for operand in operands {
+        source.push(' ');
+        source.push_str(&operand);
+    }
+    source
+}
+
+/// Parse a source file, using `initial_memory` if the file has no registers line. Labels which
+/// are defined more than once are an error unless `permissive` is set.
+fn parse_file(
+    input: &str,
+    initial_memory: Result<Memory, ParseSourceError>,
+    limits: &ParseLimits,
+    permissive: bool,
+) -> Result<Machine, ParseSourceError> {
+    if input.len() > limits.max_source_length {
+        return Err(ParseSourceError::SourceTooLong {
+            length: input.len(),
+            maximum: limits.max_source_length,
+        });
+    }
+    let needs_registers = initial_memory.is_err();
+    let program = parse_program(input, needs_registers)?;
+    match build(&program, initial_memory, limits, permissive) {
+        // Lines which aren't instructions look like calls to a macro, so they get the same
+        // diagnosis as other mistakes.
+        Err(e @ ParseSourceError::UnknownMacro { .. }) => {
+            Err(diagnose(input, needs_registers).unwrap_or(e))
+        },
+        result => result,
+    }
+}
+
+/// Parse source code into its syntax tree (see [`crate::ast`]), without building a machine from
+/// it. Unlike [`parse_str`], the registers line may be left out, since the tree may be of a
+/// library.
+///
+/// # Errors
+///
+/// * [`ParseSourceError::SyntaxError`] - when there's a syntax error in the source code.
+/// * [`ParseSourceError::InvalidLabel`] - when a label has a character which can't be used in
+///   labels.
+/// * [`ParseSourceError::MalformedRegisterLine`], [`ParseSourceError::InvalidRegisterValue`] and
+///   [`ParseSourceError::RegisterValueTooLarge`] - when the registers line is invalid.
+/// * [`ParseSourceError::UnknownPseudoRegister`] - when a pseudo-register, such as `r!steps`,
+///   doesn't exist.
+/// * [`ParseSourceError::ReadOnlyRegister`] - when a pseudo-register is incremented.
+pub fn parse_ast(input: &str) -> Result<ast::Program, ParseSourceError> {
+    parse_program(input, false)
+}
+
+/// Build a machine from a program's syntax tree (see [`crate::ast`]), expanding its macros, like
+/// [`parse_str_with_limits`] does once it has parsed the source code. Pass
+/// [`ParseLimits::UNLIMITED`] for no limits, as [`parse_str`] does.
+///
+/// # Errors
+///
+/// The same as [`parse_str_with_limits`], apart from the errors found by [`parse_ast`].
+pub fn machine_from_ast(
+    program: &ast::Program,
+    limits: &ParseLimits,
+) -> Result<Machine, ParseSourceError> {
+    build(program, Err(ParseSourceError::NoInitialRegisters), limits, false)
+}
+
+/// Parse source code into its syntax tree. `needs_registers` is whether it should start with a
+/// registers line, which is used to explain syntax errors.
+fn parse_program(input: &str, needs_registers: bool) -> Result<ast::Program, ParseSourceError> {
+    use ast::ItemKind;
+    // Editors on Windows may start files with a byte order mark.
+    let input = input.strip_prefix('\u{FEFF}').unwrap_or(input);
+    check_requirements(input)?;
     let file = match RemuirParser::parse(Rule::file, input) {
         Ok(mut pairs) => pairs.next().expect("Can never fail."),
         Err(e) => {
-            if !input.trim().starts_with("registers ") {
-                return Err(PSErr::NoInitialRegisters);
-            }
-            return Err(PSErr::from(e));
+            let error = diagnose(input, needs_registers);
+            return Err(error.unwrap_or_else(|| ParseSourceError::from(e)));
         },
     };
+    let (comments, documentation_lines) = find_comments(input, &file);
 
-    let mut lines: Vec<Line> = Vec::new();
-    let mut initial_memory: Result<Memory, PSErr> = Err(PSErr::NoInitialRegisters);
-    let mut line_number: usize = 0;
-
-    for line in file.into_inner() {
-        match line.as_rule() {
-            Rule::register_line => {
-                initial_memory = Ok(parse_register_line(line.as_str())?);
+    let mut items = Vec::new();
+    for pair in file.into_inner() {
+        let span = pair.as_span().start()..pair.as_span().end();
+        let line = pair.line_col().0;
+        // The text of the directive's label, if it's a directive.
+        let directive_label = || {
+            let label = pair.clone().into_inner().next().expect("Guaranteed by Pest.");
+            label.as_str().trim_end().to_string()
+        };
+        let kind = match pair.as_rule() {
+            Rule::register_line => ItemKind::Registers(parse_register_line(pair.as_str())?),
+            Rule::macro_definition => {
+                let mut parts = pair.clone().into_inner();
+                let mut header = parts.next().expect("Guaranteed by Pest.").into_inner();
+                let name = header.next().expect("Guaranteed by Pest.").as_str().to_string();
+                let parameters = header.map(|parameter| parameter.as_str().to_string()).collect();
+                let body = parts.map(|line| line.as_str().trim().to_string()).collect();
+                ItemKind::MacroDefinition { name, parameters, body }
             },
             Rule::instruction_line => {
-                lines.push(parse_instruction_line(line.as_str(), line_number)?);
-                line_number += 1;
+                let parsed = parse_instruction_line(pair.as_str(), 0)?;
+                let label = match parsed.get_id() {
+                    Some(Identifier::Label(label)) => Some(label.clone()),
+                    _ => None,
+                };
+                ItemKind::Instruction { label, instruction: parsed.get_instruction().clone() }
             },
-            Rule::EOI => (),
+            Rule::macro_call => {
+                let (label, name, arguments) = parse_macro_call(pair.clone());
+                ItemKind::MacroCall { label, name, arguments }
+            },
+            Rule::phase_directive => ItemKind::Phase(directive_label()),
+            Rule::entry_directive => ItemKind::Entry(directive_label()),
+            Rule::export_directive => ItemKind::Export(directive_label()),
+            Rule::import_directive => ItemKind::Import(directive_label()),
+            Rule::extended_directive => ItemKind::Extended,
+            Rule::requires_directive => ItemKind::Requires(parse_requirement(pair.clone())),
+            Rule::EOI => continue,
             _ => unreachable!(),
+        };
+        // The documentation is the "##" lines right above.
+        let mut documentation = Vec::new();
+        let mut above = line - 1;
+        while let Some(text) = documentation_lines.get(&above) {
+            documentation.push(text.clone());
+            above -= 1;
+        }
+        documentation.reverse();
+        items.push(ast::Item { span, line, documentation, kind });
+    }
+    Ok(ast::Program { items, comments })
+}
+
+/// Check every `#!requires` directive before parsing the rest of the program, which may use syntax
+/// from a later version.
+fn check_requirements(input: &str) -> Result<(), ParseSourceError> {
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+        let is_requires = line
+            .strip_prefix("#!requires")
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t']));
+        if !is_requires {
+            continue;
+        }
+        let Ok(mut pairs) = RemuirParser::parse(Rule::requires_line, line) else {
+            let text = line.to_string();
+            return Err(ParseSourceError::InvalidRequirement { text, line: i + 1 });
+        };
+        let directive = pairs.next().expect("Guaranteed by Pest.").into_inner().next();
+        let requirement = parse_requirement(directive.expect("Guaranteed by Pest."));
+        if !requirement.is_met() {
+            return Err(ParseSourceError::UnmetRequirement { requirement, line: i + 1 });
         }
     }
-    Ok(Machine::new_from_lines(&lines[..], initial_memory?))
+    Ok(())
+}
+
+fn parse_requirement(directive: Pair<Rule>) -> Requirement {
+    let mut parts = directive.into_inner();
+    let comparison = match parts.next().expect("Guaranteed by Pest.").as_str() {
+        ">=" => Comparison::AtLeast,
+        _ => Comparison::Exactly,
+    };
+    let version = parts
+        .next()
+        .expect("Guaranteed by Pest.")
+        .as_str()
+        .split('.')
+        .map(|number| number.parse().unwrap_or(u64::MAX))
+        .collect();
+    Requirement { comparison, version }
+}
+
+/// Find every comment in the parsed source code, which is anything the grammar skipped from a `#`
+/// to the end of its line. The text of each line which is nothing but a `##` comment is also
+/// given, by line number.
+fn find_comments(input: &str, file: &Pair<Rule>) -> (Vec<ast::Comment>, VecMap<usize, String>) {
+    // Only whitespace, line breaks and comments are left between these.
+    let mut parsed = Vec::new();
+    for pair in file.clone().into_inner() {
+        if pair.as_rule() == Rule::macro_definition {
+            parsed.extend(pair.into_inner().map(|part| part.as_span()));
+        }
+        else {
+            parsed.push(pair.as_span());
+        }
+    }
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut comments = Vec::new();
+    let mut documentation_lines = VecMap::default();
+    let mut gap_start = 0;
+    for span in parsed {
+        let mut rest = gap_start..span.start();
+        while let Some(hash) = input[rest.clone()].find('#').map(|hash| rest.start + hash) {
+            let end = input[hash..].find(['\r', '\n']).map_or(input.len(), |end| hash + end);
+            let line = line_starts.partition_point(|&start| start <= hash);
+            let is_documentation = input[hash..end].starts_with("##");
+            let text = &input[hash + if is_documentation { 2 } else { 1 }..end];
+            if is_documentation && input[line_starts[line - 1]..hash].trim().is_empty() {
+                documentation_lines.update(line, text.trim().to_string());
+            }
+            comments.push(ast::Comment {
+                span: hash..end,
+                line,
+                text: text.to_string(),
+                is_documentation,
+            });
+            rest.start = end;
+        }
+        gap_start = span.end();
+    }
+    (comments, documentation_lines)
+}
+
+/// Build a machine from a program's syntax tree, using `initial_memory` if the program has no
+/// registers line. Labels which are defined more than once are an error unless `permissive` is
+/// set.
+fn build(
+    program: &ast::Program,
+    mut initial_memory: Result<Memory, ParseSourceError>,
+    limits: &ParseLimits,
+    permissive: bool,
+) -> Result<Machine, ParseSourceError> {
+    use ast::ItemKind;
+    use ParseSourceError as PSErr;
+    let library = initial_memory.is_ok();
+    let mut expander = Expander::new(limits, find_scratch_register(program));
+    let mut phases: Vec<String> = Vec::new();
+    let mut entry: Option<String> = None;
+    let mut exports: Vec<String> = Vec::new();
+    let mut imports: Vec<String> = Vec::new();
+    let mut extended = false;
+    // The line of source code each line of the program came from.
+    let mut source_lines: Vec<usize> = Vec::new();
+
+    for item in &program.items {
+        match &item.kind {
+            ItemKind::Registers(memory) => {
+                let count = memory.get_nat_count();
+                if count > limits.max_register.saturating_add(1) {
+                    return Err(PSErr::RegisterTooLarge {
+                        register: RegisterNumber::Natural(count - 1),
+                        maximum: limits.max_register,
+                    });
+                }
+                initial_memory = Ok(memory.clone());
+            },
+            ItemKind::MacroDefinition { name, parameters, body } => {
+                expander.define(name, parameters, body)?;
+            },
+            ItemKind::Instruction { label, instruction } => {
+                expander.push(label.clone().map(Identifier::Label), instruction.clone())?;
+            },
+            ItemKind::MacroCall { label, name, arguments } => {
+                expander.call(label.as_deref(), name, arguments)?;
+            },
+            ItemKind::Phase(label) => phases.push(label.clone()),
+            ItemKind::Entry(label) => {
+                if entry.is_some() {
+                    return Err(PSErr::MultipleEntries);
+                }
+                entry = Some(label.clone());
+            },
+            ItemKind::Export(label) => exports.push(label.clone()),
+            ItemKind::Import(label) => imports.push(label.clone()),
+            ItemKind::Extended => extended = true,
+            // Requirements were checked before the program was parsed.
+            ItemKind::Requires(_) => (),
+        }
+        source_lines.resize(expander.lines.len(), item.line);
+    }
+    let lines = expander.finish();
+    if !extended {
+        let pseudo = lines.iter().find_map(|line| match line.get_instruction().get_register() {
+            Some(register @ RegisterNumber::Pseudo(_)) => Some((register, line.get_line_number())),
+            _ => None,
+        });
+        if let Some((register, line)) = pseudo {
+            return Err(PSErr::NotExtended { register, line: source_lines[line] });
+        }
+    }
+    for line in &lines {
+        if let Some(&Identifier::Relative(offset)) = line.get_instruction().get_jump_target() {
+            if line.get_line_number().checked_add_signed(offset).is_none() {
+                let line = source_lines[line.get_line_number()];
+                return Err(PSErr::JumpBeforeStart { offset, line });
+            }
+        }
+    }
+    if let Some(error) = check_label_offsets(&lines, &source_lines, permissive) {
+        return Err(error);
+    }
+    let mut machine = Machine::new_from_lines(&lines[..], initial_memory?);
+    machine.set_source_lines(source_lines);
+    let duplicate = machine.get_duplicate_labels().into_iter().next();
+    if let (false, Some(duplicate)) = (permissive, duplicate) {
+        return Err(PSErr::DuplicateLabel(duplicate));
+    }
+    // A library can jump to labels of the programs it's linked with, so only the linker can tell
+    // whether its targets exist. So can a main program, but only to labels with a scope.
+    if !permissive && !library {
+        if let Some(error) = find_undefined_labels(&machine).into_iter().next() {
+            return Err(error);
+        }
+    }
+    for label in phases {
+        let label = expander.resolve(label);
+        let result = machine.add_phase(label);
+        if let Err(MachineEditError::LabelNotFound { label, suggestion }) = result {
+            return Err(PSErr::PhaseLabelNotFound { label, suggestion });
+        }
+    }
+    if let Some(label) = entry {
+        let label = expander.resolve(label);
+        let result = machine.set_entry(label);
+        if let Err(MachineEditError::LabelNotFound { label, suggestion }) = result {
+            return Err(PSErr::EntryLabelNotFound { label, suggestion });
+        }
+    }
+    for label in exports {
+        let label = expander.resolve(label);
+        let result = machine.add_export(label);
+        if let Err(MachineEditError::LabelNotFound { label, suggestion }) = result {
+            return Err(PSErr::ExportLabelNotFound { label, suggestion });
+        }
+    }
+    for label in imports {
+        machine.add_import(label);
+    }
+    for item in &program.items {
+        if let (Some(label), false) = (item.kind.get_label(), item.documentation.is_empty()) {
+            // The label of a call to a macro with an empty body can end up halting instead.
+            let label = expander.resolve(label.to_string());
+            let _ = machine.set_label_documentation(label, item.documentation.join("\n"));
+        }
+    }
+    Ok(machine)
+}
+
+/// Check that every jump to a number of lines after a label, such as `loop+2`, goes to a line of
+/// the program or just past the last one, which halts. The label must be defined, unless
+/// `permissive` is set, in which case the jump halts like other jumps to undefined labels.
+fn check_label_offsets(
+    lines: &[Line],
+    source_lines: &[usize],
+    permissive: bool,
+) -> Option<ParseSourceError> {
+    let labels: Vec<&str> = lines
+        .iter()
+        .filter_map(|line| match line.get_id() {
+            Some(Identifier::Label(label)) => Some(label.as_str()),
+            _ => None,
+        })
+        .collect();
+    lines.iter().find_map(|line| {
+        let Some(Identifier::LabelOffset(label, offset)) = line.get_instruction().get_jump_target()
+        else {
+            return None;
+        };
+        // The last definition of a label wins, as in the machine.
+        let labelled = lines
+            .iter()
+            .rev()
+            .find(|labelled| matches!(labelled.get_id(), Some(Identifier::Label(l)) if l == label));
+        match labelled {
+            Some(labelled) if labelled.get_line_number().saturating_add(*offset) > lines.len() => {
+                Some(ParseSourceError::JumpPastEnd {
+                    label: label.clone(),
+                    offset: *offset,
+                    line: source_lines[line.get_line_number()],
+                })
+            },
+            None if !permissive => Some(ParseSourceError::UndefinedLabel {
+                label: label.clone(),
+                line: source_lines[line.get_line_number()],
+                suggestion: suggest::closest(label, labels.iter().copied()).map(str::to_string),
+            }),
+            _ => None,
+        }
+    })
+}
+
+/// Find every jump to a label which isn't defined anywhere in the machine, as the errors
+/// [`parse_str`] would give for them, in the order of the lines they're on. The errors give the
+/// lines of source code, counting from 1 (see [`Machine::get_source_line`]). Labels with a scope,
+/// such as `library::loop`, are left for the linker.
+#[must_use]
+pub fn find_undefined_labels(machine: &Machine) -> Vec<ParseSourceError> {
+    machine
+        .get_lines()
+        .iter()
+        .filter_map(|line| match line.get_instruction().get_jump_target() {
+            Some(Identifier::Label(label))
+                if !label.contains(SCOPE_SEPARATOR) && machine.get_label_line(label).is_none() =>
+            {
+                Some(ParseSourceError::UndefinedLabel {
+                    label: label.clone(),
+                    line: machine.get_source_line(line.get_line_number()),
+                    suggestion: machine.closest_label(label),
+                })
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// The instructions [`parse_any_instruction`] recognises.
+pub const INSTRUCTION_NAMES: [&str; 5] = ["inc", "dec", "decjz", "decjnz", "halt"];
+
+/// An instruction parsed by [`parse_any_instruction`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedInstruction {
+    pub instruction: Instruction,
+    /// The bytes of the input the instruction was read from, without surrounding whitespace.
+    pub span: Range<usize>,
+    /// Whether it was a `dec`, which only the REPL has. `instruction` is then a DECJZ to HALT, as
+    /// [`parse_dec`] gives.
+    pub is_dec: bool,
+}
+
+/// Parse any single instruction, including the REPL's `dec`, without a label.
+///
+/// # Errors
+///
+/// * [`ParseSourceError::UnknownInstruction`] - when the first word isn't an instruction. Its
+///   span is where the word is, and it suggests the closest instruction if there's one.
+/// * [`ParseSourceError::TooManyArgument`] - when words are left after the instruction.
+/// * [`ParseSourceError::SyntaxError`] - when the instruction's arguments are invalid.
+///
+/// # Examples
+/// ```
+/// use remuir::{ instruction::Instruction, memory::RegisterNumber, parser::* };
+/// let parsed = parse_any_instruction("  inc r2").unwrap();
+/// assert_eq!(parsed.instruction, Instruction::INC(RegisterNumber::Natural(2)));
+/// assert_eq!(parsed.span, 2..8);
+/// let error = parse_any_instruction("icn r0").unwrap_err();
+/// assert_eq!(error.to_string(), "Unknown instruction \"icn\". Did you mean \"inc\"?");
+/// ```
+pub fn parse_any_instruction(s: &str) -> Result<ParsedInstruction, ParseSourceError> {
+    let start = s.len() - s.trim_start().len();
+    let trimmed = s.trim();
+    let span = start..start + trimmed.len();
+    let word = trimmed.split_whitespace().next().unwrap_or_default();
+    let (rule, expected) = match word {
+        "inc" => (Rule::inc, 1),
+        "dec" => (Rule::dec, 1),
+        "decjz" => (Rule::decjz, 2),
+        "decjnz" => (Rule::decjnz, 2),
+        "halt" => (Rule::halt, 0),
+        _ => {
+            return Err(ParseSourceError::UnknownInstruction {
+                word: word.to_string(),
+                span: start..start + word.len(),
+                suggestion: suggest::closest(word, INSTRUCTION_NAMES),
+            });
+        },
+    };
+    let parsed = RemuirParser::parse(rule, trimmed)?.next().expect("Guaranteed by Pest.");
+    // Labels may contain spaces, so only an instruction without one can have words left over.
+    if parsed.as_span().end() < trimmed.len() {
+        return Err(ParseSourceError::TooManyArgument {
+            received: trimmed.split_whitespace().count() - 1,
+            instruction: word.to_string(),
+            expected,
+        });
+    }
+    let instruction = match rule {
+        Rule::inc => parse_inc(trimmed)?,
+        Rule::dec => parse_dec(trimmed)?,
+        Rule::decjz => parse_decjz(trimmed)?,
+        Rule::decjnz => parse_decjnz(trimmed)?,
+        _ => Instruction::HALT,
+    };
+    Ok(ParsedInstruction { instruction, span, is_dec: rule == Rule::dec })
 }
 
 /// Parse a dec instruction. For REPL mode only.
@@ -220,20 +1928,9 @@ pub fn parse_dec(s: &str) -> Result<Instruction, ParseSourceError> {
         .next()
         .unwrap();
 
-    let reg_num: RegisterNumber = match dec.as_rule() {
-        Rule::pos_register_num => RegisterNumber::Natural(
-            dec
-                .as_str()
-                .parse()
-                .expect("Guaranteed by Pest.")
-        ),
-        Rule::neg_register_num => RegisterNumber::Negative(
-            dec
-                .as_str()
-                .parse()
-                .expect("Guaranteed by Pest.")
-        ),
-        _ => unreachable!(),
-    };
+    let reg_num = parse_register(&dec)?;
+    if let RegisterNumber::Pseudo(_) = reg_num {
+        return Err(ParseSourceError::ReadOnlyRegister(reg_num));
+    }
     Ok(Instruction::DECJZ(reg_num, Identifier::Halt))
 }
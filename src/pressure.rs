@@ -0,0 +1,180 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Register pressure: how many registers a program is using as it runs.
+//!
+//! Programs keep their data in unary, so the space they use is the number of registers rather than
+//! the size of the numbers in them. Every Nth step, the number of nonzero registers (natural and
+//! negative) is recorded, along with the highest natural register which is nonzero. The result
+//! can be shown as sparklines in the terminal, or written as CSV to be plotted elsewhere.
+//!
+//! At most [`MAX_SAMPLES`] samples are kept. Once there would be more, every other sample is
+//! dropped and N is doubled, so a long run is still sampled evenly from start to end.
+
+use std::{ fmt::Display, io::{ self, Write }, num::NonZeroUsize };
+
+use crate::{
+    machine::{ Machine, TerminationReason },
+    memory::{ Memory, Register },
+};
+
+/// The most samples kept of one run, besides the one at the end.
+pub const MAX_SAMPLES: usize = 4096;
+
+/// The widest a sparkline gets. Longer runs are squeezed into this many columns.
+const SPARKLINE_WIDTH: usize = 60;
+
+/// The characters of a sparkline, from lowest to highest.
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The registers in use after some number of steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub step: usize,
+    /// How many registers, natural and negative, aren't 0.
+    pub nonzero: usize,
+    /// The number of the highest natural register which isn't 0, if there is one.
+    pub highest: Option<usize>,
+}
+
+impl Sample {
+    fn new(step: usize, memory: &Memory) -> Sample {
+        let zero = Register::from(0);
+        let naturals = memory.get_nat_registers();
+        let nonzero = naturals
+            .iter()
+            .chain(memory.get_neg_registers())
+            .filter(|&register| *register != zero)
+            .count();
+        let highest = naturals.iter().rposition(|register| *register != zero);
+        Sample { step, nonzero, highest }
+    }
+}
+
+/// The register pressure recorded during a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterPressure {
+    interval: NonZeroUsize,
+    samples: Vec<Sample>,
+}
+
+impl RegisterPressure {
+    /// Run the machine from its current line until it halts, sampling the registers every
+    /// `interval` steps, and once more at the end. The interval is doubled whenever there would
+    /// be more than [`MAX_SAMPLES`] samples.
+    ///
+    /// Like [`Machine::run`], recording stops early without halting at the machine's step limit,
+    /// at a cap of its sandbox, or if a protected register would be modified.
+    pub fn record(machine: &mut Machine, mut interval: NonZeroUsize) -> RegisterPressure {
+        let mut samples = Vec::new();
+        let mut steps: usize = 0;
+        while !machine.is_halted() && !machine.is_step_limit_reached() {
+            if steps % interval == 0 {
+                if samples.len() == MAX_SAMPLES {
+                    samples = samples.into_iter().step_by(2).collect();
+                    interval = interval.saturating_add(interval.get());
+                }
+                if steps % interval == 0 {
+                    samples.push(Sample::new(steps, machine.get_state()));
+                }
+            }
+            match machine.step() {
+                Ok(Some(
+                    TerminationReason::ProtectedRegister(_)
+                    | TerminationReason::InvalidJump(_)
+                    | TerminationReason::ResourceLimit(_)
+                    | TerminationReason::Io(_),
+                ))
+                | Err(_) => break,
+                Ok(_) => (),
+            }
+            steps += 1;
+        }
+        if samples.last().is_none_or(|sample| sample.step != steps) {
+            samples.push(Sample::new(steps, machine.get_state()));
+        }
+        RegisterPressure { interval, samples }
+    }
+
+    #[must_use]
+    pub fn get_samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// Get the sample with the most nonzero registers, the first one if there's a tie.
+    #[must_use]
+    pub fn peak(&self) -> Option<&Sample> {
+        self.samples.iter().rev().max_by_key(|sample| sample.nonzero)
+    }
+
+    /// Write the samples as CSV, with a header row. `highest` is left empty when every natural
+    /// register is 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to `out` fails.
+    pub fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "step,nonzero,highest")?;
+        for sample in &self.samples {
+            let highest = sample.highest.map(|highest| highest.to_string()).unwrap_or_default();
+            writeln!(out, "{},{},{highest}", sample.step, sample.nonzero)?;
+        }
+        Ok(())
+    }
+}
+
+/// Draw values as a sparkline, squeezing them into at most [`SPARKLINE_WIDTH`] columns by taking
+/// the largest value in each.
+fn sparkline(values: &[usize]) -> String {
+    let max = values.iter().copied().max().unwrap_or(0);
+    let per_column = values.len().div_ceil(SPARKLINE_WIDTH).max(1);
+    values
+        .chunks(per_column)
+        .map(|chunk| {
+            let value = chunk.iter().copied().max().unwrap_or(0);
+            match max {
+                0 => SPARKS[0],
+                _ => SPARKS[value * (SPARKS.len() - 1) / max],
+            }
+        })
+        .collect()
+}
+
+impl Display for RegisterPressure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let steps = self.samples.last().map_or(0, |sample| sample.step);
+        writeln!(f, "Register pressure over {steps} steps (sampled every {}):", self.interval)?;
+        if let Some(peak) = self.peak() {
+            writeln!(f, "Nonzero registers (peak {} at step {}):", peak.nonzero, peak.step)?;
+            let nonzero: Vec<usize> = self.samples.iter().map(|sample| sample.nonzero).collect();
+            writeln!(f, "  {}", sparkline(&nonzero))?;
+        }
+        let highest = self.samples
+            .iter()
+            .filter_map(|sample| Some((sample.highest?, sample.step)))
+            .rev()
+            .max_by_key(|&(highest, _)| highest);
+        if let Some((highest, step)) = highest {
+            writeln!(f, "Highest nonzero register (peak r{highest} at step {step}):")?;
+            let values: Vec<usize> = self.samples
+                .iter()
+                .map(|sample| sample.highest.map_or(0, |highest| highest + 1))
+                .collect();
+            writeln!(f, "  {}", sparkline(&values))?;
+        }
+        Ok(())
+    }
+}
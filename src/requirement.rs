@@ -0,0 +1,96 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! The versions of remuir a program can be run by, given with the `#!requires` directive, such as
+//! `#!requires remuir >= 0.3`.
+//!
+//! Every `#!requires` directive is checked before the rest of the program is parsed, so a program
+//! which uses features from a later version fails with a clear error instead of a confusing
+//! syntax error. Versions are compared by their numbers, so `0.10` is later than `0.9`.
+//!
+//! # Examples
+//! ```
+//! use remuir::requirement::{ Comparison, Requirement };
+//!
+//! let requirement = Requirement { comparison: Comparison::AtLeast, version: vec![0, 3] };
+//! assert_eq!(requirement.to_string(), "remuir >= 0.3");
+//! assert!(requirement.is_met_by("0.3.1"));
+//! assert!(!requirement.is_met_by("0.2.0"));
+//! ```
+
+use std::fmt::Display;
+
+/// The version of remuir which is running.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    /// `>=`, the given version or any later one.
+    AtLeast,
+    /// `=`, only versions which start with the given numbers, so `= 0.2` allows 0.2.0 and 0.2.5.
+    Exactly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    pub comparison: Comparison,
+    /// The numbers of the version, from the major version down. Missing numbers count as 0 for
+    /// [`Comparison::AtLeast`].
+    pub version: Vec<u64>,
+}
+
+impl Requirement {
+    /// Check whether a version of remuir, such as `"0.2.0"`, meets the requirement. Anything after
+    /// a `-` or `+` in the version, such as a pre-release, is ignored.
+    #[must_use]
+    pub fn is_met_by(&self, version: &str) -> bool {
+        let numbers: Vec<u64> = version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|number| number.parse().unwrap_or(0))
+            .collect();
+        match self.comparison {
+            Comparison::AtLeast => {
+                let length = numbers.len().max(self.version.len());
+                let padded = |numbers: &[u64]| {
+                    (0..length).map(|i| numbers.get(i).copied().unwrap_or(0)).collect::<Vec<u64>>()
+                };
+                padded(&numbers) >= padded(&self.version)
+            },
+            Comparison::Exactly => numbers.starts_with(&self.version),
+        }
+    }
+
+    /// Check whether the running version of remuir, [`VERSION`], meets the requirement.
+    #[must_use]
+    pub fn is_met(&self) -> bool {
+        self.is_met_by(VERSION)
+    }
+}
+
+impl Display for Requirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let comparison = match self.comparison {
+            Comparison::AtLeast => ">=",
+            Comparison::Exactly => "=",
+        };
+        let version: Vec<String> = self.version.iter().map(u64::to_string).collect();
+        write!(f, "remuir {comparison} {}", version.join("."))
+    }
+}
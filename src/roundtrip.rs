@@ -0,0 +1,80 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Checking that a program printed as source code parses back into the same program.
+//!
+//! [`Machine::to_source`] and the parser are meant to be inverses, which is what the cache,
+//! `--specialize` and the obfuscator rely on when they print a program. [`check`] tests this for
+//! one machine, so that tests (including property tests, which generate machines at random) keep
+//! the grammar and the printer in step as they grow:
+//!
+//! ```
+//! use remuir::{ parser::parse_str, roundtrip };
+//!
+//! let machine = parse_str("registers 1\n## Counts down.\nloop: decjz r0 +2\ngoto loop").unwrap();
+//! roundtrip::check(&machine).unwrap();
+//! ```
+
+use thiserror::Error;
+
+use crate::{
+    machine::Machine,
+    parser::{ self, ParseSourceError },
+};
+
+#[derive(Debug, Error)]
+pub enum RoundTripError {
+    #[error("The printed program doesn't parse!\n{error}\nPrinted:\n{printed}")]
+    Unparseable { printed: String, error: ParseSourceError },
+    #[error("The printed program parses as a different program.\nPrinted:\n{printed}\nReprinted:\n{reprinted}")]
+    Changed { printed: String, reprinted: String },
+}
+
+/// Check that printing `machine` as source code and parsing it gives back an equal machine.
+///
+/// Only the program and the initial registers are printed, so the machine shouldn't have been
+/// run or changed since it was parsed or constructed.
+///
+/// # Errors
+///
+/// * [`RoundTripError::Unparseable`] - when the printed source code doesn't parse.
+/// * [`RoundTripError::Changed`] - when it parses, but into a machine which isn't equal.
+pub fn check(machine: &Machine) -> Result<(), RoundTripError> {
+    let printed = machine.to_source();
+    let reparsed = parser::parse_str(&printed)
+        .map_err(|error| RoundTripError::Unparseable { printed: printed.clone(), error })?;
+    if &reparsed == machine {
+        Ok(())
+    }
+    else {
+        Err(RoundTripError::Changed { printed, reprinted: reparsed.to_source() })
+    }
+}
+
+/// Parse source code, then [`check`] the machine it gives.
+///
+/// # Errors
+///
+/// * [`RoundTripError::Unparseable`] - when `source`, or the program printed from it, doesn't
+///   parse.
+/// * [`RoundTripError::Changed`] - when the printed program parses into a different machine.
+pub fn check_source(source: &str) -> Result<(), RoundTripError> {
+    let machine = parser::parse_str(source).map_err(|error| RoundTripError::Unparseable {
+        printed: source.to_string(),
+        error,
+    })?;
+    check(&machine)
+}
@@ -0,0 +1,627 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! A JSON-RPC 2.0 interface, so other programs can drive a machine without parsing the text of
+//! the REPL.
+//!
+//! Requests and responses are sent one per line. The methods are:
+//!
+//! * `load` with `{"source": "..."}` - parse a program and make it the current machine. Returns
+//!   `{"lines": N}`.
+//! * `step` - execute one instruction. Returns the machine's position (see below), along with the
+//!   same fields as `run` if the instruction triggered a data breakpoint.
+//! * `run` - run until the machine halts or reaches a breakpoint. Returns the position, along with
+//!   `"reason"`, which is one of `"halted"`, `"breakpoint"`, `"empty"`, `"protectedRegister"`,
+//!   `"invalidJump"`, `"dataBreakpoint"`, `"stepLimit"`, `"resourceLimit"`, `"io"` or
+//!   `"interrupted"`, such as after Ctrl+C (see [`crate::interrupt`]). For a data breakpoint,
+//!   `"register"`, `"old"` and `"new"` give the register which changed and its values before and
+//!   after, as strings. For a step limit, `"summary"` gives where the machine was, as
+//!   in [`RunawaySummary::to_json`]. For another cap of the sandbox (see `setSandbox`), or a
+//!   `read` with no input left, `"message"` says what happened.
+//! * `reset` - go back to the initial registers and the first instruction. Returns the position.
+//! * `getRegisters` - returns `{"registers": [...]}`, the natural registers starting with r0.
+//!   Registers can be larger than JSON numbers can represent exactly, so they're given as strings.
+//!   If the server includes scratch registers (see [`RpcServer::set_include_scratch`]), there's
+//!   also `"scratch": [...]`, the negative registers starting with r-0.
+//! * `setBreakpoint` and `removeBreakpoint` with `{"label": "..."}` or `{"line": N}`.
+//! * `setDataBreakpoint` and `removeDataBreakpoint` with `{"register": "r3"}` - stop after any
+//!   instruction which changes the register. Returns `{"dataBreakpoints": [...]}`, the registers
+//!   with data breakpoints.
+//! * `setCostModel` with the cost of each kind of instruction, such as `{"decjz_taken": 2}` (see
+//!   [`CostModel`]). Costs which aren't given are 1. The model stays in use for programs loaded
+//!   later. Returns `{"costModel": "..."}`, the whole model written as for `--cost`.
+//! * `setStepLimit` with `{"steps": N}` - make `run` stop once the machine has taken N steps in
+//!   total, or never with `{"steps": null}`. The limit stays in use for programs loaded later.
+//!   Returns `{"stepLimit": N}`.
+//! * `setSandbox` with `{"steps": N, "timeMs": N, "registers": N, "limbs": N, "calls": N}` - cap
+//!   the resources each `run`, `step` or `execute` may use (see [`Sandbox`]). Caps which are left
+//!   out or `null` are unlimited. The sandbox stays in use for programs loaded later, and starts
+//!   as [`Sandbox::SERVER`], so a request can't take up the whole machine. Returns the sandbox in
+//!   the same form.
+//! * `addInput` with `{"values": ["3", "5"]}` - queue values for the program's `read`
+//!   instructions, which never read from STDIN here. The values can be strings or numbers. The
+//!   input stays queued for programs loaded later. Returns `{"input": [...]}`, every value which
+//!   is still to be read.
+//! * `getOutput` - returns `{"output": [...]}`, the values written by `write` instructions since
+//!   the last `getOutput`, oldest first.
+//! * `getLabelInfo` with `{"label": "..."}` - returns `{"line": N, "documentation": "..."}`, the
+//!   line the label is on and the `##` comments above it, or `null` if it has none. Editors can
+//!   show this when hovering over a label.
+//! * `getPhases` - returns `{"phases": [...], "steps": N, "cost": N}`, where each phase is
+//!   `{"phase": "...", "steps": N, "cost": N}` and the phase is `null` for steps taken before the
+//!   first phase. The costs are only present once a cost model has been set.
+//! * `execute` with `{"code": "..."}` - run a notebook cell (see [`RpcServer::execute`]). Returns
+//!   `{"output": "...", "registers": [...]}`, with `"scratch"` as for `getRegisters`.
+//!
+//! Requests can also be sent in a batch, as an array on one line, which is answered with an array
+//! of the responses.
+//!
+//! The position is `{"line": N, "halted": BOOL, "steps": N}`, where `line` is the line which will
+//! be executed next, or `null` if the machine has halted.
+//!
+//! # Examples
+//! ```
+//! let mut server = remuir::rpc::RpcServer::default();
+//! let response = server.handle(r#"{"jsonrpc": "2.0", "id": 1, "method": "getRegisters"}"#);
+//! assert_eq!(response.as_deref(), Some(r#"{"id":1,"jsonrpc":"2.0","result":{"registers":[]}}"#));
+//! ```
+
+use std::{
+    io::{self, BufRead, Write},
+    time::Duration,
+};
+
+use serde_json::{ json, Map, Value };
+
+use crate::{
+    cost::CostModel,
+    io_handler::BufferIo,
+    machine::{ BreakpointToggle, Identifier, Machine, MachineEditError, TerminationReason },
+    memory::{ Register, RegisterNumber },
+    parser,
+    runaway::RunawaySummary,
+    sandbox::Sandbox,
+    tui::{ self, Mode, RemuirError },
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Used for errors from the machine itself, such as stepping when it has halted.
+const MACHINE_ERROR: i64 = -32000;
+
+/// An error to send back instead of a result.
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> RpcError {
+        RpcError { code, message: message.to_string() }
+    }
+}
+
+/// The result of running a notebook cell with [`RpcServer::execute`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Execution {
+    /// Everything the cell printed, as it would appear in the REPL.
+    pub output: String,
+    /// The natural registers afterwards, starting with r0.
+    pub registers: Vec<String>,
+}
+
+/// A machine which is driven by JSON-RPC requests. It starts with an empty program.
+pub struct RpcServer {
+    machine: Machine,
+    /// The mode which commands run by [`RpcServer::execute`] are in. It starts in REPL mode, and
+    /// switches to debug mode once a program is loaded.
+    mode: Mode,
+    /// The cost model given to every machine which is loaded.
+    cost_model: Option<CostModel>,
+    /// The sandbox given to every machine which is loaded, including its step limit.
+    sandbox: Sandbox,
+    /// The input and output of every machine which is loaded, since STDIN and STDOUT carry the
+    /// requests and responses.
+    io: BufferIo,
+    /// Whether the negative registers are sent along with the natural ones.
+    include_scratch: bool,
+}
+
+impl Default for RpcServer {
+    fn default() -> Self {
+        let io = BufferIo::default();
+        let mut machine = Machine::default();
+        machine.set_io_handler(io.clone());
+        let mut server = RpcServer {
+            machine,
+            mode: Mode::Repl,
+            cost_model: None,
+            sandbox: Sandbox::SERVER,
+            io,
+            include_scratch: false,
+        };
+        // Instructions typed in REPL mode keep to the sandbox too.
+        server.apply_sandbox();
+        server
+    }
+}
+
+impl RpcServer {
+    /// Handle a single request, or a batch of them in an array, returning the response to send
+    /// back. Returns [`None`] if there's nothing to send back, since every request was a
+    /// notification (it had no `id`). Anything which isn't a JSON-RPC 2.0 request gets an error,
+    /// with the `id` set to `null` if it doesn't have one.
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        let request: Value = match serde_json::from_str(request) {
+            Ok(request) => request,
+            Err(e) => {
+                return Some(response(Value::Null, Err(RpcError::new(PARSE_ERROR, e))).to_string())
+            },
+        };
+        match request {
+            Value::Array(requests) if requests.is_empty() => {
+                let error = RpcError::new(INVALID_REQUEST, "An empty batch has no requests.");
+                Some(response(Value::Null, Err(error)).to_string())
+            },
+            Value::Array(requests) => {
+                let responses: Vec<Value> =
+                    requests.iter().filter_map(|request| self.handle_one(request)).collect();
+                (!responses.is_empty()).then(|| Value::Array(responses).to_string())
+            },
+            request => self.handle_one(&request).map(|response| response.to_string()),
+        }
+    }
+
+    /// Handle one request of a batch, or one sent on its own.
+    fn handle_one(&mut self, request: &Value) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let method = request.get("method").and_then(Value::as_str);
+        match (method, request.get("jsonrpc")) {
+            (Some(method), Some(version)) if version == "2.0" => {
+                let params = request.get("params").and_then(Value::as_object).cloned();
+                let result = self.call(method, &params.unwrap_or_default());
+                id.map(|id| response(id, result))
+            },
+            _ => {
+                let error = RpcError::new(INVALID_REQUEST, "Not a JSON-RPC 2.0 request.");
+                Some(response(id.unwrap_or(Value::Null), Err(error)))
+            },
+        }
+    }
+
+    #[must_use]
+    pub const fn get_machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// Send the negative registers, which many programs use as scratch space, as `"scratch"`
+    /// alongside the natural registers, since some programs leave their results there.
+    pub fn set_include_scratch(&mut self, include: bool) {
+        self.include_scratch = include;
+    }
+
+    /// Answer requests from `input`, one per line, until it ends.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned if reading from `input` or writing to `out` fails.
+    pub fn serve(&mut self, input: &mut impl BufRead, out: &mut impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line) {
+                writeln!(out, "{response}")?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a notebook cell.
+    ///
+    /// A cell which starts with a registers line and has more lines after it is a program: it's
+    /// loaded and played until it halts or reaches a breakpoint. Any other cell is a list of
+    /// commands, one per line, which are run as in the REPL, or in the debugger once a program has
+    /// been loaded. In REPL mode, each cell keeps to the sandbox as one execution, and its step
+    /// limit is how many instructions the cell may execute.
+    pub fn execute(&mut self, code: &str) -> Execution {
+        let mut out: Vec<u8> = Vec::new();
+        if is_program(code) {
+            match parser::parse_str(code) {
+                Ok(machine) => {
+                    self.load(machine);
+                    self.command("play", &mut out);
+                },
+                Err(e) => out.extend_from_slice(format!("{}\n", RemuirError::from(e)).as_bytes()),
+            }
+        }
+        else {
+            // In REPL mode, each cell is one execution with a step limit of its own.
+            if let Mode::Repl = self.mode {
+                let steps = self.machine.get_steps();
+                let limit = self.sandbox.steps.map(|limit| steps.saturating_add(limit));
+                self.machine.set_step_limit(limit);
+                self.machine.start_execution();
+            }
+            for line in code.lines() {
+                self.command(line, &mut out);
+            }
+        }
+        Execution {
+            output: String::from_utf8_lossy(&out).into_owned(),
+            registers: self.registers(),
+        }
+    }
+
+    fn command(&mut self, line: &str, out: &mut Vec<u8>) {
+        if let Err(e) = tui::command(line, &mut self.machine, &mut self.mode, out) {
+            out.extend_from_slice(format!("{e}\n").as_bytes());
+        }
+    }
+
+    fn load(&mut self, machine: Machine) {
+        self.machine = machine;
+        self.machine.set_cost_model(self.cost_model);
+        self.machine.set_io_handler(self.io.clone());
+        self.apply_sandbox();
+        self.mode = Mode::Debug {
+            previous_line: None,
+            previous_memory: None,
+            previous_call_stack: Vec::new(),
+        };
+    }
+
+    /// Give the machine the sandbox, and keep a history while there's a step limit, so a summary
+    /// can be given when it's reached.
+    fn apply_sandbox(&mut self) {
+        self.machine.set_sandbox(self.sandbox);
+        if self.sandbox.steps.is_some() {
+            self.machine.set_history_limit(Some(tui::HISTORY_LIMIT));
+        }
+    }
+
+    fn registers(&self) -> Vec<String> {
+        let memory = self.machine.get_state();
+        let mut registers: Vec<String> = memory
+            .get_nat_registers()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        registers.resize(memory.get_nat_count(), String::from("0"));
+        registers
+    }
+
+    /// Get the registers as JSON, with the negative registers as well if they're included.
+    fn registers_json(&self, registers: Vec<String>) -> Value {
+        let mut result = json!({ "registers": registers });
+        if self.include_scratch {
+            let scratch: Vec<String> = self.machine
+                .get_state()
+                .get_neg_registers()
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            result["scratch"] = json!(scratch);
+        }
+        result
+    }
+
+    fn call(&mut self, method: &str, params: &Map<String, Value>) -> Result<Value, RpcError> {
+        match method {
+            "load" => {
+                let source = params
+                    .get("source")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a \"source\" string."))?;
+                let machine = parser::parse_str(source)
+                    .map_err(|e| RpcError::new(MACHINE_ERROR, e))?;
+                self.load(machine);
+                Ok(json!({ "lines": self.machine.get_lines().len() }))
+            },
+            "step" => {
+                let reason = self.machine.step().map_err(|e| RpcError::new(MACHINE_ERROR, e))?;
+                let mut position = self.position();
+                if let Some(reason @ TerminationReason::Watchpoint { .. }) = reason {
+                    add_reason(&mut position, &reason);
+                }
+                Ok(position)
+            },
+            "run" => {
+                let reason = self.machine.debug().map_err(|e| RpcError::new(MACHINE_ERROR, e))?;
+                let mut position = self.position();
+                add_reason(&mut position, &reason);
+                if let TerminationReason::StepLimitReached = reason {
+                    position["summary"] = RunawaySummary::new(&self.machine).to_json();
+                }
+                Ok(position)
+            },
+            "reset" => {
+                self.machine.reset();
+                Ok(self.position())
+            },
+            "getRegisters" => Ok(self.registers_json(self.registers())),
+            "setBreakpoint" | "removeBreakpoint" => {
+                let id = breakpoint_identifier(params)?;
+                let toggle = self.machine
+                    .toggle_breakpoint(&id)
+                    .map_err(|e| RpcError::new(MACHINE_ERROR, e))?;
+                let added = matches!(toggle, BreakpointToggle::Added);
+                // Toggle back if the breakpoint was already in the requested state.
+                if added != (method == "setBreakpoint") {
+                    self.machine
+                        .toggle_breakpoint(&id)
+                        .map_err(|e| RpcError::new(MACHINE_ERROR, e))?;
+                }
+                Ok(json!({ "breakpoints": self.machine.get_breakpoints() }))
+            },
+            "setDataBreakpoint" | "removeDataBreakpoint" => {
+                let register = params
+                    .get("register")
+                    .and_then(Value::as_str)
+                    .and_then(|register| register.parse::<RegisterNumber>().ok())
+                    .ok_or_else(|| {
+                        RpcError::new(INVALID_PARAMS, "Expected a \"register\" string, such as \"r3\".")
+                    })?;
+                let toggle = self.machine.toggle_watchpoint(register);
+                let added = matches!(toggle, BreakpointToggle::Added);
+                // Toggle back if the data breakpoint was already in the requested state.
+                if added != (method == "setDataBreakpoint") {
+                    self.machine.toggle_watchpoint(register);
+                }
+                let registers: Vec<String> = self.machine
+                    .get_watchpoints()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                Ok(json!({ "dataBreakpoints": registers }))
+            },
+            "setCostModel" => {
+                let mut costs = Vec::new();
+                for (name, cost) in params {
+                    let cost = cost.as_u64().ok_or_else(|| {
+                        RpcError::new(INVALID_PARAMS, format!("Expected \"{name}\" to be a number."))
+                    })?;
+                    costs.push(format!("{name}={cost}"));
+                }
+                let model: CostModel = costs
+                    .join(",")
+                    .parse()
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                self.cost_model = Some(model);
+                self.machine.set_cost_model(self.cost_model);
+                Ok(json!({ "costModel": model.to_string() }))
+            },
+            "setStepLimit" => {
+                self.sandbox.steps = optional_count(params, "steps")?;
+                self.apply_sandbox();
+                Ok(json!({ "stepLimit": self.sandbox.steps }))
+            },
+            "setSandbox" => {
+                let time = optional_count(params, "timeMs")?;
+                self.sandbox = Sandbox {
+                    steps: optional_count(params, "steps")?,
+                    time: time.map(|time| Duration::from_millis(time as u64)),
+                    registers: optional_count(params, "registers")?,
+                    limbs: optional_count(params, "limbs")?,
+                    calls: optional_count(params, "calls")?,
+                };
+                self.apply_sandbox();
+                Ok(self.sandbox_json())
+            },
+            "addInput" => {
+                let values = params
+                    .get("values")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a \"values\" array."))?;
+                let mut input = Vec::new();
+                for value in values {
+                    let number = value
+                        .as_str()
+                        .map(str::to_string)
+                        .or_else(|| value.as_u64().map(|n| n.to_string()))
+                        .and_then(|number| number.parse::<u128>().ok())
+                        .ok_or_else(|| {
+                            let message = format!("Expected {value} to be a natural number.");
+                            RpcError::new(INVALID_PARAMS, message)
+                        })?;
+                    input.push(Register::from(number));
+                }
+                for value in input {
+                    self.io.push_input(value);
+                }
+                let remaining: Vec<String> =
+                    self.io.get_remaining_input().iter().map(ToString::to_string).collect();
+                Ok(json!({ "input": remaining }))
+            },
+            "getOutput" => {
+                let output: Vec<String> =
+                    self.io.take_output().iter().map(ToString::to_string).collect();
+                Ok(json!({ "output": output }))
+            },
+            "getPhases" => Ok(self.phases()),
+            "getLabelInfo" => {
+                let label = params
+                    .get("label")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a \"label\" string."))?;
+                let line = self.machine.get_label_line(label).ok_or_else(|| {
+                    let suggestion = self.machine.closest_label(label);
+                    let label = label.to_string();
+                    RpcError::new(MACHINE_ERROR, MachineEditError::LabelNotFound {
+                        label,
+                        suggestion,
+                    })
+                })?;
+                let documentation = self.machine.get_label_documentation(label);
+                Ok(json!({ "line": line, "documentation": documentation }))
+            },
+            "execute" => {
+                let code = params
+                    .get("code")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Expected a \"code\" string."))?;
+                let Execution { output, registers } = self.execute(code);
+                let mut result = self.registers_json(registers);
+                result["output"] = json!(output);
+                Ok(result)
+            },
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("There is no method \"{method}\"."))),
+        }
+    }
+
+    fn sandbox_json(&self) -> Value {
+        let time = self.sandbox.time.map(|time| time.as_millis());
+        json!({
+            "steps": self.sandbox.steps,
+            "timeMs": time,
+            "registers": self.sandbox.registers,
+            "limbs": self.sandbox.limbs,
+            "calls": self.sandbox.calls,
+        })
+    }
+
+    fn phases(&self) -> Value {
+        let has_costs = self.machine.get_cost_model().is_some();
+        let phases: Vec<Value> = self.machine
+            .get_phase_steps()
+            .iter()
+            .map(|(phase, steps)| {
+                let mut entry = json!({ "phase": phase, "steps": steps });
+                if has_costs {
+                    let cost = self.machine
+                        .get_phase_costs()
+                        .iter()
+                        .find(|(costed, _)| costed == phase)
+                        .map_or(0, |&(_, cost)| cost);
+                    entry["cost"] = json!(cost);
+                }
+                entry
+            })
+            .collect();
+        let mut result = json!({ "phases": phases, "steps": self.machine.get_steps() });
+        if has_costs {
+            result["cost"] = json!(self.machine.get_cost());
+        }
+        result
+    }
+
+    fn position(&self) -> Value {
+        let halted = self.machine.is_halted();
+        json!({
+            "line": (!halted).then(|| self.machine.get_current_line_number()),
+            "halted": halted,
+            "steps": self.machine.get_steps(),
+        })
+    }
+}
+
+/// Check whether a notebook cell is a program rather than a list of commands, that is, whether
+/// it starts with a registers line and has more lines after it. Blank lines and comments don't
+/// count.
+fn is_program(code: &str) -> bool {
+    let mut lines = code
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    let starts_with_registers = lines
+        .next()
+        .is_some_and(|line| line.split_whitespace().next() == Some("registers"));
+    starts_with_registers && lines.next().is_some()
+}
+
+fn breakpoint_identifier(params: &Map<String, Value>) -> Result<Identifier, RpcError> {
+    if let Some(label) = params.get("label").and_then(Value::as_str) {
+        if label.eq_ignore_ascii_case("HALT") {
+            return Err(RpcError::new(INVALID_PARAMS, "Cannot use HALT as a breakpoint label."));
+        }
+        return Ok(Identifier::Label(String::from(label)));
+    }
+    params
+        .get("line")
+        .and_then(Value::as_u64)
+        .and_then(|line| usize::try_from(line).ok())
+        .map(Identifier::Line)
+        .ok_or_else(|| {
+            RpcError::new(INVALID_PARAMS, "Expected a \"label\" string or \"line\" number.")
+        })
+}
+
+/// Get a count which may be left out or `null` from the parameters, where [`None`] means
+/// unlimited.
+fn optional_count(params: &Map<String, Value>, name: &str) -> Result<Option<usize>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(count) => {
+            let count = count.as_u64().and_then(|count| usize::try_from(count).ok());
+            Ok(Some(count.ok_or_else(|| {
+                let message = format!("Expected \"{name}\" to be a number or null.");
+                RpcError::new(INVALID_PARAMS, message)
+            })?))
+        },
+    }
+}
+
+/// Add why the machine stopped to its position.
+fn add_reason(position: &mut Value, reason: &TerminationReason) {
+    let name = match reason {
+        TerminationReason::Breakpoint => "breakpoint",
+        TerminationReason::Empty => "empty",
+        TerminationReason::Halted => "halted",
+        TerminationReason::ProtectedRegister(_) => "protectedRegister",
+        TerminationReason::InvalidJump(_) => "invalidJump",
+        TerminationReason::Interrupted => "interrupted",
+        TerminationReason::StepLimitReached => "stepLimit",
+        TerminationReason::ResourceLimit(limit) => {
+            position["message"] = json!(limit.to_string());
+            "resourceLimit"
+        },
+        TerminationReason::Io(e) => {
+            position["message"] = json!(e.to_string());
+            "io"
+        },
+        TerminationReason::Watchpoint { register, old, new } => {
+            position["register"] = json!(register.to_string());
+            position["old"] = json!(old.to_string());
+            position["new"] = json!(new.to_string());
+            "dataBreakpoint"
+        },
+    };
+    position["reason"] = json!(name);
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(RpcError { code, message }) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    }
+}
+
+/// Answer requests from `input`, one per line, until it ends, with a new [`RpcServer`].
+///
+/// # Errors
+///
+/// * [`io::Error`] - returned if reading from `input` or writing to `out` fails.
+pub fn serve(input: &mut impl BufRead, out: &mut impl Write) -> io::Result<()> {
+    RpcServer::default().serve(input, out)
+}
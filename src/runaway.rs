@@ -0,0 +1,137 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Summarising where a machine was when it reached its step limit, which is usually somewhere in
+//! an infinite loop.
+//!
+//! The summary is built from the machine's history (see [`Machine::set_history_limit`]), so its
+//! backtrace and hottest lines only cover the most recently executed lines. Without a history,
+//! only the current line is known.
+
+use std::fmt::Display;
+
+use serde_json::{ json, Value };
+
+use crate::{ machine::{ LineNumber, Machine }, manifest::Manifest };
+
+/// How many of the most recent jumps a summary keeps.
+pub const BACKTRACE_LENGTH: usize = 5;
+/// How many of the most executed lines a summary keeps.
+pub const HOTTEST_LINES: usize = 5;
+
+/// Where a machine was when it stopped without halting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunawaySummary {
+    steps: usize,
+    line: String,
+    line_number: LineNumber,
+    /// The most recent jumps, most recent first, as pairs of the line which jumped and the line it
+    /// jumped to.
+    backtrace: Vec<(String, String)>,
+    /// The most executed lines in the history, most executed first, with ties in line order.
+    hottest: Vec<(String, usize)>,
+    /// How many lines the history covered.
+    window: usize,
+    manifest: Manifest,
+}
+
+impl RunawaySummary {
+    #[must_use]
+    pub fn new(machine: &Machine) -> RunawaySummary {
+        let line_number = machine.get_current_line_number();
+        let line = machine
+            .get_lines()
+            .get(line_number)
+            .map_or_else(|| String::from("HALT"), ToString::to_string);
+        let backtrace = machine
+            .get_backtrace()
+            .into_iter()
+            .take(BACKTRACE_LENGTH)
+            .map(|(from, to)| (machine.describe_line(from), machine.describe_line(to)))
+            .collect();
+        let mut counts: Vec<(LineNumber, usize)> = Vec::new();
+        for &line in machine.get_history() {
+            match counts.iter_mut().find(|(counted, _)| *counted == line) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((line, 1)),
+            }
+        }
+        counts.sort_by(|(a_line, a_count), (b_line, b_count)| {
+            b_count.cmp(a_count).then(a_line.cmp(b_line))
+        });
+        let hottest = counts
+            .into_iter()
+            .take(HOTTEST_LINES)
+            .map(|(line, count)| (machine.describe_line(line), count))
+            .collect();
+        RunawaySummary {
+            steps: machine.get_steps(),
+            line,
+            line_number,
+            backtrace,
+            hottest,
+            window: machine.get_history().len(),
+            manifest: Manifest::new(machine),
+        }
+    }
+
+    /// Get the summary as a JSON object, such as
+    /// `{"steps": 100, "line": 1, "source": "1    loop: inc r0", "backtrace": [{"from": "line 2",
+    /// "to": "line 1 (loop)"}], "hottestLines": [{"line": "line 1 (loop)", "count": 50}],
+    /// "window": 100, "manifest": {...}}`, with the machine's [`Manifest`].
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        let backtrace: Vec<Value> = self.backtrace
+            .iter()
+            .map(|(from, to)| json!({ "from": from, "to": to }))
+            .collect();
+        let hottest: Vec<Value> = self.hottest
+            .iter()
+            .map(|(line, count)| json!({ "line": line, "count": count }))
+            .collect();
+        json!({
+            "steps": self.steps,
+            "line": self.line_number,
+            "source": self.line,
+            "backtrace": backtrace,
+            "hottestLines": hottest,
+            "window": self.window,
+            "manifest": self.manifest.to_json(),
+        })
+    }
+}
+
+impl Display for RunawaySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Stopped after {} steps, before:", self.steps)?;
+        writeln!(f, "  {}", self.line)?;
+        if !self.backtrace.is_empty() {
+            writeln!(f, "Most recent jumps:")?;
+            for (i, (from, to)) in self.backtrace.iter().enumerate() {
+                writeln!(f, "  #{i} {to} <- {from}")?;
+            }
+        }
+        if !self.hottest.is_empty() {
+            writeln!(f, "Hottest lines in the last {} steps:", self.window)?;
+            for (line, count) in &self.hottest {
+                #[allow(clippy::cast_precision_loss)]
+                let percent = 100.0 * *count as f64 / self.window as f64;
+                writeln!(f, "  {line}: {count} ({percent:.1}%)")?;
+            }
+        }
+        Ok(())
+    }
+}
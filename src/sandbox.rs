@@ -0,0 +1,86 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Caps on the resources a machine may use while running untrusted programs.
+//!
+//! A [`Sandbox`] is given to a [`Machine`](crate::machine::Machine) with
+//! [`Machine::set_sandbox`](crate::machine::Machine::set_sandbox), and is enforced by the machine
+//! itself before every step, so the JSON-RPC server, the notebook kernel, test blocks and the
+//! command line all get the same guarantees. A step which would go over a cap isn't executed, and
+//! the machine stops with [`TerminationReason::ResourceLimit`](crate::machine::TerminationReason).
+//!
+//! The step cap is the machine's step limit. The number of registers and of calls waiting to
+//! return are checked before each instruction, so a program can't make the machine allocate
+//! them. Wall time and the memory taken
+//! up by the registers' limbs (each 128 bits) only change slowly, so they are checked every
+//! [`CHECK_INTERVAL`] steps. Wall time is counted from the start of each execution (see
+//! [`Machine::start_execution`](crate::machine::Machine::start_execution)), so time spent paused
+//! in the debugger doesn't count.
+
+use std::time::Duration;
+
+use thiserror::Error;
+
+/// How many steps are taken between checks of the wall time and the memory used.
+pub const CHECK_INTERVAL: usize = 1024;
+
+/// The most resources one execution may use. [`None`] means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    pub steps: Option<usize>,
+    pub time: Option<Duration>,
+    /// The most registers, natural and negative, which may exist at once.
+    pub registers: Option<usize>,
+    /// The most limbs of 128 bits which the values of every register together may take up.
+    pub limbs: Option<usize>,
+    /// The most calls which may be waiting to return at once.
+    pub calls: Option<usize>,
+}
+
+impl Sandbox {
+    /// A sandbox with no caps at all, which is the default.
+    pub const UNLIMITED: Sandbox = Sandbox {
+        steps: None,
+        time: None,
+        registers: None,
+        limbs: None,
+        calls: None,
+    };
+
+    /// The caps used by long-running servers, where one request mustn't take up the whole
+    /// machine: 60 seconds per execution, a million registers, 2^24 limbs (256 MiB) and a million
+    /// calls waiting to return. The number of steps isn't capped, as the time is.
+    pub const SERVER: Sandbox = Sandbox {
+        steps: None,
+        time: Some(Duration::from_secs(60)),
+        registers: Some(1_000_000),
+        limbs: Some(1 << 24),
+        calls: Some(1_000_000),
+    };
+}
+
+/// A cap of a [`Sandbox`] which stopped the machine.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceLimit {
+    #[error("The machine ran for longer than the limit of {} ms.", .0.as_millis())]
+    Time(Duration),
+    #[error("The machine would use more than the limit of {0} registers.")]
+    Registers(usize),
+    #[error("The registers took up more than the limit of {0} limbs of 128 bits.")]
+    Limbs(usize),
+    #[error("The machine would have more than the limit of {0} calls waiting to return.")]
+    Calls(usize),
+}
@@ -0,0 +1,204 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Backward slicing: finding the lines which can affect a register's final value.
+//!
+//! A line is in the slice of a register if it modifies a register in the slice, or if it decides
+//! whether such a line is executed. Every instruction modifies its own register (even DECJZ, unless
+//! it jumps), so the slice starts with the lines which use the register, and grows with the
+//! DECJZ instructions they are control dependent on and the lines which use those instructions'
+//! registers, until nothing changes. Registers which are always 0 are ignored, so `decjz rZ L` is
+//! treated as an unconditional jump, and `decjnz rZ L` never jumps.
+//!
+//! The slice is rendered like a [`Listing`](crate::listing::Listing), but only with the lines in
+//! the slice. Jumps keep their original targets, so the result is a reading aid rather than a
+//! program which can be run.
+
+use std::fmt::Display;
+
+use crate::{
+    instruction::Instruction,
+    machine::{ Identifier, LineNumber, Machine },
+    memory::RegisterNumber,
+    optimize,
+};
+
+/// The lines of a machine's program which can affect a register's final value.
+pub struct Slice<'a> {
+    machine: &'a Machine,
+    register: RegisterNumber,
+    lines: Vec<LineNumber>,
+}
+
+impl<'a> Slice<'a> {
+    /// Find the backward slice of the given register.
+    #[must_use]
+    pub fn new(machine: &'a Machine, register: RegisterNumber) -> Slice<'a> {
+        let line_count = machine.get_lines().len();
+        let zero = optimize::always_zero_registers(machine.get_lines());
+        let control_dependencies = control_dependencies(machine, &zero);
+        let mut registers = vec![register];
+        let mut in_slice = vec![false; line_count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for line in machine.get_lines() {
+                let n = line.get_line_number();
+                // WRITE doesn't change any register, so it never affects one.
+                let register = line.get_instruction().get_register()
+                    .filter(|_| !matches!(line.get_instruction(), Instruction::WRITE(_)));
+                if !in_slice[n] && register.is_some_and(|register| registers.contains(&register)) {
+                    in_slice[n] = true;
+                    changed = true;
+                }
+            }
+            for n in 0..line_count {
+                if !in_slice[n] {
+                    continue;
+                }
+                for &branch in &control_dependencies[n] {
+                    // Only DECJZ instructions branch, so there's always a register.
+                    let Some(branch_register) =
+                        machine.get_lines()[branch].get_instruction().get_register()
+                    else {
+                        continue;
+                    };
+                    if !registers.contains(&branch_register) && !zero.contains(&branch_register) {
+                        registers.push(branch_register);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        let lines = (0..line_count).filter(|&n| in_slice[n]).collect();
+        Slice { machine, register, lines }
+    }
+
+    #[must_use]
+    pub const fn get_register(&self) -> RegisterNumber {
+        self.register
+    }
+
+    /// Get the line numbers in the slice, in ascending order.
+    #[must_use]
+    pub fn get_lines(&self) -> &[LineNumber] {
+        &self.lines
+    }
+}
+
+impl Display for Slice<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines = self.machine.get_lines();
+        writeln!(f, "Lines which can affect {}:", self.register)?;
+        let width = lines.len().saturating_sub(1).to_string().len();
+        for &n in &self.lines {
+            write!(f, "{n:>width$}  ")?;
+            if let Some(Identifier::Label(label)) = lines[n].get_id() {
+                write!(f, "{label}: ")?;
+            }
+            writeln!(f, "{}", lines[n].get_instruction())?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the lines which each line may go to next, where the line after the last one means HALT.
+/// `zero` are the registers which are always 0.
+pub(crate) fn successors(machine: &Machine, zero: &[RegisterNumber]) -> Vec<Vec<LineNumber>> {
+    let lines = machine.get_lines();
+    let halt = lines.len();
+    let resolve = |target: &Identifier| match target {
+        Identifier::Line(n) => *n,
+        Identifier::Label(label) => machine.get_label_line(label).unwrap_or(halt),
+        Identifier::Halt => halt,
+        Identifier::Relative(_) | Identifier::LabelOffset(..) => unreachable!(),
+    };
+    // RETURN may go back to the line after any CALL, or halt if there's nothing to return to.
+    let mut returns: Vec<LineNumber> = lines
+        .iter()
+        .filter(|line| matches!(line.get_instruction(), Instruction::CALL(_)))
+        .map(|line| line.get_line_number() + 1)
+        .collect();
+    returns.push(halt);
+    returns.dedup();
+    lines
+        .iter()
+        .map(|line| {
+            let next = line.get_line_number() + 1;
+            match line.get_instruction() {
+                Instruction::INC(_) | Instruction::READ(_) | Instruction::WRITE(_) => vec![next],
+                Instruction::HALT => vec![halt],
+                Instruction::GOTO(target) | Instruction::CALL(target) => vec![resolve(target)],
+                Instruction::RETURN => returns.clone(),
+                Instruction::DECJZ(register, target) | Instruction::DECJNZ(register, target) => {
+                    let target = resolve(target);
+                    let jumps_on_zero = matches!(line.get_instruction(), Instruction::DECJZ(..));
+                    if zero.contains(register) {
+                        vec![if jumps_on_zero { target } else { next }]
+                    }
+                    else if target == next {
+                        vec![target]
+                    }
+                    else {
+                        vec![next, target]
+                    }
+                },
+            }
+        })
+        .collect()
+}
+
+/// Find the DECJZ instructions which each line is control dependent on, that is, the branches
+/// where one way always leads to the line and the other way may avoid it.
+fn control_dependencies(machine: &Machine, zero: &[RegisterNumber]) -> Vec<Vec<LineNumber>> {
+    let successors = successors(machine, zero);
+    let halt = successors.len();
+    // post_dominators[n][m] is true if every path from n to HALT goes through m.
+    let mut post_dominators = vec![vec![true; halt + 1]; halt + 1];
+    post_dominators[halt] = vec![false; halt + 1];
+    post_dominators[halt][halt] = true;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for n in (0..halt).rev() {
+            let mut new = vec![true; halt + 1];
+            for &s in &successors[n] {
+                for (m, is_post_dominator) in new.iter_mut().enumerate() {
+                    *is_post_dominator &= post_dominators[s][m];
+                }
+            }
+            new[n] = true;
+            if new != post_dominators[n] {
+                post_dominators[n] = new;
+                changed = true;
+            }
+        }
+    }
+    let mut dependencies = vec![Vec::new(); halt];
+    for (branch, branch_successors) in successors.iter().enumerate() {
+        if branch_successors.len() < 2 {
+            continue;
+        }
+        for (n, line_dependencies) in dependencies.iter_mut().enumerate() {
+            let strictly_post_dominates_branch = n != branch && post_dominators[branch][n];
+            let reached_from_branch = branch_successors.iter().any(|&s| post_dominators[s][n]);
+            if reached_from_branch && !strictly_post_dominates_branch {
+                line_dependencies.push(branch);
+            }
+        }
+    }
+    dependencies
+}
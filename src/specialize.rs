@@ -0,0 +1,426 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! Partial evaluation of programs whose inputs are partly known in advance.
+//!
+//! Given the values of some (natural) input registers, [`specialize`] produces a residual program
+//! which computes the same result as the original whenever those registers start with the given
+//! values. Instructions on the known registers are run ahead of time, so loops bounded by them are
+//! unrolled and their DECJZ decisions are folded away. The registers which aren't inputs, such as
+//! the negative ones used as scratch space, always start at 0, so they're known too, unless a loop
+//! over an unknown register changes them. The known registers are only touched just before
+//! halting, to leave them with the same final values the original program would.
+
+use std::collections::{ HashMap, HashSet };
+
+use thiserror::Error;
+
+use crate::{
+    instruction::Instruction,
+    machine::{ Identifier, Line, LineNumber, Machine },
+    memory::{ Register, RegisterNumber },
+    optimize,
+};
+
+/// The most lines a residual program may have before specialisation gives up.
+pub const MAX_LINES: usize = 100_000;
+/// The most copies of a single line a residual program may have before specialisation gives up.
+///
+/// This is reached when a known register is changed inside a loop which depends on an unknown
+/// register, since then each iteration would need its own copy of the loop.
+pub const MAX_COPIES: usize = 1_000;
+/// The most instructions on known registers which may be run in a row before specialisation
+/// gives up.
+pub const MAX_STATIC_STEPS: usize = 1_000_000;
+
+#[derive(Error, Debug)]
+pub enum SpecializeError {
+    #[error("Only natural registers can be specialised, but {0} was given.")]
+    NegativeRegister(RegisterNumber),
+    #[error("Cannot find label {label:?}, which line {line} jumps to.")]
+    LabelNotFound { label: String, line: LineNumber },
+    #[error("Register {register} would become larger than 2^128 - 1 on line {line}.")]
+    Overflow { register: RegisterNumber, line: LineNumber },
+    #[error("The specialised program would have more than {MAX_LINES} lines.")]
+    TooManyLines,
+    #[error("Line {line} would need more than {MAX_COPIES} copies. Does a loop depending on an unknown register change a known register?")]
+    TooManyCopies { line: LineNumber },
+    #[error("Programs with subroutines can't be specialised, but line {line} calls one.")]
+    Subroutine { line: LineNumber },
+    #[error("Programs which read or write can't be specialised, but line {line} does.")]
+    Io { line: LineNumber },
+    #[error("Ran {MAX_STATIC_STEPS} instructions on known registers in a row from line {line}.")]
+    TooManySteps { line: LineNumber },
+}
+
+/// A line of the original program together with the values of the known registers.
+type State = (LineNumber, Vec<u128>);
+
+/// Where the original program ends up after running all instructions on known registers.
+enum Point {
+    /// The next instruction uses a register which isn't known.
+    Instruction(State),
+    /// The program halts with the known registers set to the given values.
+    Halt(Vec<u128>),
+    /// The program only uses known registers from here on and never halts.
+    Forever(State),
+}
+
+impl Point {
+    fn key(&self, halt_line: LineNumber) -> State {
+        match self {
+            Point::Instruction(state) | Point::Forever(state) => state.clone(),
+            Point::Halt(values) => (halt_line, values.clone()),
+        }
+    }
+}
+
+struct Specializer<'a> {
+    machine: &'a Machine,
+    known: Vec<RegisterNumber>,
+    initial: Vec<u128>,
+    /// A register which the program never uses, so it's always 0 and can be used to jump.
+    goto_register: RegisterNumber,
+    labels: HashMap<State, String>,
+    copies: HashMap<LineNumber, usize>,
+    emitted: HashSet<State>,
+    pending: Vec<Point>,
+    lines: Vec<Line>,
+}
+
+/// Specialise a machine for the given values of some of its natural registers.
+///
+/// The returned machine starts with the known registers set to their given values.
+///
+/// # Errors
+///
+/// * [`SpecializeError::NegativeRegister`] - returned if a negative register is given.
+/// * [`SpecializeError::Subroutine`] - returned if the program uses CALL or RETURN, since where
+///   RETURN goes depends on the call stack.
+/// * [`SpecializeError::Io`] - returned if the program uses READ or WRITE, since running them
+///   ahead of time would change the order of the input and output.
+/// * [`SpecializeError::LabelNotFound`] - returned if the program jumps to a label which doesn't
+///   exist.
+/// * [`SpecializeError::Overflow`] - returned if a known register would grow too large.
+/// * [`SpecializeError::TooManyLines`] - returned if the residual program would be too large.
+/// * [`SpecializeError::TooManyCopies`] - returned if a line would need to be specialised for too
+///   many different values of the known registers.
+/// * [`SpecializeError::TooManySteps`] - returned if the known registers keep growing without
+///   ever reaching an instruction on an unknown register.
+pub fn specialize(
+    machine: &Machine,
+    known: &[(RegisterNumber, u128)],
+) -> Result<Machine, SpecializeError> {
+    for (register, _) in known {
+        if let RegisterNumber::Negative(_) = register {
+            return Err(SpecializeError::NegativeRegister(*register));
+        }
+    }
+    let subroutine = machine.get_lines().iter().find(|line| {
+        matches!(line.get_instruction(), Instruction::CALL(_) | Instruction::RETURN)
+    });
+    if let Some(line) = subroutine {
+        return Err(SpecializeError::Subroutine { line: line.get_line_number() });
+    }
+    let io = machine.get_lines().iter().find(|line| {
+        matches!(line.get_instruction(), Instruction::READ(_) | Instruction::WRITE(_))
+    });
+    if let Some(line) = io {
+        return Err(SpecializeError::Io { line: line.get_line_number() });
+    }
+    let mut known_registers: Vec<RegisterNumber> = known.iter().map(|(r, _)| *r).collect();
+    let mut initial: Vec<u128> = known.iter().map(|(_, v)| *v).collect();
+    // Registers which are always 0 are known too.
+    for register in optimize::always_zero_registers(machine.get_lines()) {
+        if !known_registers.contains(&register) {
+            known_registers.push(register);
+            initial.push(0);
+        }
+    }
+    // So are the registers which aren't inputs, that is, the negative registers and the natural
+    // ones past the registers line, since they always start with the same value. Treating them as
+    // known unrolls every loop which changes them, though, which never ends if the loop depends
+    // on an unknown register, so then they're left unknown after all.
+    let inputs = machine.get_initial_memory().get_nat_registers().len();
+    let (mut all_registers, mut all_initial) = (known_registers.clone(), initial.clone());
+    for line in machine.get_lines() {
+        let register = match line.get_instruction().get_register() {
+            Some(register @ RegisterNumber::Negative(_)) => register,
+            Some(register @ RegisterNumber::Natural(n)) if n >= inputs => register,
+            _ => continue,
+        };
+        let value = machine.get_initial_memory().get_value(register).to_u128();
+        if let (false, Some(value)) = (all_registers.contains(&register), value) {
+            all_registers.push(register);
+            all_initial.push(value);
+        }
+    }
+    if all_registers.len() > known_registers.len() {
+        match specialize_known(machine, known, all_registers, all_initial) {
+            Err(
+                SpecializeError::TooManyCopies { .. }
+                | SpecializeError::TooManyLines
+                | SpecializeError::TooManySteps { .. },
+            ) => {},
+            result => return result,
+        }
+    }
+    specialize_known(machine, known, known_registers, initial)
+}
+
+/// Specialise a machine, running the instructions on the given registers, which start with the
+/// given values, ahead of time. Only the given values of natural registers are set in the
+/// returned machine.
+fn specialize_known(
+    machine: &Machine,
+    known: &[(RegisterNumber, u128)],
+    known_registers: Vec<RegisterNumber>,
+    initial: Vec<u128>,
+) -> Result<Machine, SpecializeError> {
+    let most_negative = machine
+        .get_lines()
+        .iter()
+        .filter_map(|line| match line.get_instruction().get_register() {
+            Some(RegisterNumber::Negative(n)) => Some(n),
+            Some(RegisterNumber::Natural(_) | RegisterNumber::Pseudo(_)) | None => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let mut specializer = Specializer {
+        machine,
+        known: known_registers,
+        initial,
+        goto_register: RegisterNumber::Negative(most_negative + 1),
+        labels: HashMap::new(),
+        copies: HashMap::new(),
+        emitted: HashSet::new(),
+        pending: Vec::new(),
+        lines: Vec::new(),
+    };
+
+    let start = specializer.run_known((machine.get_entry_line(), specializer.initial.clone()))?;
+    specializer.emit_from(start)?;
+    while let Some(point) = specializer.pending.pop() {
+        specializer.emit_from(point)?;
+    }
+
+    // Only keep labels which are actually jumped to.
+    let mut lines = specializer.lines;
+    for i in 0..lines.len() {
+        let is_jumped_to = lines[i].get_id().is_some_and(|id| {
+            lines.iter().any(|line| line.get_instruction().get_jump_target() == Some(id))
+        });
+        if !is_jumped_to {
+            lines[i].change_id(None);
+        }
+    }
+
+    let mut memory = machine.get_initial_memory().clone();
+    for (register, value) in known {
+        memory.set_register(*register, Register::from(*value));
+    }
+    Ok(Machine::new_from_lines(&lines, memory))
+}
+
+impl Specializer<'_> {
+    fn halt_line(&self) -> LineNumber {
+        self.machine.get_lines().len()
+    }
+
+    /// Find the line number an identifier points to.
+    fn resolve(&self, id: &Identifier, line: LineNumber) -> Result<LineNumber, SpecializeError> {
+        match id {
+            Identifier::Halt => Ok(self.halt_line()),
+            Identifier::Line(n) => Ok(*n),
+            Identifier::Label(label) => self.machine.get_label_line(label).ok_or_else(|| {
+                SpecializeError::LabelNotFound { label: label.clone(), line }
+            }),
+            Identifier::Relative(_) | Identifier::LabelOffset(..) => unreachable!(),
+        }
+    }
+
+    /// Run instructions on known registers from the given state until reaching one which uses an
+    /// unknown register.
+    fn run_known(&self, state: State) -> Result<Point, SpecializeError> {
+        let (mut line, mut values) = state;
+        let start = line;
+        let mut seen: HashSet<State> = HashSet::new();
+        for _ in 0..MAX_STATIC_STEPS {
+            let Some(current) = self.machine.get_lines().get(line) else {
+                return Ok(Point::Halt(values));
+            };
+            let instruction = current.get_instruction();
+            if let Instruction::GOTO(target) = instruction {
+                if !seen.insert((line, values.clone())) {
+                    return Ok(Point::Forever((line, values)));
+                }
+                line = self.resolve(target, line)?;
+                continue;
+            }
+            let Some(register) = instruction.get_register() else {
+                return Ok(Point::Halt(values));
+            };
+            let Some(i) = self.known.iter().position(|r| *r == register) else {
+                return Ok(Point::Instruction((line, values)));
+            };
+            if !seen.insert((line, values.clone())) {
+                return Ok(Point::Forever((line, values)));
+            }
+            match instruction {
+                Instruction::INC(_) => {
+                    values[i] = values[i]
+                        .checked_add(1)
+                        .ok_or(SpecializeError::Overflow { register, line })?;
+                    line += 1;
+                },
+                Instruction::DECJZ(_, target) => {
+                    if values[i] == 0 {
+                        line = self.resolve(target, line)?;
+                    }
+                    else {
+                        values[i] -= 1;
+                        line += 1;
+                    }
+                },
+                Instruction::DECJNZ(_, target) => {
+                    if values[i] == 0 {
+                        line += 1;
+                    }
+                    else {
+                        values[i] -= 1;
+                        line = self.resolve(target, line)?;
+                    }
+                },
+                Instruction::GOTO(_)
+                | Instruction::CALL(_)
+                | Instruction::RETURN
+                | Instruction::HALT => unreachable!("None of these has a register"),
+                Instruction::READ(_) | Instruction::WRITE(_) => {
+                    unreachable!("Programs which read or write are rejected")
+                },
+            }
+        }
+        Err(SpecializeError::TooManySteps { line: start })
+    }
+
+    /// Get the identifier a residual jump to the given point should use, queueing the point to be
+    /// emitted if it hasn't been already.
+    fn jump_target(&mut self, point: Point) -> Result<Identifier, SpecializeError> {
+        // Halting without changing any known register needs no extra lines.
+        if let Point::Halt(values) = &point {
+            if values == &self.initial {
+                return Ok(Identifier::Halt);
+            }
+        }
+        let key = point.key(self.halt_line());
+        let label = self.label(&key)?;
+        if !self.emitted.contains(&key) {
+            self.pending.push(point);
+        }
+        Ok(Identifier::Label(label))
+    }
+
+    /// Get the residual label of a program point, creating one if necessary.
+    fn label(&mut self, key: &State) -> Result<String, SpecializeError> {
+        if let Some(label) = self.labels.get(key) {
+            return Ok(label.clone());
+        }
+        let copies = self.copies.entry(key.0).or_insert(0);
+        *copies += 1;
+        if *copies > MAX_COPIES {
+            return Err(SpecializeError::TooManyCopies { line: key.0 });
+        }
+        let label = format!("s{}", self.labels.len());
+        self.labels.insert(key.clone(), label.clone());
+        Ok(label)
+    }
+
+    fn push_line(
+        &mut self,
+        label: Option<String>,
+        instruction: Instruction,
+    ) -> Result<(), SpecializeError> {
+        let line_number = self.lines.len();
+        if line_number >= MAX_LINES {
+            return Err(SpecializeError::TooManyLines);
+        }
+        self.lines.push(Line::new(line_number, label.map(Identifier::Label), instruction));
+        Ok(())
+    }
+
+    /// Emit residual lines starting from the given point, falling through to following points
+    /// until reaching one which has already been emitted.
+    fn emit_from(&mut self, mut point: Point) -> Result<(), SpecializeError> {
+        loop {
+            let key = point.key(self.halt_line());
+            if self.emitted.contains(&key) {
+                let target = Identifier::Label(self.label(&key)?);
+                self.push_line(None, Instruction::DECJZ(self.goto_register, target))?;
+                return Ok(());
+            }
+            let label = self.label(&key)?;
+            self.emitted.insert(key);
+            match point {
+                Point::Halt(values) => return self.emit_halt(label, &values),
+                Point::Forever(_) => {
+                    let target = Identifier::Label(label.clone());
+                    self.push_line(Some(label), Instruction::DECJZ(self.goto_register, target))?;
+                    return Ok(());
+                },
+                Point::Instruction((line, values)) => {
+                    let instruction = self.machine.get_lines()[line].get_instruction().clone();
+                    match instruction {
+                        Instruction::INC(register) => {
+                            self.push_line(Some(label), Instruction::INC(register))?;
+                        },
+                        Instruction::DECJZ(_, ref target) | Instruction::DECJNZ(_, ref target) => {
+                            let target_line = self.resolve(target, line)?;
+                            let target_point = self.run_known((target_line, values.clone()))?;
+                            let target = self.jump_target(target_point)?;
+                            self.push_line(Some(label), instruction.with_jump_target(target))?;
+                        },
+                        Instruction::GOTO(_) | Instruction::HALT => {
+                            unreachable!("run_known follows GOTO and stops at HALT")
+                        },
+                        Instruction::CALL(_) | Instruction::RETURN => {
+                            unreachable!("Programs with subroutines are rejected")
+                        },
+                        Instruction::READ(_) | Instruction::WRITE(_) => {
+                            unreachable!("Programs which read or write are rejected")
+                        },
+                    }
+                    point = self.run_known((line + 1, values))?;
+                },
+            }
+        }
+    }
+
+    /// Emit lines which set the known registers to their final values and then halt.
+    fn emit_halt(&mut self, label: String, values: &[u128]) -> Result<(), SpecializeError> {
+        let mut label = Some(label);
+        for (i, register) in self.known.clone().into_iter().enumerate() {
+            for _ in self.initial[i]..values[i] {
+                self.push_line(label.take(), Instruction::INC(register))?;
+            }
+            // The register is never 0 here, so this never jumps.
+            for _ in values[i]..self.initial[i] {
+                self.push_line(label.take(), Instruction::DECJZ(register, Identifier::Halt))?;
+            }
+        }
+        self.push_line(label.take(), Instruction::HALT)
+    }
+}
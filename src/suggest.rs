@@ -0,0 +1,70 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Suggesting what was meant when a word isn't recognised, such as `inc` for `icn`.
+//!
+//! Words are compared by their edit distance: how many characters have to be inserted, deleted,
+//! replaced or swapped with their neighbour to turn one into the other.
+
+/// Get the candidate closest to `word`, if any is close enough to be a likely typo. Ties go to the
+/// candidate which comes first.
+///
+/// # Examples
+/// ```
+/// use remuir::suggest::closest;
+/// assert_eq!(closest("icn", ["inc", "dec", "halt"]), Some("inc"));
+/// assert_eq!(closest("jump", ["inc", "dec", "halt"]), None);
+/// ```
+pub fn closest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    // Short words need an exact match but for one mistake, longer ones may have more.
+    let allowed = (word.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != word)
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= allowed)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Get " Did you mean "x"?", to end an error message with, or nothing if there's no suggestion.
+#[must_use]
+pub fn did_you_mean(suggestion: Option<&str>) -> String {
+    suggestion.map(|suggestion| format!(" Did you mean {suggestion:?}?")).unwrap_or_default()
+}
+
+/// Get the number of single-character insertions, deletions, substitutions and swaps of adjacent
+/// characters needed to turn `a` into `b` (the optimal string alignment distance).
+#[must_use]
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // The distances between prefixes of `a` and `b`, keeping the last two rows.
+    let mut previous: Vec<usize> = Vec::new();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let before = std::mem::replace(&mut previous, row.clone());
+        row[0] = i;
+        for j in 1..=b.len() {
+            let substitution = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            row[j] = substitution.min(previous[j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+    }
+    row[b.len()]
+}
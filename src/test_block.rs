@@ -0,0 +1,245 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Unit tests written inside a program's source code.
+//!
+//! A test block is a comment, so it's ignored when the program is run, and it's read by
+//! [`find_tests`]. It has a name and some statements between braces, separated by `/` or by
+//! line breaks. A block may span several lines as long as each one is a comment:
+//!
+//! ```text
+//! #test adds { registers 2 3 / expect registers 0 5 }
+//! #test adds zero {
+//! #   registers 0 4
+//! #   expect r1 = 4
+//! # }
+//! ```
+//!
+//! The statements are:
+//!
+//! * `registers ...` - start with these registers instead of those in the registers line.
+//! * `expect registers ...` - the natural registers the program must halt with. Registers which
+//!   aren't given must be 0.
+//! * `expect rN = V` - the value one register must have when the program halts.
+//! * `limit N` - fail if the program hasn't halted after this many steps, rather than
+//!   [`DEFAULT_STEP_LIMIT`].
+//!
+//! Every test also fails if the program doesn't halt.
+
+use std::fmt::Display;
+
+use thiserror::Error;
+
+use crate::{
+    io_handler::BufferIo,
+    machine::{ Machine, TerminationReason },
+    memory::{ Memory, RegisterNumber },
+    parser::{ self, ParseSourceError },
+};
+
+/// How many steps a test may take if it doesn't give a `limit`.
+pub const DEFAULT_STEP_LIMIT: usize = 1_000_000;
+
+#[derive(Error, Debug)]
+pub enum TestBlockError {
+    #[error("The test {name:?} on line {line} has no closing \"}}\".")]
+    Unclosed { name: String, line: usize },
+    #[error("Line {line}: expected a test such as \"#test name {{ ... }}\".")]
+    MissingBrace { line: usize },
+    #[error(
+        "Line {line}: unknown test statement {statement:?}. Use \"registers\", \
+        \"expect registers\", \"expect rN = V\" or \"limit\"."
+    )]
+    UnknownStatement { statement: String, line: usize },
+    #[error("Line {line}: {source}")]
+    InvalidRegisters { source: ParseSourceError, line: usize },
+}
+
+/// A test read from a test block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestBlock {
+    pub name: String,
+    /// The line of the source code the block starts on, counting from 1.
+    pub line: usize,
+    /// The registers to start with, if not those in the registers line.
+    pub registers: Option<Memory>,
+    pub expected_registers: Option<Memory>,
+    pub expected_values: Vec<(RegisterNumber, u128)>,
+    pub step_limit: usize,
+}
+
+/// Whether a test passed, and if not, why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    Passed,
+    Failed(String),
+}
+
+impl Display for TestOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestOutcome::Passed => write!(f, "ok"),
+            TestOutcome::Failed(reason) => write!(f, "FAILED: {reason}"),
+        }
+    }
+}
+
+impl TestBlock {
+    /// Run the test on a copy of the machine, from its entry point. The program has no input, and
+    /// its output is thrown away.
+    #[must_use]
+    pub fn run(&self, machine: &Machine) -> TestOutcome {
+        let mut machine = machine.clone();
+        machine.set_io_handler(BufferIo::default());
+        if let Some(registers) = &self.registers {
+            machine.set_initial_memory(registers.clone());
+        }
+        machine.reset();
+        machine.start_execution();
+        while !machine.is_halted() && machine.get_steps() < self.step_limit {
+            if !matches!(machine.step(), Ok(None | Some(TerminationReason::Halted))) {
+                break;
+            }
+        }
+        if !machine.is_halted() {
+            let reason = if let Some(limit) = machine.get_resource_limit() {
+                limit.to_string()
+            }
+            else if let Some(e) = machine.get_io_error() {
+                e.to_string()
+            }
+            else if machine.get_steps() >= self.step_limit {
+                format!("didn't halt within {} steps", self.step_limit)
+            }
+            else {
+                format!("stopped without halting on line {}", machine.get_current_line_number())
+            };
+            return TestOutcome::Failed(reason);
+        }
+        let memory = machine.get_state();
+        if let Some(expected) = &self.expected_registers {
+            let count = expected.get_nat_count().max(memory.get_nat_count());
+            let same = (0..count).all(|n| {
+                let register = RegisterNumber::Natural(n);
+                memory.get_value(register) == expected.get_value(register)
+            });
+            if !same {
+                return TestOutcome::Failed(format!("expected {expected}, got {memory}"));
+            }
+        }
+        for &(register, value) in &self.expected_values {
+            let actual = memory.get_value(register);
+            if actual.to_u128() != Some(value) {
+                return TestOutcome::Failed(format!("expected {register} = {value}, got {actual}"));
+            }
+        }
+        TestOutcome::Passed
+    }
+}
+
+/// Find every test block in the source code, in order.
+///
+/// # Errors
+///
+/// * [`TestBlockError::MissingBrace`] - when a line starts with `#test` but has no `{`.
+/// * [`TestBlockError::Unclosed`] - when a block has no `}` before the next line which isn't a
+///   comment.
+/// * [`TestBlockError::UnknownStatement`] - when a block has a statement which isn't one of those
+///   listed in the [module documentation](self).
+/// * [`TestBlockError::InvalidRegisters`] - when a `registers` statement can't be parsed.
+pub fn find_tests(source: &str) -> Result<Vec<TestBlock>, TestBlockError> {
+    let mut tests = Vec::new();
+    let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+    while let Some((line, text)) = lines.next() {
+        let Some(rest) = text.strip_prefix("#test").filter(|rest| rest.starts_with([' ', '\t']))
+        else {
+            continue;
+        };
+        let (name, mut body) = rest.split_once('{').ok_or(TestBlockError::MissingBrace { line })?;
+        let name = name.trim().to_string();
+        // Each statement with the line it's on.
+        let mut statements: Vec<(usize, String)> = Vec::new();
+        let mut current = line;
+        loop {
+            let (inside, closed) = match body.split_once('}') {
+                Some((inside, _)) => (inside, true),
+                None => (body, false),
+            };
+            statements.extend(
+                inside
+                    .split('/')
+                    .map(str::trim)
+                    .filter(|statement| !statement.is_empty())
+                    .map(|statement| (current, statement.to_string())),
+            );
+            if closed {
+                break;
+            }
+            // The block goes on to the next line, which must still be a comment.
+            match lines.next() {
+                Some((next_line, next)) if next.starts_with('#') => {
+                    current = next_line;
+                    body = &next[1..];
+                },
+                _ => return Err(TestBlockError::Unclosed { name, line }),
+            }
+        }
+        tests.push(parse_statements(name, line, &statements)?);
+    }
+    Ok(tests)
+}
+
+fn parse_statements(
+    name: String,
+    line: usize,
+    statements: &[(usize, String)],
+) -> Result<TestBlock, TestBlockError> {
+    let mut test = TestBlock {
+        name,
+        line,
+        registers: None,
+        expected_registers: None,
+        expected_values: Vec::new(),
+        step_limit: DEFAULT_STEP_LIMIT,
+    };
+    for (line, statement) in statements {
+        let line = *line;
+        let registers = |s: &str| {
+            parser::parse_register_line(s)
+                .map_err(|source| TestBlockError::InvalidRegisters { source, line })
+        };
+        let unknown = || TestBlockError::UnknownStatement { statement: statement.clone(), line };
+        if statement.starts_with("registers") {
+            test.registers = Some(registers(statement)?);
+        }
+        else if let Some(expected) = statement.strip_prefix("expect registers") {
+            test.expected_registers = Some(registers(&format!("registers{expected}"))?);
+        }
+        else if let Some(expected) = statement.strip_prefix("expect ") {
+            let (register, value) = expected.split_once('=').ok_or_else(unknown)?;
+            let register = register.trim().parse().map_err(|_| unknown())?;
+            let value = value.trim().parse().map_err(|_| unknown())?;
+            test.expected_values.push((register, value));
+        }
+        else if let Some(limit) = statement.strip_prefix("limit ") {
+            test.step_limit = limit.trim().parse().map_err(|_| unknown())?;
+        }
+        else {
+            return Err(unknown());
+        }
+    }
+    Ok(test)
+}
@@ -0,0 +1,113 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Helpers for testing interactive sessions without a terminal.
+
+use crate::{
+    command::MultiLine,
+    machine::Machine,
+    tui::{self, Mode, ReplState},
+};
+
+/// A scripted REPL or debug session. Each command's output is captured so it can be checked.
+///
+/// ```
+/// use remuir::testing::ReplSession;
+///
+/// let mut session = ReplSession::repl();
+/// session.expect("inc r1", "Register r1 is now 1.");
+/// session.expect("registers", "registers 0 1");
+/// ```
+#[derive(Debug)]
+pub struct ReplSession {
+    machine: Machine,
+    mode: Mode,
+    stopped: bool,
+}
+
+impl ReplSession {
+    /// Start a REPL session with empty registers.
+    #[must_use]
+    pub fn repl() -> Self {
+        Self { machine: Machine::default(), mode: Mode::Repl, stopped: false }
+    }
+
+    /// Start a debug session on the given machine.
+    #[must_use]
+    pub fn debug(mut machine: Machine) -> Self {
+        machine.set_history_limit(Some(tui::HISTORY_LIMIT));
+        Self {
+            machine,
+            mode: Mode::Debug {
+                previous_line: None,
+                previous_memory: None,
+                previous_call_stack: Vec::new(),
+            },
+            stopped: false,
+        }
+    }
+
+    /// Run one command and return everything it printed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the session has already been stopped, or if the command returns an error.
+    pub fn send(&mut self, command: &str) -> String {
+        assert!(!self.stopped, "Sent {command:?} to a session which has already stopped.");
+        let mut out: Vec<u8> = Vec::new();
+        match tui::command(command, &mut self.machine, &mut self.mode, &mut out) {
+            Ok(ReplState::KeepLooping) => (),
+            Ok(ReplState::Stop) => self.stopped = true,
+            Err(e) => panic!("Command {command:?} failed: {e}"),
+        }
+        String::from_utf8(out).expect("Sessions only print UTF-8.")
+    }
+
+    /// Run each line of the script as a command and return everything they printed. Commands
+    /// can go over more than one line, as they can when typed in (see [`MultiLine`]).
+    pub fn send_script(&mut self, script: &str) -> String {
+        let mut lines = MultiLine::default();
+        let mut commands: Vec<String> =
+            script.lines().filter_map(|line| lines.push(line)).collect();
+        commands.extend(lines.finish());
+        commands.iter().map(|command| self.send(command)).collect()
+    }
+
+    /// Run one command and check its output contains `expected`, returning the full output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the output doesn't contain `expected`.
+    pub fn expect(&mut self, command: &str, expected: &str) -> String {
+        let output = self.send(command);
+        assert!(
+            output.contains(expected),
+            "Output of {command:?} didn't contain {expected:?}.\nOutput was:\n{output}",
+        );
+        output
+    }
+
+    #[must_use]
+    pub const fn get_machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// Check if a command such as "quit" has ended the session.
+    #[must_use]
+    pub const fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+}
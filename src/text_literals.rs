@@ -20,27 +20,82 @@ help, h             Display this help text.
 
 registers, r        Display the current state of the (natural) registers.
 registers [NUMBERS] Set the registers to the given state. See README.md for more details.
+save-as [NAME]      Save the registers under the given name.
+restore [NAME]      Set the registers to those saved under the given name.
+diff [NAME] [NAME]  List the registers which differ between two saved snapshots, or between one
+                    and the registers now if only one name is given.
+transcript start [PATH]
+                    Record every command and its output in the given Markdown file.
+transcript stop     Stop recording.
+xref, x [REGISTER]  List every line which uses the given register.
 
 remuir instructions:
 inc r[NUMBER]           Increase the given register by 1.
 decjz r[NUMBER] [LABEL] Decrease the given register by 1. The label is ignored in REPL mode.
-dec r[NUMBER]           Shorter decrement instruction, only available in REPL and debug mode.";
+decjnz r[NUMBER] [LABEL]
+                        Decrease the given register by 1 unless it's 0. The label is ignored in
+                        REPL mode.
+dec r[NUMBER]           Shorter decrement instruction, only available in REPL and debug mode.
+                        Unlike decjz, decrementing a register which is already 0 is an error.
+
+Commands are case-insensitive. Wrap labels containing spaces in quotes, such as b \"loop 1\".
+End a line with \\ to carry a command on onto the next line. Labels before instructions, such
+as \"loop: inc r0\" or \"loop:\" on a line of its own, are ignored.";
 
 pub const HELP_TEXT_DEBUG: &str = "Debug specific commands:
 exit, quit, q         Quit the debug REPL.
 help, h               Display this help text.
 
-breakpoint, b [LABEL] Add a breakpoint to the given label or line number.
+backtrace, bt         List the subroutines which haven't returned, innermost first, and the
+                      jumps which led to the current line, most recent first.
+breakpoint, b [LABEL] Add a breakpoint to the given label or line number. Use again to remove it.
+breakpoint, b [LABEL] hit [N]
+                      Add a breakpoint which only stops on the Nth time the line is executed.
+diff [NAME] [NAME]    List the registers which differ between two saved snapshots, or between one
+                      and the registers now if only one name is given.
+history [NUMBER]      List the last lines which were executed (10 by default).
+info label [LABEL]    Show the line a label is on and its documentation, from the \"##\" comments
+                      right above it.
+list, l               Show the program, marking the next line with => and breakpoints with *.
+machine [list]        List the machines in this session, marking the one being debugged with =>.
+machine add [PATH]    Load another program into this session, such as an optimised version.
+machine switch [N]    Debug the Nth machine instead. Every other command acts on this machine.
+machine lockstep [N] [M] [STEPS]
+                      Step machines N and M together until their registers differ, for at most
+                      STEPS steps (100000 by default), and show the differences.
+phases                Show how many steps have been spent in each phase (see README.md).
+registers             Display the current state of the (natural) registers.
 play, p               Execute the program until a breakpoint is reached or the machine halts.
+                      Press Ctrl+C to stop a run which takes too long.
+protect r[NUMBER]     Stop before any instruction modifies the given register.
+                      Use again to unprotect.
 registers [NUMBERS]   Set the registers to the given state. See README.md for more details.
 reset, r              Set the state of the registers to their initial state and point to the first instruction.
+restore [NAME]        Set the registers to those saved under the given name.
+run [NUMBERS]         Set new initial registers (if given), reset, and play.
+                      For example, \"run 3 5\".
+save-as [NAME]        Save the registers under the given name. Resets leave saved registers alone.
 step, s               Take a step (execute the current instruction and point to the next instruction).
+transcript start [PATH]
+                      Record every command and its output in the given Markdown file.
+transcript stop       Stop recording.
+xref, x [LABEL]       List every line which jumps to the given label.
+xref, x [REGISTER]    List every line which uses the given register.
 undo, u               Undo the last step. Currently, you can only undo 1 step, not multiple!
+watchpoint r[NUMBER]  Stop after any instruction changes the given register. Use again to remove it.
 
 remuir instructions:
 inc r[NUMBER]           Increase the given register by 1.
 decjz r[NUMBER] [LABEL] Decrease the given register by 1. The label is ignored in debug mode.
-dec r[NUMBER]           Shorter decrement instruction, only available in REPL and debug mode.";
+decjnz r[NUMBER] [LABEL]
+                        Decrease the given register by 1 unless it's 0. The label is ignored in
+                        debug mode.
+dec r[NUMBER]           Shorter decrement instruction, only available in REPL and debug mode.
+                        Unlike decjz, decrementing a register which is already 0 is an error.
+
+Commands are case-insensitive. Wrap labels containing spaces in quotes, such as b \"loop 1\".
+End a line with \\ to carry a command on onto the next line. Labels before instructions, such
+as \"loop: inc r0\" or \"loop:\" on a line of its own, are ignored.";
 
 fn welcome_text(mode: &str) -> String {
     format!("remuir {} in {mode} mode. Type \"h\" for help.", env!("CARGO_PKG_VERSION"))
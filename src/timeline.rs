@@ -0,0 +1,124 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Recording an execution as a timeline of label-to-label regions.
+//!
+//! Every time execution reaches a labelled line, the current region ends and a new one named after
+//! the label begins. Time is measured in steps. A timeline can be written in the Chrome trace-event
+//! JSON format, which can be opened in timeline viewers such as Perfetto or `chrome://tracing`,
+//! with one step shown as one microsecond.
+
+use std::io::{self, Write};
+
+use crate::{ machine::{ Identifier, Machine, TerminationReason }, manifest::Manifest };
+
+/// The name of the region before execution reaches any label.
+const START_REGION: &str = "(start)";
+
+/// A region of execution, starting when a label was reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineEvent {
+    pub name: String,
+    pub start: usize,
+    pub duration: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timeline {
+    events: Vec<TimelineEvent>,
+}
+
+impl Timeline {
+    /// Run the machine from its current line until it halts, recording each region.
+    ///
+    /// If a protected register would be modified, recording stops early without halting.
+    pub fn record(machine: &mut Machine) -> Timeline {
+        let mut timeline = Timeline::default();
+        let mut current = TimelineEvent { name: String::from(START_REGION), start: 0, duration: 0 };
+        let mut steps: usize = 0;
+        while !machine.is_halted() {
+            if let Some(Identifier::Label(label)) = machine.peek_next_line().get_id() {
+                let next = TimelineEvent { name: label.clone(), start: steps, duration: 0 };
+                timeline.push(std::mem::replace(&mut current, next));
+            }
+            match machine.step() {
+                Ok(Some(
+                    TerminationReason::ProtectedRegister(_)
+                    | TerminationReason::InvalidJump(_)
+                    | TerminationReason::ResourceLimit(_)
+                    | TerminationReason::Io(_),
+                ))
+                | Err(_) => break,
+                Ok(_) => (),
+            }
+            steps += 1;
+            current.duration += 1;
+        }
+        timeline.push(current);
+        timeline
+    }
+
+    /// Add an event, unless it's empty.
+    fn push(&mut self, event: TimelineEvent) {
+        if event.duration > 0 {
+            self.events.push(event);
+        }
+    }
+
+    #[must_use]
+    pub fn get_events(&self) -> &[TimelineEvent] {
+        &self.events
+    }
+
+    /// Write the timeline in the Chrome trace-event JSON format, with the manifest of the run as
+    /// its `otherData`, which viewers show as the trace's metadata.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when writing to `out` fails.
+    pub fn write_trace_json(
+        &self,
+        out: &mut impl Write,
+        manifest: &Manifest,
+    ) -> io::Result<()> {
+        writeln!(out, "{{\"traceEvents\":[")?;
+        for (i, event) in self.events.iter().enumerate() {
+            let separator = if i + 1 < self.events.len() { "," } else { "" };
+            writeln!(
+                out,
+                "{{\"name\":\"{}\",\"cat\":\"label\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":1}}{separator}",
+                escape_json(&event.name),
+                event.start,
+                event.duration,
+            )?;
+        }
+        writeln!(out, "],\"otherData\":{}}}", manifest.to_json())
+    }
+}
+
+/// Escape a string so it can be put between quotes in JSON.
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,170 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Sampling an execution, for runs too long to record in full.
+//!
+//! Rather than recording every step like a [`crate::timeline::Timeline`], a sampled trace records
+//! the line about to be executed every Nth step, along with every jump to a chosen set of labels.
+//! The number of samples on each line is proportional to the time spent there, so the report
+//! gives a statistical picture of where time goes without slowing the run down much.
+
+use std::{ fmt::Display, num::NonZeroUsize };
+
+use crate::machine::{ Identifier, LineNumber, Machine, TerminationReason };
+
+/// What a sampled trace records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceConfig {
+    /// Record the line about to be executed every this many steps, or never if [`None`].
+    pub interval: Option<NonZeroUsize>,
+    /// Record every jump to one of these labels.
+    pub labels: Vec<String>,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        TraceConfig { interval: NonZeroUsize::new(1000), labels: Vec::new() }
+    }
+}
+
+/// Something which was recorded while sampling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sample {
+    /// The line about to be executed after `step` steps.
+    Step { step: usize, line: LineNumber },
+    /// A jump from a line to one of the chosen labels, taken on step `step` (counting from 1).
+    Jump { step: usize, from: LineNumber, label: String },
+}
+
+/// The samples recorded during a run, which can be displayed as a report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampledTrace {
+    interval: Option<NonZeroUsize>,
+    steps: usize,
+    samples: Vec<Sample>,
+    /// The chosen labels, in the order they're first defined in the program.
+    labels: Vec<String>,
+}
+
+impl SampledTrace {
+    /// Run the machine from its current line until it halts, recording samples as configured.
+    ///
+    /// If a protected register would be modified, recording stops early without halting.
+    pub fn record(machine: &mut Machine, config: &TraceConfig) -> SampledTrace {
+        let mut samples = Vec::new();
+        let mut steps: usize = 0;
+        while !machine.is_halted() {
+            let line = machine.get_current_line_number();
+            if config.interval.is_some_and(|interval| steps % interval == 0) {
+                samples.push(Sample::Step { step: steps, line });
+            }
+            match machine.step() {
+                Ok(Some(
+                    TerminationReason::ProtectedRegister(_)
+                    | TerminationReason::InvalidJump(_)
+                    | TerminationReason::ResourceLimit(_)
+                    | TerminationReason::Io(_),
+                ))
+                | Err(_) => break,
+                Ok(_) => (),
+            }
+            steps += 1;
+            // Like a backtrace, any move to a line other than the next one is a jump.
+            let to = machine.get_current_line_number();
+            if to == line + 1 || machine.is_halted() {
+                continue;
+            }
+            if let Some(Identifier::Label(label)) = machine.peek_next_line().get_id() {
+                if config.labels.contains(label) {
+                    samples.push(Sample::Jump { step: steps, from: line, label: label.clone() });
+                }
+            }
+        }
+        let defined = machine.get_labels();
+        let mut labels = config.labels.clone();
+        labels.sort_by_key(|label| defined.iter().position(|(defined, _)| defined == label));
+        labels.dedup();
+        SampledTrace { interval: config.interval, steps, samples, labels }
+    }
+
+    #[must_use]
+    pub fn get_samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// Get how many steps were taken in total, including those which weren't sampled.
+    #[must_use]
+    pub fn get_steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Get how many times each line was sampled, most sampled first, with ties in line order.
+    #[must_use]
+    pub fn line_counts(&self) -> Vec<(LineNumber, usize)> {
+        let mut counts: Vec<(LineNumber, usize)> = Vec::new();
+        for sample in &self.samples {
+            if let Sample::Step { line, .. } = sample {
+                match counts.iter_mut().find(|(counted, _)| counted == line) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((*line, 1)),
+                }
+            }
+        }
+        counts.sort_by(|(a_line, a_count), (b_line, b_count)| {
+            b_count.cmp(a_count).then(a_line.cmp(b_line))
+        });
+        counts
+    }
+
+    /// Get how many times each chosen label was jumped to, in the order the labels are first
+    /// defined in the program. Labels which were never jumped to are left out.
+    #[must_use]
+    pub fn jump_counts(&self) -> Vec<(&str, usize)> {
+        self.labels
+            .iter()
+            .map(|label| {
+                let jumps = self.samples.iter().filter(|sample| {
+                    matches!(sample, Sample::Jump { label: jumped, .. } if jumped == label)
+                });
+                (label.as_str(), jumps.count())
+            })
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+}
+
+impl Display for SampledTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Steps: {}", self.steps)?;
+        let lines = self.line_counts();
+        if let Some(interval) = self.interval {
+            let total: usize = lines.iter().map(|&(_, count)| count).sum();
+            writeln!(f, "Samples (every {interval} steps): {total}")?;
+            for (line, count) in lines {
+                let percent = 100.0 * count as f64 / total as f64;
+                writeln!(f, "  line {line}: {count} ({percent:.1}%)")?;
+            }
+        }
+        let jumps = self.jump_counts();
+        if !jumps.is_empty() {
+            writeln!(f, "Jumps:")?;
+            for (label, count) in jumps {
+                writeln!(f, "  {label}: {count}")?;
+            }
+        }
+        Ok(())
+    }
+}
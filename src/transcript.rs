@@ -0,0 +1,82 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Recording interactive sessions as Markdown, so that an exploration in the REPL or debugger can
+//! be turned straight into a write-up. The file starts with a heading, and each command typed
+//! goes in a fenced code block with its output:
+//!
+//! ````markdown
+//! # remuir transcript
+//!
+//! ```text
+//! remuir> inc r1
+//! Register r1 is now 1.
+//! ```
+//! ````
+//!
+//! Each command is written as soon as it has finished, so the transcript survives a crash.
+
+use std::{ fs::File, io::{ self, Write }, path::{ Path, PathBuf } };
+
+/// A Markdown file which commands and their output are being recorded into.
+#[derive(Debug)]
+pub struct Transcript {
+    path: PathBuf,
+    file: File,
+}
+
+impl Transcript {
+    /// Start a transcript in the given file, replacing whatever was in it.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the file couldn't be created or written.
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Transcript> {
+        let path = path.into();
+        let mut file = File::create(&path)?;
+        writeln!(file, "# remuir transcript")?;
+        Ok(Transcript { path, file })
+    }
+
+    /// Get the file the transcript is being written to.
+    #[must_use]
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add a command and its output to the transcript.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the file couldn't be written.
+    pub fn record(&mut self, command: &str, output: &str) -> io::Result<()> {
+        let output = output.trim_matches('\n');
+        // The fence has to be longer than any run of backticks in the block.
+        let longest_run = output
+            .split(|c| c != '`')
+            .chain(command.split(|c| c != '`'))
+            .map(str::len)
+            .max()
+            .unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+        writeln!(self.file, "\n{fence}text\nremuir> {command}")?;
+        if !output.is_empty() {
+            writeln!(self.file, "{output}")?;
+        }
+        writeln!(self.file, "{fence}")?;
+        self.file.flush()
+    }
+}
@@ -15,10 +15,52 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 
 //! Helper functions for interactive modes ("tui").
+//!
+//! Nothing here touches STDIN or STDOUT directly: sessions read from any [`BufRead`] and write to
+//! any [`Write`], so tests and other frontends can drive them.
 
-use std::{fmt::Display, io::{self, Write}, process::ExitCode};
+use std::{
+    fmt::Display,
+    io::{self, BufRead, Write},
+    num::NonZeroU32,
+    path::PathBuf,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
 
-use remuir::{instruction::Instruction, machine::{BreakpointToggle, Identifier, Machine, RuntimeError, TerminationReason}, memory::Memory, parser};
+use crate::{
+    autosave::Autosave,
+    bytecode,
+    command::{ self, Command, MultiLine },
+    compare,
+    cost::CostModel,
+    crash,
+    dialect,
+    import,
+    instruction::Instruction,
+    interrupt,
+    link,
+    listing::Listing,
+    machine::{
+        BreakpointToggle,
+        Identifier,
+        LineNumber,
+        Machine,
+        MachineEditError,
+        ProtectionToggle,
+        RuntimeError,
+        TerminationReason,
+    },
+    memory::{DecrementZeroError, Memory, RegisterNumber},
+    parser,
+    runaway::RunawaySummary,
+    sandbox::Sandbox,
+    specialize,
+    suggest,
+    test_block,
+    text_literals,
+    transcript::Transcript,
+};
 use thiserror::Error;
 
 pub enum ExitStatus {
@@ -64,41 +106,161 @@ pub enum RemuirError {
     RuntimeError(#[from] RuntimeError),
     #[error("Invalid syntax when parsing source code!\n{0}")]
     InvalidSyntax(#[from] parser::ParseSourceError),
+    #[error("Found {} errors when parsing source code!\n{}", .0.len(), join_errors(.0))]
+    InvalidSyntaxMany(Vec<parser::ParseSourceError>),
     #[error("Can't undo, previous state is unavailable.")]
     CannotUndo,
+    #[error("Failed to specialise program!\n{0}")]
+    Specialize(#[from] specialize::SpecializeError),
+    #[error("Failed to link program!\n{0}")]
+    Link(#[from] link::LinkError),
+    #[error("Failed to import table!\n{0}")]
+    Import(#[from] import::ImportError),
+    #[error("Failed to compare programs!\n{0}")]
+    Compare(#[from] compare::CompareError),
+    #[error("Failed to load bytecode!\n{0}")]
+    Bytecode(#[from] bytecode::BytecodeError),
+    #[error("Failed to translate program!\n{0}")]
+    Dialect(#[from] dialect::DialectError),
+    #[error("Failed to read tests!\n{0}")]
+    TestBlock(#[from] test_block::TestBlockError),
+    #[error("{failed} of {total} tests failed.")]
+    TestsFailed { failed: usize, total: usize },
+    #[error("Found {0} warnings, which aren't allowed with --deny-warnings.")]
+    Warnings(usize),
+    #[error("{} isn't formatted. Run \"remuir fmt --write\" on it to format it.", .0.display())]
+    Unformatted(PathBuf),
+    #[error("There is no example called \"{0}\". Use \"remuir examples list\" to see them all.")]
+    UnknownExample(String),
 }
 
+/// Put each error on its own paragraph.
+fn join_errors(errors: &[parser::ParseSourceError]) -> String {
+    errors.iter().map(ToString::to_string).collect::<Vec<String>>().join("\n\n")
+}
+
+/// What interactive modes can assume about the terminal they're driven from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Terminal {
+    /// A person at a terminal which understands escape codes: print prompts and redraw status
+    /// lines in place.
+    #[default]
+    Interactive,
+    /// A script, a pipe or a dumb terminal: no prompts and no escape codes, only whole lines.
+    Plain,
+}
+
+impl Terminal {
+    /// Choose how to drive interactive modes, given whether the streams they use are terminals.
+    /// A terminal with `TERM=dumb` can't handle escape codes, so it's plain as well.
+    #[must_use]
+    pub fn detect(is_terminal: bool) -> Self {
+        let dumb = std::env::var_os("TERM").is_some_and(|term| term == "dumb");
+        if is_terminal && !dumb { Self::Interactive } else { Self::Plain }
+    }
+}
+
+
 pub mod printers {
     //! Functions which print commonly used and long texts.
 
     use std::io::{self, Write};
+    use crate::{
+        machine::LineNumber,
+        memory::{ Register, RegisterNumber },
+        io_handler::IoError,
+        sandbox::ResourceLimit,
+    };
     use crate::text_literals;
+    use super::Terminal;
     
     /// Print a message when quitting an interactive mode.
     /// 
     /// Currently, it just prints a newline, but in the future this could have a goodbye message.
-    pub fn goodbye() -> io::Result<()> {
-        writeln!(io::stdout())
+    pub fn goodbye(out: &mut impl Write) -> io::Result<()> {
+        writeln!(out)
+    }
+
+    pub fn help_repl(out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{}", text_literals::HELP_TEXT_REPL)
+    }
+
+    pub fn help_debug(out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{}", text_literals::HELP_TEXT_DEBUG)
+    }
+
+    /// Print why the machine stopped before modifying a protected register.
+    pub fn protected_register(out: &mut impl Write, reg_num: RegisterNumber) -> io::Result<()> {
+        writeln!(
+            out,
+            "Stopped: the next instruction would modify protected register {reg_num}. Use \"protect {reg_num}\" to unprotect it.",
+        )
+    }
+
+    /// Print why the machine stopped before jumping outside the program.
+    pub fn invalid_jump(out: &mut impl Write, line: LineNumber) -> io::Result<()> {
+        writeln!(
+            out,
+            "Stopped: line {line} would jump past the end of the program or to a label which doesn't exist.",
+        )
+    }
+
+    /// Print which cap of the machine's sandbox stopped it.
+    pub fn resource_limit(out: &mut impl Write, limit: &ResourceLimit) -> io::Result<()> {
+        writeln!(out, "Stopped: {limit}")
     }
 
-    pub fn help_repl() -> io::Result<()> {
-        writeln!(io::stdout(), "{}", text_literals::HELP_TEXT_REPL)
+    /// Print why a READ or WRITE instruction failed.
+    pub fn io_error(out: &mut impl Write, error: &IoError) -> io::Result<()> {
+        writeln!(out, "Stopped: {error}")
     }
 
-    pub fn help_debug() -> io::Result<()> {
-        writeln!(io::stdout(), "{}", text_literals::HELP_TEXT_DEBUG)
+    /// Print how a register with a watchpoint was changed.
+    pub fn watchpoint(
+        out: &mut impl Write,
+        register: RegisterNumber,
+        old: &Register,
+        new: &Register,
+    ) -> io::Result<()> {
+        writeln!(out, "Watchpoint: {register} changed from {old} to {new}.")
     }
 
-    pub fn print_prompt() -> io::Result<()> {
-        write!(io::stdout(), "\nremuir> ")?;
-        io::stdout().flush()?;
+    /// Explain that a halted machine can be inspected but not stepped.
+    pub fn post_mortem(out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "Machine is already halted, so cannot step.")?;
+        writeln!(
+            out,
+            "It can still be inspected with \"registers\", \"list\", \"history\" and \"backtrace\",",
+        )?;
+        writeln!(out, "or started again with \"reset\" or \"run\".")
+    }
+
+    /// Print the prompt for the next command, unless the terminal is plain.
+    pub fn print_prompt(out: &mut impl Write, terminal: Terminal) -> io::Result<()> {
+        if terminal == Terminal::Interactive {
+            write!(out, "\nremuir> ")?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Print the prompt for the next line of a command which goes over more than one line.
+    pub fn print_continuation_prompt(out: &mut impl Write, terminal: Terminal) -> io::Result<()> {
+        if terminal == Terminal::Interactive {
+            write!(out, "   ...> ")?;
+        }
+        out.flush()?;
         Ok(())
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Mode {
-    Debug { previous_line: Option<usize>, previous_memory: Option<Memory> },
+    Debug {
+        previous_line: Option<usize>,
+        previous_memory: Option<Memory>,
+        previous_call_stack: Vec<LineNumber>,
+    },
     Repl,
 }
 
@@ -111,23 +273,28 @@ impl Mode {
         }
     }
 
-    pub fn set_previous(&mut self, new_line: usize, new_memory: Memory) {
+    pub fn set_previous(&mut self, new_line: usize, new_memory: Memory, call_stack: &[LineNumber]) {
         match self {
-            Self::Debug { previous_line, previous_memory } => {
+            Self::Debug { previous_line, previous_memory, previous_call_stack } => {
                 *previous_line = Some(new_line);
                 *previous_memory = Some(new_memory);
+                *previous_call_stack = call_stack.to_vec();
             },
             Self::Repl => panic!("Tried to change previous state in REPL mode!"),
         }
     }
 
-    pub fn get_previous(&self) -> Result<(usize, Memory), RemuirError> {
+    pub fn get_previous(&self) -> Result<(usize, Memory, Vec<LineNumber>), RemuirError> {
         match self {
-            Self::Debug { previous_line, previous_memory } => {
+            Self::Debug { previous_line, previous_memory, previous_call_stack } => {
                 if previous_line.is_none() || previous_memory.is_none() {
                     return Err(RemuirError::CannotUndo);
                 }
-                Ok((previous_line.unwrap(), previous_memory.clone().unwrap()))
+                Ok((
+                    previous_line.unwrap(),
+                    previous_memory.clone().unwrap(),
+                    previous_call_stack.clone(),
+                ))
             },
             Self::Repl => panic!("Tried to access previous state in REPL mode!"),
         }
@@ -143,85 +310,533 @@ impl Display for Mode {
     }
 }
 
+/// How many executed lines are remembered in debug mode, for "history" and "backtrace".
+pub const HISTORY_LIMIT: usize = 10_000;
+
+/// The most steps "machine lockstep" takes when no limit is given.
+const LOCKSTEP_LIMIT: usize = 100_000;
+
+/// The full names of the commands, to suggest when an unknown command looks like a typo.
+const COMMANDS: [&str; 26] = [
+    "exit", "quit", "help", "play", "run", "registers", "reset", "step", "undo", "inc", "dec",
+    "decjz", "decjnz", "breakpoint", "protect", "watchpoint", "machine", "list", "phases",
+    "history", "backtrace", "xref", "save-as", "restore", "diff", "info",
+];
+
 #[derive(Debug, Clone, Copy)]
 pub enum ReplState {
     KeepLooping,
     Stop,
 }
 
+/// Run an interactive REPL session, reading commands from `input` until it's exhausted or the user
+/// quits, and writing everything to `out`. Prompts are only printed if the terminal is
+/// [`Terminal::Interactive`].
+///
+/// # Errors
+///
+/// * [`RemuirError::IOError`] - returned when reading from `input` or writing to `out` fails.
+pub fn repl(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> Result<(), RemuirError> {
+    repl_in_sandbox(input, out, Sandbox::UNLIMITED, terminal)
+}
+
+/// The same as [`repl`], except that each command keeps to the caps of the sandbox, as if it were
+/// one execution of its own. The step limit is how many instructions each command may execute, so
+/// a command can never hang the session.
+///
+/// # Errors
+///
+/// * [`RemuirError::IOError`] - returned when reading from `input` or writing to `out` fails.
+pub fn repl_in_sandbox(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    sandbox: Sandbox,
+    terminal: Terminal,
+) -> Result<(), RemuirError> {
+    writeln!(out, "{}", text_literals::welcome_repl())?;
+    let mut machine = Machine::default();
+    machine.set_sandbox(sandbox);
+    let mut mode = Mode::Repl;
+    let mut transcript = None;
+
+    loop {
+        writeln!(out, "\n{}", machine.display_nat_registers())?;
+        if terminal == Terminal::Interactive {
+            write!(out, "remuir> ")?;
+        }
+        out.flush()?;
+        let Some(line) = read_command(input, out, terminal)? else {
+            break;
+        };
+        crash::record_command(&line, &machine);
+        if transcript_command(&line, &mut transcript, out)? {
+            continue;
+        }
+        let limit = sandbox.steps.map(|steps| machine.get_steps().saturating_add(steps));
+        machine.set_step_limit(limit);
+        machine.start_execution();
+        let state = recorded(&line, &mut transcript, out, |mut out| {
+            command(&line, &mut machine, &mut mode, &mut out)
+        })?;
+        if let ReplState::Stop = state {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Run an interactive debug session on the given machine, reading commands from `input` until
+/// it's exhausted or the user quits, and writing everything to `out`. Prompts are only printed if
+/// the terminal is [`Terminal::Interactive`].
+///
+/// # Errors
+///
+/// * [`RemuirError::IOError`] - returned when reading from `input` or writing to `out` fails.
+pub fn debug(
+    machine: Machine,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> Result<(), RemuirError> {
+    debug_session(machine, None, input, out, terminal)
+}
+
+/// The same as [`debug`], except that the session is saved as it goes (see [`crate::autosave`]).
+/// If the last session of the program wasn't quit, the user is first asked whether to restore it.
+///
+/// # Errors
+///
+/// * [`RemuirError::IOError`] - returned when reading from `input` or writing to `out` fails.
+///   Failing to save the session is only a warning, after which it's no longer saved.
+pub fn debug_with_autosave(
+    machine: Machine,
+    autosave: Autosave,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> Result<(), RemuirError> {
+    debug_session(machine, Some(autosave), input, out, terminal)
+}
+
+fn debug_session(
+    machine: Machine,
+    mut autosave: Option<Autosave>,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> Result<(), RemuirError> {
+    writeln!(out, "{}", text_literals::welcome_debug())?;
+    let mut session = Session::new(machine);
+    let mut transcript = None;
+    if let Some(saved) = &mut autosave {
+        offer_restore(&mut session, saved, input, out, terminal)?;
+    }
+
+    loop {
+        if session.machines.len() > 1 {
+            writeln!(out, "\nMachine {}:", session.current + 1)?;
+        }
+        let (_, machine, _) = &session.machines[session.current];
+        writeln!(out, "\n{}", machine.display_nat_registers())?;
+        if machine.is_halted() {
+            writeln!(out, "Next line:\nNone (machine halted).")?;
+        }
+        else {
+            writeln!(out, "Next line:\n{}", machine.peek_next_line())?;
+        }
+        printers::print_prompt(out, terminal)?;
+        let Some(line) = read_command(input, out, terminal)? else {
+            break;
+        };
+        crash::record_command(&line, &session.machines[session.current].1);
+        // Transcripts aren't autosaved, so restoring a session doesn't overwrite one.
+        if transcript_command(&line, &mut transcript, out)? {
+            continue;
+        }
+        let state = recorded(&line, &mut transcript, out, |mut out| {
+            session.dispatch(&line, &mut out)
+        })?;
+        if let Some(saved) = &mut autosave {
+            let (_, machine, _) = &session.machines[session.current];
+            let result = match state {
+                ReplState::Stop => saved.discard(),
+                ReplState::KeepLooping => saved.record(&line, machine),
+            };
+            if let Err(e) = result {
+                writeln!(out, "Couldn't autosave the session, so it won't be saved again: {e}")?;
+                autosave = None;
+            }
+        }
+        if let ReplState::Stop = state {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Ask whether to restore the autosaved session of the program, if there's one, and if so replay
+/// its commands without showing their output.
+fn offer_restore(
+    session: &mut Session,
+    autosave: &mut Autosave,
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> Result<(), RemuirError> {
+    let Some(commands) = autosave.saved_commands() else { return Ok(()) };
+    write!(
+        out,
+        "The last session of this program ({} commands) wasn't quit. Restore it? [y/N] ",
+        commands.len(),
+    )?;
+    // A script reading whole lines would never see the question otherwise.
+    if terminal == Terminal::Plain {
+        writeln!(out)?;
+    }
+    out.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        writeln!(out, "Starting a new session.")?;
+        return Ok(());
+    }
+    for line in &commands {
+        session.dispatch(line, &mut io::sink())?;
+        let (_, machine, _) = &session.machines[session.current];
+        autosave.record(line, machine)?;
+    }
+    writeln!(out, "Restored the session.")?;
+    Ok(())
+}
+
+/// Start or stop recording the session, if `line` is a "transcript" command. Returns whether it
+/// was one.
+fn transcript_command(
+    line: &str,
+    transcript: &mut Option<Transcript>,
+    out: &mut impl Write,
+) -> Result<bool, RemuirError> {
+    let Ok(Some(command)) = Command::parse(line) else { return Ok(false) };
+    if command.keyword != "transcript" {
+        return Ok(false);
+    }
+    let usage = "Correct usage: transcript [start PATH | stop]";
+    match (command.args.first().map(|arg| arg.to_lowercase()).as_deref(), command.args.get(1)) {
+        (Some("start"), Some(path)) => match Transcript::create(path) {
+            Ok(started) => {
+                writeln!(out, "Recording the session to {path}.")?;
+                *transcript = Some(started);
+            },
+            Err(e) => writeln!(out, "Couldn't start a transcript in {path}: {e}")?,
+        },
+        (Some("stop"), None) => match transcript.take() {
+            Some(stopped) => {
+                writeln!(out, "Stopped recording to {}.", stopped.get_path().display())?;
+            },
+            None => writeln!(out, "There's no transcript to stop.")?,
+        },
+        _ => writeln!(out, "{usage}")?,
+    }
+    Ok(true)
+}
+
+/// Run a command with `run`, which writes its output to the given writer. If there's a
+/// transcript, the output is also recorded in it after `line`.
+fn recorded<T>(
+    line: &str,
+    transcript: &mut Option<Transcript>,
+    out: &mut impl Write,
+    run: impl FnOnce(&mut dyn Write) -> Result<T, RemuirError>,
+) -> Result<T, RemuirError> {
+    let Some(recording) = transcript else { return run(out) };
+    let mut output = Vec::new();
+    let result = run(&mut output);
+    out.write_all(&output)?;
+    if !line.is_empty() {
+        if let Err(e) = recording.record(line, &String::from_utf8_lossy(&output)) {
+            writeln!(out, "Couldn't write to the transcript, so it has been stopped: {e}")?;
+            *transcript = None;
+        }
+    }
+    result
+}
+
+/// Read one command from `input`, without surrounding whitespace. A command can go over more than
+/// one line (see [`MultiLine`]). Returns [`None`] if the session should end because `input` is
+/// exhausted.
+fn read_command(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> Result<Option<String>, RemuirError> {
+    let mut lines = MultiLine::default();
+    loop {
+        let mut line = String::new();
+        let bytes = input.read_line(&mut line)?;
+
+        // Handle EOF/Ctrl+D, running a command which was started first.
+        if bytes == 0 {
+            if let Some(command) = lines.finish() {
+                return Ok(Some(command));
+            }
+            printers::goodbye(out)?;
+            return Ok(None);
+        }
+        if let Some(command) = lines.push(&line) {
+            return Ok(Some(command));
+        }
+        printers::print_continuation_prompt(out, terminal)?;
+    }
+}
+
+/// The machines loaded in a debug session, each with a name and its own undo state. Commands other
+/// than "machine" act on the current one.
+struct Session {
+    machines: Vec<(String, Machine, Mode)>,
+    current: usize,
+}
+
+impl Session {
+    fn new(machine: Machine) -> Session {
+        let mut session = Session { machines: Vec::new(), current: 0 };
+        session.add(String::from("(initial program)"), machine);
+        session
+    }
+
+    fn add(&mut self, name: String, mut machine: Machine) {
+        machine.set_history_limit(Some(HISTORY_LIMIT));
+        let mode = Mode::Debug {
+            previous_line: None,
+            previous_memory: None,
+            previous_call_stack: Vec::new(),
+        };
+        self.machines.push((name, machine, mode));
+    }
+
+    /// Get the index of the machine numbered by the given argument, counting from 1.
+    fn index(&self, arg: Option<&String>) -> Option<usize> {
+        let number = arg?.parse::<usize>().ok()?;
+        (1..=self.machines.len()).contains(&number).then(|| number - 1)
+    }
+
+    /// Run a command on the session. "machine" commands act on the whole session, and every
+    /// other command on the current machine.
+    fn dispatch(&mut self, line: &str, out: &mut impl Write) -> Result<ReplState, RemuirError> {
+        match Command::parse(line) {
+            Ok(Some(parsed)) if parsed.keyword == "machine" => {
+                self.command(&parsed, out)?;
+                Ok(ReplState::KeepLooping)
+            },
+            _ => {
+                let (_, machine, mode) = &mut self.machines[self.current];
+                command(line, machine, mode, out)
+            },
+        }
+    }
+
+    fn command(&mut self, command: &Command, out: &mut impl Write) -> Result<(), RemuirError> {
+        let usage = "Correct usage: machine [list | add PATH | switch NUMBER | lockstep NUMBER NUMBER [STEPS]]";
+        match command.args.first().map(|arg| arg.to_lowercase()).as_deref() {
+            None | Some("list") => {
+                for (i, (name, machine, _)) in self.machines.iter().enumerate() {
+                    let marker = if i == self.current { "=>" } else { "  " };
+                    let state = if machine.is_halted() { "halted" } else { "running" };
+                    writeln!(out, "{marker} {}  {name} ({state})", i + 1)?;
+                }
+            },
+            Some("add") => {
+                let Some(path) = command.args.get(1) else {
+                    writeln!(out, "{usage}")?;
+                    return Ok(());
+                };
+                let machine = match std::fs::read_to_string(path) {
+                    Ok(source) => parser::parse_str(&source),
+                    Err(e) => {
+                        writeln!(out, "Error opening and reading file! {e}")?;
+                        return Ok(());
+                    },
+                };
+                match machine {
+                    Ok(machine) => {
+                        self.add(path.clone(), machine);
+                        let number = self.machines.len();
+                        writeln!(
+                            out,
+                            "Added machine {number}. Use \"machine switch {number}\" to debug it.",
+                        )?;
+                    },
+                    Err(e) => writeln!(out, "{}", RemuirError::from(e))?,
+                }
+            },
+            Some("switch") => {
+                let Some(index) = self.index(command.args.get(1)) else {
+                    writeln!(out, "There's no machine with that number. {usage}")?;
+                    return Ok(());
+                };
+                self.current = index;
+                writeln!(out, "Switched to machine {}.", index + 1)?;
+            },
+            Some("lockstep") => {
+                let (Some(left), Some(right)) =
+                    (self.index(command.args.get(1)), self.index(command.args.get(2)))
+                else {
+                    writeln!(out, "There's no machine with that number. {usage}")?;
+                    return Ok(());
+                };
+                if left == right {
+                    writeln!(out, "Please choose two different machines.")?;
+                    return Ok(());
+                }
+                let max_steps = match command.args.get(3).map(|arg| arg.parse::<usize>()) {
+                    None => LOCKSTEP_LIMIT,
+                    Some(Ok(steps)) => steps,
+                    Some(Err(_)) => {
+                        writeln!(out, "{usage}")?;
+                        return Ok(());
+                    },
+                };
+                let (low, high) = (left.min(right), left.max(right));
+                let (before, after) = self.machines.split_at_mut(high);
+                let (low_machine, high_machine) = (&mut before[low].1, &mut after[0].1);
+                let result = if left < right {
+                    low_machine.lockstep(high_machine, max_steps)
+                }
+                else {
+                    high_machine.lockstep(low_machine, max_steps)
+                };
+                // The machines have moved on, so the last step can no longer be undone.
+                for index in [left, right] {
+                    self.machines[index].2 = Mode::Debug {
+                        previous_line: None,
+                        previous_memory: None,
+                        previous_call_stack: Vec::new(),
+                    };
+                }
+                writeln!(out, "Left is machine {}, right is machine {}.", left + 1, right + 1)?;
+                write!(out, "{result}")?;
+            },
+            Some(_) => writeln!(out, "{usage}")?,
+        }
+        Ok(())
+    }
+}
+
 #[allow(clippy::too_many_lines)]
-pub fn command(input: &str, machine: &mut Machine, mode: &mut Mode) -> Result<ReplState, RemuirError> {
-    // Exact matches.
-    match input {
+pub fn command(
+    input: &str,
+    machine: &mut Machine,
+    mode: &mut Mode,
+    out: &mut impl Write,
+) -> Result<ReplState, RemuirError> {
+    let input = command::strip_label(input);
+    let command = match Command::parse(input) {
+        Ok(Some(command)) => command,
+        // Ignore blank lines.
+        Ok(None) => return Ok(ReplState::KeepLooping),
+        Err(e) => {
+            writeln!(out, "{e}")?;
+            return Ok(ReplState::KeepLooping);
+        },
+    };
+    match command.keyword.as_str() {
         "exit" | "quit" | "q" => {
-            printers::goodbye()?;
+            printers::goodbye(out)?;
             return Ok(ReplState::Stop);
         },
         "help" | "h" => {
             match mode {
-                Mode::Repl => printers::help_repl()?,
-                Mode::Debug { .. } => printers::help_debug()?,
+                Mode::Repl => printers::help_repl(out)?,
+                Mode::Debug { .. } => printers::help_debug(out)?,
             }
-            return Ok(ReplState::KeepLooping);
         },
         "play" | "p" => {
             if !mode.is_debug() {
-                writeln!(io::stdout(), "\"play\" is not available in REPL mode.")?;
+                writeln!(out, "\"play\" is not available in REPL mode.")?;
                 return Ok(ReplState::KeepLooping);
             }
-            match machine.debug() {
-                Ok(TerminationReason::Breakpoint) => {
-                    writeln!(io::stdout(), "Reached breakpoint!")?;
-                    return Ok(ReplState::KeepLooping);
-                },
-                Ok(TerminationReason::Empty) => {
-                    writeln!(
-                        io::stdout(),
-                        "Program source code contains no lines of code. Cannot debug an empty program."
-                    )?;
-                    return Ok(ReplState::KeepLooping);
-                },
-                Ok(TerminationReason::Halted) => {
-                    writeln!(io::stdout(), "Machine successfully halted.")?;
-                    return Ok(ReplState::KeepLooping);
-                },
-                Err(RuntimeError::Halted) => {
-                    writeln!(io::stdout(), "Machine is already halted, so cannot step.")?;
-                    return Ok(ReplState::KeepLooping);
-                },
+            play(machine, out)?;
+        },
+        "run" => {
+            if !mode.is_debug() {
+                writeln!(out, "\"run\" is not available in REPL mode.")?;
+                return Ok(ReplState::KeepLooping);
             }
+            // Like "registers", but the new values are also used by later resets.
+            if !command.args.is_empty() {
+                match parser::parse_register_line(&format!("registers {}", command.rest())) {
+                    Ok(mem) => machine.set_initial_memory(mem),
+                    Err(parser::ParseSourceError::SyntaxError(_)) => {
+                        writeln!(out, "Correct usage: run [NUMBERS]")?;
+                        return Ok(ReplState::KeepLooping);
+                    },
+                    Err(e) => {
+                        writeln!(out, "{e}")?;
+                        return Ok(ReplState::KeepLooping);
+                    },
+                }
+            }
+            machine.reset();
+            writeln!(out, "Running with {}.", machine.get_initial_memory())?;
+            play(machine, out)?;
+        },
+        "registers" if command.args.is_empty() => {
+            writeln!(out, "{}", machine.display_nat_registers())?;
+        },
+        // In REPL mode, there's nothing to reset to, so "r" is short for "registers".
+        "r" if !mode.is_debug() => {
+            writeln!(out, "{}", machine.display_nat_registers())?;
         },
         "reset" | "r" => {
             machine.reset();
-            writeln!(io::stdout(), "Reset machine state!")?;
-            return Ok(ReplState::KeepLooping)
+            writeln!(out, "Reset machine state!")?;
         },
         "step" | "s" => {
             if !mode.is_debug() {
-                writeln!(io::stdout(), "\"step\" is not available in REPL mode.")?;
+                writeln!(out, "\"step\" is not available in REPL mode.")?;
                 return Ok(ReplState::KeepLooping);
             }
-            mode.set_previous(machine.get_current_line_number(), machine.get_state().clone());
+            mode.set_previous(
+                machine.get_current_line_number(),
+                machine.get_state().clone(),
+                machine.get_call_stack(),
+            );
             match machine.step() {
                 Err(RuntimeError::Halted) => {
-                    writeln!(io::stdout(), "Machine is already halted, so cannot step.")?;
-                    return Ok(ReplState::KeepLooping)
+                    printers::post_mortem(out)?;
+                },
+                Ok(Some(TerminationReason::Halted)) => {
+                    writeln!(out, "Machine successfully halted.")?;
+                },
+                Ok(Some(TerminationReason::ProtectedRegister(reg_num))) => {
+                    printers::protected_register(out, reg_num)?;
+                },
+                Ok(Some(TerminationReason::InvalidJump(line))) => {
+                    printers::invalid_jump(out, line)?;
                 },
-                Ok(Some(TerminationReason::Halted)) => writeln!(io::stdout(), "Machine successfully halted.")?,
+                Ok(Some(TerminationReason::Watchpoint { register, old, new })) => {
+                    printers::watchpoint(out, register, &old, &new)?;
+                },
+                Ok(Some(TerminationReason::ResourceLimit(limit))) => {
+                    printers::resource_limit(out, &limit)?;
+                },
+                Ok(Some(TerminationReason::Io(e))) => printers::io_error(out, &e)?,
                 Ok(None) => (),
                 _ => unreachable!(),
-            };
-            return Ok(ReplState::KeepLooping);
+            }
         },
         "undo" | "u" => {
             if !mode.is_debug() {
-                writeln!(io::stdout(), "\"undo\" is not available in REPL mode.")?;
+                writeln!(out, "\"undo\" is not available in REPL mode.")?;
                 return Ok(ReplState::KeepLooping);
             }
-            let (previous_line, previous_memory) = match mode.get_previous() {
-                Ok((a, b)) => (a, b),
+            let (previous_line, previous_memory, previous_call_stack) = match mode.get_previous() {
+                Ok(previous) => previous,
                 Err(e) => {
-                    writeln!(io::stdout(), "{e}")?;
+                    writeln!(out, "{e}")?;
                     return Ok(ReplState::KeepLooping);
                 },
             };
@@ -229,110 +844,308 @@ pub fn command(input: &str, machine: &mut Machine, mode: &mut Mode) -> Result<Re
                 .go_to_identifier(&Identifier::Line(previous_line))
                 .expect("Line number must be correct.");
             machine.replace_memory(previous_memory);
-            writeln!(io::stdout(), "Undid step.")?;
-            return Ok(ReplState::KeepLooping);
+            machine.replace_call_stack(previous_call_stack);
+            writeln!(out, "Undid step.")?;
         },
-        _ => (),
-    }
-
-    // Try to parse a memory init line.
-    if let Ok(mem) = parser::parse_register_line(input) {
-        machine.replace_memory(mem);
-        writeln!(io::stdout(), 
-            "Registers successfully changed!"
-        )?;
-        return Ok(ReplState::KeepLooping);
-    }
-    // Match the start of the input to find the right command.
-    let mut input_split = input.split(' ');
-    match input_split.next() {
-        Some("inc") => {
-            match parser::parse_inc(input) {
-                Ok(Instruction::INC(reg_num)) => {
-                    let _ = machine.execute(Instruction::INC(reg_num));
-                    writeln!(io::stdout(), "Register {reg_num} is now {}.", machine.display_register(reg_num))?;
+        "registers" => {
+            match parser::parse_register_line(&command.normalised()) {
+                Ok(mem) => {
+                    machine.replace_memory(mem);
+                    writeln!(out, "Registers successfully changed!")?;
                 },
-                Err(parser::ParseSourceError::SyntaxError(b)) => {
-                    writeln!(io::stdout(), "Syntax error:\n{b}")?;
-                    writeln!(io::stdout(), "Correct usage: inc r[NUMBER]")?;
+                Err(parser::ParseSourceError::SyntaxError(_)) => {
+                    writeln!(out, "Correct usage: registers [NUMBERS]")?;
                 },
-                _ => unreachable!(),
+                Err(e) => writeln!(out, "{e}")?,
             }
         },
-        Some("decjz") => {
-            match parser::parse_decjz(input) {
-                Ok(Instruction::DECJZ(reg_num, label)) => {
-                    if machine.execute(Instruction::DECJZ(reg_num, label)).is_some() {
-                        writeln!(io::stdout(), "Register was already 0. Not jumping due to being in {mode} mode.")?;
-                    } else {
-                        writeln!(io::stdout(), "Register {reg_num} is now {}.", machine.display_register(reg_num))?;
-                    }
+        "inc" | "dec" | "decjz" | "decjnz" => {
+            execute_instruction(&command, machine, mode, out)?;
+        },
+        "breakpoint" | "break" | "b" => {
+            if !mode.is_debug() {
+                writeln!(out, "\"breakpoint\" is not available in REPL mode.")?;
+                return Ok(ReplState::KeepLooping);
+            }
+            // "break [LABEL] hit [N]" only stops on the Nth execution of the line.
+            let mut args = &command.args[..];
+            let mut hit = None;
+            if let [rest @ .., keyword, n] = args {
+                if keyword.eq_ignore_ascii_case("hit") && !rest.is_empty() {
+                    let Ok(n @ 1..) = n.parse::<usize>() else {
+                        writeln!(out, "Correct usage: breakpoint [LABEL] hit [N], where N is at least 1.")?;
+                        return Ok(ReplState::KeepLooping);
+                    };
+                    args = rest;
+                    hit = Some(n);
+                }
+            }
+            let Some(ident) = get_ident(args, out)? else { return Ok(ReplState::KeepLooping) };
+            if let Some(n) = hit {
+                match machine.set_breakpoint_on_hit(&ident, n) {
+                    Ok(()) => writeln!(out, "Added breakpoint which stops on hit {n}.")?,
+                    Err(e) => writeln!(out, "{e}")?,
+                }
+                return Ok(ReplState::KeepLooping);
+            }
+            match machine.toggle_breakpoint(&ident) {
+                Ok(BreakpointToggle::Added) => {
+                    writeln!(out, "Added breakpoint.")?;
                 },
-                Err(parser::ParseSourceError::SyntaxError(b)) => {
-                    writeln!(io::stdout(), "Syntax error:\n{b}")?;
-                    writeln!(io::stdout(), "Correct usage: decjz r[NUMBER] [LABEL]")?;
+                Ok(BreakpointToggle::Removed) => {
+                    writeln!(out, "Removed breakpoint.")?;
+                },
+                Err(e) => {
+                    writeln!(out, "{e}")?;
                 },
-                _ => unreachable!(),
             }
         },
-        Some("dec") => {
-            match parser::parse_dec(input) {
-                Ok(Instruction::DECJZ(reg_num, label)) => {
-                    let _ = machine.execute(Instruction::DECJZ(reg_num, label));
-                    writeln!(io::stdout(), "Register {reg_num} is now {}.", machine.display_register(reg_num))?;
+        "protect" => {
+            if !mode.is_debug() {
+                writeln!(out, "\"protect\" is not available in REPL mode.")?;
+                return Ok(ReplState::KeepLooping);
+            }
+            let Some(Ok(reg_num)) = command.args.first().map(|arg| arg.parse::<RegisterNumber>())
+            else {
+                writeln!(out, "Correct usage: protect r[NUMBER]")?;
+                return Ok(ReplState::KeepLooping);
+            };
+            match machine.toggle_protection(reg_num) {
+                ProtectionToggle::Protected => {
+                    writeln!(out, "Register {reg_num} is now protected.")?;
                 },
-                Err(parser::ParseSourceError::SyntaxError(b)) => {
-                    writeln!(io::stdout(), "Syntax error:\n{b}")?;
-                    writeln!(io::stdout(), "Correct usage: dec r[NUMBER]")?;
+                ProtectionToggle::Unprotected => {
+                    writeln!(out, "Register {reg_num} is no longer protected.")?;
                 },
-                _ => unreachable!(),
             }
         },
-        Some("breakpoint" | "break" | "b") => {
+        "watchpoint" | "watch" => {
             if !mode.is_debug() {
-                writeln!(io::stdout(), "\"step\" is not available in REPL mode.")?;
+                writeln!(out, "\"watchpoint\" is not available in REPL mode.")?;
                 return Ok(ReplState::KeepLooping);
             }
-            let Some(ident) = get_ident(input_split)? else { return Ok(ReplState::KeepLooping) };
-            match machine.toggle_breakpoint(&ident) {
-                Ok(BreakpointToggle::Added) => {
-                    writeln!(io::stdout(), "Added breakpoint.")?;
-                },
-                Ok(BreakpointToggle::Removed) => {
-                    writeln!(io::stdout(), "Removed breakpoint.")?;
+            let Some(Ok(reg_num)) = command.args.first().map(|arg| arg.parse::<RegisterNumber>())
+            else {
+                writeln!(out, "Correct usage: watchpoint r[NUMBER]")?;
+                return Ok(ReplState::KeepLooping);
+            };
+            match machine.toggle_watchpoint(reg_num) {
+                BreakpointToggle::Added => writeln!(out, "Watchpoint added to {reg_num}.")?,
+                BreakpointToggle::Removed => writeln!(out, "Watchpoint removed from {reg_num}.")?,
+            }
+        },
+        "machine" => {
+            if !mode.is_debug() {
+                writeln!(out, "\"machine\" is not available in REPL mode.")?;
+                return Ok(ReplState::KeepLooping);
+            }
+            writeln!(out, "\"machine\" is only available when debugging with --debug.")?;
+        },
+        "list" | "l" => {
+            if !mode.is_debug() {
+                writeln!(out, "\"list\" is not available in REPL mode.")?;
+                return Ok(ReplState::KeepLooping);
+            }
+            write!(out, "{}", Listing::new(machine))?;
+        },
+        "phases" => {
+            if !mode.is_debug() {
+                writeln!(out, "\"phases\" is not available in REPL mode.")?;
+                return Ok(ReplState::KeepLooping);
+            }
+            if machine.get_phases().is_empty() {
+                writeln!(out, "No phases. Mark a label as a phase with \"#!phase [LABEL]\".")?;
+            }
+            else {
+                phase_report(out, machine)?;
+            }
+        },
+        "history" => {
+            if !mode.is_debug() {
+                writeln!(out, "\"history\" is not available in REPL mode.")?;
+                return Ok(ReplState::KeepLooping);
+            }
+            let count = match command.args.first().map(|n| n.parse::<usize>()) {
+                None => 10,
+                Some(Ok(n)) => n,
+                Some(Err(_)) => {
+                    writeln!(out, "Correct usage: history [NUMBER]")?;
+                    return Ok(ReplState::KeepLooping);
                 },
-                Err(e) => {
-                    writeln!(io::stdout(), "{e}")?;
+            };
+            print_history(count, machine, out)?;
+        },
+        "backtrace" | "bt" => {
+            if !mode.is_debug() {
+                writeln!(out, "\"backtrace\" is not available in REPL mode.")?;
+                return Ok(ReplState::KeepLooping);
+            }
+            print_backtrace(machine, out)?;
+        },
+        "xref" | "x" => {
+            print_xref(&command.rest(), machine, out)?;
+        },
+        "info" => {
+            if !mode.is_debug() {
+                writeln!(out, "\"info\" is not available in REPL mode.")?;
+                return Ok(ReplState::KeepLooping);
+            }
+            match command.args.split_first() {
+                Some((kind, label)) if kind.eq_ignore_ascii_case("label") && !label.is_empty() => {
+                    print_label_info(&label.join(" "), machine, out)?;
                 },
+                _ => writeln!(out, "Correct usage: info label [LABEL]")?,
+            }
+        },
+        "save-as" => {
+            let [name] = &command.args[..] else {
+                writeln!(out, "Correct usage: save-as [NAME]")?;
+                return Ok(ReplState::KeepLooping);
             };
+            machine.save_snapshot(name.clone());
+            writeln!(out, "Saved the registers as {name:?}.")?;
         },
-        _ => {
+        "restore" => {
+            let [name] = &command.args[..] else {
+                writeln!(out, "Correct usage: restore [NAME]")?;
+                return Ok(ReplState::KeepLooping);
+            };
+            match machine.restore_snapshot(name) {
+                Ok(()) => writeln!(out, "Restored the registers saved as {name:?}.")?,
+                Err(e) => writeln!(out, "{e}")?,
+            }
+        },
+        "diff" => {
+            print_snapshot_diff(&command.args, machine, out)?;
+        },
+        keyword => {
             writeln!(
-                io::stdout(),
+                out,
                 "Unknown command \"{input}\". Type \"help\" for a list of commands.",
             )?;
-            if input.starts_with("register ") {
-                writeln!(io::stdout(), "Note: \"register\" is close to \"registers\".")?;
+            if let Some(suggestion) = suggest::closest(keyword, COMMANDS) {
+                writeln!(out, "Note: \"{keyword}\" is close to \"{suggestion}\".")?;
             }
         }
     }
     Ok(ReplState::KeepLooping)
 }
 
-fn get_ident<'a>(mut iter: impl Iterator<Item = &'a str>) -> Result<Option<Identifier>, RemuirError> {
-    let Some(next) = iter.next() else {
+/// Execute an instruction typed into the REPL, without jumping.
+fn execute_instruction(
+    command: &Command,
+    machine: &mut Machine,
+    mode: &Mode,
+    out: &mut impl Write,
+) -> Result<(), RemuirError> {
+    let parsed = match parser::parse_any_instruction(&command.normalised()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            writeln!(out, "{e}")?;
+            let usage = match command.keyword.as_str() {
+                "decjz" | "decjnz" => format!("{} r[NUMBER] [LABEL]", command.keyword),
+                keyword => format!("{keyword} r[NUMBER]"),
+            };
+            writeln!(out, "Correct usage: {usage}")?;
+            return Ok(());
+        },
+    };
+    match parsed.instruction {
+        // A dec is a DECJZ to HALT, which keeps to the sandbox like any other step, but taking the
+        // jump means the register was 0 and couldn't be decremented.
+        instruction @ Instruction::DECJZ(reg_num, _) if parsed.is_dec => {
+            match machine.execute_step(instruction) {
+                Err(e) => writeln!(out, "{e}")?,
+                Ok(Some(_)) => writeln!(out, "{}", DecrementZeroError(reg_num))?,
+                Ok(None) => {
+                    let value = machine.display_register(reg_num);
+                    writeln!(out, "Register {reg_num} is now {value}.")?;
+                },
+            }
+        },
+        Instruction::INC(reg_num) => match machine.execute_step(Instruction::INC(reg_num)) {
+            Err(e) => writeln!(out, "{e}")?,
+            Ok(_) => {
+                writeln!(out, "Register {reg_num} is now {}.", machine.display_register(reg_num))?;
+            },
+        },
+        instruction @ Instruction::DECJZ(reg_num, _) => match machine.execute_step(instruction) {
+            Err(e) => writeln!(out, "{e}")?,
+            Ok(Some(_)) => {
+                writeln!(out, "Register was already 0. Not jumping due to being in {mode} mode.")?;
+            },
+            Ok(None) => {
+                writeln!(out, "Register {reg_num} is now {}.", machine.display_register(reg_num))?;
+            },
+        },
+        instruction @ Instruction::DECJNZ(reg_num, _) => match machine.execute_step(instruction) {
+            Err(e) => writeln!(out, "{e}")?,
+            Ok(Some(_)) => {
+                let value = machine.display_register(reg_num);
+                writeln!(out, "Register {reg_num} is now {value}. Not jumping due to being in {mode} mode.")?;
+            },
+            Ok(None) => writeln!(out, "Register was already 0.")?,
+        },
+        Instruction::GOTO(_) | Instruction::CALL(_) | Instruction::RETURN | Instruction::HALT => {
+            unreachable!("Only instructions with a register are executed.")
+        },
+        Instruction::READ(_) | Instruction::WRITE(_) => {
+            unreachable!("Only instructions which don't read or write are executed.")
+        },
+    }
+    Ok(())
+}
+
+/// Print the registers which differ between two snapshots, or a snapshot and the current
+/// registers if only one is named.
+fn print_snapshot_diff(
+    args: &[String],
+    machine: &Machine,
+    out: &mut impl Write,
+) -> Result<(), RemuirError> {
+    let (before, after) = match args {
+        [before] => (before, None),
+        [before, after] => (before, Some(after)),
+        _ => {
+            writeln!(out, "Correct usage: diff [NAME] [NAME], or diff [NAME] to compare with now")?;
+            return Ok(());
+        },
+    };
+    let memories = machine.get_snapshot(before).and_then(|before| {
+        let after = after.map_or(Ok(machine.get_state()), |after| machine.get_snapshot(after))?;
+        Ok((before, after))
+    });
+    let (before, after) = match memories {
+        Ok(memories) => memories,
+        Err(e) => {
+            writeln!(out, "{e}")?;
+            return Ok(());
+        },
+    };
+    let registers = before.differing_registers(after);
+    if registers.is_empty() {
+        writeln!(out, "No differences.")?;
+    }
+    for (register, before, after) in registers {
+        writeln!(out, "{register}: {before} -> {after}")?;
+    }
+    Ok(())
+}
+
+fn get_ident(args: &[String], out: &mut impl Write) -> Result<Option<Identifier>, RemuirError> {
+    let Some(next) = args.first() else {
         writeln!(
-            io::stdout(),
+            out,
             "Please provide a label or line number to attach a breakpoint to.",
         )?;
         return Ok(None)
     };
     let ident: Identifier;
     // Check if a line number is specified.
-    if next.chars().all(|c| c.is_ascii_digit()) {
+    if !next.is_empty() && next.chars().all(|c| c.is_ascii_digit()) {
         let Ok(num) = next.parse::<usize>() else {
             writeln!(
-                io::stdout(),
+                out,
                 "Line number too large to attach breakpoint. Must be <={}.",
                 usize::MAX,
             )?;
@@ -341,17 +1154,333 @@ fn get_ident<'a>(mut iter: impl Iterator<Item = &'a str>) -> Result<Option<Ident
         ident = Identifier::Line(num);
     }
     else if next.to_lowercase().as_str() == "halt" {
-        writeln!(io::stdout(), "Cannot use HALT as a breakpoint label.")?;
+        writeln!(out, "Cannot use HALT as a breakpoint label.")?;
         return Ok(None);
     }
-    // Reconstruct label since it can include spaces.
+    // Reconstruct label since it can include spaces. It may be followed by a number of lines, as
+    // in "loop+2".
     else {
-        let mut label = String::from(next);
-        for s in iter {
-            label.push(' ');
-            label.push_str(s);
-        }
-        ident = Identifier::Label(label);
+        let Ok(parsed) = args.join(" ").parse();
+        ident = parsed;
     }
     Ok(Some(ident))
 }
+
+/// Run the machine until it reaches a breakpoint or stops, and print why it stopped.
+fn play(machine: &mut Machine, out: &mut impl Write) -> Result<(), RemuirError> {
+    // Ctrl+C pressed at the prompt isn't meant for this run.
+    interrupt::clear();
+    match machine.debug() {
+        Ok(TerminationReason::Breakpoint) => {
+            writeln!(out, "Reached breakpoint!")?;
+        },
+        Ok(TerminationReason::Empty) => {
+            writeln!(
+                out,
+                "Program source code contains no lines of code. Cannot debug an empty program."
+            )?;
+        },
+        Ok(TerminationReason::Halted) => {
+            writeln!(out, "Machine successfully halted.")?;
+        },
+        Ok(TerminationReason::ProtectedRegister(reg_num)) => {
+            printers::protected_register(out, reg_num)?;
+        },
+        Ok(TerminationReason::InvalidJump(line)) => {
+            printers::invalid_jump(out, line)?;
+        },
+        Ok(TerminationReason::Watchpoint { register, old, new }) => {
+            printers::watchpoint(out, register, &old, &new)?;
+        },
+        Ok(TerminationReason::Interrupted) => {
+            writeln!(
+                out,
+                "Interrupted after {} steps. Type \"play\" to carry on.",
+                machine.get_steps(),
+            )?;
+        },
+        Ok(TerminationReason::StepLimitReached) => {
+            write!(out, "Reached the step limit. {}", RunawaySummary::new(machine))?;
+        },
+        Ok(TerminationReason::ResourceLimit(limit)) => {
+            printers::resource_limit(out, &limit)?;
+        },
+        Ok(TerminationReason::Io(e)) => printers::io_error(out, &e)?,
+        Err(RuntimeError::Halted) => {
+            printers::post_mortem(out)?;
+        },
+        Err(e) => {
+            writeln!(out, "{e}")?;
+        },
+    }
+    Ok(())
+}
+
+/// Print the last `count` executed lines, oldest first.
+fn print_history(count: usize, machine: &Machine, out: &mut impl Write) -> Result<(), RemuirError> {
+    let history = machine.get_history();
+    if history.is_empty() {
+        writeln!(out, "No lines have been executed yet.")?;
+        return Ok(());
+    }
+    let skipped = history.len().saturating_sub(count);
+    // Number each line by the step it was executed in, counting from 1.
+    let first_step = machine.get_steps() - history.len() + skipped + 1;
+    for (step, &n) in (first_step..).zip(history.iter().skip(skipped)) {
+        writeln!(out, "step {step}: {}", machine.get_lines()[n])?;
+    }
+    Ok(())
+}
+
+/// Print the subroutines which have been called but haven't returned yet, innermost first, and
+/// the jumps which led to the current line, most recent first.
+fn print_backtrace(machine: &Machine, out: &mut impl Write) -> Result<(), RemuirError> {
+    let call_stack = machine.get_call_stack();
+    if !call_stack.is_empty() {
+        writeln!(out, "Call stack, innermost first:")?;
+        for (i, &returns_to) in call_stack.iter().rev().enumerate() {
+            let call = returns_to - 1;
+            let Some(called) = machine.get_lines()[call].get_instruction().get_jump_target() else {
+                continue;
+            };
+            writeln!(out, "#{i} {called}, called from {}", machine.describe_line(call))?;
+        }
+        writeln!(out, "Jumps:")?;
+    }
+    let backtrace = machine.get_backtrace();
+    if backtrace.is_empty() {
+        writeln!(out, "No jumps have been taken yet.")?;
+    }
+    for (i, &(from, to)) in backtrace.iter().enumerate() {
+        writeln!(out, "#{i} {} <- {}", machine.describe_line(to), machine.describe_line(from))?;
+    }
+    Ok(())
+}
+
+/// Run the machine at the given number of steps per second, redrawing a single status line with
+/// the step count, the next line and the natural registers after every step.
+///
+/// The status line is redrawn by returning to the start of the line, so `out` should be a
+/// terminal. A newline is written once the machine stops. If the terminal is
+/// [`Terminal::Plain`], each status is written on a line of its own instead.
+pub fn live(
+    machine: &mut Machine,
+    speed: NonZeroU32,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> io::Result<()> {
+    let delay = Duration::from_secs(1) / speed.get();
+    write_live_status(machine, out, terminal)?;
+    out.flush()?;
+    while !machine.is_halted() {
+        std::thread::sleep(delay);
+        if let Err(_)
+        | Ok(Some(
+            TerminationReason::ProtectedRegister(_)
+            | TerminationReason::InvalidJump(_)
+            | TerminationReason::ResourceLimit(_)
+            | TerminationReason::Io(_),
+        )) = machine.step()
+        {
+            break;
+        }
+        write_live_status(machine, out, terminal)?;
+        out.flush()?;
+    }
+    if terminal == Terminal::Plain { Ok(()) } else { writeln!(out) }
+}
+
+fn write_live_status(
+    machine: &Machine,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> io::Result<()> {
+    write_status_start(out, terminal)?;
+    write!(out, "step {} | ", machine.get_steps())?;
+    if machine.is_halted() {
+        write!(out, "HALT")?;
+    }
+    else {
+        write!(out, "line {}", machine.peek_next_line())?;
+    }
+    write!(out, " | {}", machine.display_nat_registers())?;
+    write_status_end(out, terminal)
+}
+
+/// Start a status line, clearing the whole line first in case the last status line was longer.
+fn write_status_start(out: &mut impl Write, terminal: Terminal) -> io::Result<()> {
+    if terminal == Terminal::Plain { Ok(()) } else { write!(out, "\r\x1b[2K") }
+}
+
+/// End a status line, which is only left open when the next one is drawn over it.
+fn write_status_end(out: &mut impl Write, terminal: Terminal) -> io::Result<()> {
+    if terminal == Terminal::Plain { writeln!(out) } else { Ok(()) }
+}
+
+/// How many steps [`progress`] takes between checking whether to redraw.
+const PROGRESS_CHUNK: usize = 1 << 16;
+
+/// Run the machine until it stops, redrawing a single status line with the steps taken, the
+/// steps per second and, if the machine has a step limit, roughly how long until it's reached.
+///
+/// The status line is redrawn at most once every `interval`, so it costs next to nothing on long
+/// runs, and is never drawn at all for runs shorter than `interval`. Like [`live`], `out` should be
+/// a terminal. The status line is cleared once the machine stops. If the terminal is plain, each
+/// status is written on a line of its own, and left there.
+pub fn progress(
+    machine: &mut Machine,
+    interval: Duration,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> io::Result<()> {
+    let start = Instant::now();
+    let start_steps = machine.get_steps();
+    let mut last_drawn = start;
+    let mut drawn = false;
+    while machine.run_steps(PROGRESS_CHUNK) == PROGRESS_CHUNK {
+        if last_drawn.elapsed() < interval {
+            continue;
+        }
+        last_drawn = Instant::now();
+        let steps = machine.get_steps() - start_steps;
+        write_progress(machine, steps, start.elapsed(), out, terminal)?;
+        out.flush()?;
+        drawn = true;
+    }
+    if drawn && terminal == Terminal::Interactive {
+        write!(out, "\r\x1b[2K")?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+fn write_progress(
+    machine: &Machine,
+    steps: usize,
+    elapsed: Duration,
+    out: &mut impl Write,
+    terminal: Terminal,
+) -> io::Result<()> {
+    #[allow(clippy::cast_precision_loss)]
+    let rate = steps as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    write_status_start(out, terminal)?;
+    write!(out, "step {} | {rate:.0} steps/s", machine.get_steps())?;
+    if let Some(limit) = machine.get_step_limit() {
+        #[allow(clippy::cast_precision_loss)]
+        let remaining = limit.saturating_sub(machine.get_steps()) as f64 / rate.max(1.0);
+        write!(out, " | {} until the limit of {limit}", format_duration(remaining))?;
+    }
+    write_status_end(out, terminal)
+}
+
+/// Format a number of seconds like `1:02:03`, or `2:03` if it's under an hour.
+fn format_duration(seconds: f64) -> String {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let seconds = seconds.ceil() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    }
+    else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+/// Write a table of the steps spent in each phase of the machine, one phase per line. If the
+/// machine has a cost model, a table of the cost of each phase follows.
+pub fn phase_report(out: &mut impl Write, machine: &Machine) -> io::Result<()> {
+    let name = |phase: &Option<String>| phase.clone().unwrap_or_else(|| String::from("(start)"));
+    let width = machine
+        .get_phase_steps()
+        .iter()
+        .map(|(phase, _)| name(phase).chars().count())
+        .max()
+        .unwrap_or(0);
+    writeln!(out, "Steps per phase:")?;
+    for (phase, steps) in machine.get_phase_steps() {
+        writeln!(out, "{:width$}  {steps}", name(phase))?;
+    }
+    writeln!(out, "{:width$}  {}", "total", machine.get_steps())?;
+    if let Some(model) = machine.get_cost_model() {
+        let total = machine.get_cost();
+        writeln!(out, "Cost per phase ({model}):")?;
+        for (phase, cost) in machine.get_phase_costs() {
+            let percent = if total == 0 { 0.0 } else { 100.0 * *cost as f64 / total as f64 };
+            writeln!(out, "{:width$}  {cost} ({percent:.1}%)", name(phase))?;
+        }
+        writeln!(out, "{:width$}  {total}", "total")?;
+    }
+    Ok(())
+}
+
+/// Write a table of how the `count` hottest registers of the machine were used, hottest first.
+pub fn heat_report(out: &mut impl Write, machine: &Machine, count: usize) -> io::Result<()> {
+    let heat = machine.get_register_heat();
+    writeln!(out, "Hottest registers:")?;
+    writeln!(out, "register       reads  increments  decrements")?;
+    for (register, heat) in heat.iter().take(count) {
+        writeln!(
+            out,
+            "{:8}  {:>10}  {:>10}  {:>10}",
+            register.to_string(),
+            heat.reads,
+            heat.increments,
+            heat.decrements,
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the number of steps the machine has taken and their total cost under the given model.
+pub fn cost_report(out: &mut impl Write, machine: &Machine, model: &CostModel) -> io::Result<()> {
+    writeln!(out, "Steps: {}", machine.get_steps())?;
+    writeln!(out, "Cost: {} ({model})", machine.get_cost())
+}
+
+/// Print the line a label is on and its documentation.
+fn print_label_info(
+    label: &str,
+    machine: &Machine,
+    out: &mut impl Write,
+) -> Result<(), RemuirError> {
+    let Some(line) = machine.get_label_line(label) else {
+        let suggestion = machine.closest_label(label);
+        let error = MachineEditError::LabelNotFound { label: label.to_string(), suggestion };
+        writeln!(out, "{error}")?;
+        return Ok(());
+    };
+    writeln!(out, "{}", machine.get_lines()[line])?;
+    match machine.get_label_documentation(label) {
+        Some(documentation) => writeln!(out, "{documentation}")?,
+        None => writeln!(out, "{label:?} isn't documented. Add \"##\" comments above it.")?,
+    }
+    Ok(())
+}
+
+/// Print every line which jumps to the given label, or every line which uses the given register.
+fn print_xref(target: &str, machine: &Machine, out: &mut impl Write) -> Result<(), RemuirError> {
+    if target.is_empty() {
+        writeln!(out, "Please provide a label or register to cross-reference.")?;
+        return Ok(());
+    }
+    let xref = machine.get_cross_reference();
+    let line_numbers = if let Ok(reg_num) = target.parse::<RegisterNumber>() {
+        writeln!(out, "Lines which use {reg_num}:")?;
+        xref.uses_register(reg_num)
+    }
+    else {
+        let ident = match target.to_lowercase().as_str() {
+            "halt" => Identifier::Halt,
+            _ => Identifier::Label(target.to_string()),
+        };
+        writeln!(out, "Lines which jump to {ident}:")?;
+        xref.jumps_to(&ident)
+    };
+    if line_numbers.is_empty() {
+        writeln!(out, "None.")?;
+    }
+    for &n in line_numbers {
+        writeln!(out, "{}", machine.get_lines()[n])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,252 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! The Unlimited Register Machine (URM) of Cutland's *Computability*, as a [`Dialect`].
+//!
+//! A URM program is a numbered list of instructions on the registers R1, R2, ...:
+//!
+//! * `Z(n)` - set Rn to 0.
+//! * `S(n)` - add 1 to Rn.
+//! * `T(m, n)` - copy Rm into Rn.
+//! * `J(m, n, q)` - jump to instruction q if Rm and Rn are equal, or go on to the next
+//!   instruction if they aren't.
+//!
+//! The program halts once it goes past its last instruction, including by jumping to an
+//! instruction which doesn't exist. Instructions may be numbered, as in `1 Z(2)` or `I1: Z(2)`,
+//! and `#` starts a comment. A first line such as `registers 0 3 4`, as in remuir, sets the
+//! registers, which are otherwise 0.
+//!
+//! Rn becomes the register rn, and instruction i starts at the label `Ii`, so a program can be
+//! debugged with the textbook's numbering. T and J, which remuir has no single instruction for,
+//! count in r-1, and leave it at 0.
+//!
+//! ```
+//! use remuir::{ parser::parse_str_dialect, urm::Urm };
+//!
+//! // Add R2 to R1, counting up to it in R3.
+//! let source = "registers 0 4 3\n1 J(2, 3, 5)\n2 S(1)\n3 S(3)\n4 J(1, 1, 1)";
+//! let mut machine = parse_str_dialect(source, &Urm).unwrap();
+//! machine.run();
+//! assert_eq!(machine.display_nat_registers(), "registers 0 7 3 3");
+//! ```
+
+use crate::{
+    dialect::{ self, Dialect, DialectError, Program, Statement, Token },
+    instruction::Instruction,
+    machine::Identifier,
+    memory::{ Memory, RegisterNumber },
+    parser,
+};
+
+/// The register which T and J count in.
+const SCRATCH: RegisterNumber = RegisterNumber::Negative(1);
+
+/// The URM dialect. See the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Urm;
+
+/// A URM instruction, with its registers and the instruction it may jump to.
+enum Operation {
+    Zero(usize),
+    Successor(usize),
+    Transfer(usize, usize),
+    Jump(usize, usize, usize),
+}
+
+impl Dialect for Urm {
+    fn get_name(&self) -> &str {
+        "urm"
+    }
+
+    fn get_extensions(&self) -> &[&str] {
+        &["urm"]
+    }
+
+    fn tokenize(&self, source: &str) -> Vec<Token> {
+        let code: Vec<&str> = source
+            .lines()
+            .map(|line| line.split_once('#').map_or(line, |(code, _)| code))
+            .collect();
+        dialect::tokenize(&code.join("\n"))
+    }
+
+    fn parse(&self, tokens: &[Token]) -> Result<Program, DialectError> {
+        let mut tokens = Cursor { tokens, next: 0 };
+        let registers = match tokens.peek() {
+            Some(first) if first.text.eq_ignore_ascii_case("registers") => {
+                let line = first.line;
+                let mut text = Vec::new();
+                while let Some(token) = tokens.peek().filter(|token| token.line == line) {
+                    text.push(token.text.as_str());
+                    tokens.next();
+                }
+                parser::parse_register_line(&text.join(" "))?
+            },
+            _ => Memory::default(),
+        };
+        let mut operations = Vec::new();
+        while let Some(mut token) = tokens.next() {
+            let number = token.text.strip_prefix(['I', 'i']).unwrap_or(&token.text);
+            if let Ok(number) = number.trim_end_matches('.').parse::<usize>() {
+                let expected = operations.len() + 1;
+                if number != expected {
+                    return Err(syntax_error(
+                        token,
+                        format!("This is instruction {expected}, but it's numbered {number}."),
+                    ));
+                }
+                if tokens.peek().is_some_and(|next| next.text == ":") {
+                    tokens.next();
+                }
+                token = tokens.expect_any(token, "an instruction after its number")?;
+            }
+            let arity = match token.text.to_uppercase().as_str() {
+                "Z" | "S" => 1,
+                "T" => 2,
+                "J" => 3,
+                _ => {
+                    return Err(syntax_error(
+                        token,
+                        format!("Unknown instruction \"{}\". Use Z, S, T or J.", token.text),
+                    ))
+                },
+            };
+            tokens.expect(token, "(")?;
+            let mut arguments = Vec::new();
+            for i in 0..arity {
+                if i > 0 {
+                    tokens.expect(token, ",")?;
+                }
+                let argument = tokens.expect_any(token, "a number")?;
+                arguments.push(argument.text.parse::<usize>().map_err(|_| {
+                    syntax_error(argument, format!("\"{}\" isn't a number.", argument.text))
+                })?);
+            }
+            tokens.expect(token, ")")?;
+            if tokens.peek().is_some_and(|next| next.text == ";") {
+                tokens.next();
+            }
+            operations.push(match arguments[..] {
+                [n] if token.text.eq_ignore_ascii_case("Z") => Operation::Zero(n),
+                [n] => Operation::Successor(n),
+                [m, n] => Operation::Transfer(m, n),
+                [m, n, q] => Operation::Jump(m, n, q),
+                _ => unreachable!("every instruction has 1 to 3 arguments"),
+            });
+        }
+        let mut program = Program::new(registers);
+        for (i, operation) in operations.iter().enumerate() {
+            lower(&mut program.statements, operation, i + 1, operations.len());
+        }
+        Ok(program)
+    }
+}
+
+/// Add the remuir instructions which carry out the `number`th URM instruction.
+fn lower(statements: &mut Vec<Statement>, operation: &Operation, number: usize, count: usize) {
+    let label = |suffix: &str| format!("I{number}{suffix}");
+    let jump = |q: usize| {
+        if (1..=count).contains(&q) { Identifier::Label(format!("I{q}")) } else { Identifier::Halt }
+    };
+    let next = jump(number + 1);
+    let mut push = |label: Option<String>, instruction: Instruction| {
+        statements.push(Statement { label, instruction });
+    };
+    match *operation {
+        Operation::Zero(n) => {
+            let n = RegisterNumber::Natural(n);
+            push(Some(label("")), Instruction::DECJZ(n, next));
+            push(None, Instruction::GOTO(Identifier::Label(label(""))));
+        },
+        Operation::Successor(n) => {
+            push(Some(label("")), Instruction::INC(RegisterNumber::Natural(n)));
+        },
+        Operation::Transfer(m, n) if m == n => push(Some(label("")), Instruction::GOTO(next)),
+        Operation::Transfer(m, n) => {
+            let (m, n) = (RegisterNumber::Natural(m), RegisterNumber::Natural(n));
+            // Clear Rn, move Rm into both Rn and the scratch register, then move it back.
+            push(Some(label("")), Instruction::DECJZ(n, Identifier::Label(label("_copy"))));
+            push(None, Instruction::GOTO(Identifier::Label(label(""))));
+            push(Some(label("_copy")), Instruction::DECJZ(m, Identifier::Label(label("_back"))));
+            push(None, Instruction::INC(n));
+            push(None, Instruction::INC(SCRATCH));
+            push(None, Instruction::GOTO(Identifier::Label(label("_copy"))));
+            push(Some(label("_back")), Instruction::DECJZ(SCRATCH, next));
+            push(None, Instruction::INC(m));
+            push(None, Instruction::GOTO(Identifier::Label(label("_back"))));
+        },
+        Operation::Jump(m, n, q) if m == n => push(Some(label("")), Instruction::GOTO(jump(q))),
+        Operation::Jump(m, n, q) => {
+            let (m, n) = (RegisterNumber::Natural(m), RegisterNumber::Natural(n));
+            // Decrement both registers together, counting in the scratch register, until one of
+            // them is 0. They were equal if both are. Then add the count back to both.
+            push(Some(label("")), Instruction::DECJZ(m, Identifier::Label(label("_m0"))));
+            push(None, Instruction::DECJZ(n, Identifier::Label(label("_n0"))));
+            push(None, Instruction::INC(SCRATCH));
+            push(None, Instruction::GOTO(Identifier::Label(label(""))));
+            push(Some(label("_m0")), Instruction::DECJZ(n, Identifier::Label(label("_equal"))));
+            push(None, Instruction::INC(n));
+            push(None, Instruction::GOTO(Identifier::Label(label("_unequal"))));
+            push(Some(label("_n0")), Instruction::INC(m));
+            push(Some(label("_unequal")), Instruction::DECJZ(SCRATCH, next));
+            push(None, Instruction::INC(m));
+            push(None, Instruction::INC(n));
+            push(None, Instruction::GOTO(Identifier::Label(label("_unequal"))));
+            push(Some(label("_equal")), Instruction::DECJZ(SCRATCH, jump(q)));
+            push(None, Instruction::INC(m));
+            push(None, Instruction::INC(n));
+            push(None, Instruction::GOTO(Identifier::Label(label("_equal"))));
+        },
+    }
+}
+
+fn syntax_error(token: &Token, message: String) -> DialectError {
+    DialectError::Syntax { line: token.line, message }
+}
+
+/// The tokens of a program, read one at a time.
+struct Cursor<'a> {
+    tokens: &'a [Token],
+    next: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek()?;
+        self.next += 1;
+        Some(token)
+    }
+
+    /// Take the next token, whatever it is. `after` is the token before, for its line number.
+    fn expect_any(&mut self, after: &Token, what: &str) -> Result<&'a Token, DialectError> {
+        self.next().ok_or_else(|| syntax_error(after, format!("Expected {what}.")))
+    }
+
+    /// Take the next token, which must be `text`.
+    fn expect(&mut self, after: &Token, text: &str) -> Result<(), DialectError> {
+        match self.next() {
+            Some(token) if token.text == text => Ok(()),
+            Some(token) => {
+                Err(syntax_error(token, format!("Expected \"{text}\", not \"{}\".", token.text)))
+            },
+            None => Err(syntax_error(after, format!("Expected \"{text}\"."))),
+        }
+    }
+}
@@ -31,10 +31,17 @@ along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 //! us_presidents.update(42, String::from("Bill Clinton"));
 //! assert_eq!("Bill Clinton", us_presidents.get(&42).unwrap());
 //! ```
-#[derive(Default, Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct VecMap<K, V> {
     pub vec: Vec<(K, V)>
 }
+
+// Deriving `Default` would needlessly require `K: Default` and `V: Default`.
+impl<K, V> Default for VecMap<K, V> {
+    fn default() -> Self {
+        VecMap { vec: Vec::new() }
+    }
+}
 impl<K, V> VecMap<K, V> {
     #[must_use]
     pub fn from_slice(tuples: &[(K, V)]) -> VecMap<K, V>
@@ -87,6 +94,14 @@ impl<K, V> VecMap<K, V> {
             None => self.update(key, func(identity)),
         }
     }
+
+    /// Remove a key from the map, returning its value if it was there.
+    pub fn remove(&mut self, key: &K) -> Option<V>
+    where
+        K: PartialEq
+    {
+        self.position(key).map(|i| self.vec.remove(i).1)
+    }
     
     #[must_use]
     pub fn keys(&self) -> Vec<&K> {
@@ -0,0 +1,195 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Rerunning the test blocks (see [`crate::test_block`]) of programs whenever they change.
+//!
+//! Every time [`TestWatcher::poll`] is called, it reads each program in a directory, and runs the
+//! tests of those whose contents changed since the last poll. Only the tests whose outcome changed
+//! are reported, so a long-running session shows what each edit fixed or broke. Files are
+//! compared by content rather than modification time, which is too coarse on some file systems.
+
+use std::{
+    fs,
+    io::{ self, Write },
+    path::{ Path, PathBuf },
+};
+
+use crate::{
+    machine::{ fnv1a, Machine, FNV_OFFSET_BASIS },
+    test_block::{ self, TestOutcome },
+    tui::RemuirError,
+};
+
+/// The file extension of the programs which are watched.
+const EXTENSION: &str = "remuir";
+
+/// The last known state of a watched program.
+struct Watched {
+    path: PathBuf,
+    hash: u64,
+    /// The outcome of each test, or why the tests couldn't be run.
+    results: Result<Vec<(String, TestOutcome)>, String>,
+}
+
+/// Watches a directory of programs, or a single program, and reruns their tests.
+pub struct TestWatcher {
+    path: PathBuf,
+    watched: Vec<Watched>,
+}
+
+impl TestWatcher {
+    #[must_use]
+    pub fn new(path: PathBuf) -> TestWatcher {
+        TestWatcher { path, watched: Vec::new() }
+    }
+
+    /// Rerun the tests of every program which was added or changed since the last poll, using
+    /// `load` to turn its source code into a machine, and write what changed to `out`. Returns
+    /// whether anything changed.
+    ///
+    /// # Errors
+    ///
+    /// * [`io::Error`] - returned when the directory can't be read or writing to `out` fails.
+    ///   Programs which can't be read are reported, rather than being an error.
+    pub fn poll(
+        &mut self,
+        load: impl Fn(&str) -> Result<Machine, RemuirError>,
+        out: &mut impl Write,
+    ) -> io::Result<bool> {
+        let mut paths = Vec::new();
+        find_programs(&self.path, &mut paths)?;
+        paths.sort();
+        let mut changed = false;
+        let removed: Vec<PathBuf> = self
+            .watched
+            .iter()
+            .map(|watched| watched.path.clone())
+            .filter(|path| !paths.contains(path))
+            .collect();
+        for path in removed {
+            writeln!(out, "{}: removed", path.display())?;
+            self.watched.retain(|watched| watched.path != path);
+            changed = true;
+        }
+        for path in paths {
+            let source = fs::read_to_string(&path);
+            let hash = match &source {
+                Ok(source) => fnv1a(FNV_OFFSET_BASIS, source.as_bytes()),
+                Err(_) => 0,
+            };
+            let index = self.watched.iter().position(|watched| watched.path == path);
+            if index.is_some_and(|i| self.watched[i].hash == hash) {
+                continue;
+            }
+            changed = true;
+            let results = source
+                .map_err(|e| e.to_string())
+                .and_then(|source| run_tests(&source, &load));
+            let previous = index.map(|i| self.watched.remove(i));
+            report(out, &path, previous.as_ref().map(|watched| &watched.results), &results)?;
+            self.watched.push(Watched { path, hash, results });
+        }
+        if changed {
+            let (passed, failed) = self.totals();
+            writeln!(out, "Total: {passed} passed, {failed} failed")?;
+        }
+        Ok(changed)
+    }
+
+    /// Get how many tests passed and failed as of the last poll, across every program.
+    #[must_use]
+    pub fn totals(&self) -> (usize, usize) {
+        let outcomes = self
+            .watched
+            .iter()
+            .filter_map(|watched| watched.results.as_ref().ok())
+            .flatten();
+        let (passed, failed): (Vec<_>, Vec<_>) =
+            outcomes.partition(|(_, outcome)| *outcome == TestOutcome::Passed);
+        (passed.len(), failed.len())
+    }
+}
+
+/// Find every program in a directory and its subdirectories, or the path itself if it's a file.
+fn find_programs(path: &Path, programs: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        programs.push(path.to_path_buf());
+        return Ok(());
+    }
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_programs(&path, programs)?;
+        }
+        else if path.extension().is_some_and(|extension| extension == EXTENSION) {
+            programs.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn run_tests(
+    source: &str,
+    load: impl Fn(&str) -> Result<Machine, RemuirError>,
+) -> Result<Vec<(String, TestOutcome)>, String> {
+    let tests = test_block::find_tests(source).map_err(|e| e.to_string())?;
+    if tests.is_empty() {
+        return Ok(Vec::new());
+    }
+    let machine = load(source).map_err(|e| e.to_string())?;
+    Ok(tests.iter().map(|test| (test.name.clone(), test.run(&machine))).collect())
+}
+
+/// Write the tests of a program whose outcome differs from before, or every failure if the
+/// program is new.
+fn report(
+    out: &mut impl Write,
+    path: &Path,
+    previous: Option<&Result<Vec<(String, TestOutcome)>, String>>,
+    results: &Result<Vec<(String, TestOutcome)>, String>,
+) -> io::Result<()> {
+    let outcomes = match results {
+        Ok(outcomes) => outcomes,
+        Err(e) => return writeln!(out, "{}: {e}", path.display()),
+    };
+    let failed = outcomes.iter().filter(|(_, outcome)| *outcome != TestOutcome::Passed).count();
+    writeln!(out, "{}: {} passed, {failed} failed", path.display(), outcomes.len() - failed)?;
+    let before = |name: &str| match previous {
+        Some(Ok(outcomes)) => {
+            outcomes.iter().find(|(old, _)| old == name).map(|(_, outcome)| outcome)
+        },
+        _ => None,
+    };
+    for (name, outcome) in outcomes {
+        match (previous, before(name), outcome) {
+            (Some(Ok(_)), Some(old), new) if old == new => (),
+            (Some(Ok(_)), Some(TestOutcome::Passed), TestOutcome::Failed(reason)) => {
+                writeln!(out, "  broken: {name} ... {reason}")?;
+            },
+            (Some(Ok(_)), Some(_), TestOutcome::Passed) => writeln!(out, "  fixed: {name}")?,
+            (Some(Ok(_)), _, new) => writeln!(out, "  new: {name} ... {new}")?,
+            (_, _, TestOutcome::Failed(reason)) => writeln!(out, "  failed: {name} ... {reason}")?,
+            (_, _, TestOutcome::Passed) => (),
+        }
+    }
+    if let Some(Ok(old)) = previous {
+        let removed = old.iter().filter(|(name, _)| outcomes.iter().all(|(new, _)| new != name));
+        for (name, _) in removed {
+            writeln!(out, "  removed: {name}")?;
+        }
+    }
+    Ok(())
+}
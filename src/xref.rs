@@ -0,0 +1,118 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+//! An index of where labels and registers are referenced in a program.
+//!
+//! The index is built once when a [`Machine`](crate::machine::Machine) is constructed, and kept up
+//! to date as labels are added, so looking up every line which jumps to a label doesn't need to
+//! scan the whole program each time. Jumps which land on a label's line by its number, such as
+//! relative jumps, are listed under the label as well.
+
+use crate::{
+    machine::{ Identifier, Line, LineNumber },
+    memory::RegisterNumber,
+    vecmap::VecMap,
+};
+
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct CrossReference {
+    jumps: VecMap<Identifier, Vec<LineNumber>>,
+    label_offsets: VecMap<Identifier, Vec<LineNumber>>,
+    registers: VecMap<RegisterNumber, Vec<LineNumber>>,
+}
+
+impl CrossReference {
+    /// Build a cross-reference index from the lines of a program.
+    #[must_use]
+    pub fn new_from_lines(lines: &[Line]) -> CrossReference {
+        let mut xref = CrossReference::default();
+        for line in lines {
+            xref.add_line(line);
+        }
+        for line in lines {
+            if let Some(label @ Identifier::Label(_)) = line.get_id() {
+                xref.add_label(label, line.get_line_number());
+            }
+        }
+        xref
+    }
+
+    /// Add the references made by a line to the index.
+    pub fn add_line(&mut self, line: &Line) {
+        let line_number = line.get_line_number();
+        let instruction = line.get_instruction();
+        if let Some(target) = instruction.get_jump_target() {
+            self.jumps.update_with_fn(target.clone(), &Vec::new(), |lines| {
+                with_line(lines, line_number)
+            });
+        }
+        if let Some(register) = instruction.get_register() {
+            self.registers.update_with_fn(register, &Vec::new(), |lines| {
+                with_line(lines, line_number)
+            });
+        }
+    }
+
+    /// Add a label to the index, which is on the given line. Jumps to the line by its number, which
+    /// relative jumps and jumps to a number of lines after a label have become, are jumps to the
+    /// label too.
+    pub fn add_label(&mut self, label: &Identifier, line_number: LineNumber) {
+        let jumps = self.jumps_to(&Identifier::Line(line_number)).to_vec();
+        for jump in jumps {
+            self.jumps.update_with_fn(label.clone(), &Vec::new(), |lines| with_line(lines, jump));
+        }
+    }
+
+    /// Add a line which jumps a number of lines after the given label, such as `goto start+2`.
+    /// The jump itself has already been resolved to a line number by then.
+    pub fn add_label_offset(&mut self, label: &str, line_number: LineNumber) {
+        let label = Identifier::Label(label.to_string());
+        self.label_offsets.update_with_fn(label, &Vec::new(), |lines| {
+            with_line(lines, line_number)
+        });
+    }
+
+    /// Get the line numbers of every line which may jump to the given identifier, in ascending
+    /// order.
+    #[must_use]
+    pub fn jumps_to(&self, id: &Identifier) -> &[LineNumber] {
+        self.jumps.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Get the line numbers of every line which jumps a number of lines after the given label, in
+    /// ascending order.
+    #[must_use]
+    pub fn jumps_after(&self, label: &Identifier) -> &[LineNumber] {
+        self.label_offsets.get(label).map_or(&[], Vec::as_slice)
+    }
+
+    /// Get the line numbers of every line which reads or modifies the given register, in ascending
+    /// order.
+    #[must_use]
+    pub fn uses_register(&self, register: RegisterNumber) -> &[LineNumber] {
+        self.registers.get(&register).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Add a line number to a list of them, keeping it in ascending order.
+fn with_line(lines: &[LineNumber], line_number: LineNumber) -> Vec<LineNumber> {
+    let mut lines = lines.to_vec();
+    if let Err(i) = lines.binary_search(&line_number) {
+        lines.insert(i, line_number);
+    }
+    lines
+}
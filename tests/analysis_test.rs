@@ -0,0 +1,54 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{ analysis::Analysis, examples, parser::parse_str };
+
+#[test]
+fn same_seed_same_results() {
+    let mut machine = examples::square(3);
+    let first = Analysis::sample(&mut machine, &[(0, 0..=20)], 50, 7, 100_000);
+    let second = Analysis::sample(&mut machine, &[(0, 0..=20)], 50, 7, 100_000);
+    assert_eq!(first, second);
+    assert_eq!(first.get_runs(), 50);
+    assert_eq!(first.get_halted_count(), 50);
+    // The machine is left as it was.
+    assert_eq!(machine.get_initial_memory().get_nat_registers_as_u128(), vec![3]);
+}
+
+#[test]
+fn step_limit_and_percentiles() {
+    // Takes 2 * r1 + 1 steps to halt, unless r1 is 0, when it never halts.
+    let mut machine = parse_str("registers 0 0
+decjz r1 forever
+loop: decjz r1 halt
+decjz r-1 loop
+forever: decjz r-1 forever").unwrap();
+    let analysis = Analysis::sample(&mut machine, &[(1, 0..=3)], 200, 1, 100);
+    assert!(analysis.get_halted_count() < 200);
+    assert!(analysis.get_halted_steps().iter().all(|steps| [2, 4, 6].contains(steps)));
+    assert_eq!(analysis.percentile(0), Some(2));
+    assert_eq!(analysis.percentile(100), Some(6));
+    let report = analysis.to_string();
+    assert!(report.contains("Runs: 200\nHalted within 100 steps: "));
+    assert!(report.contains("min 2, "));
+}
+
+#[test]
+fn no_runs() {
+    let analysis = Analysis::sample(&mut examples::copy(1), &[], 0, 0, 10);
+    assert_eq!(analysis.percentile(50), None);
+    assert_eq!(analysis.to_string(), "Runs: 0\nHalted within 10 steps: 0 (0.0%)\n");
+}
@@ -0,0 +1,132 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    ast::{ ItemKind, Program },
+    instruction::Instruction,
+    machine::Identifier,
+    memory::RegisterNumber,
+    parser::{ machine_from_ast, parse_ast, parse_str, ParseLimits, ParseSourceError },
+};
+
+const SOURCE: &str = "registers 0 3
+#!phase copying # The only phase.
+macro twice r
+inc r
+inc r
+endmacro
+
+## Copies r1 into r0.
+## Twice.
+copying: decjz r1 +3
+twice r0
+goto copying";
+
+#[test]
+fn items_keep_where_they_came_from() {
+    let program = parse_ast(SOURCE).unwrap();
+    let kinds: Vec<&ItemKind> = program.items.iter().map(|item| &item.kind).collect();
+    assert!(matches!(kinds[0], ItemKind::Registers(memory) if memory.get_nat_count() == 2));
+    assert_eq!(kinds[1], &ItemKind::Phase(String::from("copying")));
+    assert_eq!(kinds[2], &ItemKind::MacroDefinition {
+        name: String::from("twice"),
+        parameters: vec![String::from("r")],
+        body: vec![String::from("inc r"), String::from("inc r")],
+    });
+    assert_eq!(kinds[3], &ItemKind::Instruction {
+        label: Some(String::from("copying")),
+        instruction: Instruction::DECJZ(RegisterNumber::Natural(1), Identifier::Relative(3)),
+    });
+    assert_eq!(kinds[4], &ItemKind::MacroCall {
+        label: None,
+        name: String::from("twice"),
+        arguments: vec![String::from("r0")],
+    });
+    let call = &program.items[4];
+    assert_eq!(&SOURCE[call.span.clone()], "twice r0");
+    assert_eq!(call.line, 11);
+    assert_eq!(program.items[3].documentation, vec!["Copies r1 into r0.", "Twice."]);
+    assert!(call.documentation.is_empty());
+    assert_eq!(program.get_labels(), vec!["copying"]);
+
+    let comments: Vec<(&str, usize, bool)> = program.comments
+        .iter()
+        .map(|comment| (comment.text.as_str(), comment.line, comment.is_documentation))
+        .collect();
+    assert_eq!(comments, vec![
+        (" The only phase.", 2, false),
+        (" Copies r1 into r0.", 8, true),
+        (" Twice.", 9, true),
+    ]);
+    assert_eq!(&SOURCE[program.comments[0].span.clone()], "# The only phase.");
+}
+
+#[test]
+fn machines_are_built_from_the_tree() {
+    let machine = machine_from_ast(&parse_ast(SOURCE).unwrap(), &ParseLimits::UNLIMITED).unwrap();
+    let parsed = parse_str(SOURCE).unwrap();
+    assert_eq!(machine.to_source(), parsed.to_source());
+    assert_eq!(machine.get_label_documentation("copying"), Some("Copies r1 into r0.\nTwice."));
+    // Without a registers line, only a library's tree can be parsed.
+    assert!(parse_ast("inc r0").is_ok());
+    assert!(machine_from_ast(&parse_ast("inc r0").unwrap(), &ParseLimits::UNLIMITED).is_err());
+    assert!(machine_from_ast(&Program::default(), &ParseLimits::UNLIMITED).is_err());
+}
+
+#[test]
+fn every_comment_is_kept() {
+    let source = "registers 0\n#phaser note\nmacro twice r\ninc r # Once.\n  # Between.\n\
+        inc r\nendmacro\n#!extended # Directive.\ntwice r0\n# The end.";
+    let program = parse_ast(source).unwrap();
+    let comments: Vec<(&str, usize)> = program.comments
+        .iter()
+        .map(|comment| (comment.text.as_str(), comment.line))
+        .collect();
+    assert_eq!(comments, vec![
+        ("phaser note", 2),
+        (" Once.", 4),
+        (" Between.", 5),
+        (" Directive.", 8),
+        (" The end.", 10),
+    ]);
+    assert!(matches!(
+        &program.items[1].kind,
+        ItemKind::MacroDefinition { body, .. } if body == &["inc r", "inc r"],
+    ));
+}
+
+#[test]
+fn machines_are_built_within_limits() {
+    let program = parse_ast(SOURCE).unwrap();
+    let limits = ParseLimits { max_lines: 2, ..ParseLimits::default() };
+    assert!(matches!(
+        machine_from_ast(&program, &limits),
+        Err(ParseSourceError::TooManyLines { maximum: 2 }),
+    ));
+}
+
+#[test]
+fn trees_can_be_transformed() {
+    let mut program = parse_ast(SOURCE).unwrap();
+    for item in &mut program.items {
+        if let ItemKind::MacroCall { name, .. } = &mut item.kind {
+            *name = String::from("zero");
+        }
+    }
+    let mut machine = machine_from_ast(&program, &ParseLimits::UNLIMITED).unwrap();
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 0 0");
+}
@@ -0,0 +1,97 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::{ fs, num::NonZeroUsize, path::{ Path, PathBuf } };
+
+use remuir::{
+    autosave::Autosave,
+    machine::Machine,
+    parser::parse_str,
+    tui::{ self, Terminal },
+};
+
+const SOURCE: &str = "registers 0 2\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop";
+
+fn temporary_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("remuir-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    directory
+}
+
+fn session(directory: &Path, machine: Machine, every: usize, script: &str) -> String {
+    let every = NonZeroUsize::new(every).unwrap();
+    let autosave = Autosave::new(directory.to_path_buf(), &machine, every);
+    let mut out: Vec<u8> = Vec::new();
+    let mut input = script.as_bytes();
+    tui::debug_with_autosave(machine, autosave, &mut input, &mut out, Terminal::Interactive)
+        .unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn unfinished_sessions_can_be_restored() {
+    let directory = temporary_directory("autosave");
+    let machine = parse_str(SOURCE).unwrap();
+    // The input ends without quitting, as with Ctrl+D.
+    session(&directory, machine.clone(), 1, "b 2\ns\n\ns\nsave-as two\n");
+    let autosave = Autosave::new(directory.clone(), &machine, NonZeroUsize::MIN);
+    let commands = autosave.saved_commands().unwrap();
+    assert_eq!(commands, ["b 2", "s", "s", "save-as two"]);
+    let file = fs::read_dir(&directory).unwrap().next().unwrap().unwrap().path();
+    let contents = fs::read_to_string(file).unwrap();
+    assert!(contents.contains("# line 2\n# registers 1 1\n"));
+    assert!(contents.contains(&format!("# fingerprint {:016x}\n", machine.fingerprint())));
+
+    let out = session(&directory, machine.clone(), 1, "y\nplay\nq\n");
+    assert!(out.contains("The last session of this program (4 commands) wasn't quit."));
+    assert!(out.contains("Restored the session."));
+    assert!(out.contains("registers 1 1\nNext line:\n2    decjz r2 loop"), "{out}");
+    // The breakpoint came back too.
+    assert!(out.contains("remuir> Reached breakpoint!"), "{out}");
+    assert!(autosave.saved_commands().is_none());
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn sessions_are_saved_every_so_many_commands() {
+    let directory = temporary_directory("autosave-every");
+    let machine = parse_str(SOURCE).unwrap();
+    let autosave = Autosave::new(directory.clone(), &machine, NonZeroUsize::MIN);
+    session(&directory, machine.clone(), 3, "s\ns\n");
+    assert!(autosave.saved_commands().is_none());
+    session(&directory, machine.clone(), 3, "s\ns\ns\ns\n");
+    assert_eq!(autosave.saved_commands().unwrap().len(), 3);
+
+    let out = session(&directory, machine.clone(), 1, "n\nregisters\n");
+    assert!(out.contains("Starting a new session."));
+    assert_eq!(autosave.saved_commands().unwrap(), ["registers"]);
+    // Another program has its own session.
+    let other = parse_str("registers 1\ninc r0").unwrap();
+    assert!(Autosave::new(directory.clone(), &other, NonZeroUsize::MIN).saved_commands().is_none());
+    let _ = fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn autosaving_can_be_configured() {
+    assert_eq!(Autosave::configured_every(""), Ok(None));
+    assert_eq!(Autosave::configured_every("autosave = on"), Ok(NonZeroUsize::new(1)));
+    let config = "# Debug sessions\n\ntheme = dark\nautosave=3\n# autosave = 4\n";
+    assert_eq!(Autosave::configured_every(config), Ok(NonZeroUsize::new(3)));
+    // The last setting counts.
+    assert_eq!(Autosave::configured_every("autosave = 2\nautosave = off"), Ok(None));
+    assert!(Autosave::configured_every("autosave = 0").is_err());
+    assert!(Autosave::configured_every("autosave = yes").is_err());
+}
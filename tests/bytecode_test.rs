@@ -0,0 +1,101 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+use remuir::{
+    bytecode::{ BytecodeError, VERSION },
+    machine::Machine,
+    memory::{ Memory, Register },
+    parser::parse_str,
+};
+
+const SOURCE: &str = "registers 0 3 0
+#!phase copy
+#!entry copy
+#!export copy
+#!import lib::done
+## Only reached by jumping from outside.
+back: inc r1
+decjz r-1 HALT
+copy: decjz r1 lib::done
+inc r0
+inc r-2
+decjnz r-2 -3
+call +2
+return
+goto +1
+lib::done: halt";
+
+#[test]
+fn programs_are_loaded_back_from_bytes() {
+    let machine = parse_str(SOURCE).unwrap();
+    let mut loaded = Machine::from_bytes(&machine.to_bytes()).unwrap();
+    assert!(loaded.diff(&machine).is_empty());
+    assert_eq!(loaded.to_source(), machine.to_source());
+    assert_eq!(loaded.fingerprint(), machine.fingerprint());
+    assert_eq!(loaded.get_imports(), ["lib::done"]);
+    assert_eq!(loaded.get_current_line_number(), 2);
+    loaded.run();
+    assert_eq!(loaded.display_nat_registers(), "registers 3 0 0");
+}
+
+#[test]
+fn large_registers_and_pseudo_registers_are_kept() {
+    let source = "registers 0\n#!extended\ndecjz r!steps +2\ndecjnz r!visits -1\nwrite r0";
+    let mut machine = parse_str(source).unwrap();
+    let large = Register::new(&[7, 0, 1]);
+    machine.set_initial_memory(Memory::new_from_slice(&[Register::from(0), large.clone()]));
+    let loaded = Machine::from_bytes(&machine.to_bytes()).unwrap();
+    assert_eq!(loaded.get_initial_memory(), machine.get_initial_memory());
+    assert_eq!(loaded.get_initial_memory().get_nat_registers()[1], large);
+    assert_eq!(loaded.fingerprint(), machine.fingerprint());
+}
+
+#[test]
+fn jumps_past_the_end_are_kept() {
+    let mut machine = parse_str("registers 0 3\ndecjz r1 +10\ninc r0").unwrap();
+    let mut loaded = Machine::from_bytes(&machine.to_bytes()).unwrap();
+    assert_eq!(loaded.to_source(), machine.to_source());
+    machine.run();
+    loaded.run();
+    assert_eq!(loaded.display_nat_registers(), "registers 1 2");
+    assert_eq!(loaded.display_nat_registers(), machine.display_nat_registers());
+}
+
+#[test]
+fn other_files_and_later_versions_are_refused() {
+    let mut bytes = parse_str(SOURCE).unwrap().to_bytes();
+    assert!(matches!(Machine::from_bytes(SOURCE.as_bytes()), Err(BytecodeError::NotBytecode)));
+    bytes[4] = VERSION + 1;
+    assert!(matches!(
+        Machine::from_bytes(&bytes),
+        Err(BytecodeError::UnsupportedVersion { version }) if version == VERSION + 1,
+    ));
+}
+
+#[test]
+fn damaged_bytecode_is_an_error() {
+    let bytes = parse_str(SOURCE).unwrap().to_bytes();
+    for length in 0..bytes.len() {
+        assert!(Machine::from_bytes(&bytes[..length]).is_err(), "{length} bytes were loaded.");
+    }
+    let mut extended = bytes.clone();
+    extended.push(0);
+    assert!(matches!(
+        Machine::from_bytes(&extended),
+        Err(BytecodeError::Corrupt { offset }) if offset == bytes.len(),
+    ));
+}
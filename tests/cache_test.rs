@@ -0,0 +1,105 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::fs;
+
+use remuir::{
+    cache::Cache,
+    instruction::Instruction,
+    machine::{ Identifier, Line, Machine },
+    memory::{ Memory, Register, RegisterNumber },
+    parser::parse_str,
+};
+
+const SOURCE: &str = "registers 0 3 0
+#!phase copy
+#!entry copy
+## Only reached by jumping from outside.
+back: inc r1
+decjz r-1 HALT
+copy: decjz r1 lib::done
+inc r0
+inc r-2
+decjnz r-2 copy
+lib::done: halt";
+
+fn temporary_cache(name: &str) -> Cache {
+    let directory = std::env::temp_dir().join(format!("remuir-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    Cache::new(directory)
+}
+
+#[test]
+fn stored_programs_are_read_back() {
+    let cache = temporary_cache("stored");
+    let machine = parse_str(SOURCE).unwrap();
+    let key = Cache::key(SOURCE, &[], false);
+    assert!(cache.get(key).is_none());
+    cache.put(key, &machine).unwrap();
+    let mut cached = cache.get(key).unwrap();
+    assert!(cached.diff(&machine).is_empty());
+    assert_eq!(cached.to_source(), machine.to_source());
+    let documentation = cached.get_label_documentation("back");
+    assert_eq!(documentation, Some("Only reached by jumping from outside."));
+    assert_eq!(cached.get_current_line_number(), 2);
+    cached.run();
+    assert_eq!(cached.display_nat_registers(), "registers 3 0 0");
+    assert_eq!(cache.clear().unwrap(), 1);
+    assert!(cache.get(key).is_none());
+    assert_eq!(cache.clear().unwrap(), 0);
+}
+
+#[test]
+fn keys_depend_on_everything_a_program_is_built_from() {
+    let library = vec![(String::from("lib"), String::from("done: halt"))];
+    let key = Cache::key(SOURCE, &library, false);
+    assert_eq!(key, Cache::key(SOURCE, &library, false));
+    assert_ne!(key, Cache::key(SOURCE, &library, true));
+    assert_ne!(key, Cache::key(SOURCE, &[], false));
+    assert_ne!(key, Cache::key(&SOURCE.replace("inc r0", "inc r1"), &library, false));
+    let renamed = vec![(String::from("li"), String::from("bdone: halt"))];
+    assert_ne!(key, Cache::key(SOURCE, &renamed, false));
+}
+
+#[test]
+fn damaged_entries_are_ignored() {
+    let cache = temporary_cache("damaged");
+    let key = Cache::key(SOURCE, &[], false);
+    cache.put(key, &parse_str(SOURCE).unwrap()).unwrap();
+    let path = cache.get_directory().join(format!("{key:016x}.rmbc"));
+    let mut entry = fs::read(&path).unwrap();
+    // Change the fingerprint, as if the bytecode after it were damaged.
+    entry[0] ^= 1;
+    fs::write(&path, &entry).unwrap();
+    assert!(cache.get(key).is_none());
+    fs::write(&path, "registers 0\nhalt").unwrap();
+    assert!(cache.get(key).is_none());
+    cache.clear().unwrap();
+}
+
+#[test]
+fn jumps_to_line_numbers_are_kept() {
+    let cache = temporary_cache("lines");
+    let lines = vec![
+        Line::new(0, None, Instruction::INC(RegisterNumber::Natural(1))),
+        Line::new(1, None, Instruction::DECJZ(RegisterNumber::Natural(0), Identifier::Line(0))),
+        Line::new(2, None, Instruction::GOTO(Identifier::Line(3))),
+    ];
+    let machine = Machine::new_from_lines(&lines, Memory::new_from_slice(&[Register::from(0)]));
+    cache.put(1, &machine).unwrap();
+    assert_eq!(cache.get(1), Some(machine));
+    cache.clear().unwrap();
+}
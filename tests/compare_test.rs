@@ -0,0 +1,98 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    compare::{ CompareError, Comparison, MAX_GRID_SIZE },
+    examples,
+    parser::parse_str,
+};
+
+fn names() -> [String; 2] {
+    [String::from("slow"), String::from("fast")]
+}
+
+#[test]
+fn grid_and_speedup() {
+    // Moves r0 into r1 one at a time, taking 2 * r0 + 1 steps.
+    let mut slow = parse_str("registers 0 0
+loop: decjz r0 halt
+inc r1
+decjz r-1 loop").unwrap();
+    // Halts straight away.
+    let mut fast = parse_str("registers 0 0
+decjz r-1 halt").unwrap();
+    let comparison =
+        Comparison::run(names(), [&mut slow, &mut fast], &[(0, 0..=3)], 1000).unwrap();
+    let rows = comparison.get_rows();
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[2].inputs, vec![2]);
+    assert_eq!(rows[2].steps, [Some(7), Some(1)]);
+    assert_eq!(comparison.get_wins(), [0, 3]);
+    assert_eq!(comparison.get_total_steps(), [1 + 4 + 7 + 10, 4]);
+    assert_eq!(comparison.get_speedup(), Some(5.5));
+    let report = comparison.to_string();
+    assert!(report.contains("Wins: slow 0, fast 3, ties 1\n"));
+    assert!(report.contains("Speedup: fast took 5.50x fewer steps than slow\n"));
+    // The machines are left as they were.
+    assert_eq!(slow.get_initial_memory().get_nat_registers_as_u128(), vec![0, 0]);
+}
+
+#[test]
+fn last_register_changes_fastest() {
+    let comparison = Comparison::run(
+        names(),
+        [&mut examples::add(0, 0), &mut examples::add(0, 0)],
+        &[(0, 0..=1), (1, 5..=6)],
+        1000,
+    ).unwrap();
+    let inputs: Vec<Vec<u128>> =
+        comparison.get_rows().iter().map(|row| row.inputs.clone()).collect();
+    assert_eq!(inputs, vec![vec![0, 5], vec![0, 6], vec![1, 5], vec![1, 6]]);
+    assert!(comparison.get_rows().iter().all(|row| row.winner().is_none()));
+    assert!(comparison.to_string().contains("Speedup: none"));
+}
+
+#[test]
+fn step_limit() {
+    let mut forever = parse_str("registers 0
+loop: decjz r-1 loop").unwrap();
+    let comparison = Comparison::run(
+        names(),
+        [&mut forever, &mut examples::copy(0)],
+        &[(0, 0..=0)],
+        50,
+    ).unwrap();
+    assert_eq!(comparison.get_rows()[0].steps[0], None);
+    assert_eq!(comparison.get_rows()[0].winner(), Some(1));
+    assert_eq!(comparison.get_speedup(), None);
+    let report = comparison.to_string();
+    assert!(report.contains(">50"));
+    assert!(report.contains("no input which both programs halted on"));
+}
+
+#[test]
+fn grid_too_large() {
+    let result = Comparison::run(
+        names(),
+        [&mut examples::copy(0), &mut examples::copy(0)],
+        &[(0, 0..=100), (1, 0..=100)],
+        10,
+    );
+    assert_eq!(
+        result,
+        Err(CompareError::GridTooLarge { size: 101 * 101, maximum: MAX_GRID_SIZE }),
+    );
+}
@@ -0,0 +1,123 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    concurrent::{ Assertion, Comparison, Concurrent, Exploration, Scheduler },
+    machine::Machine,
+    memory::RegisterNumber,
+    parser::parse_str,
+};
+
+/// Enter the critical section (count it in r0) if the lock r1 is free, and take the lock. Checking
+/// and taking the lock are separate instructions, so two copies can both get in.
+const LOCK: &str = "registers 0 0
+check: decjz r1 take
+decjz r-1 done
+take: inc r1
+inc r0
+done: halt";
+
+fn two_locks(scheduler: Scheduler) -> Concurrent {
+    let machines: Vec<Machine> = (0..2).map(|_| parse_str(LOCK).unwrap()).collect();
+    let memory = machines[0].get_initial_memory().clone();
+    Concurrent::new(machines, memory, scheduler)
+}
+
+fn run(scheduler: Scheduler) -> Concurrent {
+    let mut concurrent = two_locks(scheduler);
+    assert!(concurrent.run(100));
+    concurrent
+}
+
+#[test]
+fn round_robin_interleaves_a_race() {
+    let mut concurrent = run(Scheduler::RoundRobin);
+    // Both machines saw the lock free before either took it.
+    assert_eq!(concurrent.get_memory().to_string(), "registers 2 2");
+    assert_eq!(concurrent.get_steps(), 8);
+    assert_eq!(concurrent.get_schedule(), [0, 1, 0, 1, 0, 1, 0, 1]);
+    assert!(concurrent.get_machines().iter().all(Machine::is_halted));
+    assert_eq!(concurrent.step(), None);
+}
+
+#[test]
+fn random_scheduling_depends_only_on_the_seed() {
+    let concurrent = run(Scheduler::Random { seed: 1 });
+    assert_eq!(concurrent.get_memory().to_string(), "registers 1 0");
+    let again = run(Scheduler::Random { seed: 1 });
+    assert_eq!(again.get_steps(), concurrent.get_steps());
+    assert_eq!(run(Scheduler::Random { seed: 0 }).get_memory().to_string(), "registers 2 2");
+}
+
+#[test]
+fn step_limit() {
+    let looping = "registers 0\nloop: inc r0\ndecjz r-1 loop";
+    let machines = vec![parse_str(looping).unwrap(), parse_str(LOCK).unwrap()];
+    let memory = machines[1].get_initial_memory().clone();
+    let mut concurrent = Concurrent::new(machines, memory, Scheduler::RoundRobin);
+    assert!(!concurrent.run(50));
+    assert!(!concurrent.is_stopped());
+    assert_eq!(concurrent.get_steps(), 50);
+    // Once the second machine halts, the first gets every step.
+    assert_eq!(concurrent.step(), Some(0));
+    assert_eq!(concurrent.step(), Some(0));
+}
+
+#[test]
+fn exploration_finds_a_race() {
+    let concurrent = two_locks(Scheduler::RoundRobin);
+    let at_most_one: Assertion = "r0 <= 1".parse().unwrap();
+    let Exploration::Violated { schedule, memory } = concurrent.explore(20, |memory| {
+        at_most_one.holds(memory)
+    })
+    else {
+        panic!("Both machines can enter the critical section.");
+    };
+    assert_eq!(memory.to_string(), "registers 2 2");
+    // Replaying the schedule reaches the same state.
+    let mut replay = two_locks(Scheduler::RoundRobin);
+    for &index in &schedule {
+        replay.step_machine(index);
+    }
+    assert_eq!(replay.get_schedule(), schedule);
+    assert!(!at_most_one.holds(replay.get_memory()));
+}
+
+#[test]
+fn exploration_counts_interleavings() {
+    let concurrent = two_locks(Scheduler::RoundRobin);
+    let held = |steps| concurrent.explore(steps, |_| true);
+    assert_eq!(held(20), Exploration::Held { interleavings: 60, complete: true });
+    assert_eq!(held(1), Exploration::Held { interleavings: 2, complete: false });
+    assert_eq!(held(0), Exploration::Held { interleavings: 1, complete: false });
+    // Nothing was stepped.
+    assert_eq!(concurrent.get_steps(), 0);
+}
+
+#[test]
+fn parse_assertions() {
+    let assertion: Assertion = " r-2>=10 ".parse().unwrap();
+    assert_eq!(assertion, Assertion {
+        register: RegisterNumber::Negative(2),
+        comparison: Comparison::GreaterOrEqual,
+        value: 10,
+    });
+    assert_eq!("r1 != 0".parse::<Assertion>().unwrap().comparison, Comparison::NotEqual);
+    assert_eq!("r1 < 0".parse::<Assertion>().unwrap().comparison, Comparison::Less);
+    assert!("r1".parse::<Assertion>().is_err());
+    assert!("1 == r1".parse::<Assertion>().is_err());
+    assert!("r1 <= -1".parse::<Assertion>().is_err());
+}
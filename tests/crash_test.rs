@@ -0,0 +1,45 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::fs;
+
+use remuir::{ crash, tui::{ self, Terminal } };
+
+#[test]
+fn crash_reports_describe_the_session() {
+    assert!(crash::report("nothing yet").is_none());
+    let script = (0..25).map(|i| format!("inc r{}\n", i % 3)).collect::<String>() + "dec r1\n";
+    tui::repl(&mut script.as_bytes(), &mut Vec::new(), Terminal::Interactive).unwrap();
+    let report = crash::report("oops").unwrap();
+    assert!(report.contains("crashed: oops\n"), "{report}");
+    // Only the last commands are kept, and the last one is the one which was running.
+    assert!(report.contains("Last commands, oldest first:\n  inc r0\n  inc r1\n"), "{report}");
+    assert!(report.contains("  inc r0\n  dec r1\n\nBefore the last command"), "{report}");
+    assert_eq!(report.matches("\n  inc").count(), crash::COMMAND_LIMIT - 1);
+    assert!(report.contains("on line 0 with registers 9 8 8."), "{report}");
+    assert!(report.contains("Manifest:\nremuir "), "{report}");
+    assert!(report.contains("\nstep limit none\n"), "{report}");
+
+    crash::install_hook();
+    let panicked = std::panic::catch_unwind(|| panic!("something broke"));
+    let _ = std::panic::take_hook();
+    assert!(panicked.is_err());
+    let path = std::env::temp_dir().join(format!("remuir-crash-{}.txt", std::process::id()));
+    let written = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+    assert!(written.contains("crashed: something broke (at tests/crash_test.rs:"), "{written}");
+    assert!(written.contains("  dec r1\n"), "{written}");
+}
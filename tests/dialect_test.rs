@@ -0,0 +1,156 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::path::Path;
+
+use remuir::{
+    dialect::{ Dialect, DialectError, Program, Registry, Statement, Token },
+    instruction::Instruction,
+    machine::Identifier,
+    memory::{ Memory, RegisterNumber },
+    parser::ParseSourceError,
+};
+
+/// Lines such as `loop: - 1 end`, `+ 0` and `end: - 1 halt`.
+struct Signs;
+
+impl Dialect for Signs {
+    fn get_name(&self) -> &str {
+        "signs"
+    }
+
+    fn get_extensions(&self) -> &[&str] {
+        &["sgn", "signs"]
+    }
+
+    fn parse(&self, tokens: &[Token]) -> Result<Program, DialectError> {
+        let mut program = Program::new(Memory::default());
+        let mut tokens = tokens.iter().peekable();
+        while let Some(token) = tokens.next() {
+            let mut label = None;
+            let mut sign = token;
+            if tokens.peek().is_some_and(|next| next.text == ":") {
+                label = Some(token.text.clone());
+                tokens.next();
+                sign = tokens.next().unwrap();
+            }
+            let mut next = |what: &str| {
+                tokens.next().map(|token| token.text.clone()).ok_or(DialectError::Syntax {
+                    line: sign.line,
+                    message: format!("Expected {what}."),
+                })
+            };
+            let register = RegisterNumber::Natural(next("a register")?.parse().unwrap());
+            let instruction = match sign.text.as_str() {
+                "+" => Instruction::INC(register),
+                "-" => Instruction::DECJZ(register, next("a label")?.parse().unwrap()),
+                _ => {
+                    return Err(DialectError::Syntax {
+                        line: sign.line,
+                        message: format!("Unknown sign \"{}\".", sign.text),
+                    })
+                },
+            };
+            program.statements.push(Statement { label, instruction });
+        }
+        Ok(program)
+    }
+}
+
+/// Signs, under another name.
+struct Renamed(&'static str);
+
+impl Dialect for Renamed {
+    fn get_name(&self) -> &str {
+        self.0
+    }
+
+    fn get_extensions(&self) -> &[&str] {
+        &["sgn"]
+    }
+
+    fn parse(&self, tokens: &[Token]) -> Result<Program, DialectError> {
+        Signs.parse(tokens)
+    }
+}
+
+const ADD: &str = "loop: - 1 end\n+ 0\n- 2 loop\nend: - 2 halt";
+
+#[test]
+fn default_tokens() {
+    let tokens = Signs.tokenize("J(1, 2,3)\n\n  a:b ;c");
+    let texts: Vec<_> = tokens.iter().map(|token| token.text.as_str()).collect();
+    assert_eq!(texts, ["J", "(", "1", ",", "2", ",", "3", ")", "a", ":", "b", ";", "c"]);
+    assert_eq!(tokens[7].line, 1);
+    assert_eq!(tokens[8].line, 3);
+}
+
+#[test]
+fn translation() {
+    let source = Signs.translate(ADD).unwrap();
+    assert_eq!(
+        source,
+        "registers 0\nloop: decjz r1 end\ninc r0\ndecjz r2 loop\nend: decjz r2 HALT\n",
+    );
+    let mut machine = remuir::parser::parse_str(&source.replace("registers 0", "registers 0 3"))
+        .unwrap();
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 3 0 0");
+}
+
+#[test]
+fn program_to_source() {
+    let mut program = Program::new(Memory::new_from_slice(&[1.into(), 2.into()]));
+    program.statements.push(Statement {
+        label: Some(String::from("start")),
+        instruction: Instruction::GOTO(Identifier::Halt),
+    });
+    assert_eq!(program.to_source(), "registers 1 2\nstart: goto HALT\n");
+}
+
+#[test]
+fn errors() {
+    assert!(matches!(
+        Signs.translate("+ 0\n* 1"),
+        Err(DialectError::Syntax { line: 2, .. }),
+    ));
+    assert!(matches!(
+        Signs.translate("+ 0\n- 1"),
+        Err(DialectError::Syntax { line: 2, .. }),
+    ));
+    assert!(matches!(
+        Signs.translate("a: + 0\na: + 1"),
+        Err(DialectError::InvalidSource(ParseSourceError::DuplicateLabel(_))),
+    ));
+}
+
+#[test]
+fn registry() {
+    let mut registry = Registry::default();
+    assert!(registry.find("signs").is_none());
+    registry.register(Signs);
+    assert_eq!(registry.find("SIGNS").unwrap().get_name(), "signs");
+    assert_eq!(registry.for_path(Path::new("add.SGN")).unwrap().get_name(), "signs");
+    assert_eq!(registry.for_path(Path::new("add.signs")).unwrap().get_name(), "signs");
+    assert!(registry.for_path(Path::new("add.remuir")).is_none());
+    assert!(registry.for_path(Path::new("sgn")).is_none());
+    // Later dialects take over extensions, and replace dialects with the same name.
+    registry.register(Renamed("other"));
+    assert_eq!(registry.for_path(Path::new("add.sgn")).unwrap().get_name(), "other");
+    registry.register(Renamed("Signs"));
+    assert_eq!(registry.get_names(), ["remuir", "urm", "other", "Signs"]);
+    assert!(registry.for_path(Path::new("add.signs")).is_none());
+}
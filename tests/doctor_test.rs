@@ -0,0 +1,81 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    doctor::{ self, Outcome, Report },
+    machine::Machine,
+    parser::{ parse_str, ParseSourceError },
+};
+
+#[test]
+fn report_on_a_program_which_halts() {
+    let source = "registers 0 3\nloop: decjz r1 halt\ninc r0\ngoto loop";
+    let report = Report::new(source, false, parse_str);
+    assert_eq!(
+        report.outcome,
+        Some(Outcome::Halted { steps: 10, registers: String::from("registers 3 0") }),
+    );
+    assert_eq!(report.reproduction, None);
+    let text = report.to_string();
+    let version = env!("CARGO_PKG_VERSION");
+    assert!(text.starts_with(&format!("remuir {version}\n\nParse: ok, 4 lines\n")), "{text}");
+    assert!(text.contains("\nMetrics:\nInstructions: 3 (1 inc, 1 decjz, 1 goto)\n"), "{text}");
+    assert!(text.contains("\nConfiguration:\nremuir "), "{text}");
+    assert!(text.ends_with("\nRun: halted after 10 steps with registers 3 0\n"), "{text}");
+}
+
+#[test]
+fn report_on_a_program_which_doesnt_parse() {
+    let report = Report::new("registers 0\nincc r0", false, parse_str);
+    assert!(report.parse_error.is_some());
+    assert_eq!(report.outcome, None);
+    assert!(report.to_string().contains("\nParse: failed\n"));
+}
+
+#[test]
+fn runs_are_sandboxed() {
+    let report = Report::new("registers 0\nloop: inc r0\ngoto loop", false, parse_str);
+    let limit = doctor::SANDBOX.steps.unwrap();
+    let reason = format!("The machine didn't halt within the limit of {limit} steps.");
+    assert_eq!(report.outcome, Some(Outcome::Stopped(reason)));
+}
+
+#[test]
+fn crashes_are_shrunk() {
+    // Pretend that loading any program which increments r7 crashes.
+    let load = |source: &str| -> Result<Machine, ParseSourceError> {
+        assert!(!source.contains("inc r7"), "r7 is cursed");
+        parse_str(source)
+    };
+    let source = "registers 0\ninc r1\ninc r2\ninc r7\ninc r3\ninc r4";
+    let report = Report::new(source, false, load);
+    let crashed = Outcome::Crashed(String::from("r7 is cursed"));
+    assert_eq!(report.outcome, Some(crashed));
+    assert_eq!(report.reproduction.as_deref(), Some("inc r7"));
+    let text = report.to_string();
+    assert!(text.contains("\nParse: crashed\n"), "{text}");
+    assert!(text.ends_with(
+        "\nSmallest program found which crashes the same way (1 of 6 lines):\n    inc r7\n",
+    ), "{text}");
+}
+
+#[test]
+fn minimise_keeps_what_is_needed() {
+    let source = "a\nb\nc\nd\ne\nf\ng";
+    let needed = |candidate: &str| candidate.contains('b') && candidate.contains('f');
+    let shrunk = doctor::minimise(source, needed);
+    assert_eq!(shrunk, "b\nf");
+}
@@ -0,0 +1,119 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+#![cfg(feature = "emit")]
+
+use std::{ net::UdpSocket, time::Duration };
+
+use remuir::{
+    emit::{ self, Callback, Event, Osc },
+    memory::Register,
+    parser::parse_str,
+};
+
+const SOURCE: &str = "registers 2000 0\nloop: decjz r0 HALT\ninc r1\ngoto loop";
+
+#[test]
+fn events_are_sent_at_the_start_and_end() {
+    let mut machine = parse_str(SOURCE).unwrap();
+    let mut events = Vec::new();
+    let mut sink = Callback(|event: &Event| events.push(event.clone()));
+    emit::run(&mut machine, &mut sink, Duration::ZERO).unwrap();
+    let first = events.first().unwrap();
+    assert_eq!((first.steps, first.halted), (0, false));
+    assert_eq!(first.registers, vec![Register::from(2000), Register::from(0)]);
+    let last = events.last().unwrap();
+    assert_eq!((last.steps, last.halted), (6001, true));
+    assert_eq!(last.registers, vec![Register::from(0), Register::from(2000)]);
+    // One more for every check while running, since the interval is 0.
+    assert_eq!(events.len(), 2 + 6001 / emit::CHECK_INTERVAL);
+    assert!(events[1..events.len() - 1].iter().all(|event| !event.halted));
+}
+
+#[test]
+fn long_intervals_only_send_the_first_and_last_events() {
+    let mut machine = parse_str(SOURCE).unwrap();
+    let mut count = 0;
+    emit::run(&mut machine, &mut Callback(|_: &Event| count += 1), Duration::from_secs(3600))
+        .unwrap();
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn events_are_encoded_as_osc() {
+    let event = Event {
+        steps: 7,
+        registers: vec![Register::from(1 << 40), Register::new(&[0, 1])],
+        halted: true,
+    };
+    let mut expected = b"/remuir/halted\0\0,ihs\0\0\0\0".to_vec();
+    expected.extend_from_slice(&7_i32.to_be_bytes());
+    expected.extend_from_slice(&(1_i64 << 40).to_be_bytes());
+    expected.extend_from_slice(b"340282366920938463463374607431768211456\0");
+    assert_eq!(emit::encode_osc(&event), expected);
+}
+
+#[test]
+fn osc_messages_are_sent_over_udp() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut machine = parse_str("registers 1\ninc r0").unwrap();
+    let mut osc = Osc::connect(receiver.local_addr().unwrap()).unwrap();
+    emit::run(&mut machine, &mut osc, Duration::ZERO).unwrap();
+    let mut buffer = [0; 64];
+    let length = receiver.recv(&mut buffer).unwrap();
+    assert!(buffer[..length].starts_with(b"/remuir/registers\0\0\0,ii\0"));
+    let length = receiver.recv(&mut buffer).unwrap();
+    let mut expected = b"/remuir/halted\0\0,ii\0".to_vec();
+    expected.extend_from_slice(&1_i32.to_be_bytes());
+    expected.extend_from_slice(&2_i32.to_be_bytes());
+    assert_eq!(&buffer[..length], expected);
+}
+
+#[test]
+fn long_osc_messages_leave_off_registers() {
+    let event = Event { steps: 7, registers: vec![Register::from(1); 20_000], halted: false };
+    let message = emit::encode_osc_within(&event, emit::MAX_MESSAGE_LENGTH);
+    assert!(message.len() <= emit::MAX_MESSAGE_LENGTH);
+    // The address takes 20 bytes, then each argument takes 4 bytes and a byte for its tag.
+    let tags = message[20..].iter().position(|&byte| byte == 0).unwrap() - 1;
+    assert_eq!(tags, (emit::MAX_MESSAGE_LENGTH - 20 - 4) / 5);
+    assert_eq!(emit::encode_osc_within(&event, 0).len(), 28);
+    assert_eq!(emit::encode_osc(&event).len(), 20 + 20_004 + 20_001 * 4);
+}
+
+#[test]
+fn refused_osc_messages_are_dropped() {
+    let address = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let mut machine = parse_str(SOURCE).unwrap();
+    let mut osc = Osc::connect(address).unwrap();
+    emit::run(&mut machine, &mut osc, Duration::ZERO).unwrap();
+    assert!(machine.is_halted());
+}
+
+#[test]
+fn osc_messages_are_sent_over_ipv6() {
+    // Not every machine has IPv6 set up.
+    let Ok(receiver) = UdpSocket::bind("[::1]:0") else { return };
+    receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut machine = parse_str("registers 1\ninc r0").unwrap();
+    let mut osc = Osc::connect(receiver.local_addr().unwrap()).unwrap();
+    emit::run(&mut machine, &mut osc, Duration::ZERO).unwrap();
+    let mut buffer = [0; 64];
+    let length = receiver.recv(&mut buffer).unwrap();
+    assert!(buffer[..length].starts_with(b"/remuir/registers\0\0\0,ii\0"));
+}
@@ -0,0 +1,90 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    explain::Effect,
+    memory::RegisterNumber,
+    parser::parse_str,
+};
+
+#[test]
+fn multiplication_is_explained_with_its_parts() {
+    let machine = parse_str("registers 0 3 2
+outer: decjz r1 done
+copy: decjz r2 restore
+inc r0
+inc r-2
+decjz r-1 copy
+restore: decjz r-2 outer
+inc r2
+decjz r-1 restore
+done: halt").unwrap();
+    assert_eq!(machine.explain().to_string(), "   \
+# r-1 is always 0 and only used by DECJZ, so each use is a goto.
+   # lines 0–7: r0 += r1 × r2; r1 := 0 (multiplication by repeated addition, using r-2)
+0  outer: decjz r1 done
+   #   lines 1–7: r0 += r2 (copy loop, using r-2)
+   #     lines 1–4: r0 += r2; r-2 += r2; r2 := 0 (move loop)
+1  copy: decjz r2 restore
+2  inc r0
+3  inc r-2
+4  decjz r-1 copy
+   #     lines 5–7: r2 += r-2; r-2 := 0 (addition)
+5  restore: decjz r-2 outer
+6  inc r2
+7  decjz r-1 restore
+8  done: halt
+");
+}
+
+#[test]
+fn addition_and_draining() {
+    let machine = parse_str("registers 3 4
+inc r2
+add: decjz r0 clear
+inc r1
+inc r1
+decjz r-1 add
+clear: decjz r1 HALT
+decjz r-1 clear").unwrap();
+    let explanation = machine.explain();
+    let effects: Vec<(usize, usize, &Effect)> = explanation
+        .get_idioms()
+        .iter()
+        .map(|idiom| (idiom.start, idiom.end, &idiom.effect))
+        .collect();
+    assert_eq!(effects, vec![
+        (1, 4, &Effect::Move {
+            from: RegisterNumber::Natural(0),
+            to: vec![(RegisterNumber::Natural(1), 2)],
+        }),
+        (5, 6, &Effect::Drain(RegisterNumber::Natural(1))),
+    ]);
+    assert!(explanation.to_string().contains("# lines 1–4: r1 += 2 × r0; r0 := 0 (addition)\n"));
+}
+
+#[test]
+fn loops_entered_in_the_middle_are_not_explained() {
+    let machine = parse_str("registers 3
+decjz r-1 middle
+add: decjz r0 HALT
+middle: inc r1
+decjz r-1 add").unwrap();
+    assert!(machine.explain().get_idioms().is_empty());
+    // Nor are loops which change the register they count down.
+    let machine = parse_str("registers 3\nloop: decjz r0 HALT\ninc r0\ndecjz r-1 loop").unwrap();
+    assert!(machine.explain().get_idioms().is_empty());
+}
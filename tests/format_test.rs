@@ -0,0 +1,94 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    examples,
+    parser::{ format_source, parse_str, ParseSourceError },
+};
+
+const MESSY: &str = "\u{FEFF}# Moves r1 into r0.
+
+
+registers 0   2 # inputs
+#!entry   start
+macro move FROM TO # moves
+  top: decjz FROM end
+ inc TO # add
+
+  goto top
+   endmacro
+start:   move r1 r0
+## Counts.
+  a long label: inc r3
+
+decjz r-1   halt
+
+
+# The end.
+
+";
+
+const FORMATTED: &str = "# Moves r1 into r0.
+
+registers 0 2 # inputs
+#!entry start
+macro move FROM TO # moves
+    top: decjz FROM end
+    inc TO # add
+
+    goto top
+endmacro
+start:        move r1 r0
+## Counts.
+a long label: inc r3
+
+              decjz r-1 HALT
+
+# The end.
+";
+
+#[test]
+fn labels_are_aligned_and_comments_kept() {
+    assert_eq!(format_source(MESSY).unwrap(), FORMATTED);
+}
+
+#[test]
+fn formatting_keeps_the_program_the_same() {
+    let original = parse_str(MESSY).unwrap();
+    let formatted = parse_str(FORMATTED).unwrap();
+    assert_eq!(formatted.to_source(), original.to_source());
+    assert_eq!(formatted.get_label_documentation("a long label"), Some("Counts."));
+}
+
+#[test]
+fn formatting_twice_changes_nothing() {
+    assert_eq!(format_source(FORMATTED).unwrap(), FORMATTED);
+    for (name, _) in examples::GALLERY {
+        let formatted = format_source(&examples::by_name(name).unwrap().to_source()).unwrap();
+        assert_eq!(format_source(&formatted).unwrap(), formatted, "{name}");
+    }
+    // Libraries don't need a registers line.
+    let formatted = format_source(include_str!("../examples/square.remuir")).unwrap();
+    assert_eq!(format_source(&formatted).unwrap(), formatted);
+}
+
+#[test]
+fn only_valid_programs_are_formatted() {
+    assert!(matches!(
+        format_source("registers 0\ninc r0 r1"),
+        Err(ParseSourceError::SyntaxError(_)),
+    ));
+}
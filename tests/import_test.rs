@@ -0,0 +1,90 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    import::{ import_csv, ColumnMapping, ImportError },
+    memory::Memory,
+    parser::parse_str,
+};
+
+fn import(csv: &str) -> Result<String, ImportError> {
+    import_csv(csv, &ColumnMapping::default(), &Memory::new_from_slice(&[0, 4].map(Into::into)))
+}
+
+#[test]
+fn import_table() {
+    // Copies r1 to r0, jumping back to q0 from q1.
+    let source = import("state,instruction,register,next,target
+q0,decjz,r1,,halt
+q1,inc,0,q0,
+").unwrap();
+    assert_eq!(source, "registers 0 4\nq0: decjz r1 HALT\nq1: inc r0\ndecjz r-1 q0\n");
+    let mut machine = parse_str(&source).unwrap();
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 4 0");
+}
+
+#[test]
+fn custom_columns_and_quotes() {
+    let mut columns = ColumnMapping::default();
+    assert!(columns.set("instruction", "Op"));
+    assert!(columns.set("target", "If zero"));
+    assert!(!columns.set("colour", "Colour"));
+    let csv = "Op,register,\"If zero\",Comment\n-,-1,HALT,\"Uses r-1, so GOTO uses r-2\"\ninc,r-1,,\n";
+    let source = import_csv(csv, &columns, &Memory::default()).unwrap();
+    assert!(source.ends_with("\ndecjz r-1 HALT\ninc r-1\n"));
+}
+
+#[test]
+fn import_errors() {
+    assert!(matches!(import(""), Err(ImportError::Empty)));
+    assert!(matches!(
+        import("state,register\n"),
+        Err(ImportError::MissingColumn(c)) if c == "instruction"
+    ));
+    let error = import("instruction,register\ninc,r0\nmul,r1").unwrap_err();
+    assert_eq!(error.to_string(), "Row 3 has an unknown instruction \"mul\". Use inc, decjz or decjnz.");
+    assert!(matches!(
+        import("instruction,register\ninc,x"),
+        Err(ImportError::InvalidRegister { row: 2, .. }),
+    ));
+    assert!(matches!(
+        import("instruction,register\ndecjz,r0"),
+        Err(ImportError::MissingTarget { row: 2 }),
+    ));
+    assert!(matches!(
+        import("instruction,register,target\ndecjz,r0,nowhere"),
+        Err(ImportError::UnknownState { row: 2, .. }),
+    ));
+    assert!(matches!(
+        import("state,instruction,register\na,inc,r0\na,inc,r0"),
+        Err(ImportError::InvalidState { row: 3, .. }),
+    ));
+    assert!(matches!(
+        import("instruction,register\n\"inc,r0"),
+        Err(ImportError::UnterminatedQuote { row: 2 }),
+    ));
+}
+
+#[test]
+fn import_decjnz() {
+    let csv = "state,instruction,register,next,target\nq0,jnz,1,,q1\nq1,inc,0,halt,";
+    let source = import(csv).unwrap();
+    assert!(source.contains("q0: decjnz r1 q1\n"));
+    let mut machine = parse_str(&source).unwrap();
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 1 3");
+}
@@ -0,0 +1,99 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::{ sync::{ atomic::{ AtomicBool, Ordering }, Arc }, thread, time::Duration };
+
+use remuir::{
+    interrupt,
+    machine::{ TerminationReason, CANCEL_CHECK_INTERVAL },
+    parser::parse_str,
+    testing::ReplSession,
+};
+
+/// Loops forever, counting in r0.
+const FOREVER: &str = "registers 0\nloop: inc r0\ndecjz r1 loop";
+
+// Every interrupt is in this one test, since the flag is shared by the whole process.
+#[test]
+fn interrupts_stop_runs_at_a_step() {
+    let mut machine = parse_str(FOREVER).unwrap();
+    let interrupter = thread::spawn(|| {
+        thread::sleep(Duration::from_millis(50));
+        interrupt::request();
+    });
+    assert!(matches!(machine.debug(), Ok(TerminationReason::Interrupted)));
+    interrupter.join().unwrap();
+    let steps = machine.get_steps();
+    assert!(steps > 0);
+    assert!(!interrupt::take());
+    // The state is kept, so the run can carry on.
+    interrupt::request();
+    assert!(matches!(machine.debug(), Ok(TerminationReason::Interrupted)));
+    assert_eq!(machine.get_steps(), steps);
+
+    // A real Ctrl+C requests an interrupt rather than ending the process.
+    #[cfg(unix)]
+    {
+        assert!(interrupt::install_handler());
+        // SAFETY: raising a signal which has a handler installed.
+        assert_eq!(unsafe { libc::raise(libc::SIGINT) }, 0);
+        // The request is made on the handler's own thread, so it can take a moment.
+        let requested = (0..500).any(|_| {
+            thread::sleep(Duration::from_millis(10));
+            interrupt::take()
+        });
+        assert!(requested);
+        assert!(interrupt::install_handler());
+    }
+    // An interrupt requested at the prompt, before "play", is ignored.
+    interrupt::request();
+    let mut session = ReplSession::debug(parse_str("registers 0\ninc r0").unwrap());
+    session.expect("play", "Machine successfully halted.");
+    let mut session = ReplSession::debug(machine);
+    let interrupter = thread::spawn(|| {
+        thread::sleep(Duration::from_millis(50));
+        interrupt::request();
+    });
+    session.expect("play", "Interrupted after ");
+    interrupter.join().unwrap();
+    assert!(session.get_machine().get_steps() > steps);
+}
+
+// A cancellation flag belongs to its caller, so this doesn't touch the shared interrupt flag.
+#[test]
+fn cancellation_flags_stop_runs_from_another_thread() {
+    let mut machine = parse_str(FOREVER).unwrap();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let canceller = {
+        let cancel = Arc::clone(&cancel);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cancel.store(true, Ordering::Relaxed);
+        })
+    };
+    assert!(matches!(machine.run_with_cancel(&cancel), TerminationReason::Interrupted));
+    canceller.join().unwrap();
+    let steps = machine.get_steps();
+    assert!(steps > 0);
+    assert_eq!(steps % CANCEL_CHECK_INTERVAL, 0);
+    // The flag is left set, so running again stops straight away.
+    assert!(matches!(machine.run_with_cancel(&cancel), TerminationReason::Interrupted));
+    assert_eq!(machine.get_steps(), steps);
+
+    let mut machine = parse_str("registers 0\ninc r0").unwrap();
+    assert!(matches!(machine.run_with_cancel(&AtomicBool::new(false)), TerminationReason::Halted));
+    assert_eq!(machine.get_steps(), 1);
+}
@@ -0,0 +1,107 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    io_handler::{ BufferIo, IoError },
+    machine::{ RuntimeError, TerminationReason },
+    memory::{ Register, RegisterNumber },
+    parser::{ parse_str, ParseSourceError },
+};
+
+/// Writes one more than each number it reads, until it reads 0.
+const ECHO: &str = "registers 0
+loop: read r1
+decjz r1 done
+inc r1
+inc r1
+write r1
+goto loop
+done: halt";
+
+fn registers(values: &[u128]) -> Vec<Register> {
+    values.iter().map(|&value| Register::from(value)).collect()
+}
+
+#[test]
+fn reads_and_writes_go_through_the_handler() {
+    let mut machine = parse_str(ECHO).unwrap();
+    let io = BufferIo::new(registers(&[3, 9, 0, 5]));
+    machine.set_io_handler(io.clone());
+    machine.run();
+    assert!(machine.is_halted());
+    assert_eq!(io.get_output(), registers(&[4, 10]));
+    assert_eq!(io.get_remaining_input(), registers(&[5]));
+}
+
+#[test]
+fn running_out_of_input_stops_before_the_read() {
+    let mut machine = parse_str(ECHO).unwrap();
+    machine.set_io_handler(BufferIo::new(registers(&[1])));
+    machine.run();
+    assert!(!machine.is_halted());
+    assert_eq!(machine.get_current_line_number(), 0);
+    assert_eq!(machine.get_steps(), 6);
+    assert_eq!(machine.get_io_error(), Some(&IoError::EndOfInput));
+    // Resetting forgets the error.
+    machine.reset();
+    assert_eq!(machine.get_io_error(), None);
+}
+
+#[test]
+fn call_returns_io_errors() {
+    let mut machine = parse_str(ECHO).unwrap();
+    machine.set_io_handler(BufferIo::default());
+    assert!(matches!(machine.call(&[]), Err(RuntimeError::Io(IoError::EndOfInput))));
+}
+
+#[test]
+fn reads_trigger_watchpoints() {
+    let mut machine = parse_str("registers 0\nread r0\nwrite r0").unwrap();
+    let io = BufferIo::new(registers(&[2]));
+    machine.set_io_handler(io.clone());
+    machine.toggle_watchpoint(RegisterNumber::Natural(0));
+    assert!(matches!(
+        machine.step(),
+        Ok(Some(TerminationReason::Watchpoint { register: RegisterNumber::Natural(0), .. })),
+    ));
+    // Writing doesn't change the register.
+    assert!(matches!(machine.step(), Ok(Some(TerminationReason::Halted))));
+    assert_eq!(io.get_output(), registers(&[2]));
+}
+
+#[test]
+fn copies_share_the_handler() {
+    let mut machine = parse_str("registers 7\nwrite r0").unwrap();
+    let io = BufferIo::default();
+    machine.set_io_handler(io.clone());
+    let mut copy = machine.clone();
+    machine.run();
+    copy.run();
+    assert_eq!(io.take_output(), registers(&[7, 7]));
+    assert!(io.get_output().is_empty());
+    // The handler doesn't make machines differ.
+    assert_eq!(copy, machine);
+}
+
+#[test]
+fn pseudo_registers_cant_be_read_into() {
+    assert!(matches!(
+        parse_str("registers 0\n#!extended\nread r!steps"),
+        Err(ParseSourceError::ReadOnlyRegister(RegisterNumber::Pseudo(_))),
+    ));
+    let source = parse_str("registers 0\nread r1\nwrite r-2").unwrap().to_source();
+    assert!(source.ends_with("\nread r1\nwrite r-2"), "{source}");
+}
@@ -0,0 +1,125 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    link::{ link, LinkError },
+    machine::Machine,
+    parser::{ parse_library_str, parse_str },
+};
+
+fn double() -> (String, Machine) {
+    let library = parse_library_str("start: decjz r1 done
+inc r0
+inc r0
+decjz r9 start
+done: decjz r9 halt").unwrap();
+    (String::from("double"), library)
+}
+
+#[test]
+fn library_labels_are_scoped() {
+    let main = parse_str("registers 0 3\nstart: decjz r9 double::start").unwrap();
+    let mut linked = link(&main, &[double()]).unwrap();
+    let labels: Vec<&str> = linked.get_labels().iter().map(|(label, _)| label.as_str()).collect();
+    assert_eq!(labels, ["start", "double::start", "double::done"]);
+    assert_eq!(linked.get_label_line("double::start"), Some(1));
+    linked.run();
+    assert_eq!(linked.display_nat_registers(), "registers 6 0 0 0 0 0 0 0 0 0");
+}
+
+#[test]
+fn unqualified_references_prefer_own_scope() {
+    let main = parse_str("registers 0 1\ndecjz r9 double::start\nstart: inc r2").unwrap();
+    let linked = link(&main, &[double()]).unwrap();
+    // The library's "decjz r9 start" loops back to its own start, not the main program's.
+    assert_eq!(linked.get_lines()[5].to_string(), "5    decjz r9 double::start");
+}
+
+#[test]
+fn library_can_use_main_labels() {
+    let main = parse_str("registers 0 0\ndecjz r0 lib::go\nback: inc r0\ndecjz r1 halt").unwrap();
+    let library = parse_library_str("go: decjz r0 back").unwrap();
+    let mut linked = link(&main, &[(String::from("lib"), library)]).unwrap();
+    linked.run();
+    assert_eq!(linked.display_nat_registers(), "registers 1 0");
+}
+
+#[test]
+fn link_errors() {
+    let main = parse_str("registers 0\ndecjz r0 double::missing").unwrap();
+    assert_eq!(link(&main, &[double()]).unwrap_err(), LinkError::LabelNotFound {
+        label: String::from("double::missing"),
+        scope: String::from("the main program"),
+        line: 0,
+    });
+
+    let main = parse_str("registers 0\ndouble::start: inc r0").unwrap();
+    assert_eq!(link(&main, &[double()]).unwrap_err(), LinkError::DuplicateLabel {
+        label: String::from("double::start"),
+    });
+
+    let main = parse_str("registers 0\ninc r0").unwrap();
+    let (_, library) = double();
+    assert_eq!(
+        link(&main, &[(String::from("a:b"), library)]).unwrap_err(),
+        LinkError::InvalidLibraryName(String::from("a:b")),
+    );
+}
+
+#[test]
+fn imports_and_exports_are_enforced() {
+    let library = || {
+        let source = "#!export start\nstart: decjz r1 done\ninc r0\ndecjz r9 start\ndone: halt";
+        (String::from("lib"), parse_library_str(source).unwrap())
+    };
+    let main = parse_str("registers 0 2\n#!import lib::start\ndecjz r9 lib::start").unwrap();
+    let mut linked = link(&main, &[library()]).unwrap();
+    linked.run();
+    assert_eq!(linked.display_nat_registers(), "registers 2 0 0 0 0 0 0 0 0 0");
+
+    let main = parse_str("registers 0\ndecjz r9 lib::done").unwrap();
+    assert_eq!(link(&main, &[library()]).unwrap_err(), LinkError::NotExported {
+        label: String::from("lib::done"),
+        scope: String::from("the main program"),
+    });
+    let main = parse_str("registers 0\n#!import lib::done\ninc r0").unwrap();
+    assert!(matches!(link(&main, &[library()]), Err(LinkError::NotExported { .. })));
+    let source = "registers 0\n#!import lib::start\ninc r0\ndecjz r9 double::start";
+    let main = parse_str(source).unwrap();
+    assert_eq!(link(&main, &[library(), double()]).unwrap_err(), LinkError::NotImported {
+        label: String::from("double::start"),
+        scope: String::from("the main program"),
+        line: 1,
+    });
+    let main = parse_str("registers 0\n#!import lib::stop\ninc r0").unwrap();
+    assert_eq!(link(&main, &[library()]).unwrap_err(), LinkError::ImportNotFound {
+        label: String::from("lib::stop"),
+        scope: String::from("the main program"),
+    });
+    // A library's own labels don't need to be exported or imported.
+    let main = parse_str("registers 0\n#!export end\nend: halt").unwrap();
+    assert!(link(&main, &[library()]).is_ok());
+}
+
+#[test]
+fn comments_starting_with_import_or_export_are_ignored() {
+    let main = parse_str("registers 0 2\n#import foo\n#export stuff later\ndecjz r9 double::start")
+        .unwrap();
+    assert!(main.get_imports().is_empty());
+    assert!(main.get_exports().is_empty());
+    // Without any imports, every library label can be used.
+    assert!(link(&main, &[double()]).is_ok());
+}
@@ -0,0 +1,63 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    examples,
+    memory::RegisterNumber,
+    parser::{ lint, parse_str, Warning },
+};
+
+#[test]
+fn examples_have_no_warnings() {
+    for (name, _) in examples::GALLERY {
+        assert_eq!(lint(&examples::by_name(name).unwrap()), Vec::new(), "{name}");
+    }
+}
+
+#[test]
+fn warnings_are_in_line_order() {
+    let machine = parse_str("registers 0 1
+#!entry start
+unused: inc r0
+start: decjz r1 end
+decjz r-1 start
+inc r0
+inc r0
+end: decjz r3 end
+decjnz r-2 end
+decjz r-1 end").unwrap();
+    assert_eq!(lint(&machine), vec![
+        Warning::UnusedLabel { label: String::from("unused"), line: 3 },
+        Warning::Unreachable { first: 3, last: 3 },
+        Warning::Unreachable { first: 6, last: 7 },
+        Warning::NeverIncremented { register: RegisterNumber::Natural(3), line: 8 },
+        Warning::NeverIncremented { register: RegisterNumber::Negative(2), line: 9 },
+    ]);
+    assert_eq!(lint(&machine)[2].to_string(), "Lines 6 to 7 can never be reached.");
+}
+
+#[test]
+fn labels_used_by_directives_are_not_unused() {
+    let machine = parse_str("registers 0\n#!phase counting\n#!export done\ncounting: inc r0\ndone: halt")
+        .unwrap();
+    assert_eq!(lint(&machine), Vec::new());
+}
+
+#[test]
+fn labels_used_with_an_offset_are_not_unused() {
+    let machine = parse_str("registers 0 2\nstart: inc r2\ndecjz r1 HALT\ninc r0\ngoto start+1").unwrap();
+    assert_eq!(lint(&machine), Vec::new());
+}
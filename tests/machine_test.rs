@@ -0,0 +1,484 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::num::NonZeroUsize;
+
+use remuir::{
+    cost::{ CostModel, CostModelParseError },
+    listing::Listing,
+    lockstep::Lockstep,
+    memory::{ Register, RegisterNumber },
+    parser::{ parse_str, ParseSourceError },
+    machine::{ Identifier, Machine, RuntimeError, TerminationReason },
+};
+
+fn copy_machine() -> Machine {
+    parse_str("registers 0 3
+loop1: decjz r1 loop2
+inc r0
+inc r-2
+decjz r-1 loop1
+loop2: decjz r-2 halt
+inc r1
+decjz r-1 loop2").unwrap()
+}
+
+#[test]
+fn xref_jumps_to_label() {
+    let machine = copy_machine();
+    let xref = machine.get_cross_reference();
+    assert_eq!(xref.jumps_to(&Identifier::Label(String::from("loop1"))), &[3]);
+    assert_eq!(xref.jumps_to(&Identifier::Label(String::from("loop2"))), &[0, 6]);
+    assert_eq!(xref.jumps_to(&Identifier::Halt), &[4]);
+    assert!(xref.jumps_to(&Identifier::Label(String::from("nowhere"))).is_empty());
+}
+
+#[test]
+fn xref_jumps_by_line_number() {
+    let mut machine = parse_str("registers 0 3
+inc r5
+decjz r5 +1
+loop: decjz r1 loop+3
+inc r0
+goto loop
+inc r2").unwrap();
+    // The relative jump on line 1 lands on loop too.
+    let lines = machine.get_cross_reference().jumps_to(&Identifier::Label(String::from("loop")));
+    assert_eq!(lines, &[1, 4]);
+    machine.add_new_label(String::from("end"), 5).unwrap();
+    let lines = machine.get_cross_reference().jumps_to(&Identifier::Label(String::from("end")));
+    assert_eq!(lines, &[2]);
+}
+
+#[test]
+fn xref_uses_register() {
+    let machine = copy_machine();
+    let xref = machine.get_cross_reference();
+    assert_eq!(xref.uses_register(RegisterNumber::Natural(1)), &[0, 5]);
+    assert_eq!(xref.uses_register(RegisterNumber::Negative(1)), &[3, 6]);
+    assert!(xref.uses_register(RegisterNumber::Natural(7)).is_empty());
+}
+
+#[test]
+fn protected_register_stops_machine() {
+    let mut machine = copy_machine();
+    machine.toggle_protection(RegisterNumber::Natural(1));
+    // The first line decrements r1, so it shouldn't be executed.
+    assert!(matches!(
+        machine.debug(),
+        Ok(TerminationReason::ProtectedRegister(RegisterNumber::Natural(1))),
+    ));
+    assert_eq!(machine.get_current_line_number(), 0);
+    assert_eq!(machine.display_nat_registers(), "registers 0 3");
+
+    machine.toggle_protection(RegisterNumber::Natural(1));
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 3 3");
+}
+
+#[test]
+fn steps_counted_per_phase() {
+    let mut machine = parse_str("registers 2
+#!phase drain
+#!phase fill
+inc r0
+drain: decjz r0 fill
+decjz r-1 drain
+fill: decjz r-2 HALT").unwrap();
+    machine.run();
+    assert_eq!(machine.get_steps(), 9);
+    assert_eq!(
+        machine.get_phase_steps(),
+        &[(None, 1), (Some(String::from("drain")), 7), (Some(String::from("fill")), 1)],
+    );
+    machine.reset();
+    assert_eq!(machine.get_steps(), 0);
+    assert!(machine.get_phase_steps().is_empty());
+}
+
+#[test]
+fn phase_with_unknown_label() {
+    assert!(matches!(
+        parse_str("registers 2\n#!phase nowhere\ninc r0"),
+        Err(ParseSourceError::PhaseLabelNotFound { .. }),
+    ));
+}
+
+#[test]
+fn comments_can_start_with_directive_words() {
+    let source = "#!/usr/bin/env remuir\nregisters 2\n#phase one\n#extended\nloop: decjz r0 HALT\n\
+        # phase two\ngoto loop";
+    let mut machine = parse_str(source).unwrap();
+    assert!(machine.get_phases().is_empty());
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 0");
+    let Err(ParseSourceError::SyntaxError(error)) = parse_str("registers 2\n#!phse loop\ninc r0")
+    else {
+        panic!("Expected a syntax error.");
+    };
+    assert!(error.to_string().contains("expected a directive"));
+}
+
+#[test]
+fn entry_point() {
+    let mut machine = parse_str("registers 0 2
+add: inc r0
+decjz r-1 done
+#!entry main
+main: decjz r1 done
+decjz r-1 add
+done: decjz r-1 halt").unwrap();
+    assert_eq!(machine.get_entry(), Some("main"));
+    assert_eq!(machine.get_current_line_number(), 2);
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 1 1");
+    machine.reset();
+    assert_eq!(machine.get_current_line_number(), 2);
+    assert_eq!(
+        parse_str(&machine.to_source()).unwrap().to_source(),
+        machine.to_source(),
+    );
+}
+
+#[test]
+fn entry_point_errors() {
+    assert!(matches!(
+        parse_str("registers 2\n#!entry nowhere\ninc r0"),
+        Err(ParseSourceError::EntryLabelNotFound { .. }),
+    ));
+    assert!(matches!(
+        parse_str("registers 2\n#!entry a\n#!entry a\na: inc r0"),
+        Err(ParseSourceError::MultipleEntries),
+    ));
+}
+
+#[test]
+fn comments_starting_with_entry_or_phase_are_ignored() {
+    let machine = parse_str("registers 2\n#entry point is the first line\n#phase: none\nhere: inc r0")
+        .unwrap();
+    assert_eq!(machine.get_entry(), None);
+    assert!(machine.get_phases().is_empty());
+    assert_eq!(machine.get_current_line_number(), 0);
+}
+
+#[test]
+fn call_uses_textbook_convention() {
+    // r0 := r1 + r2
+    let mut machine = parse_str("registers 0
+first: decjz r1 second
+inc r0
+decjz r-1 first
+second: decjz r2 HALT
+inc r0
+decjz r-1 second").unwrap();
+    let outputs = machine.call(&[3, 4]).unwrap();
+    assert_eq!(outputs[0].to_u128(), Some(7));
+    let outputs = machine.call(&[10, 0]).unwrap();
+    assert_eq!(outputs[0].to_u128(), Some(10));
+}
+
+#[test]
+fn call_respects_step_limit() {
+    let mut machine = parse_str("registers 0
+loop: inc r0
+decjz r-1 loop").unwrap();
+    machine.set_step_limit(Some(100));
+    assert!(matches!(machine.call(&[]), Err(RuntimeError::StepLimitReached { limit: 100 })));
+}
+
+#[test]
+fn pseudo_registers_count_steps_and_visits() {
+    // Count the second and later visits to "again" in r1, and stop once r!steps reaches 0 again,
+    // which it never does, so the step limit ends the run.
+    let mut machine = parse_str("registers 0
+#!extended
+again: decjz r!visits skip
+inc r1
+skip: inc r0
+decjz r!steps again
+decjz r-1 again").unwrap();
+    machine.set_step_limit(Some(12));
+    machine.run();
+    assert_eq!(machine.get_state().get_nat_registers_as_u128(), vec![3, 2]);
+    // Resetting starts counting again.
+    machine.reset();
+    machine.run_steps(6);
+    assert_eq!(machine.get_state().get_nat_registers_as_u128(), vec![1, 1]);
+}
+
+#[test]
+fn run_stops_at_step_limit() {
+    let mut machine = parse_str("registers 0
+loop: inc r0
+decjz r-1 loop").unwrap();
+    machine.set_step_limit(Some(100));
+    assert_eq!(machine.run_steps(30), 30);
+    machine.run();
+    assert!(!machine.is_halted());
+    assert!(machine.is_step_limit_reached());
+    assert_eq!(machine.get_steps(), 100);
+    assert_eq!(machine.run_steps(30), 0);
+}
+
+#[test]
+fn diff_reports_differences() {
+    let left = copy_machine();
+    let mut right = parse_str("registers 0 4
+loop1: decjz r1 loop2
+inc r0
+inc r-2
+decjz r-1 loop1
+loop2: decjz r-2 HALT
+inc r2").unwrap();
+    assert!(left.diff(&copy_machine()).is_empty());
+
+    let diff = left.diff(&right);
+    assert_eq!(diff.lines.iter().map(|(n, _, _)| *n).collect::<Vec<_>>(), vec![5, 6]);
+    assert!(diff.labels.is_empty());
+    assert_eq!(
+        diff.to_string(),
+        "Lines:
+  5: inc r1 != inc r2
+  6: decjz r-1 loop2 != (missing)
+Registers:
+  r1: 3 != 4
+",
+    );
+
+    // Decrementing r1 makes the registers match, but the instruction pointers now differ.
+    right.step().unwrap();
+    let diff = left.diff(&right);
+    assert!(diff.registers.is_empty());
+    assert_eq!(diff.current_line, Some((0, 1)));
+}
+
+#[test]
+fn display_listing() {
+    let mut machine = parse_str("registers 10 5
+loop: decjz r1 halt
+decjz r0 halt
+decjz r2 loop").unwrap();
+    machine.toggle_breakpoint(&Identifier::Line(1)).unwrap();
+    machine.step().unwrap();
+    assert_eq!(machine.to_string(), "registers 10 4
+   0  loop: decjz r1 HALT
+=>*1  decjz r0 HALT
+   2  decjz r2 loop
+");
+}
+
+#[test]
+fn metrics() {
+    let metrics = copy_machine().metrics();
+    assert_eq!(metrics.lines, 7);
+    assert_eq!(metrics.inc_count, 3);
+    assert_eq!(metrics.decjz_count, 4);
+    assert_eq!(metrics.labels, 2);
+    assert_eq!(metrics.max_natural_register, Some(1));
+    assert_eq!(metrics.max_negative_register, Some(2));
+    assert_eq!(metrics.cyclomatic_complexity, 5);
+    assert_eq!(metrics.to_string(), "Instructions: 7 (3 inc, 4 decjz)
+Labels: 2
+Highest natural register: r1
+Highest negative register: r-2
+Cyclomatic complexity: 5
+");
+}
+
+#[test]
+fn metrics_jump_to_next_line_is_not_a_branch() {
+    let metrics = parse_str("registers 0\ndecjz r0 next\nnext: inc r0").unwrap().metrics();
+    assert_eq!(metrics.cyclomatic_complexity, 1);
+    assert_eq!(metrics.max_negative_register, None);
+}
+
+#[test]
+fn compaction_keeps_output() {
+    let source = "registers 0 3
+inc r8
+inc r-4
+loop: decjz r8 done
+decjz r-4 loop
+done: decjz r1 halt
+inc r0
+decjz r9 done";
+    let mut compacted = parse_str(source).unwrap();
+    compacted.set_compaction_interval(NonZeroUsize::new(2));
+    compacted.run();
+    let mut plain = parse_str(source).unwrap();
+    plain.run();
+    let stored = |machine: &Machine| machine.get_state().get_nat_registers().len();
+    assert!(stored(&compacted) < stored(&plain));
+    assert_eq!(compacted.display_nat_registers(), plain.display_nat_registers());
+    assert_eq!(compacted.get_state(), plain.get_state());
+}
+
+#[test]
+fn lockstep_agrees_with_itself() {
+    let result = copy_machine().lockstep(&mut copy_machine(), 1000);
+    assert_eq!(result, Lockstep::Agreed { steps: 23 });
+    assert!(result.is_agreement());
+
+    let mut forever = parse_str("registers 0\nloop: decjz r0 loop").unwrap();
+    let mut also_forever = parse_str("registers 0\ninc r-1\nloop: decjz r-2 loop").unwrap();
+    assert_eq!(forever.lockstep(&mut also_forever, 50), Lockstep::StepLimitReached { steps: 50 });
+}
+
+#[test]
+fn lockstep_finds_first_difference() {
+    let mut left = parse_str("registers 0 0\ninc r0\ninc r1\ninc r1").unwrap();
+    let mut right = parse_str("registers 0 0\ninc r0\ninc r0\ninc r1").unwrap();
+    let result = left.lockstep(&mut right, 1000);
+    assert_eq!(result, Lockstep::Diverged {
+        steps: 2,
+        registers: vec![
+            (RegisterNumber::Natural(0), Register::from(1), Register::from(2)),
+            (RegisterNumber::Natural(1), Register::from(1), Register::from(0)),
+        ],
+        stopped: (false, false),
+    });
+    assert_eq!(result.to_string(), "Machines differ after 2 steps.\n  r0: 1 != 2\n  r1: 1 != 0\n");
+
+    let mut left = parse_str("registers 0\ninc r0").unwrap();
+    let mut right = parse_str("registers 0\ninc r0\ninc r-1").unwrap();
+    assert_eq!(left.lockstep(&mut right, 1000), Lockstep::Diverged {
+        steps: 1,
+        registers: Vec::new(),
+        stopped: (true, false),
+    });
+}
+
+#[test]
+fn fingerprint() {
+    let machine = copy_machine();
+    // Registers, comments and formatting don't change the fingerprint.
+    let reformatted = parse_str("# Copy r1 to r0.\nregisters 5 1\n\nloop1:  decjz r1 loop2\ninc r0\n\
+        inc r-2\ndecjz r-1 loop1\nloop2: decjz r-2 halt\ninc r1 # Restore r1.\ndecjz r-1 loop2\n");
+    assert_eq!(machine.fingerprint(), reformatted.unwrap().fingerprint());
+    assert_eq!(machine.fingerprint(), parse_str(&machine.to_source()).unwrap().fingerprint());
+    // The fingerprint is stable, so it can be stored.
+    assert_eq!(machine.fingerprint(), 15_025_104_680_486_863_984);
+    // Any change to the instructions or labels does change it.
+    let renamed = parse_str(&machine.to_source().replace("loop2", "done")).unwrap();
+    assert_ne!(machine.fingerprint(), renamed.fingerprint());
+    let changed = parse_str(&machine.to_source().replace("inc r0", "inc r1")).unwrap();
+    assert_ne!(machine.fingerprint(), changed.fingerprint());
+}
+
+#[test]
+fn register_heat() {
+    let mut machine = copy_machine();
+    machine.run();
+    assert!(machine.get_register_heat().is_empty());
+    machine.reset();
+    machine.set_register_heat(true);
+    machine.run();
+    let heat: Vec<(RegisterNumber, usize, usize, usize)> = machine
+        .get_register_heat()
+        .into_iter()
+        .map(|(register, heat)| (register, heat.reads, heat.increments, heat.decrements))
+        .collect();
+    assert_eq!(heat, vec![
+        (RegisterNumber::Natural(1), 4, 3, 3),
+        (RegisterNumber::Negative(2), 4, 3, 3),
+        (RegisterNumber::Negative(1), 6, 0, 0),
+        (RegisterNumber::Natural(0), 0, 3, 0),
+    ]);
+    let mut out = Vec::new();
+    remuir::tui::heat_report(&mut out, &machine, 2).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "Hottest registers:
+register       reads  increments  decrements
+r1                 4           3           3
+r-2                4           3           3
+");
+    machine.reset();
+    assert!(machine.get_register_heat().is_empty());
+}
+
+#[test]
+fn cost_model() {
+    let mut machine = copy_machine();
+    machine.run();
+    assert_eq!(machine.get_cost(), 0);
+    machine.reset();
+    // Without any costs given, the cost is the number of steps.
+    machine.set_cost_model(Some(CostModel::default()));
+    machine.run();
+    assert_eq!(machine.get_steps(), 23);
+    assert_eq!(machine.get_cost(), 23);
+    machine.reset();
+    assert_eq!(machine.get_cost(), 0);
+    let model: CostModel = "inc=0, decjz_taken=2".parse().unwrap();
+    assert_eq!(model.to_string(), "inc=0,decjz_taken=2,decjz_not_taken=1,decjnz_taken=1,\
+        decjnz_not_taken=1,goto=1,call=1,return=1,read=1,write=1,halt=1");
+    machine.set_cost_model(Some(model));
+    machine.run();
+    // 8 jumps taken and 6 not taken.
+    assert_eq!(machine.get_cost(), 22);
+    assert_eq!(
+        "jump=2".parse::<CostModel>(),
+        Err(CostModelParseError::UnknownName(String::from("jump"))),
+    );
+    assert_eq!(
+        "inc=-1".parse::<CostModel>(),
+        Err(CostModelParseError::Malformed(String::from("inc=-1"))),
+    );
+}
+
+#[test]
+fn unknown_labels_suggest_the_closest() {
+    let mut machine = parse_str("registers 0\nloop: inc r0\ndone: halt").unwrap();
+    let error = machine.go_to_identifier(&Identifier::Label(String::from("lop"))).unwrap_err();
+    assert_eq!(error.to_string(), "Cannot find label \"lop\" in the code! Did you mean \"loop\"?");
+    let error = machine.toggle_breakpoint(&Identifier::Label(String::from("end"))).unwrap_err();
+    assert_eq!(error.to_string(), "Cannot find label \"end\" in the code!");
+    assert!(matches!(
+        parse_str("registers 0\n#!entry dne\ndone: halt"),
+        Err(ParseSourceError::EntryLabelNotFound { suggestion: Some(label), .. })
+            if label == "done",
+    ));
+}
+
+#[test]
+fn relative_identifiers_count_from_the_current_line() {
+    let mut machine = copy_machine();
+    machine.go_to_identifier(&Identifier::Relative(2)).unwrap();
+    assert_eq!(machine.get_current_line_number(), 2);
+    machine.go_to_identifier(&Identifier::Relative(-1)).unwrap();
+    assert_eq!(machine.get_current_line_number(), 1);
+    assert!(machine.go_to_identifier(&Identifier::Relative(-2)).is_err());
+    assert!(machine.go_to_identifier(&Identifier::Relative(100)).is_err());
+    machine.toggle_breakpoint(&Identifier::Relative(1)).unwrap();
+    assert_eq!(machine.get_breakpoints(), [2]);
+}
+
+#[test]
+fn listing_shows_jumps_after_labels() {
+    let mut machine = parse_str("registers 0 1
+decjz r1 +2
+loop: inc r0
+decjz r1 -2
+goto loop").unwrap();
+    // A jump to a line without a label above it keeps its line number.
+    assert_eq!(Listing::new(&machine).to_string(), "=> 0  decjz r1 loop+1
+   1  loop: inc r0
+   2  decjz r1 0
+   3  goto loop
+");
+    // Breakpoints can be given the same way.
+    machine.toggle_breakpoint(&"loop+1".parse().unwrap()).unwrap();
+    assert_eq!(machine.get_breakpoints(), &[2]);
+}
@@ -0,0 +1,70 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{ cost::CostModel, manifest::Manifest, parser::parse_str };
+
+const SOURCE: &str = "registers 2 3
+loop: decjz r0 end
+inc r1
+decjnz r1 loop
+end: halt";
+
+#[test]
+fn manifest_describes_the_machine() {
+    let mut machine = parse_str(SOURCE).unwrap();
+    machine.set_strict_jumps(true);
+    machine.set_step_limit(Some(100));
+    machine.set_cost_model(Some(CostModel::default()));
+    let manifest = Manifest::new(&machine);
+    assert_eq!(manifest.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(manifest.fingerprint, machine.fingerprint());
+    assert_eq!(manifest.registers, "registers 2 3");
+    assert!(manifest.strict_jumps);
+    assert_eq!(manifest.step_limit, Some(100));
+    assert_eq!(manifest.cost_model, Some(CostModel::default().to_string()));
+    assert!(!manifest.optimized);
+    assert_eq!(manifest.seed, None);
+
+    // The initial registers are kept, however far the machine has run.
+    machine.run();
+    assert_eq!(Manifest::new(&machine), manifest);
+}
+
+#[test]
+fn manifest_formats() {
+    let machine = parse_str(SOURCE).unwrap();
+    let manifest = Manifest {
+        version: String::from("0.1.0"),
+        optimized: true,
+        seed: Some(42),
+        ..Manifest::new(&machine)
+    };
+    let fingerprint = format!("{:016x}", machine.fingerprint());
+    assert_eq!(manifest.to_string(), format!("remuir 0.1.0
+fingerprint {fingerprint}
+registers 2 3
+optimised yes
+strict jumps no
+step limit none
+cost model none
+seed 42
+"));
+    let json = manifest.to_json();
+    assert_eq!(json["fingerprint"], fingerprint.as_str());
+    assert_eq!(json["optimized"], true);
+    assert_eq!(json["seed"], 42);
+    assert!(json["stepLimit"].is_null());
+}
@@ -0,0 +1,77 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{ obfuscate::obfuscate, parser::{ parse_str, parse_str_permissive } };
+
+/// Multiply r1 by r2 into r0.
+const MULTIPLY: &str = "registers 0 3 2
+#!phase outer
+outer: decjz r1 done
+copy: decjz r2 restore
+inc r0
+inc r-2
+decjz r-1 copy
+restore: decjz r-2 outer
+inc r2
+decjz r-1 restore
+done: halt";
+
+/// Run the source code and its obfuscated versions for several seeds, checking they all give the
+/// same registers. Programs are parsed permissively, so that jumps to labels which don't exist can
+/// be checked too.
+fn assert_same_result(source: &str) {
+    let mut original = parse_str_permissive(source).unwrap();
+    original.run();
+    for seed in 0..20 {
+        let obfuscated = obfuscate(&parse_str_permissive(source).unwrap(), seed);
+        // Go through the source code, as the obfuscate command does.
+        let mut reparsed = parse_str_permissive(&obfuscated.to_source()).unwrap();
+        reparsed.run();
+        assert_eq!(
+            reparsed.display_nat_registers(),
+            original.display_nat_registers(),
+            "seed {seed}:\n{}",
+            obfuscated.to_source(),
+        );
+    }
+}
+
+#[test]
+fn obfuscated_programs_compute_the_same_result() {
+    assert_same_result(MULTIPLY);
+    // Falling off the end of the program, and a DECJNZ loop.
+    assert_same_result("registers 2\nloop: decjz r0 end\ninc r1\ninc r1\ndecjnz r0 loop\nend: inc r2");
+    // An entry point which isn't the first line, and a jump to a label which doesn't exist.
+    assert_same_result("registers 1\n#!entry start\ninc r1\nstart: decjz r0 nowhere\ninc r2\ninc r2");
+}
+
+#[test]
+fn labels_and_phases_are_hidden() {
+    let source = obfuscate(&parse_str(MULTIPLY).unwrap(), 1).to_source();
+    for label in ["outer", "copy", "restore", "done", "#!phase"] {
+        assert!(!source.contains(label), "{source}");
+    }
+    // The block execution starts in stays first.
+    assert!(source.lines().nth(2).unwrap().contains(": decjz r1 "), "{source}");
+}
+
+#[test]
+fn seeds_give_the_same_program_every_time() {
+    let machine = parse_str(MULTIPLY).unwrap();
+    assert_eq!(obfuscate(&machine, 7).to_source(), obfuscate(&machine, 7).to_source());
+    assert_ne!(obfuscate(&machine, 7).to_source(), obfuscate(&machine, 8).to_source());
+    assert!(obfuscate(&parse_str("registers 1 2").unwrap(), 0).get_lines().is_empty());
+}
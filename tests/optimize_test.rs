@@ -0,0 +1,145 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+
+use remuir::{
+    machine::Machine,
+    memory::RegisterNumber,
+    optimize::{ goto_registers, peephole },
+    parser::parse_str,
+};
+
+#[test]
+fn removes_inverse_pair() {
+    let machine = parse_str("registers 1
+inc r3
+decjz r3 HALT
+inc r0").unwrap();
+    let mut optimized = peephole(&machine);
+    assert_eq!(optimized.get_lines().len(), 1);
+    optimized.run();
+    assert_eq!(optimized.display_nat_registers(), "registers 2");
+}
+
+#[test]
+fn removes_jump_to_next_line() {
+    let machine = parse_str("registers 1
+decjz r-1 next
+next: inc r0
+decjz r-1 HALT").unwrap();
+    let optimized = peephole(&machine);
+    assert_eq!(optimized.to_source(), "registers 1\n\nnext: inc r0");
+    let machine = parse_str("registers 1\ngoto next\nnext: inc r0\ngoto HALT").unwrap();
+    assert_eq!(peephole(&machine).to_source(), "registers 1\n\nnext: inc r0");
+}
+
+#[test]
+fn removes_increment_before_drain() {
+    let machine = parse_str("registers 5
+inc r0
+drain: decjz r0 done
+decjz r-1 drain
+done: inc r1").unwrap();
+    let mut optimized = peephole(&machine);
+    assert_eq!(optimized.get_lines().len(), 3);
+    optimized.run();
+    assert_eq!(optimized.display_nat_registers(), "registers 0 1");
+}
+
+#[test]
+fn keeps_pair_which_is_jumped_into() {
+    let machine = parse_str("registers 1
+inc r3
+back: decjz r3 HALT
+inc r0
+decjz r-1 back").unwrap();
+    assert_eq!(peephole(&machine).get_lines().len(), 4);
+}
+
+#[test]
+fn keeps_entry_point() {
+    let machine = parse_str("registers 1
+inc r3
+#!entry start
+start: decjz r3 HALT
+inc r0").unwrap();
+    let mut optimized = peephole(&machine);
+    assert_eq!(optimized.get_lines().len(), 3);
+    assert_eq!(optimized.get_entry(), Some("start"));
+    optimized.run();
+    // Starting at the entry point, r3 is 0 so the program halts straight away.
+    assert_eq!(optimized.display_nat_registers(), "registers 1 0 0 0");
+
+    let machine = parse_str("registers 1
+inc r0
+#!entry start
+start: decjz r-1 end
+end: inc r0").unwrap();
+    let mut optimized = peephole(&machine);
+    assert_eq!(optimized.to_source(), "registers 1\n\n#!entry end\ninc r0\nend: inc r0");
+    optimized.reset();
+    optimized.run();
+    assert_eq!(optimized.display_nat_registers(), "registers 2");
+}
+
+#[test]
+fn goto_registers_become_gotos() {
+    let machine = parse_str("registers 3
+loop: decjz r0 HALT
+inc r1
+decjnz r-2 loop
+decjz r-1 loop").unwrap();
+    // r-2 is always 0 too, but it's used by DECJNZ.
+    assert_eq!(goto_registers(machine.get_lines()), vec![RegisterNumber::Negative(1)]);
+    let mut optimized = peephole(&machine);
+    assert_eq!(optimized.get_lines()[3].get_instruction().to_string(), "goto loop");
+    optimized.run();
+    assert_eq!(optimized.display_nat_registers(), "registers 0 3");
+}
+
+/// Check that optimising the program removes a line, without changing what it computes.
+fn assert_same_result(source: &str) {
+    let mut machine = parse_str(source).unwrap();
+    let mut optimized = peephole(&machine);
+    assert!(optimized.get_lines().len() < machine.get_lines().len(), "{source}");
+    machine.run();
+    optimized.run();
+    // Registers which are only used by removed lines aren't in the optimised machine at all.
+    let registers = |machine: &Machine| {
+        machine.display_nat_registers().trim_end_matches(" 0").to_string()
+    };
+    assert_eq!(registers(&optimized), registers(&machine), "{source}");
+}
+
+#[test]
+fn keeps_relative_jumps() {
+    assert_same_result("registers 0 3\ninc r5\ndecjz r5 +1\nloop: decjz r1 +3\ninc r0\ngoto loop\ninc r2");
+    // The removed lines are jumped to, and past.
+    assert_same_result(
+        "registers 0 2\nloop: decjz r1 +6\ndecjz r3 +1\ninc r4\ndecjz r4 +1\ninc r0\ngoto loop\ninc r2",
+    );
+    assert_same_result("registers 0 1\ndecjz r1 +10\ninc r3\ndecjz r3 +1\ninc r0");
+}
+
+#[test]
+fn keeps_jumps_after_labels() {
+    assert_same_result(
+        "registers 0 3\ninc r5\ndecjz r5 +1\nloop: decjz r1 loop+3\ninc r0\ngoto loop\ninc r2",
+    );
+    assert_same_result(
+        "registers 0 2\nstart: decjz r1 start+5\ninc r3\ndecjz r3 HALT\ninc r0\ngoto start\ninc r2",
+    );
+}
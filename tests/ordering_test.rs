@@ -0,0 +1,101 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+// Reports list things in a fixed order, whatever order they were added in, so that their output
+// can be compared with golden files.
+
+use std::num::NonZeroUsize;
+
+use remuir::{
+    machine::Identifier,
+    memory::{ PseudoRegister, RegisterNumber },
+    parser::parse_str,
+    trace::{ SampledTrace, TraceConfig },
+};
+
+const SOURCE: &str = "registers 0 3
+loop: decjz r1 done
+inc r0
+inc r-1
+middle: decjz r-2 loop
+done: halt";
+
+#[test]
+fn registers_are_natural_then_negative_then_pseudo() {
+    let mut registers = [
+        RegisterNumber::Pseudo(PseudoRegister::Visits),
+        RegisterNumber::Negative(2),
+        RegisterNumber::Natural(10),
+        RegisterNumber::Pseudo(PseudoRegister::Steps),
+        RegisterNumber::Negative(1),
+        RegisterNumber::Natural(2),
+    ];
+    registers.sort();
+    let names: Vec<String> = registers.iter().map(ToString::to_string).collect();
+    assert_eq!(names, ["r2", "r10", "r-1", "r-2", "r!steps", "r!visits"]);
+}
+
+#[test]
+fn breakpoints_are_by_line() {
+    let mut machine = parse_str(SOURCE).unwrap();
+    for line in [3, 0, 2] {
+        machine.toggle_breakpoint(&Identifier::Line(line)).unwrap();
+    }
+    machine.set_breakpoint_on_hit(&Identifier::Label(String::from("middle")), 2).unwrap();
+    assert_eq!(machine.get_breakpoints(), &[0, 2, 3]);
+    machine.toggle_breakpoint(&Identifier::Line(2)).unwrap();
+    machine.toggle_breakpoint(&Identifier::Line(1)).unwrap();
+    assert_eq!(machine.get_breakpoints(), &[0, 1, 3]);
+}
+
+#[test]
+fn watchpoints_are_by_register() {
+    let mut machine = parse_str(SOURCE).unwrap();
+    for register in ["r-1", "r1", "r0"] {
+        machine.toggle_watchpoint(register.parse().unwrap());
+    }
+    let watchpoints: Vec<String> =
+        machine.get_watchpoints().iter().map(ToString::to_string).collect();
+    assert_eq!(watchpoints, ["r0", "r1", "r-1"]);
+}
+
+#[test]
+fn equally_hot_registers_are_by_register() {
+    let mut machine = parse_str("registers 0\ninc r-1\ninc r2\ninc r0").unwrap();
+    machine.set_register_heat(true);
+    machine.run();
+    let registers: Vec<String> = machine
+        .get_register_heat()
+        .iter()
+        .map(|(register, _)| register.to_string())
+        .collect();
+    assert_eq!(registers, ["r0", "r2", "r-1"]);
+}
+
+#[test]
+fn labels_are_by_first_definition() {
+    let machine = parse_str(SOURCE).unwrap();
+    let labels: Vec<&str> = machine.get_labels().iter().map(|(label, _)| label.as_str()).collect();
+    assert_eq!(labels, ["loop", "middle", "done"]);
+
+    let mut machine = parse_str(SOURCE).unwrap();
+    let config = TraceConfig {
+        interval: NonZeroUsize::new(1),
+        labels: vec![String::from("done"), String::from("loop"), String::from("done")],
+    };
+    let trace = SampledTrace::record(&mut machine, &config);
+    assert_eq!(trace.jump_counts(), vec![("loop", 3), ("done", 1)]);
+}
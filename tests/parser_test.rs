@@ -15,10 +15,24 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 
 use remuir::{
+    ast::ItemKind,
     instruction::Instruction,
-    memory::{ Memory, Register, RegisterNumber },
-    parser::parse_str,
+    memory::{ Memory, PseudoRegister, Register, RegisterNumber },
+    parser::{
+        find_undefined_labels,
+        parse_any_instruction,
+        parse_ast,
+        parse_library_str,
+        parse_str,
+        parse_str_all_errors,
+        parse_str_permissive,
+        parse_str_with_limits,
+        ParseLimits,
+        ParseSourceError,
+        SyntaxError,
+    },
     machine::{ Identifier, Line, Machine },
+    requirement::{ Comparison, Requirement },
 };
 
 fn example1_string() -> String {
@@ -93,3 +107,692 @@ decjz r-1 HALT
     let expected_output = String::from("registers 1");
     assert_eq!(expected_output, output)
 }
+
+#[test]
+fn register_line_invalid_value() {
+    use remuir::parser::{ parse_register_line, ParseSourceError };
+    assert!(matches!(
+        parse_register_line("registers 1 x 3"),
+        Err(ParseSourceError::InvalidRegisterValue { value, position: 2 }) if value == "x",
+    ));
+    assert!(matches!(
+        parse_register_line("registers 1 2 3 z"),
+        Err(ParseSourceError::InvalidRegisterValue { value, position: 4 }) if value == "z",
+    ));
+    assert!(matches!(
+        parse_register_line("registers 2^65536"),
+        Err(ParseSourceError::RegisterValueTooLarge { .. }),
+    ));
+    assert!(matches!(
+        parse_register_line("registers 1 2-3"),
+        Err(ParseSourceError::NegativeRegisterValue { value }) if value == "2-3",
+    ));
+    assert!(matches!(
+        parse_register_line("registers 2 ^3"),
+        Err(ParseSourceError::InvalidRegisterValue { value, position: 2 }) if value == "^3",
+    ));
+    assert!(parse_register_line("registers 1 2 3").is_ok());
+}
+
+#[test]
+fn register_line_arithmetic() {
+    use remuir::parser::parse_register_line;
+    let memory = parse_register_line("registers 2^64 10*3 0 2^3^2 10-2-3 (1+2)*3 1+2*3^2").unwrap();
+    assert_eq!(
+        memory.get_nat_registers_as_u128(),
+        vec![1 << 64, 30, 0, 512, 5, 9, 19],
+    );
+    let memory = parse_register_line("registers 2^128+5 340282366920938463463374607431768211461");
+    let memory = memory.unwrap();
+    assert_eq!(memory.get_nat_registers()[0], Register::new(&[5, 1]));
+    assert_eq!(memory.get_nat_registers()[1], Register::new(&[5, 1]));
+    let value = "340282366920938463463374607431768211461";
+    assert_eq!(memory.to_string(), format!("registers {value} {value}"));
+}
+
+#[test]
+fn malformed_register_line() {
+    use remuir::parser::ParseSourceError;
+    let suggestion_for = |source: &str| match parse_str(source) {
+        Err(ParseSourceError::MalformedRegisterLine { suggestion, .. }) => suggestion,
+        other => panic!("Expected a malformed registers line, got {other:?}"),
+    };
+    assert_eq!(suggestion_for("registers 1,2,3\ninc r0\n"), "registers 1 2 3");
+    assert_eq!(suggestion_for("Registers 1 2 3\ninc r0\n"), "registers 1 2 3");
+    assert_eq!(suggestion_for("# Adds one.\nregister: 4, 5\ninc r0\n"), "registers 4 5");
+    assert_eq!(suggestion_for("registers\ninc r0\n"), "registers 0");
+    assert!(matches!(
+        parse_str("registers 1 a\ninc r0\n"),
+        Err(ParseSourceError::InvalidRegisterValue { value, position: 2 }) if value == "a",
+    ));
+    assert!(matches!(parse_str("inc r0\n"), Err(ParseSourceError::NoInitialRegisters)));
+}
+
+#[test]
+fn line_endings_and_byte_order_mark() {
+    for (name, _) in remuir::examples::GALLERY {
+        let machine = remuir::examples::by_name(name).unwrap();
+        let source = format!("# The {name} example.\n{}", machine.to_source());
+        let windows = source.replace('\n', "\r\n");
+        for source in [source.clone(), windows.clone(), format!("\u{FEFF}{windows}")] {
+            let parsed = parse_str(&source).unwrap();
+            assert!(parsed.diff(&machine).is_empty(), "{source:?}");
+            // And back again.
+            assert!(parse_str(&parsed.to_source()).unwrap().diff(&machine).is_empty());
+        }
+    }
+    // A comment can end the file, with or without a line break.
+    for source in ["registers 1\r\ninc r0 # Add one.", "registers 1\r\ninc r0 # Add one.\r\n"] {
+        let mut machine = parse_str(source).unwrap();
+        machine.run();
+        assert_eq!(machine.display_nat_registers(), "registers 2");
+    }
+}
+
+#[test]
+fn unicode_labels() {
+    use remuir::parser::{ invalid_label_character, ParseSourceError };
+    let source = "registers 0 2\nboucle: decjz r1 終わり\ninc r0\ndecjz r-1 boucle\n終わり: decjz r-1 HALT";
+    let mut machine = parse_str(source).unwrap();
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 2 0");
+
+    assert_eq!(invalid_label_character("maths::add-two_1"), None);
+    assert_eq!(invalid_label_character("half way"), None);
+    assert_eq!(invalid_label_character("half/way"), Some('/'));
+    assert_eq!(invalid_label_character("a::"), Some(':'));
+    assert!(matches!(
+        parse_str("registers 1\nok: decjz r1 ok\nwhy?: inc r0"),
+        Err(ParseSourceError::InvalidLabel { label, character: '?', line: 3 }) if label == "why?",
+    ));
+    assert!(matches!(
+        parse_str("registers 1\ndecjz r1 the end.\nthe end.: inc r0"),
+        Err(ParseSourceError::InvalidLabel { character: '.', line: 2, .. }),
+    ));
+    // Registers which aren't ASCII are an error rather than a panic.
+    assert!("r٣".parse::<RegisterNumber>().is_err());
+    assert!("é1".parse::<RegisterNumber>().is_err());
+}
+
+#[test]
+fn parse_limits() {
+    let limits = ParseLimits {
+        max_source_length: 100,
+        max_lines: 3,
+        max_label_length: 5,
+        max_register: 9,
+        max_expansions: 100,
+        max_macro_depth: 4,
+    };
+    let parse = |source: &str| parse_str_with_limits(source, &limits);
+    assert!(parse("registers 0 1\nloop: decjz r9 HALT\ninc r-9\ndecjz r-1 loop").is_ok());
+    assert!(matches!(
+        parse(&format!("registers 0\n{}", "inc r0\n".repeat(20))),
+        Err(ParseSourceError::SourceTooLong { length: 152, maximum: 100 }),
+    ));
+    assert!(matches!(
+        parse("registers 0\ninc r0\ninc r0\ninc r0\ninc r0"),
+        Err(ParseSourceError::TooManyLines { maximum: 3 }),
+    ));
+    assert!(matches!(
+        parse("registers 0\ndecjz r0 longer"),
+        Err(ParseSourceError::LabelTooLong { label, maximum: 5 }) if label == "longer",
+    ));
+    assert!(matches!(
+        parse("registers 0\ninc r-10"),
+        Err(ParseSourceError::RegisterTooLarge {
+            register: RegisterNumber::Negative(10),
+            maximum: 9,
+        }),
+    ));
+    assert!(matches!(
+        parse("registers 0 0 0 0 0 0 0 0 0 0 0\ninc r0"),
+        Err(ParseSourceError::RegisterTooLarge { register: RegisterNumber::Natural(10), .. }),
+    ));
+    // Without limits, the same programs are fine.
+    assert!(parse_str("registers 0\ninc r1000000").is_ok());
+}
+
+#[test]
+fn macro_bombs_are_limited() {
+    // Each macro calls the one before twice, so m40 would expand 2^41 macros.
+    let mut source = String::from("registers 0\nmacro m0\nendmacro\n");
+    for i in 1..=40 {
+        source.push_str(&format!("macro m{i}\nm{}\nm{}\nendmacro\n", i - 1, i - 1));
+    }
+    source.push_str("m40");
+    assert!(matches!(
+        parse_str_with_limits(&source, &ParseLimits::default()),
+        Err(ParseSourceError::MacroTooDeep { maximum: 32, .. }),
+    ));
+    let limits = ParseLimits { max_macro_depth: 64, ..ParseLimits::default() };
+    assert!(matches!(
+        parse_str_with_limits(&source, &limits),
+        Err(ParseSourceError::TooManyExpansions { maximum: 10_000 }),
+    ));
+}
+
+#[test]
+fn duplicate_labels() {
+    use remuir::{ machine::DuplicateLabel, parser::{ parse_str_permissive, ParseSourceError } };
+    let source = "registers 0 1\nend: decjz r1 end\ninc r0\nend: inc r0";
+    let duplicate = DuplicateLabel { label: String::from("end"), first_line: 2, second_line: 4 };
+    assert!(matches!(
+        parse_str(source),
+        Err(ParseSourceError::DuplicateLabel(error)) if error == duplicate,
+    ));
+    let mut machine = parse_str_permissive(source).unwrap();
+    assert_eq!(machine.get_duplicate_labels(), &[duplicate]);
+    // The last definition wins.
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 2 0");
+    assert!(parse_str(&example1_string()).unwrap().get_duplicate_labels().is_empty());
+}
+
+#[test]
+fn halt_instruction() {
+    let source = "registers 0 2\nloop: decjz r1 done\ninc r0\nhalt\ndone: inc r0";
+    let mut machine = parse_str(source).unwrap();
+    assert_eq!(machine.get_lines()[2].get_instruction(), &Instruction::HALT);
+    assert_eq!(machine.get_lines()[2].to_string(), "2    halt");
+    assert_eq!(Instruction::HALT.get_register(), None);
+    assert!(parse_str(&machine.to_source()).unwrap().diff(&machine).is_empty());
+    machine.run();
+    assert!(machine.is_halted());
+    assert_eq!(machine.display_nat_registers(), "registers 1 1");
+}
+
+#[test]
+fn any_instruction() {
+    let parsed = parse_any_instruction(" decjz r-1 my label ").unwrap();
+    assert_eq!(
+        parsed.instruction,
+        Instruction::DECJZ(
+            RegisterNumber::Negative(1),
+            Identifier::Label(String::from("my label")),
+        ),
+    );
+    assert_eq!(parsed.span, 1..19);
+    assert!(!parsed.is_dec);
+    assert!(parse_any_instruction("dec r3").unwrap().is_dec);
+    assert_eq!(parse_any_instruction("halt").unwrap().instruction, Instruction::HALT);
+
+    let Err(ParseSourceError::UnknownInstruction { word, span, suggestion }) =
+        parse_any_instruction("  decjzz r0 HALT")
+    else {
+        panic!("Expected an unknown instruction.");
+    };
+    assert_eq!((word.as_str(), span, suggestion), ("decjzz", 2..8, Some("decjz")));
+    assert!(matches!(
+        parse_any_instruction("jump r0"),
+        Err(ParseSourceError::UnknownInstruction { suggestion: None, .. }),
+    ));
+    assert!(matches!(
+        parse_any_instruction("inc r0 r1"),
+        Err(ParseSourceError::TooManyArgument { received: 2, expected: 1, .. }),
+    ));
+    assert!(matches!(parse_any_instruction("inc x"), Err(ParseSourceError::SyntaxError(_))));
+}
+
+#[test]
+fn pseudo_registers_need_extended() {
+    let steps = RegisterNumber::Pseudo(PseudoRegister::Steps);
+    let machine = parse_str("registers 0\n#!extended\nloop: decjz r!steps loop").unwrap();
+    assert_eq!(machine.get_lines()[0].get_instruction(), &Instruction::DECJZ(
+        steps,
+        Identifier::Label(String::from("loop")),
+    ));
+    assert!(machine.to_source().contains("\n#!extended\n"));
+    assert!(matches!(
+        parse_str("registers 0\ninc r0\ndecjnz r!visits HALT"),
+        Err(ParseSourceError::NotExtended { line: 3, .. }),
+    ));
+    assert!(matches!(
+        parse_str("registers 0\n#!extended\ninc r!steps"),
+        Err(ParseSourceError::ReadOnlyRegister(register)) if register == steps,
+    ));
+    let Err(error) = parse_str("registers 0\n#!extended\ndecjz r!visit HALT") else {
+        panic!("Expected an unknown pseudo-register.");
+    };
+    assert_eq!(
+        error.to_string(),
+        "There is no pseudo-register \"r!visit\". Did you mean \"r!visits\"?",
+    );
+    assert_eq!("r!visits".parse::<RegisterNumber>().unwrap().to_string(), "r!visits");
+}
+
+#[test]
+fn macros_expand_when_parsing() {
+    let source = "registers 0 3 4
+macro move FROM TO
+loop: decjz FROM end # Each expansion has its own loop.
+inc TO
+decjz r-1 loop
+endmacro
+
+macro add A B C
+move A C
+move B C
+endmacro
+
+#!phase start
+start: add r1 r2 r0
+inc r0";
+    let mut machine = parse_str(source).unwrap();
+    assert_eq!(machine.get_lines().len(), 7);
+    assert_eq!(machine.get_lines()[3].to_string(), "3    move::3::loop: decjz r2 move::3::end");
+    assert_eq!(machine.get_lines()[6].to_string(), "6    move::3::end: inc r0");
+    assert_eq!(machine.get_phases(), ["move::2::loop"]);
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 8 0 0");
+    // A jump to "end" with nothing after the expansion halts.
+    let machine = parse_str("registers 0\nmacro clear R\ndecjz R end\nendmacro\nclear r0").unwrap();
+    assert_eq!(
+        machine.get_lines()[0].get_instruction(),
+        &Instruction::DECJZ(RegisterNumber::Natural(0), Identifier::Halt),
+    );
+
+    let error = |source: &str| parse_str(source).unwrap_err().to_string();
+    assert_eq!(
+        error("registers 0\nmacro clear R\ninc R\nendmacro\nclaer r0"),
+        "Unknown instruction or macro \"claer\". Did you mean \"clear\"? Macros must be defined \
+         before they're used.",
+    );
+    assert!(matches!(
+        parse_str("registers 0\nmacro clear R\ninc R\nendmacro\nclear r0 r1"),
+        Err(ParseSourceError::TooManyArgument { received: 2, expected: 1, .. }),
+    ));
+    assert!(matches!(
+        parse_str("registers 0\nmacro forever\nforever\nendmacro\nforever"),
+        Err(ParseSourceError::RecursiveMacro { .. }),
+    ));
+    assert!(matches!(
+        parse_str("registers 0\nmacro m\nhalt\nendmacro\nmacro m\nhalt\nendmacro"),
+        Err(ParseSourceError::DuplicateMacro { .. }),
+    ));
+}
+
+#[test]
+fn pseudo_instructions_are_lowered() {
+    let source = "registers 3 5 7 9
+start: copy r0 r1
+add r0 r2
+add r3 r3
+zero r0
+decjz r-2 HALT";
+    let mut machine = parse_str(source).unwrap();
+    assert_eq!(machine.get_lines()[0].to_string(), "0    copy::1::clear: decjnz r1 copy::1::clear");
+    // The scratch register isn't one the program uses.
+    assert_eq!(machine.get_lines()[3].to_string(), "3    inc r-3");
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 0 3 10 18");
+
+    assert!(matches!(
+        parse_str("registers 0\ncopy r0 loop"),
+        Err(ParseSourceError::ExpectedRegister { .. }),
+    ));
+    assert!(matches!(
+        parse_str("registers 0\nzero r!steps"),
+        Err(ParseSourceError::ReadOnlyRegister(_)),
+    ));
+    assert!(matches!(
+        parse_str("registers 0\nadd r0"),
+        Err(ParseSourceError::TooFewArguments { received: 1, expected: 2, .. }),
+    ));
+}
+
+#[test]
+fn documentation_comments_attach_to_labels() {
+    let source = "registers 3
+## Empties r0.
+##
+##   Stops once it's 0.
+loop: decjz r0 HALT
+# An ordinary comment.
+decjz r1 loop
+
+## Not directly above a label.
+
+done: halt
+macro clear R
+## Labels in macros aren't documented.
+again: decjnz R again
+endmacro
+## Clears r1.
+start: clear r1";
+    let machine = parse_str(source).unwrap();
+    assert_eq!(machine.get_label_documentation("loop"), Some("Empties r0.\n\nStops once it's 0."));
+    assert_eq!(machine.get_label_documentation("done"), None);
+    assert_eq!(machine.get_label_documentation("clear::1::again"), Some("Clears r1."));
+    assert!(machine.to_source().contains("\n## Empties r0.\n##\n## Stops once it's 0.\nloop: "));
+    // Documentation survives a round trip through the source code.
+    let reparsed = parse_str(&machine.to_source()).unwrap();
+    assert_eq!(reparsed.get_label_documentation("loop"), machine.get_label_documentation("loop"));
+    assert_eq!(reparsed.fingerprint(), machine.fingerprint());
+}
+
+#[test]
+fn exports_and_imports_are_kept() {
+    let machine = parse_library_str("#!export add\n#!import maths::double\nadd: inc r0").unwrap();
+    assert_eq!(machine.get_exports(), ["add"]);
+    assert_eq!(machine.get_imports(), ["maths::double"]);
+    assert!(machine.to_source().contains("\n#!export add\n#!import maths::double\n"));
+    assert!(matches!(
+        parse_str("registers 0\n#!export ad\nadd: inc r0"),
+        Err(ParseSourceError::ExportLabelNotFound { suggestion: Some(_), .. }),
+    ));
+}
+
+#[test]
+fn goto_jumps_unconditionally() {
+    let source = "registers 3 0
+loop: decjz r0 done
+inc r1
+goto loop
+done: goto end of program
+end of program: halt";
+    let mut machine = parse_str(source).unwrap();
+    assert_eq!(
+        machine.get_lines()[2].get_instruction(),
+        &Instruction::GOTO(Identifier::Label(String::from("loop"))),
+    );
+    assert_eq!(machine.get_lines()[3].to_string(), "3    done: goto end of program");
+    assert_eq!(parse_str(&machine.to_source()).unwrap().fingerprint(), machine.fingerprint());
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 0 3");
+    assert_eq!(machine.get_steps(), 12);
+    assert!(matches!(
+        parse_str("registers 0\ngoto lo.op"),
+        Err(ParseSourceError::InvalidLabel { character: '.', line: 2, .. }),
+    ));
+}
+
+#[test]
+fn subroutines_return_to_their_caller() {
+    let source = "registers 0
+call twice
+call twice
+halt
+twice: call once
+call once
+return
+once: inc r0
+return";
+    let mut machine = parse_str(source).unwrap();
+    assert_eq!(
+        machine.get_lines()[0].get_instruction(),
+        &Instruction::CALL(Identifier::Label(String::from("twice"))),
+    );
+    assert_eq!(machine.get_lines()[7].to_string(), "7    return");
+    assert_eq!(parse_str(&machine.to_source()).unwrap().fingerprint(), machine.fingerprint());
+    machine.run_steps(2);
+    assert_eq!(machine.get_current_line_number(), 6);
+    assert_eq!(machine.get_call_stack(), [1, 4]);
+    machine.run();
+    assert!(machine.get_call_stack().is_empty());
+    assert_eq!(machine.display_nat_registers(), "registers 4");
+    assert_eq!(machine.get_steps(), 17);
+
+    // Returning with nothing to return to halts.
+    let mut machine = parse_str("registers 0\ninc r0\nreturn\ninc r0").unwrap();
+    machine.run();
+    assert!(machine.is_halted());
+    assert_eq!(machine.display_nat_registers(), "registers 1");
+}
+
+#[test]
+fn relative_jumps_are_resolved_to_lines() {
+    let source = "registers 3 0\nloop: decjz r0 +3\ninc r1\ngoto -2\ngoto +9";
+    let mut machine = parse_str(source).unwrap();
+    let targets: Vec<_> = machine
+        .get_lines()
+        .iter()
+        .map(|line| line.get_instruction().get_jump_target().cloned())
+        .collect();
+    assert_eq!(
+        targets,
+        [Some(Identifier::Line(3)), None, Some(Identifier::Line(0)), Some(Identifier::Line(12))],
+    );
+    // They're written back as relative jumps.
+    assert!(machine.to_source().ends_with("\nloop: decjz r0 +3\ninc r1\ngoto -2\ngoto +9"));
+    assert_eq!(parse_str(&machine.to_source()).unwrap(), machine);
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 0 3");
+}
+
+#[test]
+fn relative_jumps_in_macros_count_expanded_lines() {
+    let source = "registers 2
+macro clear R
+decjz R +2
+goto -1
+endmacro
+clear r0
+clear r0
+inc r0";
+    let mut machine = parse_str(source).unwrap();
+    assert_eq!(
+        machine.get_lines()[2].get_instruction(),
+        &Instruction::DECJZ(RegisterNumber::Natural(0), Identifier::Line(4)),
+    );
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 1");
+}
+
+#[test]
+fn relative_jumps_before_the_start_are_rejected() {
+    assert!(matches!(
+        parse_str("registers 0\ninc r0\ndecjnz r0 -2"),
+        Err(ParseSourceError::JumpBeforeStart { offset: -2, line: 3 }),
+    ));
+    assert!(parse_str("registers 0\ninc r0\ncall -1").is_ok());
+    // A label may still start with "-", as long as it isn't just a number.
+    let machine = parse_str("registers 0\n-1a: goto -1a").unwrap();
+    assert_eq!(
+        machine.get_lines()[0].get_instruction(),
+        &Instruction::GOTO(Identifier::Label(String::from("-1a"))),
+    );
+}
+
+#[test]
+fn syntax_errors_point_at_the_bad_token() {
+    let Err(ParseSourceError::SyntaxError(error)) = parse_str("registers 0\nloop:  decjz rx loop")
+    else {
+        panic!("Expected a syntax error.");
+    };
+    assert_eq!(*error, SyntaxError {
+        line: 2,
+        column: 14,
+        source_line: String::from("loop:  decjz rx loop"),
+        length: 2,
+        hint: String::from("expected a register, such as \"r0\""),
+    });
+    assert_eq!(
+        error.to_string(),
+        "Syntax error on line 2, column 14: expected a register, such as \"r0\".
+  2 | loop:  decjz rx loop
+    |              ^^",
+    );
+
+    let hint = |source: &str| match parse_str(source) {
+        Err(ParseSourceError::SyntaxError(error)) => (error.line, error.column, error.hint),
+        _ => panic!("Expected a syntax error in {source:?}."),
+    };
+    let register = String::from("expected a register, such as \"r0\"");
+    assert_eq!(hint("registers 0\ninc r0\n  inc"), (3, 6, register));
+    assert_eq!(
+        hint("registers 0\ndecjz r0"),
+        (2, 9, String::from("expected a label to jump to, or a relative jump such as \"+3\"")),
+    );
+    assert_eq!(hint("registers 0\nhalt now"), (2, 6, String::from("expected the end of the line")));
+    assert_eq!(
+        hint("registers 0\nmacro twice\ninc r0"),
+        (2, 1, String::from("a macro definition must end with a line saying \"endmacro\"")),
+    );
+}
+
+#[test]
+fn all_errors_are_collected() {
+    let source = "registers 0
+inc x
+loop: decjz r0
+icn r0
+b@d: inc r0
+decjnz r0 loop";
+    let errors = parse_str_all_errors(source).unwrap_err();
+    assert_eq!(errors.len(), 4);
+    assert!(matches!(&errors[0], ParseSourceError::SyntaxError(error) if error.line == 2));
+    assert!(matches!(&errors[1], ParseSourceError::SyntaxError(error) if error.line == 3));
+    assert!(matches!(&errors[2], ParseSourceError::UnknownMacro { name, .. } if name == "icn"));
+    assert!(matches!(&errors[3], ParseSourceError::InvalidLabel { line: 5, .. }));
+    let first = parse_str(source).unwrap_err().to_string();
+    assert!(errors.iter().any(|error| error.to_string() == first));
+    assert_eq!(
+        parse_str_all_errors("registers 0\ninc r0").unwrap(),
+        parse_str("registers 0\ninc r0").unwrap(),
+    );
+    // Errors which aren't on a line stop the search.
+    let errors = parse_str_all_errors("inc x\ninc y").unwrap_err();
+    assert!(matches!(errors[..], [ParseSourceError::NoInitialRegisters]));
+}
+
+#[test]
+fn undefined_labels_are_rejected() {
+    let error = parse_str("registers 0 1\nloop: decjz r1 dne\ninc r0\ngoto loop\ndone: halt")
+        .unwrap_err();
+    assert!(matches!(
+        &error,
+        ParseSourceError::UndefinedLabel { label, line: 2, suggestion: Some(suggestion) }
+            if label == "dne" && suggestion == "done"
+    ));
+    assert_eq!(
+        error.to_string(),
+        "Line 2 jumps to \"dne\", but no line has that label. Did you mean \"done\"?",
+    );
+    // Lines are counted in the source code, so comments and macros are counted as they're written.
+    let source = "registers 0\nmacro twice\ninc r0\ninc r0\nendmacro\n# Twice.\ntwice\n\ngoto nowhere";
+    assert!(matches!(
+        parse_str(source),
+        Err(ParseSourceError::UndefinedLabel { line: 9, .. }),
+    ));
+    // Permissive parsing allows them, and the jump halts.
+    let mut machine = parse_str_permissive("registers 0 1\ndecjnz r1 nowhere\ninc r0").unwrap();
+    assert!(matches!(
+        &find_undefined_labels(&machine)[..],
+        [ParseSourceError::UndefinedLabel { line: 2, .. }],
+    ));
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 0 0");
+    // Labels with a scope are left for the linker, as are labels in libraries.
+    assert!(parse_str("registers 0\ngoto math::add").is_ok());
+    assert!(parse_library_str("goto main").is_ok());
+}
+
+#[test]
+fn jumps_to_lines_after_labels() {
+    let source = "registers 0 1
+loop: decjz r1 loop+3
+inc r0
+goto loop
+decjz r1 done+0
+done: halt";
+    let mut machine = parse_str(source).unwrap();
+    assert_eq!(
+        machine.get_lines()[0].get_instruction(),
+        &Instruction::DECJZ(RegisterNumber::Natural(1), Identifier::Line(3)),
+    );
+    assert_eq!(
+        machine.get_lines()[3].get_instruction(),
+        &Instruction::DECJZ(RegisterNumber::Natural(1), Identifier::Line(4)),
+    );
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 1 0");
+    // A jump just past the last line halts, but any further is an error.
+    assert!(parse_str("registers 0\nstart: inc r0\ngoto start+1").is_ok());
+    let error = parse_str("registers 0\nstart: inc r0\ngoto start+3").unwrap_err();
+    assert!(matches!(
+        &error,
+        ParseSourceError::JumpPastEnd { label, offset: 3, line: 3 } if label == "start"
+    ));
+    assert_eq!(error.to_string(), "The jump to start+3 on line 3 goes past the end of the program.");
+    // The label must exist, unless parsing permissively, when the jump halts.
+    assert!(matches!(
+        parse_str("registers 0\nstart: inc r0\ngoto strat+1"),
+        Err(ParseSourceError::UndefinedLabel { suggestion: Some(suggestion), .. })
+            if suggestion == "start"
+    ));
+    let mut machine = parse_str_permissive("registers 0\ngoto nowhere+1\ninc r0").unwrap();
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 0");
+    // "-" can be part of a label, so only "+" counts.
+    let machine = parse_str("registers 0\nloop-1: inc r0\ngoto loop-1").unwrap();
+    assert_eq!(
+        machine.get_lines()[1].get_instruction(),
+        &Instruction::GOTO(Identifier::Label(String::from("loop-1"))),
+    );
+}
+
+#[test]
+fn jumps_to_lines_after_labels_in_macros() {
+    // "top" is local to each expansion, and "skip" is on the same line as "end".
+    let machine = parse_str("registers 0 2
+macro drain R
+top: decjz R end
+goto top+0
+endmacro
+drain r1
+skip: drain r0
+goto skip+1").unwrap();
+    let targets: Vec<Option<&Identifier>> = machine
+        .get_lines()
+        .iter()
+        .map(|line| line.get_instruction().get_jump_target())
+        .collect();
+    assert_eq!(targets, vec![
+        Some(&Identifier::Label(String::from("drain::2::top"))),
+        Some(&Identifier::Line(0)),
+        Some(&Identifier::Label(String::from("drain::2::end"))),
+        Some(&Identifier::Line(2)),
+        Some(&Identifier::Line(3)),
+    ]);
+}
+
+#[test]
+fn requirements_are_kept_in_the_syntax_tree() {
+    let source = "registers 0\n#!requires remuir >= 0.1 # Needs GOTO.\n#!requires remuir = 0\ninc r0";
+    assert!(parse_str(source).is_ok());
+    let program = parse_ast(source).unwrap();
+    assert_eq!(program.get_requirements(), vec![
+        &Requirement { comparison: Comparison::AtLeast, version: vec![0, 1] },
+        &Requirement { comparison: Comparison::Exactly, version: vec![0] },
+    ]);
+    assert!(matches!(program.items[1].kind, ItemKind::Requires(_)));
+    assert!(program.comments.iter().any(|comment| comment.text == " Needs GOTO."));
+}
+
+#[test]
+fn unmet_requirements_fail_before_the_rest_is_parsed() {
+    // The syntax error on line 3 would otherwise be reported.
+    let error = parse_str("registers 0\n#!requires remuir >= 999.1\nsome future syntax!").unwrap_err();
+    assert!(matches!(
+        error,
+        ParseSourceError::UnmetRequirement { ref requirement, line: 2 }
+            if requirement.version == vec![999, 1],
+    ));
+    assert!(matches!(
+        parse_str("registers 0\n#!requires remuir = 0.1\ninc r0"),
+        Err(ParseSourceError::UnmetRequirement { line: 2, .. }),
+    ));
+    assert!(matches!(
+        parse_str("registers 0\n  #!requires remuir > 0.1\ninc r0"),
+        Err(ParseSourceError::InvalidRequirement { ref text, line: 2 })
+            if text == "#!requires remuir > 0.1",
+    ));
+}
+
+#[test]
+fn comments_starting_with_requires_are_ignored() {
+    let source = "registers 0\n#requires nothing\n#requires remuir = 0.1\ninc r0";
+    assert_eq!(parse_str(source).unwrap().to_source(), "registers 0\n\ninc r0");
+    assert!(parse_ast(source).unwrap().get_requirements().is_empty());
+}
@@ -0,0 +1,71 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+//! Interactive modes with a plain terminal.
+
+use std::{ num::NonZeroU32, time::Duration };
+
+use remuir::{
+    parser::parse_str,
+    tui::{self, Terminal},
+};
+
+#[test]
+fn repl_has_no_prompts() {
+    let mut input = "inc \\\nr2\nq\n".as_bytes();
+    let mut out: Vec<u8> = Vec::new();
+    tui::repl(&mut input, &mut out, Terminal::Plain).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(!out.contains("remuir>"));
+    assert!(!out.contains("...>"));
+    assert!(out.contains("\nregisters 0\nRegister r2 is now 1.\n"));
+}
+
+#[test]
+fn debugger_has_no_prompts() {
+    let machine = parse_str("registers 0\ninc r0\nhalt").unwrap();
+    let mut input = "step\nq\n".as_bytes();
+    let mut out: Vec<u8> = Vec::new();
+    tui::debug(machine, &mut input, &mut out, Terminal::Plain).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(!out.contains("remuir>"));
+    assert!(out.contains("Next line:\n1    halt\n"));
+}
+
+#[test]
+fn live_writes_a_line_per_step() {
+    let mut machine = parse_str("registers 0 1\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop").unwrap();
+    let mut out: Vec<u8> = Vec::new();
+    tui::live(&mut machine, NonZeroU32::new(1000).unwrap(), &mut out, Terminal::Plain).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(!out.contains(['\r', '\x1b']));
+    assert_eq!(out.lines().count(), 5);
+    assert!(out.starts_with("step 0 | line 0    loop: decjz r1 HALT | registers 0 1\n"));
+    assert!(out.ends_with("step 4 | HALT | registers 1 0 0\n"));
+}
+
+#[test]
+fn progress_writes_whole_lines() {
+    let mut machine = parse_str("registers 0\nloop: inc r0\ndecjz r1 loop").unwrap();
+    machine.set_step_limit(Some(200_000));
+    let mut out: Vec<u8> = Vec::new();
+    tui::progress(&mut machine, Duration::ZERO, &mut out, Terminal::Plain).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(!out.contains(['\r', '\x1b']));
+    assert!(out.starts_with("step 65536 | "));
+    assert!(out.lines().all(|line| line.contains(" until the limit of 200000")));
+    assert!(out.ends_with('\n'));
+}
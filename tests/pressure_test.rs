@@ -0,0 +1,85 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::num::NonZeroUsize;
+
+use remuir::{
+    parser::parse_str,
+    pressure::{ RegisterPressure, Sample, MAX_SAMPLES },
+};
+
+// Moves r1 into r0 and r2, so r0 and r2 fill up as r1 drains.
+const SOURCE: &str = "registers 0 2
+loop: decjz r1 done
+inc r0
+inc r2
+decjz r-1 loop
+done: halt";
+
+fn record(interval: usize) -> RegisterPressure {
+    let mut machine = parse_str(SOURCE).unwrap();
+    let pressure = RegisterPressure::record(&mut machine, NonZeroUsize::new(interval).unwrap());
+    assert!(machine.is_halted());
+    pressure
+}
+
+#[test]
+fn samples_every_nth_step_and_the_last() {
+    let pressure = record(4);
+    assert_eq!(pressure.get_samples(), &[
+        Sample { step: 0, nonzero: 1, highest: Some(1) },
+        Sample { step: 4, nonzero: 3, highest: Some(2) },
+        Sample { step: 8, nonzero: 2, highest: Some(2) },
+        Sample { step: 10, nonzero: 2, highest: Some(2) },
+    ]);
+    assert_eq!(pressure.peak(), Some(&Sample { step: 4, nonzero: 3, highest: Some(2) }));
+}
+
+#[test]
+fn writes_samples_as_csv() {
+    let mut csv = Vec::new();
+    record(4).write_csv(&mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(), "step,nonzero,highest
+0,1,1
+4,3,2
+8,2,2
+10,2,2
+");
+}
+
+#[test]
+fn prints_sparklines_with_peaks() {
+    assert_eq!(record(4).to_string(), "Register pressure over 10 steps (sampled every 4):
+Nonzero registers (peak 3 at step 4):
+  ▃█▅▅
+Highest nonzero register (peak r2 at step 4):
+  ▅███
+");
+}
+
+#[test]
+fn stops_at_the_step_limit_with_few_samples() {
+    let mut machine = parse_str("registers 0\nloop: inc r0\ngoto loop").unwrap();
+    machine.set_step_limit(Some(100_000));
+    let pressure = RegisterPressure::record(&mut machine, NonZeroUsize::MIN);
+    assert!(!machine.is_halted());
+    assert_eq!(machine.get_steps(), 100_000);
+    let samples = pressure.get_samples();
+    assert!(samples.len() <= MAX_SAMPLES + 1);
+    // The interval was doubled from 1 to 32.
+    assert_eq!(samples[1].step, 32);
+    assert_eq!(samples.last().unwrap().step, 100_000);
+}
@@ -15,10 +15,11 @@ You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 
 use remuir::{
+    examples,
     instruction::Instruction,
     memory::{ Memory, Register, RegisterNumber },
-    parser::parse_str,
-    machine::{ Identifier, Line, Machine },
+    parser::{ parse_str, parse_str_permissive },
+    machine::{ Identifier, Line, Machine, RuntimeError, TerminationReason },
 };
 
 fn get_example_machine() -> Machine {
@@ -67,10 +68,37 @@ loop2: decjz r-2 halt
 inc r1
 decjz r-1 loop2");
     let mut machine: Machine = parse_str(&source_code).unwrap();
+    assert!(machine.diff(&examples::copy(3)).is_empty());
     machine.run();
     assert_eq!(machine.display_nat_registers(), "registers 3 3")
 }
 
+#[test]
+fn example_is_even() {
+    for n in 0..6 {
+        let mut machine = examples::is_even(n);
+        machine.run();
+        assert_eq!(machine.get_state().get_nat_registers_as_u128()[0], u128::from(n % 2 == 0));
+    }
+}
+
+#[test]
+fn example_add() {
+    let mut machine = examples::add(4, 9);
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 13 0 0")
+}
+
+#[test]
+fn example_gallery() {
+    for (name, _) in examples::GALLERY {
+        let machine = examples::by_name(name).unwrap();
+        // Every example survives a round trip through its source code.
+        assert!(parse_str(&machine.to_source()).unwrap().diff(&machine).is_empty());
+    }
+    assert!(examples::by_name("nope").is_none());
+}
+
 #[test]
 fn empty_machine() {
     let source_code = String::from("registers 1 2 3");
@@ -123,3 +151,49 @@ decjz r-1 loop2");
     machine.run();
     assert_eq!(machine.display_nat_registers(), "registers 3 3")
 }
+
+#[test]
+fn decjnz() {
+    // Add r1 to r0 by looping while r1 isn't 0.
+    let source = "registers 0 3\nloop: decjnz r1 body\nhalt\nbody: inc r0\ndecjz r-1 loop\n";
+    let mut machine = parse_str(source).unwrap();
+    assert_eq!(machine.get_lines()[0].get_instruction().to_string(), "decjnz r1 body");
+    assert!(parse_str(&machine.to_source()).unwrap().diff(&machine).is_empty());
+    machine.run();
+    assert_eq!(machine.display_nat_registers(), "registers 3 0");
+    // Always-zero registers never jump, and optimising keeps the jump's target.
+    let mut optimised = remuir::optimize::peephole(&parse_str(
+        "registers 0 2\ndecjz r-1 start\nstart: decjnz r1 start\ndecjnz r-1 start\ninc r0",
+    ).unwrap());
+    optimised.run();
+    assert_eq!(optimised.display_nat_registers(), "registers 1 0");
+}
+
+#[test]
+fn strict_jumps() {
+    let lines = vec![
+        Line::new(0, None, Instruction::INC(RegisterNumber::Natural(0))),
+        Line::new(1, None, Instruction::DECJZ(RegisterNumber::Natural(1), Identifier::Line(7))),
+    ];
+    // By default, jumping past the end of the program halts.
+    let mut machine = Machine::new_from_lines(&lines, Memory::new_from_slice(&[Register::from(0)]));
+    machine.run();
+    assert!(machine.is_halted());
+    assert_eq!(machine.display_nat_registers(), "registers 1 0");
+    // When strict, the machine stops before the jump and reports the line.
+    machine.reset();
+    machine.set_strict_jumps(true);
+    machine.run();
+    assert!(!machine.is_halted());
+    assert_eq!(machine.get_current_line_number(), 1);
+    assert!(matches!(machine.step(), Ok(Some(TerminationReason::InvalidJump(1)))));
+    assert!(matches!(machine.call(&[]), Err(RuntimeError::InvalidJump(1))));
+    // Conditional jumps which aren't taken, explicit halts and running off the end are fine.
+    let mut machine = parse_str_permissive("registers 0 1\ndecjz r1 nowhere\nhalt\n").unwrap();
+    machine.set_strict_jumps(true);
+    machine.run();
+    assert!(machine.is_halted());
+    let mut machine = parse_str_permissive("registers 0 1\ndecjnz r1 nowhere\n").unwrap();
+    machine.set_strict_jumps(true);
+    assert!(matches!(machine.debug(), Ok(TerminationReason::InvalidJump(0))));
+}
@@ -0,0 +1,74 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use num_bigint::BigUint;
+use proptest::prelude::*;
+use remuir::memory::Register;
+
+/// A digit of a register, favouring the values where carrying and borrowing happen.
+fn digit() -> impl Strategy<Value = u128> {
+    prop_oneof![Just(0), Just(1), Just(u128::MAX), Just(u128::MAX - 1), any::<u128>()]
+}
+
+fn to_big(digits: &[u128]) -> BigUint {
+    digits.iter().rev().fold(BigUint::ZERO, |acc, &d| (acc << 128u32) + d)
+}
+
+proptest! {
+    #[test]
+    fn matches_bigint(
+        digits in prop::collection::vec(digit(), 0..4),
+        ops in prop::collection::vec(any::<bool>(), 0..200),
+    ) {
+        let mut register = Register::new(&digits);
+        let mut model = to_big(&digits);
+        prop_assert!(register.is_normalised());
+        for inc in ops {
+            if inc {
+                register.inc();
+                model += 1u32;
+            }
+            else if model != BigUint::ZERO {
+                register.dec();
+                model -= 1u32;
+            }
+            prop_assert!(register.is_normalised());
+            prop_assert_eq!(register.to_string(), model.to_string());
+            prop_assert_eq!(register.to_u128(), u128::try_from(&model).ok());
+        }
+    }
+
+    #[test]
+    fn equality_matches_bigint(
+        a in prop::collection::vec(digit(), 0..3),
+        b in prop::collection::vec(digit(), 0..3),
+    ) {
+        prop_assert_eq!(Register::new(&a) == Register::new(&b), to_big(&a) == to_big(&b));
+    }
+}
+
+#[test]
+fn new_removes_leading_zero_digits() {
+    assert!(Register::new(&[5, 0, 0]).is_normalised());
+    assert_eq!(Register::new(&[5, 0]).to_u128(), Some(5));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "already 0")]
+fn dec_zero_is_caught_in_debug_builds() {
+    Register::from(0).dec();
+}
@@ -0,0 +1,371 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    machine::Machine,
+    memory::RegisterNumber,
+    parser::parse_str,
+    sandbox::Sandbox,
+    testing::ReplSession,
+    tui::{ self, Terminal },
+};
+
+fn copy_machine() -> Machine {
+    parse_str("registers 0 3
+loop1: decjz r1 loop2
+inc r0
+inc r-2
+decjz r-1 loop1
+loop2: decjz r-2 halt
+inc r1
+decjz r-1 loop2").unwrap()
+}
+
+#[test]
+fn repl_registers_without_arguments_displays() {
+    let mut session = ReplSession::repl();
+    session.expect("registers 4 5", "Registers successfully changed!");
+    session.expect("registers", "registers 4 5");
+    session.expect("r", "registers 4 5");
+}
+
+#[test]
+fn repl_instructions() {
+    let mut session = ReplSession::repl();
+    session.expect("inc r1", "Register r1 is now 1.");
+    session.expect("INC  r1", "Register r1 is now 2.");
+    session.expect("dec r1", "Register r1 is now 1.");
+    session.expect("decjz r1 somewhere", "Register r1 is now 0.");
+    session.expect("decjz r1 somewhere", "Not jumping due to being in REPL mode.");
+    session.expect("inc r-1", "Register r-1 is now 1.");
+    assert_eq!(session.get_machine().get_state().get_register(RegisterNumber::Negative(1)), "1");
+}
+
+#[test]
+fn repl_dec_traps_on_zero() {
+    let mut session = ReplSession::repl();
+    session.expect("dec r2", "Cannot decrement r2, its value is already 0.");
+    session.expect("dec r-3", "Cannot decrement r-3, its value is already 0.");
+    session.expect("registers", "registers 0 0 0");
+}
+
+#[test]
+fn repl_rejects_debug_commands() {
+    let mut session = ReplSession::repl();
+    for command in ["play", "step", "undo", "breakpoint loop", "protect r0", "list", "phases"] {
+        let keyword = command.split(' ').next().unwrap();
+        session.expect(command, &format!("\"{keyword}\" is not available in REPL mode."));
+    }
+}
+
+#[test]
+fn repl_invalid_register_values() {
+    let mut session = ReplSession::repl();
+    session.expect("registers 1 x", "Invalid register value \"x\" (value number 2).");
+    session.expect("registers 2^65536", "is too large");
+    session.expect("registers", "registers 0");
+}
+
+#[test]
+fn unknown_commands() {
+    let mut session = ReplSession::repl();
+    let output = session.expect("foo", "Unknown command \"foo\".");
+    assert!(!output.contains("Note"));
+    session.expect("backtrcae", "\"backtrcae\" is close to \"backtrace\"");
+    let output = session.expect("register 1", "Unknown command");
+    assert!(output.contains("\"register\" is close to \"registers\""));
+    let output = session.expect("icn r0", "Unknown command");
+    assert!(output.contains("\"icn\" is close to \"inc\""));
+    session.expect("inc r0 r1", "Got 2 but inc expects 1.");
+    session.expect("say \"hi", "Missing closing quote");
+    assert_eq!(session.send("   "), "");
+}
+
+#[test]
+fn help_depends_on_mode() {
+    ReplSession::repl().expect("help", "REPL specific commands:");
+    ReplSession::debug(copy_machine()).expect("H", "Debug specific commands:");
+}
+
+#[test]
+fn quitting_stops_the_session() {
+    for command in ["exit", "quit", "q", "QUIT"] {
+        let mut session = ReplSession::repl();
+        session.send(command);
+        assert!(session.is_stopped());
+    }
+}
+
+#[test]
+fn debug_play_to_breakpoint_and_halt() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.expect("b loop2", "Added breakpoint.");
+    session.expect("play", "Reached breakpoint!");
+    assert_eq!(session.get_machine().get_current_line_number(), 4);
+    session.expect("list", "=>*4  loop2: decjz r-2 HALT");
+    session.expect("b loop2", "Removed breakpoint.");
+    session.expect("p", "Machine successfully halted.");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 3 3");
+    session.expect("step", "Machine is already halted, so cannot step.");
+}
+
+#[test]
+fn debug_breakpoint_errors() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.expect("b", "Please provide a label or line number");
+    session.expect("b halt", "Cannot use HALT as a breakpoint label.");
+    session.expect("b 99", "Cannot go to line number 99!");
+    session.expect("b 2", "Added breakpoint.");
+    assert_eq!(session.get_machine().get_breakpoints(), &[2]);
+}
+
+#[test]
+fn debug_breakpoint_on_hit() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.expect("b loop1 hit 3", "Added breakpoint which stops on hit 3.");
+    session.expect("play", "Reached breakpoint!");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 2 1");
+    assert_eq!(session.get_machine().get_breakpoint_hits(0), 2);
+    session.expect("play", "Machine successfully halted.");
+    session.send("reset");
+    session.expect("play", "Reached breakpoint!");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 2 1");
+    session.expect("b loop1 hit 0", "Correct usage: breakpoint [LABEL] hit [N]");
+    session.expect("b loop1", "Removed breakpoint.");
+    assert_eq!(session.get_machine().get_breakpoint_hit_target(0), None);
+}
+
+#[test]
+fn debug_play_moves_past_breakpoint() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.send("b loop1");
+    session.send("play");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 0 3");
+    session.expect("play", "Reached breakpoint!");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 1 2");
+}
+
+#[test]
+fn debug_breakpoint_on_quoted_label() {
+    let machine = parse_str("registers 0 1\nloop 1: decjz r1 halt\ninc r0\ndecjz r2 loop 1").unwrap();
+    let mut session = ReplSession::debug(machine);
+    session.expect("b \"loop 1\"", "Added breakpoint.");
+    session.expect("b loop 1", "Removed breakpoint.");
+}
+
+#[test]
+fn debug_step_undo_and_reset() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.expect("undo", "Can't undo");
+    session.send_script("s\ns");
+    assert_eq!(session.get_machine().get_current_line_number(), 2);
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 1 2");
+    session.expect("u", "Undid step.");
+    assert_eq!(session.get_machine().get_current_line_number(), 1);
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 0 2");
+    session.expect("reset", "Reset machine state!");
+    assert_eq!(session.get_machine().get_current_line_number(), 0);
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 0 3");
+}
+
+#[test]
+fn debug_set_registers() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.expect("registers 0 5", "Registers successfully changed!");
+    session.expect("registers", "registers 0 5");
+    session.send("play");
+    assert_eq!(session.get_machine().get_state().get_nat_registers_as_u128(), vec![5, 5]);
+}
+
+#[test]
+fn debug_run_with_inputs() {
+    let mut session = ReplSession::debug(copy_machine());
+    let output = session.expect("run 0 5", "Running with registers 0 5.");
+    assert!(output.contains("Machine successfully halted."));
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 5 5");
+    // The new inputs are kept for later runs and resets.
+    session.expect("b loop2", "Added breakpoint.");
+    session.expect("run", "Reached breakpoint!");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 5 0");
+    session.send("reset");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 0 5");
+    session.expect("run 1 x", "Invalid register value \"x\"");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 0 5");
+    ReplSession::repl().expect("run 1", "\"run\" is not available in REPL mode.");
+}
+
+#[test]
+fn debug_protect() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.expect("protect r0", "Register r0 is now protected.");
+    session.expect("play", "would modify protected register r0");
+    assert_eq!(session.get_machine().get_current_line_number(), 1);
+    // Instructions typed in keep to the protection too.
+    session.expect("inc r0", "Stopped before modifying protected register r0.");
+    session.expect("protect r0", "Register r0 is no longer protected.");
+    session.expect("inc r0", "Register r0 is now 1.");
+    session.expect("protect r0", "Register r0 is now protected.");
+    session.expect("dec r0", "Stopped before modifying protected register r0.");
+    session.expect("protect r0", "Register r0 is no longer protected.");
+    session.expect("dec r0", "Register r0 is now 0.");
+    session.expect("dec r0", "Cannot decrement r0, its value is already 0.");
+    session.expect("protect", "Correct usage: protect r[NUMBER]");
+}
+
+#[test]
+fn debug_watchpoint() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.expect("watchpoint r-2", "Watchpoint added to r-2.");
+    session.expect("play", "Watchpoint: r-2 changed from 0 to 1.");
+    assert_eq!(session.get_machine().get_current_line_number(), 3);
+    session.expect("play", "Watchpoint: r-2 changed from 1 to 2.");
+    session.expect("watch r0", "Watchpoint added to r0.");
+    session.expect("step", "");
+    session.expect("step", "");
+    session.expect("step", "Watchpoint: r0 changed from 2 to 3.");
+    session.expect("watch r-2", "Watchpoint removed from r-2.");
+    session.expect("watch r0", "Watchpoint removed from r0.");
+    session.expect("play", "Machine successfully halted.");
+    session.expect("watchpoint", "Correct usage: watchpoint r[NUMBER]");
+    ReplSession::repl().expect("watch r0", "\"watchpoint\" is not available in REPL mode.");
+}
+
+#[test]
+fn debug_xref() {
+    let mut session = ReplSession::debug(copy_machine());
+    let output = session.expect("xref loop2", "Lines which jump to loop2:");
+    assert!(output.contains("loop1: decjz r1 loop2"));
+    assert!(output.contains("decjz r-1 loop2"));
+    let output = session.expect("x r1", "Lines which use r1:");
+    assert_eq!(output.lines().count(), 3);
+    session.expect("x", "Please provide a label or register");
+    session.expect("x nowhere", "None.");
+}
+
+#[test]
+fn debug_phases() {
+    ReplSession::debug(copy_machine()).expect("phases", "No phases.");
+    let machine = parse_str("registers 0 2\n#!phase loop\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop").unwrap();
+    let mut session = ReplSession::debug(machine);
+    session.send("play");
+    let output = session.expect("phases", "Steps per phase:");
+    assert!(output.contains("loop  7\ntotal  7"));
+    assert!(!output.contains("Cost per phase"));
+    let mut machine = parse_str("registers 0 2\n#!phase setup\n#!phase loop\nsetup: inc r2\n\
+        decjz r2 loop\nloop: decjz r1 halt\ninc r0\ndecjz r-1 loop").unwrap();
+    machine.set_cost_model(Some("decjz_taken=3".parse().unwrap()));
+    let mut session = ReplSession::debug(machine);
+    session.send("play");
+    let output = session.expect("phases", "Steps per phase:");
+    assert!(output.contains("setup  2\nloop   7\ntotal  9\nCost per phase (inc=1,decjz_taken=3,"));
+    assert!(output.contains("setup  2 (13.3%)\nloop   13 (86.7%)\ntotal  15\n"));
+}
+
+#[test]
+fn debug_undo_keeps_breakpoints() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.send_script("b 0\ns\nu");
+    assert_eq!(session.get_machine().get_current_line_number(), 0);
+    assert!(session.get_machine().get_breakpoints().contains(&0));
+}
+
+#[test]
+fn debug_post_mortem() {
+    let machine = parse_str("registers 0 2\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop").unwrap();
+    let mut session = ReplSession::debug(machine);
+    session.expect("history", "No lines have been executed yet.");
+    session.expect("bt", "No jumps have been taken yet.");
+    session.expect("play", "Machine successfully halted.");
+    session.expect("step", "It can still be inspected with");
+    session.expect("play", "or started again with");
+    session.expect("registers", "registers 2 0");
+    let output = session.expect("history 2", "step 6: 2    decjz r2 loop");
+    assert!(output.contains("step 7: 0    loop: decjz r1 HALT"));
+    assert_eq!(output.lines().count(), 2);
+    let output = session.expect("backtrace", "#0 HALT <- line 0 (loop)");
+    assert!(output.contains("#1 line 0 (loop) <- line 2"));
+    assert_eq!(output.lines().count(), 3);
+    session.expect("history x", "Correct usage: history [NUMBER]");
+    session.send("reset");
+    session.expect("history", "No lines have been executed yet.");
+}
+
+#[test]
+fn history_is_debug_only() {
+    let mut session = ReplSession::repl();
+    session.expect("history", "not available in REPL mode");
+    session.expect("bt", "not available in REPL mode");
+}
+
+#[test]
+fn snapshots() {
+    let mut session = ReplSession::repl();
+    session.send("registers 1 2");
+    session.expect("save-as before", "Saved the registers as \"before\".");
+    session.send_script("inc r0\ndec r1\ninc r2");
+    session.expect("save-as after", "Saved the registers as \"after\".");
+    let output = session.expect("diff before after", "r0: 1 -> 2");
+    assert!(output.contains("r1: 2 -> 1\nr2: 0 -> 1"));
+    session.expect("diff after", "No differences.");
+    session.expect("restore befor", "There's no snapshot called \"befor\". Did you mean \"before\"?");
+    session.expect("restore before", "Restored the registers saved as \"before\".");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 1 2");
+    session.expect("diff before after extra", "Correct usage: diff");
+    session.expect("save-as", "Correct usage: save-as [NAME]");
+}
+
+#[test]
+fn debug_snapshots_survive_resets() {
+    let mut session = ReplSession::debug(copy_machine());
+    session.send_script("s\ns\nsave-as stepped\nreset");
+    session.expect("diff stepped", "r0: 1 -> 0");
+    session.send("restore stepped");
+    assert_eq!(session.get_machine().display_nat_registers(), "registers 1 2");
+}
+
+#[test]
+fn repl_commands_over_several_lines() {
+    let mut session = ReplSession::repl();
+    let output = session.send_script("registers \\\n  4 \\\n  5\nloop:\ninc r1\nouter loop: decjz r1 done");
+    assert!(output.contains("Registers successfully changed!"));
+    assert!(output.contains("Register r1 is now 6."));
+    assert!(output.contains("Register r1 is now 5."));
+    assert!(!output.contains("Unknown command"));
+
+    // Reading from STDIN, the next line of a command gets its own prompt.
+    let mut out = Vec::new();
+    tui::repl(&mut "inc \\\nr2\nloop:".as_bytes(), &mut out, Terminal::Interactive).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("   ...> Register r2 is now 1."));
+    // A command which is still being continued when the input ends is run anyway.
+    assert!(out.contains("Unknown command \"loop:\""));
+}
+
+#[test]
+fn repl_commands_keep_to_the_sandbox() {
+    let sandbox = Sandbox { steps: Some(1), registers: Some(3), ..Sandbox::UNLIMITED };
+    let mut out = Vec::new();
+    let mut input = "inc r1\ninc r2\ninc r5\ndecjz r1 done".as_bytes();
+    tui::repl_in_sandbox(&mut input, &mut out, sandbox, Terminal::Interactive).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    // Each command has a step limit of its own.
+    assert!(out.contains("Register r1 is now 1."));
+    assert!(out.contains("Register r2 is now 1."));
+    assert!(out.contains("Register r1 is now 0."));
+    // A register which would go over the cap isn't allocated.
+    assert!(out.contains("The machine would use more than the limit of 3 registers."));
+    assert!(!out.contains("Register r5"));
+}
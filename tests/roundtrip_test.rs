@@ -0,0 +1,192 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use proptest::{ prelude::*, sample::Index };
+use remuir::{
+    dialect::Dialect,
+    examples,
+    instruction::Instruction,
+    machine::{ Identifier, Line, Machine },
+    memory::{ Memory, PseudoRegister, Register, RegisterNumber },
+    roundtrip::{ self, RoundTripError },
+    urm::Urm,
+};
+
+/// Where a generated jump goes.
+#[derive(Debug, Clone)]
+enum Target {
+    Halt,
+    /// One of the labels which are defined, or a label which isn't if there are none.
+    Label(Index),
+    Line(usize),
+}
+
+/// A generated line, before its label and target are made valid for the rest of the program.
+#[derive(Debug, Clone)]
+struct Draft {
+    label: Option<String>,
+    kind: u8,
+    register: RegisterNumber,
+    target: Target,
+}
+
+fn register() -> impl Strategy<Value = RegisterNumber> {
+    prop_oneof![
+        4 => (0..12usize).prop_map(RegisterNumber::Natural),
+        2 => (1..12usize).prop_map(RegisterNumber::Negative),
+        1 => prop::sample::select(PseudoRegister::NAMES.to_vec())
+            .prop_map(|name| format!("r!{name}").parse().unwrap()),
+    ]
+}
+
+/// Labels are words of letters from any script, digits, "_" and "-", separated by spaces.
+fn label() -> impl Strategy<Value = String> {
+    "[a-zé_][a-zé0-9_-]{0,5}( [a-z0-9-]{1,3})?"
+        .prop_filter("HALT is a jump target", |label| !label.eq_ignore_ascii_case("halt"))
+}
+
+fn draft() -> impl Strategy<Value = Draft> {
+    let target = prop_oneof![
+        Just(Target::Halt),
+        any::<Index>().prop_map(Target::Label),
+        (0..30usize).prop_map(Target::Line),
+    ];
+    (prop::option::weighted(0.4, label()), 0..9u8, register(), target)
+        .prop_map(|(label, kind, register, target)| Draft { label, kind, register, target })
+}
+
+/// Documentation of a label, one or more lines of words.
+fn documentation() -> impl Strategy<Value = String> {
+    "[a-z]{1,5}( [a-z]{1,5}){0,2}(\n[a-z]{1,5}){0,2}"
+}
+
+prop_compose! {
+    fn machine()(
+        drafts in prop::collection::vec(draft(), 0..25),
+        registers in prop::collection::vec(prop_oneof![0..5u128, any::<u128>()], 1..5),
+        phases in prop::collection::vec(any::<Index>(), 0..3),
+        entry in prop::option::of(any::<Index>()),
+        documented in prop::collection::vec((any::<Index>(), documentation()), 0..3),
+        exports in prop::collection::vec(any::<Index>(), 0..3),
+    ) -> Machine {
+        let mut labels: Vec<String> = Vec::new();
+        let mut lines = Vec::new();
+        for (i, draft) in drafts.iter().enumerate() {
+            let label = draft.label.clone().filter(|label| !labels.contains(label));
+            labels.extend(label.clone());
+            let target = match &draft.target {
+                Target::Halt => Identifier::Halt,
+                Target::Label(_) if labels.is_empty() => Identifier::Halt,
+                Target::Label(index) => Identifier::Label(index.get(&labels).clone()),
+                Target::Line(n) => Identifier::Line(*n),
+            };
+            let register = draft.register;
+            let writable = match register {
+                RegisterNumber::Pseudo(_) => RegisterNumber::Natural(0),
+                _ => register,
+            };
+            let instruction = match draft.kind {
+                0 => Instruction::INC(writable),
+                1 => Instruction::DECJZ(register, target),
+                2 => Instruction::DECJNZ(register, target),
+                3 => Instruction::GOTO(target),
+                4 => Instruction::CALL(target),
+                5 => Instruction::RETURN,
+                6 => Instruction::READ(writable),
+                7 => Instruction::WRITE(register),
+                _ => Instruction::HALT,
+            };
+            lines.push(Line::new(i, label.map(Identifier::Label), instruction));
+        }
+        let registers: Vec<Register> = registers.into_iter().map(Register::from).collect();
+        let mut machine = Machine::new_from_lines(&lines, Memory::new_from_slice(&registers));
+        if !labels.is_empty() {
+            for phase in phases {
+                machine.add_phase(phase.get(&labels).clone()).unwrap();
+            }
+            if let Some(entry) = entry {
+                machine.set_entry(entry.get(&labels).clone()).unwrap();
+            }
+            // The parser documents labels in the order of their lines, so the maps only compare
+            // equal if the documentation is added in that order here too.
+            let mut documented: Vec<(usize, String)> = documented
+                .into_iter()
+                .map(|(label, documentation)| (label.index(labels.len()), documentation))
+                .collect();
+            documented.sort_by_key(|(label, _)| *label);
+            for (label, documentation) in documented {
+                machine.set_label_documentation(labels[label].clone(), documentation).unwrap();
+            }
+            for export in exports {
+                machine.add_export(export.get(&labels).clone()).unwrap();
+            }
+        }
+        machine
+    }
+}
+
+proptest! {
+    #[test]
+    fn printed_programs_parse_back(machine in machine()) {
+        roundtrip::check(&machine).map_err(|e| TestCaseError::fail(e.to_string()))?;
+    }
+
+    #[test]
+    fn printing_is_stable(machine in machine()) {
+        let printed = machine.to_source();
+        let reparsed = remuir::parser::parse_str(&printed).unwrap();
+        prop_assert_eq!(reparsed.to_source(), printed);
+        prop_assert_eq!(reparsed.fingerprint(), machine.fingerprint());
+    }
+}
+
+#[test]
+fn examples_round_trip() {
+    for (name, _) in examples::GALLERY {
+        let machine = examples::by_name(name).unwrap();
+        assert!(roundtrip::check(&machine).is_ok(), "{name}");
+    }
+}
+
+#[test]
+fn translated_programs_round_trip() {
+    let urm = "registers 0 4 3\nJ(3, 2, 5)\nS(1)\nS(3)\nT(1, 4)\nJ(1, 1, 1)";
+    roundtrip::check_source(&Urm.translate(urm).unwrap()).unwrap();
+}
+
+#[test]
+fn source_with_macros_round_trips_once_expanded() {
+    let source = "registers 2
+macro clear R
+loop: decjz R +2
+goto loop
+endmacro
+clear r0
+## Adds one.
+add: inc r1";
+    roundtrip::check_source(source).unwrap();
+}
+
+#[test]
+fn unprintable_programs_are_caught() {
+    let lines = [Line::new(0, Some(Identifier::Label(String::from("a: b"))), Instruction::HALT)];
+    let machine = Machine::new_from_lines(&lines, Memory::default());
+    assert!(matches!(roundtrip::check(&machine), Err(RoundTripError::Unparseable { .. })));
+    assert!(matches!(
+        roundtrip::check_source("registers 0\ninc"),
+        Err(RoundTripError::Unparseable { .. }),
+    ));
+}
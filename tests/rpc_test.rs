@@ -0,0 +1,260 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::rpc::{ self, RpcServer };
+use serde_json::{ json, Value };
+
+fn request(id: usize, method: &str, params: &str) -> String {
+    format!(r#"{{"jsonrpc": "2.0", "id": {id}, "method": "{method}", "params": {params}}}"#)
+}
+
+#[test]
+fn load_break_and_run() {
+    let mut server = RpcServer::default();
+    let source = r#"{"source": "registers 0 2\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop"}"#;
+    assert_eq!(
+        server.handle(&request(1, "load", source)).unwrap(),
+        r#"{"id":1,"jsonrpc":"2.0","result":{"lines":3}}"#,
+    );
+    server.handle(&request(2, "setBreakpoint", r#"{"label": "loop"}"#));
+    server.handle(&request(3, "setBreakpoint", r#"{"label": "loop"}"#));
+    assert_eq!(server.get_machine().get_breakpoints(), &[0]);
+    let response = server.handle(&request(4, "step", "{}")).unwrap();
+    assert!(response.contains(r#""result":{"halted":false,"line":1,"steps":1}"#));
+    let response = server.handle(&request(5, "run", "{}")).unwrap();
+    assert!(response.contains(r#""line":0,"reason":"breakpoint""#));
+    assert_eq!(
+        server.handle(&request(6, "getRegisters", "{}")).unwrap(),
+        r#"{"id":6,"jsonrpc":"2.0","result":{"registers":["1","1","0"]}}"#,
+    );
+    server.handle(&request(7, "removeBreakpoint", r#"{"line": 0}"#));
+    let response = server.handle(&request(8, "run", "{}")).unwrap();
+    assert!(response.contains(r#"{"halted":true,"line":null,"reason":"halted","steps":7}"#));
+}
+
+#[test]
+fn data_breakpoints() {
+    let mut server = RpcServer::default();
+    let source = r#"{"source": "registers 0 2\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop"}"#;
+    server.handle(&request(1, "load", source));
+    server.handle(&request(2, "setDataBreakpoint", r#"{"register": "r0"}"#));
+    let response = server.handle(&request(3, "setDataBreakpoint", r#"{"register": "r0"}"#));
+    assert!(response.unwrap().contains(r#""result":{"dataBreakpoints":["r0"]}"#));
+    let response = server.handle(&request(4, "run", "{}")).unwrap();
+    assert!(response.contains(
+        r#"{"halted":false,"line":2,"new":"1","old":"0","reason":"dataBreakpoint","register":"r0","steps":2}"#
+    ));
+    server.handle(&request(5, "step", "{}"));
+    server.handle(&request(6, "step", "{}"));
+    let response = server.handle(&request(7, "step", "{}")).unwrap();
+    assert!(response.contains(r#""new":"2","old":"1","reason":"dataBreakpoint""#));
+    let response = server.handle(&request(8, "removeDataBreakpoint", r#"{"register": "r0"}"#));
+    assert!(response.unwrap().contains(r#""result":{"dataBreakpoints":[]}"#));
+    let response = server.handle(&request(9, "setDataBreakpoint", r#"{"register": "x"}"#));
+    assert!(response.unwrap().contains(r#""code":-32602"#));
+}
+
+#[test]
+fn phases_and_costs() {
+    let mut server = RpcServer::default();
+    let source = r#"{"source": "registers 0 2\n#!phase setup\n#!phase loop\nsetup: inc r2\ndecjz r2 loop\nloop: decjz r1 halt\ninc r0\ndecjz r-1 loop"}"#;
+    server.handle(&request(1, "load", source));
+    server.handle(&request(2, "run", "{}"));
+    assert_eq!(
+        server.handle(&request(3, "getPhases", "{}")).unwrap(),
+        r#"{"id":3,"jsonrpc":"2.0","result":{"phases":[{"phase":"setup","steps":2},{"phase":"loop","steps":7}],"steps":9}}"#,
+    );
+    let response = server.handle(&request(4, "setCostModel", r#"{"decjz_taken": 3}"#)).unwrap();
+    assert!(response.contains(r#""result":{"costModel":"inc=1,decjz_taken=3,decjz_not_taken=1,"#));
+    // The model stays in use for the next program.
+    server.handle(&request(5, "load", source));
+    server.handle(&request(6, "run", "{}"));
+    assert_eq!(
+        server.handle(&request(7, "getPhases", "{}")).unwrap(),
+        r#"{"id":7,"jsonrpc":"2.0","result":{"cost":15,"phases":[{"cost":2,"phase":"setup","steps":2},{"cost":13,"phase":"loop","steps":7}],"steps":9}}"#,
+    );
+    let response = server.handle(&request(8, "setCostModel", r#"{"jump": 1}"#)).unwrap();
+    assert!(response.contains(r#""code":-32602"#));
+    let response = server.handle(&request(9, "setCostModel", r#"{"inc": -1}"#)).unwrap();
+    assert!(response.contains(r#""message":"Expected \"inc\" to be a number.""#));
+}
+
+#[test]
+fn step_limit_summary() {
+    let mut server = RpcServer::default();
+    let response = server.handle(&request(1, "setStepLimit", r#"{"steps": 10}"#)).unwrap();
+    assert!(response.contains(r#""result":{"stepLimit":10}"#));
+    // The limit stays in use for the next program.
+    let source = r#"{"source": "registers 0\nloop: inc r0\ndecjz r1 loop"}"#;
+    server.handle(&request(2, "load", source));
+    let response = server.handle(&request(3, "run", "{}")).unwrap();
+    assert!(response.contains(r#""line":0,"reason":"stepLimit","steps":10,"summary":{"#));
+    assert!(response.contains(r#""backtrace":[{"from":"line 1","to":"line 0 (loop)"},"#));
+    assert!(response.contains(r#""hottestLines":[{"count":5,"line":"line 0 (loop)"},"#));
+    assert!(response.contains(r#""source":"0    loop: inc r0","steps":10,"window":10}"#));
+    assert!(response.contains(r#""registers":"registers 0","seed":null,"stepLimit":10,"#));
+    server.handle(&request(4, "setStepLimit", r#"{"steps": null}"#));
+    let response = server.handle(&request(5, "setStepLimit", r#"{"steps": -1}"#)).unwrap();
+    assert!(response.contains(r#""code":-32602"#));
+}
+
+#[test]
+fn input_and_output() {
+    let mut server = RpcServer::default();
+    let response = server.handle(&request(1, "addInput", r#"{"values": ["4", 6]}"#)).unwrap();
+    assert!(response.contains(r#""result":{"input":["4","6"]}"#), "{response}");
+    let source = r#"{"source": "registers 0\nloop: read r1\ninc r1\nwrite r1\ngoto loop"}"#;
+    server.handle(&request(2, "load", source));
+    let response = server.handle(&request(3, "run", "{}")).unwrap();
+    assert!(response.contains(r#""message":"There was nothing left to read.","reason":"io""#));
+    let response = server.handle(&request(4, "getOutput", "{}")).unwrap();
+    assert!(response.contains(r#""result":{"output":["5","7"]}"#), "{response}");
+    let response = server.handle(&request(5, "getOutput", "{}")).unwrap();
+    assert!(response.contains(r#""result":{"output":[]}"#));
+    let response = server.handle(&request(6, "addInput", r#"{"values": ["-1"]}"#)).unwrap();
+    assert!(response.contains(r#""code":-32602"#));
+}
+
+#[test]
+fn sandbox() {
+    let mut server = RpcServer::default();
+    let params = r#"{"timeMs": 0, "registers": 5}"#;
+    let response = server.handle(&request(1, "setSandbox", params)).unwrap();
+    assert!(response.contains(
+        r#""result":{"calls":null,"limbs":null,"registers":5,"steps":null,"timeMs":0}"#
+    ));
+    server.handle(&request(2, "load", r#"{"source": "registers 0\ninc r9"}"#));
+    let response = server.handle(&request(3, "run", "{}")).unwrap();
+    assert!(response.contains(r#""reason":"resourceLimit""#));
+    assert!(response.contains("more than the limit of 5 registers"));
+    let response = server.handle(&request(4, "setSandbox", r#"{"limbs": -1}"#)).unwrap();
+    assert!(response.contains(r#""code":-32602"#));
+}
+
+#[test]
+fn label_info() {
+    let mut server = RpcServer::default();
+    let source = r#"{"source": "registers 0\ninc r0\n## Stops.\nend: halt\nlast: halt"}"#;
+    server.handle(&request(1, "load", source));
+    assert_eq!(
+        server.handle(&request(2, "getLabelInfo", r#"{"label": "end"}"#)).unwrap(),
+        r#"{"id":2,"jsonrpc":"2.0","result":{"documentation":"Stops.","line":1}}"#,
+    );
+    let response = server.handle(&request(3, "getLabelInfo", r#"{"label": "last"}"#)).unwrap();
+    assert!(response.contains(r#""documentation":null"#));
+    let response = server.handle(&request(4, "getLabelInfo", r#"{"label": "ned"}"#)).unwrap();
+    assert!(response.contains(r#""code":-32000"#));
+}
+
+#[test]
+fn errors() {
+    let mut server = RpcServer::default();
+    assert!(server.handle("{").unwrap().contains(r#""code":-32700"#));
+    assert!(server.handle(r#"{"id": 1, "method": "run"}"#).unwrap().contains(r#""code":-32600"#));
+    assert!(server.handle(&request(2, "fly", "{}")).unwrap().contains(r#""code":-32601"#));
+    assert!(server.handle(&request(3, "load", "{}")).unwrap().contains(r#""code":-32602"#));
+    let response = server.handle(&request(4, "load", r#"{"source": "inc r0"}"#)).unwrap();
+    assert!(response.contains(r#""code":-32000"#));
+    assert!(response.contains(r#""id":4"#));
+    // Requests without an id which aren't notifications still get an error.
+    for request in ["[]", r#"{"foo": 1}"#, "5", r#"{"jsonrpc": "2.0"}"#] {
+        let response = server.handle(request).unwrap();
+        assert!(response.contains(r#""code":-32600"#), "{request}");
+        assert!(response.contains(r#""id":null"#), "{request}");
+    }
+}
+
+#[test]
+fn batches() {
+    let mut server = RpcServer::default();
+    let batch = format!(
+        "[{}, {}, {}, 7]",
+        r#"{"jsonrpc": "2.0", "method": "load", "params": {"source": "registers 4"}}"#,
+        request(1, "getRegisters", "{}"),
+        request(2, "fly", "{}"),
+    );
+    let response: Value = serde_json::from_str(&server.handle(&batch).unwrap()).unwrap();
+    assert_eq!(response, json!([
+        { "jsonrpc": "2.0", "id": 1, "result": { "registers": ["4"] } },
+        {
+            "jsonrpc": "2.0",
+            "id": 2,
+            "error": { "code": -32601, "message": "There is no method \"fly\"." },
+        },
+        {
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": -32600, "message": "Not a JSON-RPC 2.0 request." },
+        },
+    ]));
+    // A batch of notifications gets no response at all.
+    let batch = r#"[{"jsonrpc": "2.0", "method": "reset"}]"#;
+    assert_eq!(server.handle(batch), None);
+}
+
+#[test]
+fn notifications_get_no_response() {
+    let mut input = concat!(
+        r#"{"jsonrpc": "2.0", "method": "load", "params": {"source": "registers 4"}}"#,
+        "\n\n",
+        r#"{"jsonrpc": "2.0", "id": "a", "method": "getRegisters"}"#,
+    ).as_bytes();
+    let mut out: Vec<u8> = Vec::new();
+    rpc::serve(&mut input, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"id\":\"a\",\"jsonrpc\":\"2.0\",\"result\":{\"registers\":[\"4\"]}}\n",
+    );
+}
+
+#[test]
+fn execute_notebook_cells() {
+    let mut server = RpcServer::default();
+    let execution = server.execute("inc r1\ninc r1\nregisters");
+    assert!(execution.output.contains("Register r1 is now 2."));
+    assert_eq!(execution.registers, ["0", "2"]);
+    // A program is loaded and played.
+    let execution = server.execute("# Copy r1.\nregisters 0 3\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop");
+    assert!(execution.output.contains("Machine successfully halted."));
+    assert_eq!(execution.registers, ["3", "0", "0"]);
+    // Later cells run debug commands on it.
+    let execution = server.execute("run 0 1\nhistory 1");
+    assert!(execution.output.contains("Running with registers 0 1."));
+    assert_eq!(execution.registers, ["1", "0", "0"]);
+    let execution = server.execute("registers 0 1\nloop: decjz");
+    assert!(execution.output.starts_with("Invalid syntax when parsing source code!"));
+    let response = server.handle(&request(1, "execute", r#"{"code": "reset"}"#)).unwrap();
+    assert!(response.contains(r#""registers":["0","1"]"#));
+}
+
+#[test]
+fn scratch_registers_are_included_on_request() {
+    let mut server = RpcServer::default();
+    let source = r#"{"source": "registers 1\ninc r-2\ninc r-2"}"#;
+    server.handle(&request(1, "load", source));
+    server.handle(&request(2, "run", "{}"));
+    assert_eq!(
+        server.handle(&request(3, "getRegisters", "{}")).unwrap(),
+        r#"{"id":3,"jsonrpc":"2.0","result":{"registers":["1"]}}"#,
+    );
+    server.set_include_scratch(true);
+    assert_eq!(
+        server.handle(&request(4, "getRegisters", "{}")).unwrap(),
+        r#"{"id":4,"jsonrpc":"2.0","result":{"registers":["1"],"scratch":["0","0","2"]}}"#,
+    );
+    let response = server.handle(&request(5, "execute", r#"{"code": "registers"}"#)).unwrap();
+    assert!(response.contains(r#""registers":["1"],"scratch":["0","0","2"]"#));
+}
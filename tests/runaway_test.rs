@@ -0,0 +1,59 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{ parser::parse_str, runaway::RunawaySummary };
+
+/// Loops forever in the inner loop, after one pass through the outer one.
+const NESTED: &str = "registers 0
+outer: inc r0
+inner: inc r1
+decjz r2 inner2
+inner2: decjz r3 inner";
+
+#[test]
+fn summary_shows_where_the_machine_loops() {
+    let mut machine = parse_str(NESTED).unwrap();
+    machine.set_history_limit(Some(9));
+    machine.set_step_limit(Some(20));
+    machine.run();
+    assert!(machine.is_step_limit_reached());
+    let summary = RunawaySummary::new(&machine);
+    assert_eq!(summary.to_string(), "Stopped after 20 steps, before:
+  2    decjz r2 inner2
+Most recent jumps:
+  #0 line 1 (inner) <- line 3 (inner2)
+  #1 line 1 (inner) <- line 3 (inner2)
+  #2 line 1 (inner) <- line 3 (inner2)
+Hottest lines in the last 9 steps:
+  line 1 (inner): 3 (33.3%)
+  line 2: 3 (33.3%)
+  line 3 (inner2): 3 (33.3%)
+");
+    assert_eq!(summary.to_json()["line"], 2);
+    assert_eq!(summary.to_json()["hottestLines"].as_array().unwrap().len(), 3);
+    assert_eq!(summary.to_json()["manifest"]["stepLimit"], 20);
+}
+
+#[test]
+fn summary_without_history_only_has_the_line() {
+    let mut machine = parse_str(NESTED).unwrap();
+    machine.set_step_limit(Some(2));
+    machine.run();
+    assert_eq!(
+        RunawaySummary::new(&machine).to_string(),
+        "Stopped after 2 steps, before:\n  2    decjz r2 inner2\n",
+    );
+}
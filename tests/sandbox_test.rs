@@ -0,0 +1,113 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::time::Duration;
+
+use remuir::{
+    instruction::Instruction,
+    machine::{ Identifier, RuntimeError },
+    memory::RegisterNumber,
+    parser::parse_str,
+    sandbox::{ ResourceLimit, Sandbox },
+};
+
+/// Never halts.
+const FOREVER: &str = "registers 0
+loop: inc r0
+goto loop";
+
+#[test]
+fn register_cap() {
+    let mut machine = parse_str("registers 0\ninc r0\ninc r100").unwrap();
+    machine.set_sandbox(Sandbox { registers: Some(10), ..Sandbox::UNLIMITED });
+    machine.run();
+    assert_eq!(machine.get_resource_limit(), Some(ResourceLimit::Registers(10)));
+    // The instruction which would have used too many registers isn't executed.
+    assert!(!machine.is_halted());
+    assert_eq!(machine.get_steps(), 1);
+}
+
+#[test]
+fn limb_cap() {
+    let mut machine = parse_str(FOREVER).unwrap();
+    machine.set_sandbox(Sandbox { limbs: Some(0), ..Sandbox::UNLIMITED });
+    machine.run();
+    assert_eq!(machine.get_resource_limit(), Some(ResourceLimit::Limbs(0)));
+}
+
+#[test]
+fn time_cap() {
+    let mut machine = parse_str(FOREVER).unwrap();
+    machine.set_sandbox(Sandbox { time: Some(Duration::ZERO), ..Sandbox::UNLIMITED });
+    machine.run();
+    assert_eq!(machine.get_resource_limit(), Some(ResourceLimit::Time(Duration::ZERO)));
+    assert_eq!(
+        machine.get_resource_limit().unwrap().to_string(),
+        "The machine ran for longer than the limit of 0 ms.",
+    );
+    // Resetting lets it run again.
+    machine.reset();
+    assert_eq!(machine.get_resource_limit(), None);
+}
+
+#[test]
+fn call_returns_the_cap_as_an_error() {
+    let mut machine = parse_str(FOREVER).unwrap();
+    machine.set_sandbox(Sandbox { time: Some(Duration::ZERO), ..Sandbox::UNLIMITED });
+    assert!(matches!(
+        machine.call(&[]),
+        Err(RuntimeError::ResourceLimit(ResourceLimit::Time(_))),
+    ));
+}
+
+#[test]
+fn step_limit_is_part_of_the_sandbox() {
+    let mut machine = parse_str(FOREVER).unwrap();
+    machine.set_step_limit(Some(5));
+    assert_eq!(machine.get_sandbox().steps, Some(5));
+    machine.run();
+    assert!(machine.is_step_limit_reached());
+    assert_eq!(machine.get_resource_limit(), None);
+}
+
+#[test]
+fn executed_instructions_keep_to_the_sandbox() {
+    let mut machine = parse_str("registers 0").unwrap();
+    machine.set_sandbox(Sandbox { steps: Some(2), registers: Some(2), ..Sandbox::UNLIMITED });
+    assert!(matches!(machine.execute_step(Instruction::INC(RegisterNumber::Natural(1))), Ok(None)));
+    assert!(matches!(
+        machine.execute_step(Instruction::INC(RegisterNumber::Natural(2))),
+        Err(RuntimeError::ResourceLimit(ResourceLimit::Registers(2))),
+    ));
+    assert_eq!(machine.display_nat_registers(), "registers 0 1");
+    let halt = Instruction::DECJZ(RegisterNumber::Natural(0), Identifier::Halt);
+    assert!(matches!(machine.execute_step(halt.clone()), Ok(Some(Identifier::Halt))));
+    assert_eq!(machine.get_steps(), 2);
+    assert!(matches!(
+        machine.execute_step(halt),
+        Err(RuntimeError::StepLimitReached { limit: 2 }),
+    ));
+}
+
+#[test]
+fn call_cap() {
+    let mut machine = parse_str("registers 0\nagain: call again").unwrap();
+    machine.set_sandbox(Sandbox { calls: Some(100), ..Sandbox::UNLIMITED });
+    machine.run();
+    assert_eq!(machine.get_resource_limit(), Some(ResourceLimit::Calls(100)));
+    assert_eq!(machine.get_call_stack().len(), 100);
+    assert_eq!(machine.get_steps(), 100);
+}
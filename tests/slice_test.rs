@@ -0,0 +1,51 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{ memory::RegisterNumber, parser::parse_str };
+
+const TWO_LOOPS: &str = "registers 2 3
+loop: decjz r1 next
+inc r0
+decjz r-1 loop
+next: decjz r2 halt
+inc r3
+decjz r-1 next";
+
+#[test]
+fn independent_loops() {
+    let machine = parse_str(TWO_LOOPS).unwrap();
+    assert_eq!(machine.slice(RegisterNumber::Natural(0)).get_lines(), &[0, 1]);
+    assert_eq!(machine.slice(RegisterNumber::Natural(3)).get_lines(), &[3, 4]);
+    assert_eq!(
+        machine.slice(RegisterNumber::Natural(3)).to_string(),
+        "Lines which can affect r3:\n3  next: decjz r2 HALT\n4  inc r3\n",
+    );
+}
+
+#[test]
+fn control_dependence_pulls_in_other_registers() {
+    // Whether r0 is incremented depends on r1, which depends on r2.
+    let machine = parse_str("registers 0 0 4
+fill: decjz r2 check
+inc r1
+decjz r-1 fill
+check: decjz r1 halt
+inc r0
+inc r5").unwrap();
+    let slice = machine.slice(RegisterNumber::Natural(0));
+    assert_eq!(slice.get_lines(), &[0, 1, 3, 4]);
+    assert_eq!(machine.slice(RegisterNumber::Natural(5)).get_lines(), &[0, 1, 3, 5]);
+}
@@ -0,0 +1,119 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    examples,
+    instruction::Instruction,
+    memory::RegisterNumber,
+    parser::parse_str,
+    specialize::{ specialize, SpecializeError },
+};
+
+// r0 += r1 * r2, keeping r1 intact.
+fn multiply_source() -> String {
+    String::from("registers 0 3 2
+outer: decjz r2 HALT
+copy: decjz r1 restore
+inc r0
+inc r-2
+decjz r-1 copy
+restore: decjz r-2 outer
+inc r1
+decjz r-1 restore")
+}
+
+#[test]
+fn specialized_gives_same_result() {
+    let mut original = parse_str(&multiply_source()).unwrap();
+    let mut specialized = specialize(&original, &[(RegisterNumber::Natural(2), 2)]).unwrap();
+    original.run();
+    specialized.run();
+    assert_eq!(specialized.display_nat_registers(), "registers 6 3 0");
+    assert_eq!(specialized.display_nat_registers(), original.display_nat_registers());
+}
+
+#[test]
+fn known_register_only_touched_when_halting() {
+    let original = parse_str(&multiply_source()).unwrap();
+    let specialized = specialize(&original, &[(RegisterNumber::Natural(2), 2)]).unwrap();
+    let uses: Vec<&Instruction> = specialized
+        .get_lines()
+        .iter()
+        .map(|line| line.get_instruction())
+        .filter(|instruction| instruction.get_register() == Some(RegisterNumber::Natural(2)))
+        .collect();
+    // Only the two decrements which leave r2 at 0.
+    assert_eq!(uses.len(), 2);
+}
+
+#[test]
+fn specialized_source_parses() {
+    let original = parse_str(&multiply_source()).unwrap();
+    let specialized = specialize(&original, &[(RegisterNumber::Natural(2), 2)]).unwrap();
+    let mut reparsed = parse_str(&specialized.to_source()).unwrap();
+    reparsed.run();
+    assert_eq!(reparsed.display_nat_registers(), "registers 6 3 0");
+}
+
+#[test]
+fn subroutines_rejected() {
+    let machine = parse_str("registers 0 1\ncall add\nhalt\nadd: inc r0\nreturn").unwrap();
+    let result = specialize(&machine, &[(RegisterNumber::Natural(1), 1)]);
+    assert!(matches!(result, Err(SpecializeError::Subroutine { line: 0 })));
+}
+
+#[test]
+fn input_and_output_rejected() {
+    let machine = parse_str("registers 0 1
+inc r0
+write r0").unwrap();
+    let result = specialize(&machine, &[(RegisterNumber::Natural(1), 1)]);
+    assert!(matches!(result, Err(SpecializeError::Io { line: 1 })));
+}
+
+#[test]
+fn negative_register_rejected() {
+    let original = parse_str(&multiply_source()).unwrap();
+    assert!(matches!(
+        specialize(&original, &[(RegisterNumber::Negative(2), 2)]),
+        Err(SpecializeError::NegativeRegister(_)),
+    ));
+}
+
+#[test]
+fn scratch_registers_are_known() {
+    // Every register but r0 is a negative one, which starts at 0, so the whole run is known.
+    let mut original = examples::square(2);
+    let mut specialized = specialize(&original, &[(RegisterNumber::Natural(0), 2)]).unwrap();
+    assert_eq!(specialized.to_source(), "registers 2\n\ninc r0\ninc r0\ninc r-2\ninc r-2\nhalt");
+    original.run();
+    specialized.run();
+    assert_eq!(specialized.display_nat_registers(), "registers 4");
+    assert_eq!(specialized.display_nat_registers(), original.display_nat_registers());
+
+    // Registers past the registers line start at 0 too.
+    let mut original = parse_str("registers 0 3\nloop: decjz r1 double\ninc r2\ngoto loop\n\
+double: decjz r2 HALT\ninc r0\ninc r0\ngoto double").unwrap();
+    let mut specialized = specialize(&original, &[(RegisterNumber::Natural(1), 3)]).unwrap();
+    assert_eq!(
+        specialized.to_source(),
+        format!("registers 0 3\n\n{}{}halt", "inc r0\n".repeat(6), "decjz r1 HALT\n".repeat(3)),
+    );
+    original.run();
+    specialized.run();
+    assert_eq!(specialized.display_nat_registers(), "registers 6 0");
+    assert_eq!(original.display_nat_registers(), "registers 6 0 0");
+}
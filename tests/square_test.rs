@@ -14,7 +14,7 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program.  If not, see <https://www.gnu.org/licenses/>. */
 
-use remuir::{ parser::parse_str, machine::Machine };
+use remuir::{ examples, parser::parse_str, machine::Machine };
 
 #[test]
 fn square() {
@@ -60,6 +60,17 @@ inc r0
 decjz r-1 addition
 ");
     let mut machine: Machine = parse_str(&source_code).unwrap();
+    let example = examples::square(3);
+    assert!(machine.diff(&example).is_empty(), "{}", machine.diff(&example));
     machine.run();
     assert_eq!(machine.display_nat_registers(), "registers 9")
 }
+
+#[test]
+fn example_square() {
+    for n in 0..6 {
+        let mut machine = examples::square(n);
+        machine.run();
+        assert_eq!(machine.get_state().get_nat_registers_as_u128()[0], n * n);
+    }
+}
@@ -0,0 +1,76 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    memory::RegisterNumber,
+    parser::parse_str,
+    test_block::{ find_tests, TestBlockError, TestOutcome, DEFAULT_STEP_LIMIT },
+};
+
+/// Add r0 to r1.
+const ADD: &str = "registers 2 3
+#test adds { registers 2 3 / expect registers 0 5 }
+#test adds zero {
+#   registers 0 4
+#   expect r1 = 4 / limit 10
+# }
+#test wrong { expect registers 0 6 }
+#test too slow { limit 3 }
+add: decjz r0 HALT
+inc r1
+decjz r-1 add";
+
+#[test]
+fn test_blocks_are_read_and_run() {
+    let tests = find_tests(ADD).unwrap();
+    let names: Vec<&str> = tests.iter().map(|test| test.name.as_str()).collect();
+    assert_eq!(names, ["adds", "adds zero", "wrong", "too slow"]);
+    assert_eq!(tests[1].line, 3);
+    assert_eq!(tests[1].expected_values, [(RegisterNumber::Natural(1), 4)]);
+    assert_eq!(tests[1].step_limit, 10);
+    assert_eq!(tests[0].step_limit, DEFAULT_STEP_LIMIT);
+    // Test blocks are comments, so they don't get in the way of running the program.
+    let machine = parse_str(ADD).unwrap();
+    let outcomes: Vec<TestOutcome> = tests.iter().map(|test| test.run(&machine)).collect();
+    assert_eq!(outcomes, [
+        TestOutcome::Passed,
+        TestOutcome::Passed,
+        TestOutcome::Failed(String::from("expected registers 0 6, got registers 0 5")),
+        TestOutcome::Failed(String::from("didn't halt within 3 steps")),
+    ]);
+}
+
+#[test]
+fn malformed_test_blocks() {
+    assert!(matches!(
+        find_tests("registers 0\n#test open { registers 1\nhalt"),
+        Err(TestBlockError::Unclosed { line: 2, .. }),
+    ));
+    assert!(matches!(
+        find_tests("registers 0\n#test no brace"),
+        Err(TestBlockError::MissingBrace { line: 2 }),
+    ));
+    assert!(matches!(
+        find_tests("registers 0\n#test odd {\n# expect r0 = 1\n# run }"),
+        Err(TestBlockError::UnknownStatement { line: 4, .. }),
+    ));
+    assert!(matches!(
+        find_tests("registers 0\n#test bad { registers 1 x }"),
+        Err(TestBlockError::InvalidRegisters { line: 2, .. }),
+    ));
+    // Other comments which start with "#test" aren't test blocks.
+    assert!(find_tests("registers 0\n#testing, this isn't a test").unwrap().is_empty());
+}
@@ -0,0 +1,89 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use remuir::{
+    instruction::Instruction,
+    machine::{ Identifier, Line, Machine },
+    manifest::Manifest,
+    memory::{ Memory, Register, RegisterNumber },
+    parser::parse_str,
+    timeline::{ Timeline, TimelineEvent },
+};
+
+fn event(name: &str, start: usize, duration: usize) -> TimelineEvent {
+    TimelineEvent { name: String::from(name), start, duration }
+}
+
+#[test]
+fn regions_between_labels() {
+    let mut machine = parse_str("registers 0 2
+inc r2
+loop: decjz r1 done
+inc r0
+decjz r-1 loop
+done: decjz r2 halt").unwrap();
+    let timeline = Timeline::record(&mut machine);
+    assert!(machine.is_halted());
+    assert_eq!(timeline.get_events(), &[
+        event("(start)", 0, 1),
+        event("loop", 1, 3),
+        event("loop", 4, 3),
+        event("loop", 7, 1),
+        event("done", 8, 1),
+    ]);
+}
+
+#[test]
+fn trace_json() {
+    let mut machine = parse_str("registers 0 1\nloop: decjz r1 halt\ndecjz r2 loop").unwrap();
+    let manifest = Manifest {
+        version: String::from("0.1.0"),
+        fingerprint: 0xc0ffee,
+        seed: Some(7),
+        ..Manifest::new(&machine)
+    };
+    let mut out: Vec<u8> = Vec::new();
+    Timeline::record(&mut machine).write_trace_json(&mut out, &manifest).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), r#"{"traceEvents":[
+{"name":"loop","cat":"label","ph":"X","ts":0,"dur":2,"pid":1,"tid":1},
+{"name":"loop","cat":"label","ph":"X","ts":2,"dur":1,"pid":1,"tid":1}
+],"otherData":{"costModel":null,"fingerprint":"0000000000c0ffee","optimized":false,"registers":"registers 0 1","seed":7,"stepLimit":null,"strictJumps":false,"version":"0.1.0"}}
+"#);
+}
+
+#[test]
+fn trace_json_escapes_labels() {
+    // Labels in source code can't contain quotes, but labels of machines built directly can.
+    let quoted = Identifier::Label(String::from("\"quoted\""));
+    let lines = [
+        Line::new(
+            0,
+            Some(quoted.clone()),
+            Instruction::DECJZ(RegisterNumber::Natural(1), Identifier::Halt),
+        ),
+        Line::new(1, None, Instruction::DECJZ(RegisterNumber::Natural(2), quoted)),
+    ];
+    let registers = Memory::new_from_slice(&[Register::from(0), Register::from(1)]);
+    let mut machine = Machine::new_from_lines(&lines, registers);
+    let manifest = Manifest::new(&machine);
+    let mut out: Vec<u8> = Vec::new();
+    Timeline::record(&mut machine).write_trace_json(&mut out, &manifest).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with(r#"{"traceEvents":[
+{"name":"\"quoted\"","cat":"label","ph":"X","ts":0,"dur":2,"pid":1,"tid":1},
+{"name":"\"quoted\"","cat":"label","ph":"X","ts":2,"dur":1,"pid":1,"tid":1}
+],"#));
+}
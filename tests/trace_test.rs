@@ -0,0 +1,70 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::num::NonZeroUsize;
+
+use remuir::{
+    parser::parse_str,
+    trace::{ Sample, SampledTrace, TraceConfig },
+};
+
+const SOURCE: &str = "registers 0 3
+loop: decjz r1 done
+inc r0
+decjz r-1 loop
+done: halt";
+
+#[test]
+fn samples_every_nth_step_and_jumps_to_labels() {
+    let mut machine = parse_str(SOURCE).unwrap();
+    let config = TraceConfig {
+        interval: NonZeroUsize::new(4),
+        labels: vec![String::from("loop"), String::from("done")],
+    };
+    let trace = SampledTrace::record(&mut machine, &config);
+    assert!(machine.is_halted());
+    assert_eq!(trace.get_steps(), 11);
+    assert_eq!(trace.get_samples(), &[
+        Sample::Step { step: 0, line: 0 },
+        Sample::Jump { step: 3, from: 2, label: String::from("loop") },
+        Sample::Step { step: 4, line: 1 },
+        Sample::Jump { step: 6, from: 2, label: String::from("loop") },
+        Sample::Step { step: 8, line: 2 },
+        Sample::Jump { step: 9, from: 2, label: String::from("loop") },
+        Sample::Jump { step: 10, from: 0, label: String::from("done") },
+    ]);
+    assert_eq!(trace.line_counts(), vec![(0, 1), (1, 1), (2, 1)]);
+    assert_eq!(trace.jump_counts(), vec![("loop", 3), ("done", 1)]);
+    assert_eq!(trace.to_string(), "Steps: 11
+Samples (every 4 steps): 3
+  line 0: 1 (33.3%)
+  line 1: 1 (33.3%)
+  line 2: 1 (33.3%)
+Jumps:
+  loop: 3
+  done: 1
+");
+}
+
+#[test]
+fn only_jumps() {
+    let mut machine = parse_str(SOURCE).unwrap();
+    let config = TraceConfig { interval: None, labels: vec![String::from("done")] };
+    let trace = SampledTrace::record(&mut machine, &config);
+    let jump = Sample::Jump { step: 10, from: 0, label: String::from("done") };
+    assert_eq!(trace.get_samples(), &[jump]);
+    assert_eq!(trace.to_string(), "Steps: 11\nJumps:\n  done: 1\n");
+}
@@ -0,0 +1,86 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::{ fs, path::PathBuf };
+
+use remuir::{ parser::parse_str, transcript::Transcript, tui::{ self, Terminal } };
+
+fn temporary_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("remuir-{name}-{}.md", std::process::id()))
+}
+
+#[test]
+fn repl_commands_are_recorded_until_stopped() {
+    let path = temporary_file("transcript-repl");
+    let script = format!(
+        "inc r1\ntranscript start {}\ninc r1\n\nr\ntranscript stop\ninc r1\nq\n",
+        path.display(),
+    );
+    let mut out: Vec<u8> = Vec::new();
+    tui::repl(&mut script.as_bytes(), &mut out, Terminal::Interactive).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains(&format!("Recording the session to {}.", path.display())), "{out}");
+    // The output still goes to the terminal while recording.
+    assert!(out.contains("Register r1 is now 2."), "{out}");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# remuir transcript
+
+```text
+remuir> inc r1
+Register r1 is now 2.
+```
+
+```text
+remuir> r
+registers 0 2
+```
+");
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn debug_sessions_can_be_recorded() {
+    let path = temporary_file("transcript-debug");
+    let machine = parse_str("registers 0 1\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop").unwrap();
+    let script = format!("transcript start {}\nb loop\nq\n", path.display());
+    let mut out: Vec<u8> = Vec::new();
+    tui::debug(machine, &mut script.as_bytes(), &mut out, Terminal::Interactive).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents.starts_with("# remuir transcript\n\n```text\nremuir> b loop\n"), "{contents}");
+    assert!(contents.ends_with("remuir> q\n```\n"), "{contents}");
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn transcript_usage() {
+    let mut out: Vec<u8> = Vec::new();
+    let mut script = "transcript stop\ntranscript\n".as_bytes();
+    tui::repl(&mut script, &mut out, Terminal::Interactive).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("There's no transcript to stop."));
+    assert!(out.contains("Correct usage: transcript [start PATH | stop]"));
+}
+
+#[test]
+fn fences_are_longer_than_backticks_in_the_output() {
+    let path = temporary_file("transcript-fences");
+    let mut transcript = Transcript::create(&path).unwrap();
+    transcript.record("help", "Use ```code``` here.\n").unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# remuir transcript\n\n````text\nremuir> help\nUse ```code``` here.\n````\n",
+    );
+    let _ = fs::remove_file(&path);
+}
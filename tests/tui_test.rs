@@ -0,0 +1,147 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::{ num::NonZeroU32, time::Duration };
+
+use remuir::{
+    machine::Machine,
+    parser::parse_str,
+    tui::{self, Mode, ReplState, Terminal},
+};
+
+#[test]
+fn command_writes_to_given_output() {
+    let mut machine = Machine::default();
+    let mut mode = Mode::Repl;
+    let mut out: Vec<u8> = Vec::new();
+    let state = tui::command("inc r2", &mut machine, &mut mode, &mut out).unwrap();
+    assert!(matches!(state, ReplState::KeepLooping));
+    assert_eq!(String::from_utf8(out).unwrap(), "Register r2 is now 1.\n");
+}
+
+#[test]
+fn debug_session_reads_from_given_input() {
+    let machine = parse_str("registers 0 2\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop").unwrap();
+    let mut input = "play\nq\n".as_bytes();
+    let mut out: Vec<u8> = Vec::new();
+    tui::debug(machine, &mut input, &mut out, Terminal::Interactive).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("Machine successfully halted."));
+    assert!(out.contains("registers 2 0 0\nNext line:\nNone (machine halted)."));
+}
+
+#[test]
+fn info_shows_label_documentation() {
+    let source = "registers 1\n## Counts down.\nloop: decjz r0 halt\nlast: halt";
+    let machine = parse_str(source).unwrap();
+    let mut input = "info label loop\ninfo label last\ninfo label lop\ninfo\nq\n".as_bytes();
+    let mut out: Vec<u8> = Vec::new();
+    tui::debug(machine, &mut input, &mut out, Terminal::Interactive).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("0    loop: decjz r0 HALT\nCounts down.\n"));
+    assert!(out.contains("\"last\" isn't documented."));
+    assert!(out.contains("Cannot find label \"lop\" in the code! Did you mean \"loop\"?"));
+    assert!(out.contains("Correct usage: info label [LABEL]"));
+}
+
+#[test]
+fn debug_session_with_several_machines() {
+    let machine = parse_str("registers 0 2\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop").unwrap();
+    let path = std::env::temp_dir().join(format!("remuir-machines-{}.remuir", std::process::id()));
+    std::fs::write(&path, "registers 0 2\nloop: decjz r1 halt\ninc r0\ninc r0\ndecjz r2 loop").unwrap();
+    let script = format!(
+        "machine add \"{}\"\nmachine\nmachine switch 2\nmachine lockstep 1 2\nmachine switch 3\n\
+        machine lockstep 2 2\nmachine fly\nq\n",
+        path.display(),
+    );
+    let mut out: Vec<u8> = Vec::new();
+    tui::debug(machine, &mut script.as_bytes(), &mut out, Terminal::Interactive).unwrap();
+    std::fs::remove_file(path).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("Added machine 2. Use \"machine switch 2\" to debug it."));
+    assert!(out.contains("=> 1  (initial program) (running)\n   2  "));
+    assert!(out.contains("Switched to machine 2.\n\nMachine 2:\n\nregisters 0 2\n"));
+    assert!(out.contains("Left is machine 1, right is machine 2.\nMachines differ after 3 steps.\n  r0: 1 != 2\n"));
+    assert!(out.contains("There's no machine with that number."));
+    assert!(out.contains("Please choose two different machines."));
+    assert!(out.contains("Correct usage: machine [list | add PATH"));
+}
+
+#[test]
+fn live_redraws_one_status_line() {
+    let mut machine = parse_str("registers 0 1\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop").unwrap();
+    let mut out: Vec<u8> = Vec::new();
+    let speed = NonZeroU32::new(1000).unwrap();
+    tui::live(&mut machine, speed, &mut out, Terminal::Interactive).unwrap();
+    assert!(machine.is_halted());
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.matches('\n').count(), 1);
+    assert!(out.starts_with("\r\x1b[2Kstep 0 | line 0    loop: decjz r1 HALT | registers 0 1"));
+    assert!(out.ends_with("\r\x1b[2Kstep 4 | HALT | registers 1 0 0\n"));
+}
+
+#[test]
+fn progress_redraws_until_the_step_limit() {
+    let mut machine = parse_str("registers 0\nloop: inc r0\ndecjz r1 loop").unwrap();
+    machine.set_step_limit(Some(200_000));
+    let mut out: Vec<u8> = Vec::new();
+    tui::progress(&mut machine, Duration::ZERO, &mut out, Terminal::Interactive).unwrap();
+    assert_eq!(machine.get_steps(), 200_000);
+    let out = String::from_utf8(out).unwrap();
+    assert!(!out.contains('\n'));
+    assert!(out.starts_with("\r\x1b[2Kstep 65536 | "));
+    assert!(out.contains(" steps/s | "));
+    assert!(out.contains(" until the limit of 200000"));
+    // The status line is cleared once the machine stops.
+    assert!(out.ends_with("\r\x1b[2K"));
+}
+
+#[test]
+fn progress_is_silent_for_short_runs() {
+    let mut machine = parse_str("registers 0 1\nloop: decjz r1 halt\ninc r0\ndecjz r2 loop").unwrap();
+    let mut out: Vec<u8> = Vec::new();
+    let interval = Duration::from_secs(60);
+    tui::progress(&mut machine, interval, &mut out, Terminal::Interactive).unwrap();
+    assert!(machine.is_halted());
+    assert!(out.is_empty());
+}
+
+#[test]
+fn debugger_steps_through_goto() {
+    let machine = parse_str("registers 0\ngoto end\ninc r0\nend: halt").unwrap();
+    let mut input = "step\nbacktrace\nlist\nq\n".as_bytes();
+    let mut out: Vec<u8> = Vec::new();
+    tui::debug(machine, &mut input, &mut out, Terminal::Interactive).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("Next line:\n2    end: halt"));
+    assert!(out.contains("#0 line 2 (end) <- line 0"));
+    assert!(out.contains("   0  goto end\n   1  inc r0\n=> 2  end: halt\n"));
+}
+
+#[test]
+fn backtrace_shows_the_call_stack() {
+    let source = "registers 0\ncall outer\nhalt\nouter: call inner\nreturn\ninner: return";
+    let machine = parse_str(source).unwrap();
+    let mut input = "s\ns\nbacktrace\nundo\nbt\nq\n".as_bytes();
+    let mut out: Vec<u8> = Vec::new();
+    tui::debug(machine, &mut input, &mut out, Terminal::Interactive).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("Call stack, innermost first:\n#0 inner, called from line 2 (outer)\n\
+        #1 outer, called from line 0\nJumps:\n#0 line 4 (inner) <- line 2 (outer)\n"), "{out}");
+    // Undoing the inner CALL takes it off the call stack.
+    assert!(out.contains("Undid step.\n"));
+    assert!(out.contains("innermost first:\n#0 outer, called from line 0\nJumps:"), "{out}");
+}
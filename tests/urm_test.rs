@@ -0,0 +1,112 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::path::Path;
+
+use remuir::{
+    dialect::{ Dialect, DialectError, Registry },
+    memory::RegisterNumber,
+    parser::parse_str_dialect,
+    urm::Urm,
+};
+
+/// Run a URM program, and return its natural registers and whether the scratch register was
+/// left at 0.
+fn run(source: &str) -> (String, bool) {
+    let mut machine = parse_str_dialect(source, &Urm).unwrap();
+    machine.run();
+    assert!(machine.is_halted());
+    let scratch = machine.get_state().get_register(RegisterNumber::Negative(1));
+    (machine.display_nat_registers(), scratch == "0")
+}
+
+#[test]
+fn zero_and_successor() {
+    assert_eq!(run("registers 0 5 2\nZ(1)\nS(2)\nS(3)"), (String::from("registers 0 0 3 1"), true));
+}
+
+#[test]
+fn transfer() {
+    assert_eq!(run("registers 0 5 2\nT(1, 2)"), (String::from("registers 0 5 5"), true));
+    assert_eq!(run("registers 0 0 2\nT(1, 2)"), (String::from("registers 0 0 0"), true));
+    assert_eq!(run("registers 0 5 2\nT(2, 2)"), (String::from("registers 0 5 2"), true));
+}
+
+#[test]
+fn jump() {
+    // R3 is set to 1 only if the jump isn't taken.
+    let compare = |r1: u128, r2: u128| {
+        run(&format!("registers 0 {r1} {r2}\n1 J(1, 2, 3)\n2 S(3)"))
+    };
+    assert_eq!(compare(3, 3), (String::from("registers 0 3 3"), true));
+    assert_eq!(compare(0, 0), (String::from("registers 0 0 0"), true));
+    assert_eq!(compare(2, 3), (String::from("registers 0 2 3 1"), true));
+    assert_eq!(compare(3, 2), (String::from("registers 0 3 2 1"), true));
+    assert_eq!(compare(0, 4), (String::from("registers 0 0 4 1"), true));
+    assert_eq!(compare(4, 0), (String::from("registers 0 4 0 1"), true));
+    // Jumping to an instruction which doesn't exist halts.
+    assert_eq!(run("J(1, 1, 9)\nS(1)"), (String::from("registers 0"), true));
+    assert_eq!(run("J(1, 1, 2)\nS(1)"), (String::from("registers 0 1"), true));
+}
+
+#[test]
+fn adds_with_a_loop() {
+    // R1 = R1 + R2, from the textbook.
+    let source = "registers 0 4 3  # R1 and R2
+I1: J(3, 2, 5)
+I2: S(1)
+I3: S(3)
+I4: J(1, 1, 1)";
+    assert_eq!(run(source), (String::from("registers 0 7 3 3"), true));
+}
+
+#[test]
+fn numbering_is_optional_but_checked() {
+    assert_eq!(run("1 S(1)\n2. S(1); i3: S(1)\nS(1)").0, "registers 0 4");
+    assert!(matches!(
+        Urm.translate("1 S(1)\n3 S(1)"),
+        Err(DialectError::Syntax { line: 2, message }) if message.contains("numbered 3"),
+    ));
+}
+
+#[test]
+fn labels_follow_the_instruction_numbers() {
+    let source = Urm.translate("S(1)\nJ(1, 2, 1)").unwrap();
+    assert!(source.starts_with("registers 0\nI1: inc r1\nI2: decjz r1 I2_m0\n"), "{source}");
+}
+
+#[test]
+fn syntax_errors() {
+    let error = |source: &str| match Urm.translate(source) {
+        Err(DialectError::Syntax { line, message }) => (line, message),
+        other => panic!("{other:?}"),
+    };
+    let unknown = String::from("Unknown instruction \"X\". Use Z, S, T or J.");
+    assert_eq!(error("S(1)\nX(1)"), (2, unknown));
+    assert_eq!(error("T(1 2)"), (1, String::from("Expected \",\", not \"2\".")));
+    assert_eq!(error("S(a)"), (1, String::from("\"a\" isn't a number.")));
+    assert_eq!(error("S(1"), (1, String::from("Expected \")\".")));
+    assert_eq!(error("1"), (1, String::from("Expected an instruction after its number.")));
+    assert!(matches!(Urm.translate("registers x\nS(1)"), Err(DialectError::InvalidSource(_))));
+}
+
+#[test]
+fn registered_by_default() {
+    let registry = Registry::default();
+    assert_eq!(registry.get_names(), ["remuir", "urm"]);
+    assert_eq!(registry.find("URM").unwrap().get_name(), "urm");
+    assert_eq!(registry.for_path(Path::new("exercise.urm")).unwrap().get_name(), "urm");
+}
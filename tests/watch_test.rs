@@ -0,0 +1,80 @@
+/* remuir: a register machine emulator written in Rust.
+Copyright (C) 2024  Charlotte Ausel
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <https://www.gnu.org/licenses/>. */
+
+use std::{ fs, path::PathBuf };
+
+use remuir::{ parser::parse_str, tui::RemuirError, watch::TestWatcher };
+
+/// Adds r1 to r0, with one test which passes and one which doesn't.
+const ADD: &str = "registers 0 2
+#test adds { registers 0 2 / expect registers 2 }
+#test wrong { registers 1 1 / expect r0 = 3 }
+loop: decjz r1 HALT
+inc r0
+decjz r-1 loop";
+
+fn temporary_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("remuir-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(directory.join("nested")).unwrap();
+    directory
+}
+
+fn poll(watcher: &mut TestWatcher) -> String {
+    let mut out = Vec::new();
+    let load = |source: &str| parse_str(source).map_err(RemuirError::from);
+    watcher.poll(load, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn only_changes_are_reported() {
+    let directory = temporary_directory("watch");
+    let add = directory.join("nested").join("add.remuir");
+    fs::write(&add, ADD).unwrap();
+    fs::write(directory.join("notes.txt"), "#test ignored {").unwrap();
+    let mut watcher = TestWatcher::new(directory.clone());
+
+    let first = poll(&mut watcher);
+    assert!(first.contains("add.remuir: 1 passed, 1 failed"), "{first}");
+    assert!(first.contains("  failed: wrong ... expected r0 = 3, got 2"), "{first}");
+    assert!(!first.contains("adds"), "{first}");
+    assert!(first.ends_with("Total: 1 passed, 1 failed\n"), "{first}");
+    assert_eq!(poll(&mut watcher), "");
+
+    // Fix one test, break the other and add another.
+    let edited = ADD
+        .replace("expect r0 = 3", "expect r0 = 2")
+        .replace("expect registers 2 }", "expect registers 3 }\n#test empty { registers 0 }");
+    fs::write(&add, edited).unwrap();
+    let second = poll(&mut watcher);
+    assert!(second.contains("  broken: adds ... expected registers 3, got registers 2 0"), "{second}");
+    assert!(second.contains("  fixed: wrong\n"), "{second}");
+    assert!(second.contains("  new: empty ... ok\n"), "{second}");
+    assert!(second.ends_with("Total: 2 passed, 1 failed\n"), "{second}");
+    assert_eq!(watcher.totals(), (2, 1));
+
+    fs::write(directory.join("broken.remuir"), "registers 0\n#test unclosed {").unwrap();
+    let third = poll(&mut watcher);
+    assert!(third.contains("broken.remuir: The test \"unclosed\" on line 2"), "{third}");
+    assert!(!third.contains("add.remuir"), "{third}");
+
+    fs::remove_file(&add).unwrap();
+    let fourth = poll(&mut watcher);
+    assert!(fourth.contains("add.remuir: removed"), "{fourth}");
+    assert_eq!(watcher.totals(), (0, 0));
+    let _ = fs::remove_dir_all(&directory);
+}